@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE users
+ADD COLUMN provider_order text[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE book
+DROP COLUMN reread,
+DROP COLUMN priority;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE book
+ADD COLUMN reread bool NOT NULL DEFAULT false,
+ADD COLUMN priority integer CHECK (priority BETWEEN 1 AND 5);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE book
+DROP COLUMN sort_title;
+
+DROP FUNCTION book_sort_title;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE FUNCTION book_sort_title(title text, language text) RETURNS text
+LANGUAGE sql IMMUTABLE PARALLEL SAFE
+RETURN lower(regexp_replace(
+	title,
+	CASE
+		WHEN language IN ('fre', 'fra', 'fr') THEN '^(l''|(le|la|les|un|une|des)\s+)'
+		WHEN language IN ('ita', 'it') THEN '^(l''|(il|lo|la|i|gli|le|un|uno|una)\s+)'
+		WHEN language IN ('spa', 'es') THEN '^(el|la|los|las|un|una)\s+'
+		WHEN language IN ('ger', 'deu', 'de') THEN '^(der|die|das|ein|eine)\s+'
+		ELSE '^(the|an|a)\s+'
+	END,
+	'',
+	'i'
+));
+
+ALTER TABLE book
+ADD COLUMN sort_title text NOT NULL GENERATED ALWAYS AS (book_sort_title(title, language)) STORED;
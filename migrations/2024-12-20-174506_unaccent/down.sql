@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX series_name_unaccent;
+DROP INDEX tag_name_unaccent;
+DROP INDEX author_name_unaccent;
+
+DROP FUNCTION name_key;
+
+DROP EXTENSION unaccent;
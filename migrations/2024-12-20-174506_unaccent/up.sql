@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE EXTENSION IF NOT EXISTS unaccent;
+
+-- unaccent is only STABLE, but it is immutable when the dictionary is given explicitly
+CREATE FUNCTION name_key(name text) RETURNS text
+LANGUAGE sql IMMUTABLE PARALLEL SAFE STRICT
+RETURN lower(public.unaccent('public.unaccent'::regdictionary, name));
+
+CREATE INDEX author_name_unaccent ON author (name_key(name));
+CREATE INDEX tag_name_unaccent ON tag (name_key(name));
+CREATE INDEX series_name_unaccent ON series (owner, name_key(name));
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE tagimplication (
+	owner uuid NOT NULL REFERENCES users(id),
+	tag INT NOT NULL REFERENCES tag(id),
+	implies INT NOT NULL REFERENCES tag(id),
+	PRIMARY KEY (owner, tag, implies),
+	CHECK (tag <> implies)
+);
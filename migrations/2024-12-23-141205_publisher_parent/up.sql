@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE publisherparent (
+	owner uuid NOT NULL REFERENCES users(id),
+	publisher TEXT NOT NULL,
+	parent TEXT NOT NULL,
+	PRIMARY KEY (owner, publisher),
+	CHECK (publisher <> parent)
+);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE booklink (
+	id SERIAL PRIMARY KEY,
+	book uuid NOT NULL REFERENCES book(id),
+	label TEXT NOT NULL,
+	url TEXT NOT NULL
+);
+
+CREATE INDEX booklink_book ON booklink(book);
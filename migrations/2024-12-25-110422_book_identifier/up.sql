@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE bookidentifier (
+	book uuid NOT NULL REFERENCES book(id),
+	scheme TEXT NOT NULL,
+	value TEXT NOT NULL,
+	PRIMARY KEY (book, scheme)
+);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users
+DROP COLUMN public_widget;
+
+ALTER TABLE book
+DROP COLUMN added;
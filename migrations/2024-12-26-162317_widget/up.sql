@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE book
+ADD COLUMN added timestamptz NOT NULL DEFAULT now();
+
+ALTER TABLE users
+ADD COLUMN public_widget bool NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE users
+ADD COLUMN hidden_pages text[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE series
+ADD COLUMN archived bool NOT NULL DEFAULT false;
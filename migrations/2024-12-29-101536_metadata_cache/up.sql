@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE metadata_cache (
+	provider TEXT NOT NULL,
+	isbn TEXT NOT NULL,
+	details TEXT,
+	fetched timestamptz NOT NULL DEFAULT now(),
+	PRIMARY KEY (provider, isbn)
+);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE book
+ADD COLUMN giveaway bool NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE book
+ADD COLUMN summary_spoilers bool NOT NULL DEFAULT false;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE book
+ADD COLUMN metadata_source text,
+ADD COLUMN metadata_fetched timestamptz;
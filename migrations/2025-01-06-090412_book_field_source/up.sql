@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE bookfieldsource (
+	book uuid NOT NULL REFERENCES book(id),
+	field TEXT NOT NULL,
+	provider TEXT,
+	changed timestamptz NOT NULL,
+	PRIMARY KEY (book, field)
+);
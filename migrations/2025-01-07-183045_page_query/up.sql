@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE pagequery (
+	owner uuid NOT NULL REFERENCES users(id),
+	page TEXT NOT NULL,
+	query TEXT NOT NULL,
+	PRIMARY KEY (owner, page)
+);
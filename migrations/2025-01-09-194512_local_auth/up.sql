@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE password (
+	owner uuid PRIMARY KEY REFERENCES users(id),
+	hash TEXT NOT NULL,
+	-- Bumped to revoke the existing sessions
+	generation INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE prefetchedwish (
+	owner uuid NOT NULL REFERENCES users(id),
+	isbn TEXT NOT NULL,
+	provider TEXT NOT NULL,
+	details TEXT NOT NULL,
+	fetched timestamptz NOT NULL DEFAULT now(),
+	PRIMARY KEY (owner, isbn)
+);
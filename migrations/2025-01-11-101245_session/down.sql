@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE session;
+
+ALTER TABLE password ADD COLUMN generation INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Sessions are now stored, revoking them removes their row
+ALTER TABLE password DROP COLUMN generation;
+
+CREATE TABLE session (
+	id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+	owner uuid NOT NULL REFERENCES users(id),
+	created timestamptz NOT NULL DEFAULT now(),
+	last_used timestamptz NOT NULL DEFAULT now(),
+	ip TEXT,
+	user_agent TEXT
+);
+
+CREATE INDEX session_owner ON session(owner);
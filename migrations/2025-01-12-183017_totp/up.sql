@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE totp (
+	owner uuid PRIMARY KEY REFERENCES users(id),
+	secret BYTEA NOT NULL,
+	-- Codes are only asked once the authenticator has been checked with a first one
+	enabled BOOLEAN NOT NULL DEFAULT false,
+	-- Time step of the last accepted code, so that a code is never accepted twice
+	last_step BIGINT NOT NULL DEFAULT 0,
+	-- Wrong codes entered since the last accepted one
+	failures INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE recoverycode (
+	owner uuid NOT NULL REFERENCES users(id),
+	hash TEXT NOT NULL,
+	PRIMARY KEY (owner, hash)
+);
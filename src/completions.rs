@@ -0,0 +1,86 @@
+//! Names suggested by the book forms, kept for each user until the library changes
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionList {
+    Authors,
+    Tags,
+    Series,
+    Locations,
+}
+
+type Lists = HashMap<(Uuid, CompletionList), Arc<Vec<String>>>;
+
+#[derive(Default)]
+pub struct Completions {
+    lists: Mutex<Lists>,
+    /// Incremented by each invalidation, so that lists loaded before it are not kept
+    generation: AtomicU64,
+}
+
+impl Completions {
+    pub fn get(&self, user: Uuid, list: CompletionList) -> Option<Arc<Vec<String>>> {
+        self.lists.lock().unwrap().get(&(user, list)).cloned()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Keep `values`, unless the lists were invalidated since `generation` was read
+    pub fn insert(&self, user: Uuid, list: CompletionList, generation: u64, values: Vec<String>) {
+        let mut lists = self.lists.lock().unwrap();
+        if self.generation() == generation {
+            lists.insert((user, list), Arc::new(values));
+        }
+    }
+
+    /// Forget all the lists, after a change to the library
+    pub fn invalidate(&self) {
+        let mut lists = self.lists.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        lists.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::{CompletionList, Completions};
+
+    #[test]
+    fn invalidate() {
+        let completions = Completions::default();
+        let user = Uuid::new_v4();
+
+        let generation = completions.generation();
+        completions.insert(
+            user,
+            CompletionList::Tags,
+            generation,
+            vec!["Fantasy".into()],
+        );
+        assert_eq!(
+            completions.get(user, CompletionList::Tags).as_deref(),
+            Some(&vec!["Fantasy".to_string()])
+        );
+        assert_eq!(completions.get(user, CompletionList::Authors), None);
+
+        completions.invalidate();
+        assert_eq!(completions.get(user, CompletionList::Tags), None);
+
+        completions.insert(user, CompletionList::Tags, generation, vec!["Stale".into()]);
+        assert_eq!(completions.get(user, CompletionList::Tags), None);
+    }
+}
@@ -0,0 +1,59 @@
+//! Database connection pool, optionally logging slow queries
+
+use std::time::{Duration, Instant};
+
+use diesel::connection::{Instrumentation, InstrumentationEvent};
+use diesel_async::{
+    pooled_connection::{deadpool::Pool, AsyncDieselConnectionManager, ManagerConfig},
+    AsyncConnection, AsyncPgConnection,
+};
+
+use crate::{DatabaseConfig, PgPool};
+
+/// Log the queries taking longer than `threshold`, they are attributed to the route being handled
+/// through the request span.
+struct SlowQueries {
+    threshold: Duration,
+    started: Option<Instant>,
+}
+
+impl Instrumentation for SlowQueries {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartQuery { .. } => self.started = Some(Instant::now()),
+            InstrumentationEvent::FinishQuery { query, .. } => {
+                let Some(elapsed) = self.started.take().map(|s| s.elapsed()) else {
+                    return;
+                };
+
+                if elapsed >= self.threshold {
+                    tracing::warn!("Slow query ({elapsed:?}): {query}");
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn pool(config: &DatabaseConfig) -> anyhow::Result<PgPool> {
+    let mut manager_config = ManagerConfig::default();
+
+    if let Some(threshold) = config.slow_query_ms {
+        let threshold = Duration::from_millis(threshold);
+
+        manager_config.custom_setup = Box::new(move |url| {
+            Box::pin(async move {
+                let mut conn = AsyncPgConnection::establish(url).await?;
+                conn.set_instrumentation(SlowQueries {
+                    threshold,
+                    started: None,
+                });
+                Ok(conn)
+            })
+        });
+    }
+
+    let manager = AsyncDieselConnectionManager::new_with_config(&config.url, manager_config);
+
+    Ok(Pool::builder(manager).build()?)
+}
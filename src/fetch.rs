@@ -0,0 +1,149 @@
+//! Fetching of the pages given by the users. Only public addresses can be reached, so that the
+//! server can't be used to probe its own network.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::Policy,
+    Url,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 5;
+/// Pages are cut after this size, the details of a book are near their beginning
+const MAX_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space (RFC 6598)
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local and link-local
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Can `url` be fetched, the hosts given by their address don't go through the resolver
+pub fn allowed(url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+
+    match url.host_str() {
+        Some(host) => match host.trim_start_matches('[').trim_end_matches(']').parse() {
+            Ok(ip) => is_public(ip),
+            Err(_) => true,
+        },
+        None => false,
+    }
+}
+
+/// Only resolves the names to their public addresses
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub struct PageFetcher {
+    client: reqwest::Client,
+}
+
+impl PageFetcher {
+    pub fn new() -> reqwest::Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent("github.com/traxys/bouquineur")
+            .timeout(TIMEOUT)
+            .dns_resolver(std::sync::Arc::new(PublicResolver))
+            .redirect(Policy::custom(|attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if !allowed(attempt.url()) {
+                    attempt.error("redirected to a non public address")
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()?;
+
+        Ok(Self { client })
+    }
+
+    /// Text of the page at `url`, which must be [allowed]. Only the start of large pages is read.
+    pub async fn fetch(&self, url: Url) -> reqwest::Result<String> {
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+
+        let mut page = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            page.extend_from_slice(&chunk);
+            if page.len() >= MAX_PAGE_SIZE {
+                page.truncate(MAX_PAGE_SIZE);
+                break;
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&page).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::Url;
+
+    use super::allowed;
+
+    #[test]
+    fn public() {
+        let allowed = |url: &str| allowed(&Url::parse(url).unwrap());
+
+        assert!(allowed("https://www.amazon.fr/dp/2070584623"));
+        assert!(allowed("http://93.184.215.14/book"));
+        assert!(!allowed("file:///etc/passwd"));
+        assert!(!allowed("http://127.0.0.1:8080/"));
+        assert!(!allowed("http://10.1.2.3/"));
+        assert!(!allowed("http://169.254.169.254/latest/meta-data/"));
+        assert!(!allowed("http://100.64.0.1/"));
+        assert!(!allowed("http://[::1]/"));
+        assert!(!allowed("http://[fd00::1]/"));
+        assert!(!allowed("http://[::ffff:192.168.1.1]/"));
+    }
+}
@@ -0,0 +1,107 @@
+//! BookBuddy exports all the books in a single CSV file, its collections are imported as tags and
+//! the books of the wishlist as not owned
+
+use crate::metadata::NullableBookDetails;
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BookBuddyRow {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    series: String,
+    #[serde(default)]
+    volume: String,
+    /// Comma separated
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    publisher: String,
+    #[serde(default, rename = "Date Published")]
+    date_published: String,
+    #[serde(default, rename = "Year Published")]
+    year_published: String,
+    #[serde(default)]
+    summary: String,
+    #[serde(default)]
+    pages: String,
+    #[serde(default)]
+    language: String,
+    #[serde(default, rename = "ISBN")]
+    isbn: String,
+    /// `Read`, `Reading` or `Unread`
+    #[serde(default)]
+    status: String,
+    /// `Yes` or `No`
+    #[serde(default)]
+    wishlist: String,
+    /// Comma separated
+    #[serde(default)]
+    tags: String,
+    /// Comma separated
+    #[serde(default)]
+    collections: String,
+}
+
+pub(super) fn parse(data: &[u8]) -> Result<Vec<NullableBookDetails>, csv::Error> {
+    let rows: Vec<BookBuddyRow> = super::rows(data)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let mut tags = super::list(&row.collections, &[',']);
+            tags.extend(super::list(&row.tags, &[',']));
+
+            let series = match row.volume.parse() {
+                Ok(volume) if !row.series.is_empty() => Some((row.series, volume)),
+                _ => None,
+            };
+
+            NullableBookDetails {
+                isbn: super::isbn(&[&row.isbn]),
+                title: super::optional(row.title),
+                authors: super::list(&row.author, &[',', ';']),
+                tags,
+                summary: super::optional(row.summary),
+                published: super::date(&row.date_published)
+                    .or_else(|| super::date(&row.year_published)),
+                publisher: super::optional(row.publisher),
+                language: super::optional(row.language),
+                page_count: row.pages.parse().ok(),
+                read: row.status.eq_ignore_ascii_case("read"),
+                owned: !row.wishlist.eq_ignore_ascii_case("yes"),
+                series,
+                ..Default::default()
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn bookbuddy() {
+        let books = super::parse(include_bytes!("../../tests/bookbuddy.csv")).unwrap();
+        assert_eq!(books.len(), 2);
+
+        let guards = &books[0];
+        assert_eq!(guards.isbn.as_deref(), Some("9780552134637"));
+        assert_eq!(guards.title.as_deref(), Some("Guards! Guards!"));
+        assert_eq!(guards.authors, ["Terry Pratchett"]);
+        assert_eq!(guards.tags, ["Living room", "humour", "favourites"]);
+        assert_eq!(guards.series, Some(("Discworld".into(), 8)));
+        assert_eq!(
+            guards.published,
+            chrono::NaiveDate::from_ymd_opt(1990, 6, 7)
+        );
+        assert_eq!(guards.page_count, Some(416));
+        assert!(guards.read);
+        assert!(guards.owned);
+
+        let hp = &books[1];
+        assert_eq!(hp.published, chrono::NaiveDate::from_ymd_opt(1998, 1, 1));
+        assert_eq!(hp.language.as_deref(), Some("French"));
+        assert!(!hp.read);
+        assert!(!hp.owned);
+    }
+}
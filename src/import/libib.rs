@@ -0,0 +1,91 @@
+//! Libib exports one CSV file per library, its groups are imported as tags
+
+use crate::metadata::NullableBookDetails;
+
+#[derive(serde::Deserialize)]
+struct LibibRow {
+    #[serde(default)]
+    item_type: String,
+    #[serde(default)]
+    title: String,
+    /// Comma separated
+    #[serde(default)]
+    creators: String,
+    #[serde(default)]
+    ean_isbn13: String,
+    #[serde(default)]
+    upc_isbn10: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    publisher: String,
+    #[serde(default)]
+    publish_date: String,
+    #[serde(default)]
+    group: String,
+    /// Comma separated
+    #[serde(default)]
+    tags: String,
+    /// Number of pages
+    #[serde(default)]
+    length: String,
+    /// `Not begun`, `In progress` or `Completed`
+    #[serde(default)]
+    status: String,
+}
+
+pub(super) fn parse(data: &[u8]) -> Result<Vec<NullableBookDetails>, csv::Error> {
+    let rows: Vec<LibibRow> = super::rows(data)?;
+
+    Ok(rows
+        .into_iter()
+        .filter(|row| row.item_type.is_empty() || row.item_type.eq_ignore_ascii_case("book"))
+        .map(|row| {
+            let mut tags = super::list(&row.group, &[]);
+            tags.extend(super::list(&row.tags, &[',']));
+
+            NullableBookDetails {
+                isbn: super::isbn(&[&row.ean_isbn13, &row.upc_isbn10]),
+                title: super::optional(row.title),
+                authors: super::list(&row.creators, &[',']),
+                tags,
+                summary: super::optional(row.description),
+                published: super::date(&row.publish_date),
+                publisher: super::optional(row.publisher),
+                page_count: row.length.parse().ok(),
+                read: row.status.eq_ignore_ascii_case("completed"),
+                owned: true,
+                ..Default::default()
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn libib() {
+        let books = super::parse(include_bytes!("../../tests/libib.csv")).unwrap();
+        assert_eq!(books.len(), 2);
+
+        let mort = &books[0];
+        assert_eq!(mort.isbn.as_deref(), Some("9780552131063"));
+        assert_eq!(mort.title.as_deref(), Some("Mort"));
+        assert_eq!(mort.authors, ["Terry Pratchett"]);
+        assert_eq!(mort.tags, ["Discworld shelf", "fantasy", "humour"]);
+        assert_eq!(
+            mort.published,
+            chrono::NaiveDate::from_ymd_opt(1987, 11, 12)
+        );
+        assert_eq!(mort.page_count, Some(272));
+        assert!(mort.read);
+        assert!(mort.owned);
+
+        // Only the ISBN-10 is known
+        let omens = &books[1];
+        assert_eq!(omens.isbn.as_deref(), Some("9780552137034"));
+        assert_eq!(omens.authors, ["Terry Pratchett", "Neil Gaiman"]);
+        assert!(omens.tags.is_empty());
+        assert!(!omens.read);
+    }
+}
@@ -0,0 +1,288 @@
+//! LibraryThing exports the books either as a tab separated file or as a JSON object keyed by book
+//! id. Its tags are imported as tags, and so are the collections except for the builtin ones which
+//! give the read and owned flags.
+
+use std::collections::BTreeMap;
+
+use crate::metadata::NullableBookDetails;
+
+use super::ImportError;
+
+/// Collections every LibraryThing account has
+const BUILTIN_COLLECTIONS: &[&str] = &[
+    "Your library",
+    "Wishlist",
+    "Currently reading",
+    "To read",
+    "Read but unowned",
+    "Favorites",
+];
+
+#[derive(serde::Deserialize)]
+struct LibraryThingRow {
+    #[serde(default, rename = "Title")]
+    title: String,
+    /// `Last, First`
+    #[serde(default, rename = "Primary Author")]
+    primary_author: String,
+    #[serde(default, rename = "Secondary Author")]
+    secondary_author: String,
+    /// `Publisher (year), binding, pages`
+    #[serde(default, rename = "Publication")]
+    publication: String,
+    #[serde(default, rename = "Date")]
+    date: String,
+    #[serde(default, rename = "Page Count")]
+    page_count: String,
+    #[serde(default, rename = "Date Read")]
+    date_read: String,
+    /// Comma separated
+    #[serde(default, rename = "Tags")]
+    tags: String,
+    /// Comma separated
+    #[serde(default, rename = "Collections")]
+    collections: String,
+    #[serde(default, rename = "Languages")]
+    languages: String,
+    /// Bracketed, like `[0552131067]`
+    #[serde(default, rename = "ISBN")]
+    isbn: String,
+    #[serde(default, rename = "ISBNs")]
+    isbns: String,
+    /// `Name (volume)`, separated by semicolons
+    #[serde(default, rename = "Series")]
+    series: String,
+}
+
+#[derive(serde::Deserialize)]
+struct LibraryThingBook {
+    #[serde(default)]
+    title: String,
+    /// Objects with the name as `fl` (first last) and `lf` (last, first)
+    #[serde(default)]
+    authors: serde_json::Value,
+    #[serde(default)]
+    publication: String,
+    #[serde(default)]
+    date: String,
+    #[serde(default)]
+    pages: String,
+    #[serde(default, alias = "datefinished")]
+    dateread: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    collections: Vec<String>,
+    #[serde(default)]
+    language: Vec<String>,
+    /// Either a list or an object keyed by the kind of ISBN
+    #[serde(default)]
+    isbn: serde_json::Value,
+    #[serde(default)]
+    ean: serde_json::Value,
+    #[serde(default)]
+    originalisbn: String,
+    #[serde(default)]
+    series: Vec<String>,
+}
+
+/// `Last, First` names are written `First Last`
+fn author(name: &str) -> Option<String> {
+    let name = name.trim();
+    match name.split_once(',') {
+        _ if name.is_empty() => None,
+        Some((last, first)) if !first.contains(',') => {
+            Some(format!("{} {}", first.trim(), last.trim()))
+        }
+        _ => Some(name.to_string()),
+    }
+}
+
+/// The publisher starts the publication, before the year and the binding
+fn publisher(publication: &str) -> Option<String> {
+    let end = publication.find([',', '(']).unwrap_or(publication.len());
+    super::optional(publication[..end].trim().to_string())
+}
+
+/// First series written as `Name (volume)`
+fn series<'a>(mut series: impl Iterator<Item = &'a str>) -> Option<(String, i32)> {
+    series.find_map(|series| {
+        let (name, volume) = series.trim().strip_suffix(')')?.rsplit_once('(')?;
+        Some((name.trim().to_string(), volume.trim().parse().ok()?))
+    })
+}
+
+/// Tags from the tags and the user collections, and the read and owned flags
+fn flags(mut tags: Vec<String>, collections: &[String], read: bool) -> (Vec<String>, bool, bool) {
+    let is_in = |name: &str| collections.iter().any(|c| c.eq_ignore_ascii_case(name));
+
+    tags.extend(
+        collections
+            .iter()
+            .filter(|c| {
+                !BUILTIN_COLLECTIONS
+                    .iter()
+                    .any(|b| b.eq_ignore_ascii_case(c))
+            })
+            .cloned(),
+    );
+
+    let owned = !is_in("Wishlist") && !is_in("Read but unowned");
+    (tags, read || is_in("Read but unowned"), owned)
+}
+
+/// Text of the export, the tab separated files are written in UTF-16 with a byte order mark
+fn text(data: &[u8]) -> String {
+    match data {
+        [0xff, 0xfe, rest @ ..] => {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+pub(super) fn parse(data: &[u8]) -> Result<Vec<NullableBookDetails>, ImportError> {
+    let text = text(data);
+
+    if text.trim_start().starts_with('{') {
+        parse_json(&text)
+    } else {
+        Ok(parse_tsv(text.as_bytes())?)
+    }
+}
+
+fn parse_tsv(data: &[u8]) -> Result<Vec<NullableBookDetails>, csv::Error> {
+    let rows: Vec<LibraryThingRow> = super::rows_with(data, b'\t')?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let collections = super::list(&row.collections, &[',']);
+            let (tags, read, owned) = flags(
+                super::list(&row.tags, &[',']),
+                &collections,
+                !row.date_read.is_empty(),
+            );
+
+            let isbn = row.isbn.trim_matches(['[', ']']);
+
+            NullableBookDetails {
+                isbn: super::isbn(&[isbn, row.isbns.as_str()]),
+                title: super::optional(row.title),
+                authors: [&row.primary_author, &row.secondary_author]
+                    .into_iter()
+                    .map(String::as_str)
+                    .filter_map(author)
+                    .collect(),
+                tags,
+                published: super::date(&row.date),
+                publisher: publisher(&row.publication),
+                language: super::list(&row.languages, &[',']).into_iter().next(),
+                page_count: row.page_count.trim().parse().ok(),
+                read,
+                owned,
+                series: series(row.series.split(';')),
+                ..Default::default()
+            }
+        })
+        .collect())
+}
+
+/// All the strings of a JSON value
+fn strings(value: &serde_json::Value) -> Vec<&str> {
+    match value {
+        serde_json::Value::String(s) => vec![s.as_str()],
+        serde_json::Value::Array(values) => values.iter().flat_map(strings).collect(),
+        serde_json::Value::Object(values) => values.values().flat_map(strings).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_json(text: &str) -> Result<Vec<NullableBookDetails>, ImportError> {
+    let books: BTreeMap<String, LibraryThingBook> = serde_json::from_str(text)?;
+
+    Ok(books
+        .into_values()
+        .map(|book| {
+            let (tags, read, owned) =
+                flags(book.tags, &book.collections, !book.dateread.is_empty());
+
+            let mut isbns = strings(&book.ean);
+            isbns.extend(strings(&book.isbn));
+            isbns.push(&book.originalisbn);
+
+            let authors = match &book.authors {
+                serde_json::Value::Array(authors) => authors
+                    .iter()
+                    .filter_map(|a| a.get("fl").or(a.get("lf"))?.as_str())
+                    .filter_map(author)
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            NullableBookDetails {
+                isbn: super::isbn(&isbns),
+                title: super::optional(book.title),
+                authors,
+                tags,
+                published: super::date(&book.date),
+                publisher: publisher(&book.publication),
+                language: book.language.into_iter().next(),
+                page_count: book.pages.trim().parse().ok(),
+                read,
+                owned,
+                series: series(book.series.iter().map(String::as_str)),
+                ..Default::default()
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    fn check(books: &[crate::metadata::NullableBookDetails]) {
+        assert_eq!(books.len(), 2);
+
+        let mort = &books[0];
+        assert_eq!(mort.isbn.as_deref(), Some("9780552131063"));
+        assert_eq!(mort.title.as_deref(), Some("Mort"));
+        assert_eq!(mort.authors, ["Terry Pratchett"]);
+        assert_eq!(mort.tags, ["fantasy", "humour", "Attic"]);
+        assert_eq!(mort.publisher.as_deref(), Some("Corgi"));
+        assert_eq!(mort.published, chrono::NaiveDate::from_ymd_opt(1987, 1, 1));
+        assert_eq!(mort.page_count, Some(272));
+        assert_eq!(mort.series, Some(("Discworld".into(), 4)));
+        assert!(mort.read);
+        assert!(mort.owned);
+
+        let omens = &books[1];
+        assert_eq!(omens.isbn.as_deref(), Some("9780552137034"));
+        assert_eq!(omens.authors, ["Terry Pratchett", "Neil Gaiman"]);
+        assert!(omens.tags.is_empty());
+        assert!(!omens.read);
+        assert!(!omens.owned);
+    }
+
+    #[test]
+    fn tsv() {
+        let data = include_str!("../../tests/librarything.tsv");
+
+        let books = super::parse(data.as_bytes()).unwrap();
+        check(&books);
+
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(data.encode_utf16().flat_map(u16::to_le_bytes));
+        let books = super::parse(&utf16).unwrap();
+        check(&books);
+    }
+
+    #[test]
+    fn json() {
+        let books = super::parse(include_bytes!("../../tests/librarything.json")).unwrap();
+        check(&books);
+    }
+}
@@ -0,0 +1,104 @@
+//! Conversion of the exports of other cataloging applications to books
+
+use chrono::NaiveDate;
+
+use crate::metadata::{isbn::find_isbn, NullableBookDetails};
+
+mod bookbuddy;
+mod libib;
+mod librarything;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("{0}")]
+    Csv(#[from] csv::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Libib,
+    BookBuddy,
+    LibraryThing,
+}
+
+impl ImportFormat {
+    pub const ALL: &'static [Self] = &[Self::Libib, Self::BookBuddy, Self::LibraryThing];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            ImportFormat::Libib => "libib",
+            ImportFormat::BookBuddy => "bookbuddy",
+            ImportFormat::LibraryThing => "librarything",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImportFormat::Libib => "Libib",
+            ImportFormat::BookBuddy => "BookBuddy",
+            ImportFormat::LibraryThing => "LibraryThing",
+        }
+    }
+
+    /// Books of the export, the rows that are not books (Libib also catalogs movies, games...) are
+    /// left out
+    pub fn parse(&self, data: &[u8]) -> Result<Vec<NullableBookDetails>, ImportError> {
+        match self {
+            ImportFormat::Libib => Ok(libib::parse(data)?),
+            ImportFormat::BookBuddy => Ok(bookbuddy::parse(data)?),
+            ImportFormat::LibraryThing => librarything::parse(data),
+        }
+    }
+}
+
+/// Read the rows of a CSV file with headers, `T` names the columns it uses
+fn rows<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<Vec<T>, csv::Error> {
+    rows_with(data, b',')
+}
+
+fn rows_with<T: serde::de::DeserializeOwned>(
+    data: &[u8],
+    delimiter: u8,
+) -> Result<Vec<T>, csv::Error> {
+    csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data)
+        .into_deserialize()
+        .collect()
+}
+
+/// Non empty values of a list separated by `separators`
+fn list(text: &str, separators: &[char]) -> Vec<String> {
+    text.split(separators)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn optional(text: String) -> Option<String> {
+    (!text.is_empty()).then_some(text)
+}
+
+/// First valid ISBN of `candidates`, as an ISBN-13
+fn isbn(candidates: &[&str]) -> Option<String> {
+    candidates
+        .iter()
+        .find_map(|candidate| find_isbn(candidate, true))
+}
+
+/// Dates are written in various ways, a lone year is taken as its first day
+fn date(text: &str) -> Option<NaiveDate> {
+    ["%Y-%m-%d", "%Y/%m/%d", "%m/%d/%Y", "%d/%m/%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+        .or_else(|| {
+            let year = text.parse().ok()?;
+            NaiveDate::from_ymd_opt(year, 1, 1)
+        })
+}
@@ -0,0 +1,485 @@
+//! Books sent by email: the `[ingest.imap]` mailbox is polled for the unread messages of the
+//! configured senders, whose ISBNs and links to Goodreads books are added to the library of the
+//! user, or to their wishlist when the subject starts with "wish".
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures_util::TryStreamExt;
+use mail_parser::{Message, MessageParser};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+
+use crate::{metadata::isbn::find_isbns, routes, AppState};
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct IngestConfig {
+    #[serde(default)]
+    pub imap: Option<ImapConfig>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ImapConfig {
+    /// Server of the mailbox, reached over TLS
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+    /// Minutes between two checks of the mailbox
+    #[serde(default = "default_interval")]
+    pub interval_minutes: u64,
+    /// User receiving the books sent from each address
+    pub senders: HashMap<String, String>,
+    /// `authserv-id` of the server receiving the messages. When set, only the messages that it
+    /// checked with DMARC are accepted, as the sender of a message can otherwise be forged.
+    #[serde(default)]
+    pub authserv_id: Option<String>,
+}
+
+fn default_port() -> u16 {
+    993
+}
+
+fn default_mailbox() -> String {
+    "INBOX".into()
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum IngestError {
+    #[error("Could not reach the server")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid server name")]
+    InvalidHost(#[from] tokio_rustls::rustls::pki_types::InvalidDnsNameError),
+    #[error("IMAP error")]
+    Imap(#[from] async_imap::error::Error),
+    #[error("Could not add the books")]
+    Import(#[from] routes::RouteError),
+}
+
+/// Books sent by a configured sender
+#[derive(Debug, PartialEq, Eq)]
+struct Sent {
+    user: String,
+    /// ISBNs and addresses of pages showing one
+    items: Vec<String>,
+    wish: bool,
+}
+
+/// The subject starts with "wish", after the prefixes of forwarded messages
+fn is_wish(subject: &str) -> bool {
+    let mut subject = subject.trim();
+    while let Some((prefix, rest)) = subject.split_once(':') {
+        if !["fw", "fwd", "tr", "re"].contains(&prefix.trim().to_ascii_lowercase().as_str()) {
+            break;
+        }
+        subject = rest.trim_start();
+    }
+
+    subject.to_ascii_lowercase().starts_with("wish")
+}
+
+/// Links to the Goodreads page of a book, as in the share emails
+fn goodreads_links(text: &str) -> Vec<String> {
+    let mut links = Vec::new();
+
+    for word in text.split(|c: char| c.is_whitespace() || "<>\"'()[]".contains(c)) {
+        let Ok(url) = reqwest::Url::parse(word) else {
+            continue;
+        };
+        let Some(id) = url.path().strip_prefix("/book/show/") else {
+            continue;
+        };
+        let id: String = id.chars().take_while(char::is_ascii_digit).collect();
+
+        if url.host_str().is_some_and(|h| h.ends_with("goodreads.com")) && !id.is_empty() {
+            let link = format!("https://www.goodreads.com/book/show/{id}");
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+    }
+
+    links
+}
+
+/// Whether the server `authserv_id` checked with DMARC that the message comes from `sender`
+fn authenticated(message: &Message, authserv_id: &str, sender: &str) -> bool {
+    let Some((_, domain)) = sender.rsplit_once('@') else {
+        return false;
+    };
+
+    message
+        .header_values("Authentication-Results")
+        .filter_map(|value| value.as_text())
+        .any(|results| {
+            let mut results = results.split(';');
+            let server = results.next().and_then(|s| s.split_whitespace().next());
+
+            server == Some(authserv_id)
+                && results.any(|result| {
+                    let mut properties = result.split_whitespace();
+                    properties
+                        .next()
+                        .is_some_and(|p| p.eq_ignore_ascii_case("dmarc=pass"))
+                        && properties.any(|p| {
+                            p.strip_prefix("header.from=")
+                                .is_some_and(|d| d.eq_ignore_ascii_case(domain))
+                        })
+                })
+        })
+}
+
+/// Books of a message, if it comes from a configured sender
+fn read_message(config: &ImapConfig, raw: &[u8]) -> Option<Sent> {
+    let message = MessageParser::default().parse(raw)?;
+    let sender = message.from()?.first()?.address()?;
+
+    let Some((_, user)) = config
+        .senders
+        .iter()
+        .find(|(address, _)| address.eq_ignore_ascii_case(sender))
+    else {
+        tracing::info!("Ignored a message from the unknown sender '{sender}'");
+        return None;
+    };
+
+    if let Some(authserv_id) = &config.authserv_id {
+        if !authenticated(&message, authserv_id, sender) {
+            tracing::warn!("Ignored a message claiming to be from '{sender}', it failed DMARC");
+            return None;
+        }
+    }
+
+    let text: String = (0..)
+        .map_while(|i| message.body_text(i))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut items = find_isbns(&text);
+    items.extend(goodreads_links(&text));
+
+    Some(Sent {
+        user: user.clone(),
+        items,
+        wish: message.subject().is_some_and(is_wish),
+    })
+}
+
+async fn connect(
+    config: &ImapConfig,
+) -> Result<async_imap::Client<tokio_rustls::client::TlsStream<TcpStream>>, IngestError> {
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(rustls_native_certs::load_native_certs()?);
+    let tls = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let stream = TcpStream::connect((config.host.as_str(), config.port)).await?;
+    let stream = TlsConnector::from(Arc::new(tls))
+        .connect(ServerName::try_from(config.host.clone())?, stream)
+        .await?;
+
+    Ok(async_imap::Client::new(stream))
+}
+
+/// Add the books of the unread messages, which are then marked as read
+async fn check_mailbox<T>(
+    state: &Arc<AppState>,
+    config: &ImapConfig,
+    mut client: async_imap::Client<T>,
+) -> Result<(), IngestError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug,
+{
+    // Greeting of the server
+    client.read_response().await.transpose()?;
+
+    let mut session = client
+        .login(&config.username, &config.password)
+        .await
+        .map_err(|(e, _)| e)?;
+    session.select(&config.mailbox).await?;
+
+    let unseen = session.uid_search("UNSEEN").await?;
+    if !unseen.is_empty() {
+        let uids = unseen
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let messages: Vec<_> = session
+            .uid_fetch(&uids, "BODY.PEEK[]")
+            .await?
+            .try_collect()
+            .await?;
+
+        for message in messages {
+            let Some(sent) = message.body().and_then(|raw| read_message(config, raw)) else {
+                continue;
+            };
+            if sent.items.is_empty() {
+                tracing::info!("No book in a message sent by '{}'", sent.user);
+                continue;
+            }
+
+            routes::import_sent(state, &sent.user, sent.items, sent.wish).await?;
+        }
+
+        // Failed books are not retried, the messages only need to be sent again
+        let _: Vec<_> = session
+            .uid_store(&uids, "+FLAGS (\\Seen)")
+            .await?
+            .try_collect()
+            .await?;
+    }
+
+    session.logout().await?;
+
+    Ok(())
+}
+
+/// Check the mailbox every `interval_minutes`, unless the library is in maintenance
+pub async fn poll(state: Arc<AppState>) {
+    let Some(config) = &state.config.ingest.imap else {
+        return;
+    };
+
+    if config.authserv_id.is_none() {
+        tracing::warn!("`ingest.imap.authserv_id` is not set, the senders are not checked");
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_minutes * 60));
+    loop {
+        interval.tick().await;
+
+        if AtomicBool::load(&state.maintenance, Ordering::Relaxed) {
+            continue;
+        }
+
+        let result = match connect(config).await {
+            Ok(client) => check_mailbox(&state, config, client).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Could not check the mailbox: {e:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::{
+        schema::{book, wish},
+        testing::TestApp,
+    };
+
+    use super::{ImapConfig, Sent};
+
+    const CONFIG: &str = r#"
+        host = "localhost"
+        username = "books@example.org"
+        password = "secret"
+        authserv_id = "mx.example.org"
+
+        [senders]
+        "Alice@example.org" = "alice"
+    "#;
+
+    fn message(from: &str, dmarc: bool, subject: &str, body: &str) -> String {
+        let authentication = match dmarc {
+            true => "mx.example.org; dkim=pass; dmarc=pass (p=reject) header.from=example.org",
+            false => "mx.example.org; dmarc=fail header.from=example.org",
+        };
+
+        format!(
+            "Authentication-Results: {authentication}\r\n\
+             From: Someone <{from}>\r\n\
+             To: books@example.org\r\n\
+             Subject: {subject}\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             \r\n\
+             {body}\r\n"
+        )
+    }
+
+    #[test]
+    fn is_wish() {
+        assert!(super::is_wish("Wishlist"));
+        assert!(super::is_wish("Fwd: RE: wish: Mort"));
+        assert!(!super::is_wish("Fwd: Your order"));
+        assert!(!super::is_wish("Order: wish"));
+    }
+
+    #[test]
+    fn goodreads_links() {
+        let text =
+            "Alice wants you to see <https://www.goodreads.com/book/show/833423.Mort?ref=share>\n\
+                    https://goodreads.com/book/show/833423 https://example.org/book/show/1";
+
+        assert_eq!(
+            super::goodreads_links(text),
+            ["https://www.goodreads.com/book/show/833423"]
+        );
+    }
+
+    #[test]
+    fn read_message() {
+        let config: ImapConfig = toml::from_str(CONFIG).unwrap();
+        let read = |message: String| super::read_message(&config, message.as_bytes());
+
+        let body = "1x Guards! Guards! ISBN 978-0552134637\r\n\
+                    https://www.goodreads.com/book/show/833423.Mort";
+        assert_eq!(
+            read(message("alice@example.org", true, "Fwd: Your order", body)),
+            Some(Sent {
+                user: "alice".into(),
+                items: vec![
+                    "9780552134637".into(),
+                    "https://www.goodreads.com/book/show/833423".into()
+                ],
+                wish: false,
+            })
+        );
+        assert!(
+            read(message("alice@example.org", true, "Wish", body))
+                .unwrap()
+                .wish
+        );
+
+        assert_eq!(
+            read(message("mallory@example.org", true, "Order", body)),
+            None
+        );
+        // The sender can be forged
+        assert_eq!(
+            read(message("alice@example.org", false, "Order", body)),
+            None
+        );
+        let forged = message("alice@example.org", false, "Order", body).replace(
+            "mx.example.org",
+            "mx.example.net; dmarc=pass header.from=example.org",
+        );
+        assert_eq!(read(forged), None);
+    }
+
+    /// IMAP server answering the commands of [`super::check_mailbox`] with `messages`, returning the
+    /// commands it received
+    async fn imap_server(listener: TcpListener, messages: Vec<String>) -> Vec<String> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut commands = Vec::new();
+
+        write.write_all(b"* OK IMAP4rev1 ready\r\n").await.unwrap();
+
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let (tag, command) = line.split_once(' ').unwrap();
+            let mut response = String::new();
+
+            if command.starts_with("SELECT") {
+                response += &format!("* {} EXISTS\r\n", messages.len());
+            } else if command.starts_with("UID SEARCH") {
+                let uids: Vec<_> = (1..=messages.len()).map(|i| i.to_string()).collect();
+                response += &format!("* SEARCH {}\r\n", uids.join(" "));
+            } else if command.starts_with("UID FETCH") {
+                for (i, message) in messages.iter().enumerate() {
+                    response += &format!(
+                        "* {} FETCH (UID {} BODY[] {{{}}}\r\n{message})\r\n",
+                        i + 1,
+                        i + 1,
+                        message.len(),
+                    );
+                }
+            } else if command.starts_with("LOGOUT") {
+                response += "* BYE\r\n";
+            }
+            response += &format!("{tag} OK done\r\n");
+
+            write.write_all(response.as_bytes()).await.unwrap();
+            commands.push(command.to_string());
+            if command.starts_with("LOGOUT") {
+                break;
+            }
+        }
+
+        commands
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn check_mailbox() {
+        let app = TestApp::new().await;
+        let config: ImapConfig = toml::from_str(CONFIG).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(imap_server(
+            listener,
+            vec![
+                message(
+                    "alice@example.org",
+                    true,
+                    "Your order",
+                    "ISBN 9780552134637",
+                ),
+                message("alice@example.org", true, "Wish", "9780552131063"),
+                message("alice@example.org", false, "Order", "9780552131063"),
+                message("mallory@example.org", true, "Order", "9780552131063"),
+            ],
+        ));
+
+        let client = async_imap::Client::new(TcpStream::connect(address).await.unwrap());
+        super::check_mailbox(&app.state, &config, client)
+            .await
+            .unwrap();
+
+        let commands = server.await.unwrap();
+        assert_eq!(commands[0], r#"LOGIN "books@example.org" "secret""#);
+        assert!(commands.iter().any(|c| c.ends_with("+FLAGS (\\Seen)")));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let (books, wishes) = loop {
+            let books: Vec<String> = book::table
+                .select(book::isbn)
+                .load(&mut conn)
+                .await
+                .unwrap();
+            let wishes: Vec<Option<String>> = wish::table
+                .select(wish::isbn)
+                .load(&mut conn)
+                .await
+                .unwrap();
+            if !books.is_empty() && !wishes.is_empty() {
+                break (books, wishes);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert_eq!(books, ["9780552134637"]);
+        assert_eq!(wishes, [Some("9780552131063".to_string())]);
+    }
+}
@@ -0,0 +1,148 @@
+//! Background jobs started from the pages, working through a list of items whose progress is
+//! polled by the browser
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+/// Finished jobs are forgotten after this long
+const RETENTION: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ItemStatus {
+    Queued,
+    Running,
+    Done(String),
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct JobProgress {
+    pub owner: Uuid,
+    pub items: Vec<(String, ItemStatus)>,
+    finished: Option<Instant>,
+}
+
+impl JobProgress {
+    pub fn is_finished(&self) -> bool {
+        self.finished.is_some()
+    }
+
+    /// Number of items that are neither queued nor running
+    pub fn completed(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|(_, status)| !matches!(status, ItemStatus::Queued | ItemStatus::Running))
+            .count()
+    }
+}
+
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Mutex<HashMap<Uuid, JobProgress>>,
+}
+
+impl Jobs {
+    /// Register a job of `owner` over `items`, all queued
+    pub fn create(&self, owner: Uuid, items: Vec<String>) -> Uuid {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| job.finished.is_none_or(|at| at.elapsed() < RETENTION));
+
+        let id = Uuid::new_v4();
+        jobs.insert(
+            id,
+            JobProgress {
+                owner,
+                items: items
+                    .into_iter()
+                    .map(|item| (item, ItemStatus::Queued))
+                    .collect(),
+                finished: None,
+            },
+        );
+
+        id
+    }
+
+    pub fn progress(&self, job: Uuid) -> Option<JobProgress> {
+        self.jobs.lock().unwrap().get(&job).cloned()
+    }
+
+    pub fn update(&self, job: Uuid, item: usize, status: ItemStatus) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&job) {
+            job.items[item].1 = status;
+        }
+    }
+
+    /// Run `task` on each item of `job` in order, in the background
+    pub fn run<F, Fut>(self: &Arc<Self>, job: Uuid, task: F)
+    where
+        F: Fn(String) -> Fut + Send + 'static,
+        Fut: Future<Output = ItemStatus> + Send + 'static,
+    {
+        let jobs = self.clone();
+        let items: Vec<String> = match self.progress(job) {
+            Some(progress) => progress.items.into_iter().map(|(item, _)| item).collect(),
+            None => return,
+        };
+
+        tokio::spawn(async move {
+            for (i, item) in items.into_iter().enumerate() {
+                jobs.update(job, i, ItemStatus::Running);
+                let status = task(item).await;
+                jobs.update(job, i, status);
+            }
+
+            if let Some(job) = jobs.jobs.lock().unwrap().get_mut(&job) {
+                job.finished = Some(Instant::now());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use super::{ItemStatus, Jobs};
+
+    #[tokio::test]
+    async fn run() {
+        let jobs = Arc::new(Jobs::default());
+        let owner = Uuid::new_v4();
+
+        let job = jobs.create(owner, vec!["1".into(), "x".into()]);
+        let progress = jobs.progress(job).unwrap();
+        assert_eq!(progress.owner, owner);
+        assert_eq!(progress.completed(), 0);
+
+        jobs.run(job, |item| async move {
+            match item.parse::<i32>() {
+                Ok(n) => ItemStatus::Done((n + 1).to_string()),
+                Err(_) => ItemStatus::Failed("not a number".into()),
+            }
+        });
+
+        while !jobs.progress(job).unwrap().is_finished() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let progress = jobs.progress(job).unwrap();
+        assert_eq!(progress.completed(), 2);
+        assert_eq!(
+            progress.items,
+            [
+                ("1".to_string(), ItemStatus::Done("2".into())),
+                ("x".to_string(), ItemStatus::Failed("not a number".into())),
+            ]
+        );
+    }
+}
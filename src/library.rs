@@ -0,0 +1,82 @@
+//! Lookup of books in the catalogue of a public library, through SRU
+
+use std::num::ParseIntError;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct LibraryConfig {
+    /// Name of the library, shown in the badges
+    pub name: String,
+    /// Base URL of the SRU endpoint
+    pub sru: String,
+    /// CQL index used to search for ISBNs
+    #[serde(default = "default_isbn_index")]
+    pub isbn_index: String,
+}
+
+fn default_isbn_index() -> String {
+    "bath.isbn".into()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LibraryError {
+    #[error("Error in HTTP request")]
+    Request(#[from] reqwest::Error),
+    #[error("Response is not a valid xml document")]
+    InvalidXml(#[from] roxmltree::Error),
+    #[error("Response does not contain the number of records")]
+    MissingCount,
+    #[error("Invalid number of records")]
+    InvalidCount(#[from] ParseIntError),
+}
+
+fn parse_count(document: &str) -> Result<u64, LibraryError> {
+    let document = roxmltree::Document::parse(document)?;
+
+    let count = document
+        .descendants()
+        .find(|e| e.tag_name().name() == "numberOfRecords")
+        .and_then(|e| e.text())
+        .ok_or(LibraryError::MissingCount)?;
+
+    Ok(count.trim().parse()?)
+}
+
+/// Number of records matching `isbn` in the catalogue
+pub async fn records(config: &LibraryConfig, isbn: &str) -> Result<u64, LibraryError> {
+    let client = reqwest::Client::builder()
+        .user_agent("github.com/traxys/bouquineur")
+        .timeout(std::time::Duration::from_secs(10))
+        .build()?;
+
+    let document = client
+        .get(&config.sru)
+        .query(&[
+            ("version", "1.2"),
+            ("operation", "searchRetrieve"),
+            ("query", &format!("{}={isbn}", config.isbn_index)),
+            ("maximumRecords", "0"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    parse_count(&document)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn parse_count() {
+        let response = r#"<?xml version="1.0" encoding="UTF-8"?>
+<srw:searchRetrieveResponse xmlns:srw="http://www.loc.gov/zing/srw/">
+  <srw:version>1.2</srw:version>
+  <srw:numberOfRecords>2</srw:numberOfRecords>
+  <srw:records/>
+</srw:searchRetrieveResponse>"#;
+
+        assert_eq!(super::parse_count(response).unwrap(), 2);
+        assert!(super::parse_count("<empty/>").is_err());
+    }
+}
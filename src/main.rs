@@ -1,20 +1,56 @@
-use std::{path::PathBuf, str::FromStr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc},
+};
 
-use anyhow::{anyhow, Context};
-use axum::{http::HeaderName, routing::get, Router};
-use diesel::Connection;
-use diesel_async::{
-    pooled_connection::{deadpool::Pool, AsyncDieselConnectionManager},
-    AsyncPgConnection,
+use anyhow::Context;
+use axum::{
+    http::HeaderName,
+    middleware,
+    routing::{get, post},
+    Router,
 };
+use completions::Completions;
+use diesel::Connection;
+use diesel_async::AsyncPgConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use metadata::MetadataProvider;
+use fetch::PageFetcher;
+use jobs::Jobs;
+use metadata::{
+    covers::{CoverSourceKind, CoverSources},
+    trust::ProviderTrust,
+    MetadataProviders,
+};
 use serde::Deserializer;
-
+use signing::UrlSigner;
+use stash::Stash;
+use throttle::{Throttle, ThrottleConfig};
+
+mod completions;
+mod db;
+mod fetch;
+mod import;
+mod ingest;
+mod jobs;
+mod library;
+mod mangaupdates;
 mod metadata;
 mod models;
+mod password;
 mod routes;
 mod schema;
+mod signing;
+mod stash;
+mod throttle;
+mod totp;
+mod volumes;
+mod wikidata;
+
+#[cfg(test)]
+mod testing;
 
 type State = axum::extract::State<Arc<AppState>>;
 
@@ -42,12 +78,36 @@ where
     de.deserialize_str(StrVisitor)
 }
 
+fn deserialize_opt_hdr<'de, D>(de: D) -> Result<Option<HeaderName>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_hdr(de).map(Some)
+}
+
 #[derive(serde::Deserialize, Debug)]
 struct AuthConfig {
-    #[serde(deserialize_with = "deserialize_hdr")]
-    header: HeaderName,
+    /// Header set by the reverse proxy with the name of the user
+    #[serde(default, deserialize_with = "deserialize_opt_hdr")]
+    header: Option<HeaderName>,
     #[serde(default)]
     admin: Vec<String>,
+    /// Login with the passwords stored in the database
+    #[serde(default)]
+    local: Option<LocalAuthConfig>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct LocalAuthConfig {
+    /// The session cookies expire after this many days
+    #[serde(default = "LocalAuthConfig::default_session_days")]
+    session_days: u32,
+}
+
+impl LocalAuthConfig {
+    fn default_session_days() -> u32 {
+        30
+    }
 }
 
 #[derive(serde::Deserialize, Debug, Default)]
@@ -59,61 +119,263 @@ struct DebugConfig {
 #[derive(serde::Deserialize, Debug)]
 struct DatabaseConfig {
     url: String,
+    /// Queries taking longer than this (in milliseconds) are logged
+    #[serde(default)]
+    slow_query_ms: Option<u64>,
 }
 
 #[derive(serde::Deserialize, Debug)]
-struct CalibreConfig {
-    fetcher: String,
+struct MetadataConfig {
+    #[serde(default)]
+    providers: Option<Vec<String>>,
+    #[serde(default)]
+    default_provider: Option<String>,
+    image_dir: PathBuf,
+    /// Sources queried when a book has no cover, in order
+    #[serde(default)]
+    cover_sources: Vec<CoverSourceKind>,
+    /// Responses of the providers are reused for this many hours, 0 disables the cache
+    #[serde(default = "MetadataConfig::default_cache_expiry_hours")]
+    cache_expiry_hours: u32,
+    /// Fields each provider is trusted for, keyed by provider
+    #[serde(default)]
+    trust: HashMap<String, ProviderTrust>,
+
+    /// Provider specific sections, like `[metadata.calibre]`
+    #[serde(flatten)]
+    options: HashMap<String, toml::Value>,
 }
 
+impl MetadataConfig {
+    fn default_cache_expiry_hours() -> u32 {
+        24 * 7
+    }
+}
+
+/// External link shown on book pages, `{isbn}` in `url` is replaced by the ISBN of the book
 #[derive(serde::Deserialize, Debug)]
-struct OpenLibraryConfig {
-    contact: String,
+struct LinkConfig {
+    name: String,
+    url: String,
 }
 
+/// Encoding of the stored covers, which are always JPEG
 #[derive(serde::Deserialize, Debug)]
-struct MetadataConfig {
+struct ImageConfig {
+    /// Covers larger than these dimensions are downscaled, keeping their aspect ratio
     #[serde(default)]
-    providers: Option<Vec<MetadataProvider>>,
+    max_width: Option<u32>,
     #[serde(default)]
-    default_provider: Option<MetadataProvider>,
-    image_dir: PathBuf,
+    max_height: Option<u32>,
+    /// JPEG quality, from 1 to 100
+    #[serde(default = "ImageConfig::default_quality")]
+    quality: u8,
+}
+
+impl ImageConfig {
+    fn default_quality() -> u8 {
+        75
+    }
+
+    fn fits(&self, width: u32, height: u32) -> bool {
+        self.max_width.is_none_or(|w| width <= w) && self.max_height.is_none_or(|h| height <= h)
+    }
+}
 
+impl Default for ImageConfig {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            max_height: None,
+            quality: Self::default_quality(),
+        }
+    }
+}
+
+/// Optional fields of the book form that can be made mandatory
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum RequiredField {
+    Cover,
+    Summary,
+    Author,
+    Tag,
+    Series,
+    Published,
+    Publisher,
+    Language,
+    PageCount,
+    GoogleId,
+    GoodreadsId,
+    AmazonId,
+    LibrarythingId,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct FormConfig {
     #[serde(default)]
-    calibre: Option<CalibreConfig>,
+    required: Vec<RequiredField>,
+}
+
+impl FormConfig {
+    fn requires(&self, field: RequiredField) -> bool {
+        self.required.contains(&field)
+    }
+}
+
+/// Policy used to sanitize the HTML of the summaries before rendering them, on top of the
+/// defaults of ammonia
+#[derive(serde::Deserialize, Debug, Default)]
+struct HtmlConfig {
+    /// Allowed tags, replacing the default ones
+    #[serde(default)]
+    tags: Option<HashSet<String>>,
+    /// Tags removed from the allowed ones, for example `["img"]`
+    #[serde(default)]
+    deny_tags: HashSet<String>,
+    /// Additional attributes allowed on each tag
+    #[serde(default)]
+    tag_attributes: HashMap<String, HashSet<String>>,
+    /// Additional attributes allowed on all the tags
     #[serde(default)]
-    open_library: Option<OpenLibraryConfig>,
+    generic_attributes: HashSet<String>,
 }
 
-impl MetadataConfig {
-    fn check_calibre(&self) -> anyhow::Result<()> {
-        let has = match &self.providers {
-            None => true,
-            Some(v) => v.contains(&MetadataProvider::Calibre),
-        };
-
-        match has && self.calibre.is_none() {
-            true => Err(anyhow!("Missing `[metadata.calibre]`")),
-            false => Ok(()),
+impl HtmlConfig {
+    /// Tags whose content is always removed, they can't be allowed
+    const CLEAN_CONTENT_TAGS: &'static [&'static str] = &["script", "style"];
+
+    /// Reject the settings that ammonia can't apply, as it would panic while sanitizing
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(tag) = self
+            .tags
+            .iter()
+            .flatten()
+            .find(|t| Self::CLEAN_CONTENT_TAGS.contains(&t.as_str()))
+        {
+            anyhow::bail!("`html.tags` can't contain {tag:?}");
+        }
+
+        if self.generic_attributes.contains("rel")
+            || self
+                .tag_attributes
+                .values()
+                .any(|attrs| attrs.contains("rel"))
+        {
+            anyhow::bail!("The `rel` attribute can't be allowed, it is set on links");
+        }
+
+        Ok(())
+    }
+
+    fn clean(&self, html: &str) -> String {
+        let mut builder = ammonia::Builder::default();
+
+        if let Some(tags) = &self.tags {
+            builder.tags(tags.iter().map(String::as_str).collect());
+        }
+
+        builder.rm_tags(self.deny_tags.iter().map(String::as_str));
+
+        for (tag, attributes) in &self.tag_attributes {
+            builder.add_tag_attributes(tag, attributes.iter().map(String::as_str));
+        }
+
+        builder.add_generic_attributes(self.generic_attributes.iter().map(String::as_str));
+
+        builder.clean(html).to_string()
+    }
+}
+
+/// `Content-Security-Policy` sent with all the responses
+#[derive(serde::Deserialize, Debug)]
+struct CspConfig {
+    /// `{nonce}` is replaced by the nonce of the inline scripts of the page
+    #[serde(default = "CspConfig::default_policy")]
+    policy: String,
+    /// Only report the violations to the browser console instead of blocking them
+    #[serde(default)]
+    report_only: bool,
+}
+
+impl CspConfig {
+    fn default_policy() -> String {
+        [
+            "default-src 'self'",
+            "script-src 'self' 'nonce-{nonce}' 'wasm-unsafe-eval' https://cdn.jsdelivr.net \
+                https://unpkg.com https://cdnjs.cloudflare.com",
+            "style-src 'self' 'unsafe-inline' https://cdn.jsdelivr.net https://cdnjs.cloudflare.com",
+            "font-src 'self' https://cdn.jsdelivr.net",
+            "img-src 'self' data: blob:",
+            "connect-src 'self' https://cdn.jsdelivr.net",
+            "object-src 'none'",
+            "base-uri 'self'",
+        ]
+        .join("; ")
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        axum::http::HeaderValue::from_str(&self.policy)
+            .with_context(|| "The policy is not a valid header value")?;
+
+        if !self.policy.contains("{nonce}") {
+            tracing::warn!("The Content-Security-Policy has no nonce, inline scripts are blocked");
         }
+
+        Ok(())
     }
+}
 
-    fn check_openlibrary(&self) -> anyhow::Result<()> {
-        let has = match &self.providers {
-            None => true,
-            Some(v) => v.contains(&MetadataProvider::OpenLibrary),
-        };
+/// Restrictions on the covers served to anonymous visitors through signed URLs
+#[derive(serde::Deserialize, Debug)]
+struct PublicImageConfig {
+    /// Only serve covers to pages of this server, or of `allowed_referers`
+    #[serde(default)]
+    hotlink_protection: bool,
+    /// Hosts (with their port if it is not the default one) allowed to embed the covers
+    #[serde(default)]
+    allowed_referers: Vec<String>,
+    /// Serve covers to requests without a `Referer`, such as direct accesses
+    #[serde(default = "PublicImageConfig::default_allow_no_referer")]
+    allow_no_referer: bool,
+    /// Public covers are downscaled to these limits
+    #[serde(flatten)]
+    encoding: ImageConfig,
+}
 
-        match has && self.open_library.is_none() {
-            true => Err(anyhow!("Missing `[metadata.open_library]`")),
-            false => Ok(()),
+impl PublicImageConfig {
+    fn default_allow_no_referer() -> bool {
+        true
+    }
+}
+
+impl Default for PublicImageConfig {
+    fn default() -> Self {
+        Self {
+            hotlink_protection: false,
+            allowed_referers: Vec::new(),
+            allow_no_referer: Self::default_allow_no_referer(),
+            encoding: ImageConfig::default(),
         }
     }
 }
 
+/// Anonymous read-only access to the library of a user
+#[derive(serde::Deserialize, Debug)]
+struct GuestConfig {
+    /// User whose library is shown to requests without the authentication header
+    user: String,
+}
+
 #[derive(serde::Deserialize, Debug)]
 struct ServerConfig {
     port: u16,
+    /// Key used to sign the URLs of public resources, random if not set
+    #[serde(default)]
+    secret: Option<String>,
+    /// Start in read-only maintenance mode, it can then be toggled by the administrators
+    #[serde(default)]
+    maintenance: bool,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -124,6 +386,30 @@ struct Config {
     auth: AuthConfig,
     database: DatabaseConfig,
     server: ServerConfig,
+    #[serde(default)]
+    images: ImageConfig,
+    #[serde(default)]
+    public_images: PublicImageConfig,
+    #[serde(default)]
+    throttle: ThrottleConfig,
+    #[serde(default)]
+    guest: Option<GuestConfig>,
+    #[serde(default)]
+    form: FormConfig,
+    #[serde(default)]
+    html: HtmlConfig,
+    #[serde(default)]
+    csp: Option<CspConfig>,
+    #[serde(default)]
+    links: Vec<LinkConfig>,
+    #[serde(default)]
+    library: Option<library::LibraryConfig>,
+    #[serde(default)]
+    wikidata: Option<wikidata::WikidataConfig>,
+    #[serde(default)]
+    mangaupdates: Option<mangaupdates::MangaUpdatesConfig>,
+    #[serde(default)]
+    ingest: ingest::IngestConfig,
 }
 
 type PgPool = diesel_async::pooled_connection::deadpool::Pool<AsyncPgConnection>;
@@ -131,6 +417,17 @@ type PgPool = diesel_async::pooled_connection::deadpool::Pool<AsyncPgConnection>
 struct AppState {
     config: Config,
     db: PgPool,
+    metadata: MetadataProviders,
+    covers: CoverSources,
+    /// Pages given by the users
+    pages: PageFetcher,
+    signer: UrlSigner,
+    throttle: Throttle,
+    /// Only allow the requests that don't modify the library
+    maintenance: AtomicBool,
+    jobs: Arc<Jobs>,
+    completions: Completions,
+    lookups: Stash<routes::Lookup>,
 }
 
 fn run_migrations(state: &AppState) -> anyhow::Result<()> {
@@ -144,14 +441,204 @@ fn run_migrations(state: &AppState) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn router(state: Arc<AppState>) -> Router {
+    // Pages accessible without authentication
+    let public = Router::new()
+        .route("/public/signed/:user/images/:id", get(routes::signed_image))
+        .route("/public/:user/ongoing", get(routes::ongoing_public))
+        .route("/public/:user/feed.atom", get(routes::public_feed))
+        .route("/widget/:user/recent", get(routes::widget_recent))
+        .route("/login", get(routes::login).post(routes::do_login))
+        .route("/login/totp", post(routes::do_login_totp))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::anonymous_access,
+        ));
+
+    Router::new()
+        .route("/", get(routes::index))
+        .route("/feed.atom", get(routes::feed))
+        .route("/add", get(routes::add_book).post(routes::do_add_book))
+        .route("/add/url", get(routes::add_from_url))
+        .route("/add/fill", get(routes::fill_missing))
+        .route("/add/lookup", get(routes::do_lookup))
+        .route("/add/opf", post(routes::add_from_opf))
+        .route("/public/images/not_found", get(routes::image_not_found))
+        .route("/public/:user/images/:id", get(routes::image))
+        .route("/book/:id", get(routes::get_book))
+        .route("/b/:code", get(routes::short_link))
+        .route("/book/:id/availability", get(routes::book_availability))
+        .route("/book/:id/giveaway", post(routes::do_toggle_giveaway))
+        .route(
+            "/book/:id/refresh",
+            get(routes::refresh_book).post(routes::do_refresh_book),
+        )
+        .route("/unread", get(routes::unread))
+        .route(
+            "/book/:id/edit",
+            get(routes::edit_book).post(routes::do_edit_book),
+        )
+        .route("/series", get(routes::series))
+        .route("/series/:id", get(routes::get_series))
+        .route(
+            "/series/:id/edit",
+            get(routes::series_edit).post(routes::do_series_edit),
+        )
+        .route("/series/:id/import", post(routes::do_series_import))
+        .route("/series/:id/merge", post(routes::do_series_merge))
+        .route("/series/:id/split", post(routes::do_series_split))
+        .route("/series/:id/lookup", post(routes::do_series_lookup))
+        .route(
+            "/series/:id/reorder",
+            get(routes::series_reorder).post(routes::do_series_reorder),
+        )
+        .route("/author/:id", get(routes::get_author))
+        .route("/author/:id/works", get(routes::author_works))
+        .route("/author/:id/wish", post(routes::do_author_wish))
+        .route("/publishers", get(routes::publishers))
+        .route("/publishers/parent", post(routes::do_set_publisher_parent))
+        .route("/publishers/wikidata", post(routes::do_publishers_wikidata))
+        .route(
+            "/tags/implications",
+            get(routes::tag_implications).post(routes::do_add_tag_implication),
+        )
+        .route(
+            "/tags/implications/delete",
+            post(routes::do_delete_tag_implication),
+        )
+        .route(
+            "/tags/implications/apply",
+            post(routes::do_apply_tag_implications),
+        )
+        .route("/import", get(routes::import).post(routes::do_import))
+        .route("/import/isbns", post(routes::do_import_isbns))
+        .route("/import/isbns/:id", get(routes::import_isbns))
+        .route(
+            "/import/isbns/:id/progress",
+            get(routes::import_isbns_progress),
+        )
+        .route("/export/json", get(routes::export_json))
+        .route("/export/goodreads", get(routes::export_goodreads))
+        .route("/export/isbns", get(routes::export_isbns))
+        .route("/export/wikidata", get(routes::export_wikidata))
+        .route("/export/settings", get(routes::export_settings))
+        .route("/import/settings", post(routes::do_import_settings))
+        .route("/labels", get(routes::labels).post(routes::print_labels))
+        .route(
+            "/inventory",
+            get(routes::inventory).post(routes::do_inventory),
+        )
+        .route("/giveaway", get(routes::giveaway))
+        .route("/giveaway/add", post(routes::do_giveaway_add))
+        .route("/giveaway/export", get(routes::giveaway_export))
+        .route("/giveaway/done", post(routes::do_giveaway_done))
+        .route("/wishlist", get(routes::wishlist))
+        .route("/wishlist/isbn", post(routes::do_wish_isbn))
+        .route("/wishlist/prefetch", post(routes::do_prefetch_wishes))
+        .route("/wishlist/prefetch/:id", get(routes::prefetch_wishes))
+        .route(
+            "/wishlist/prefetch/:id/progress",
+            get(routes::prefetch_wishes_progress),
+        )
+        .route("/wishlist/:id/delete", post(routes::do_delete_wish))
+        .route("/wishlist/:id/availability", get(routes::wish_availability))
+        .route("/api/v1/metadata", get(routes::api_metadata))
+        .route("/api/v1/metadata/:isbn", get(routes::api_metadata_isbn))
+        .route(
+            "/api/v1/books",
+            get(routes::api_books)
+                .post(routes::api_create_book)
+                .patch(routes::api_update_books),
+        )
+        .route(
+            "/api/v1/books/:id",
+            get(routes::api_book)
+                .put(routes::api_update_book)
+                .delete(routes::api_delete_book),
+        )
+        .route("/api/complete/:list", get(routes::complete))
+        .route("/covers/missing", post(routes::fetch_missing_covers))
+        .route("/covers/reencode", post(routes::reencode_covers))
+        .route("/ongoing", get(routes::ongoing))
+        .route("/ongoing/wishlist", post(routes::do_wish_missing_volumes))
+        .route(
+            "/profile",
+            get(routes::profile).post(routes::do_edit_profile),
+        )
+        .route("/profile/password", post(routes::do_change_password))
+        .route(
+            "/profile/totp",
+            get(routes::setup_totp).post(routes::do_setup_totp),
+        )
+        .route("/profile/totp/enable", post(routes::do_enable_totp))
+        .route(
+            "/profile/totp/recovery",
+            post(routes::do_new_recovery_codes),
+        )
+        .route("/profile/totp/disable", post(routes::do_disable_totp))
+        .route(
+            "/profile/sessions/:id/revoke",
+            post(routes::do_revoke_session),
+        )
+        .route("/logout", post(routes::do_logout))
+        .route("/logout/everywhere", post(routes::do_logout_everywhere))
+        .route("/admin/traffic", get(routes::admin_traffic))
+        .route("/admin/maintenance", post(routes::do_toggle_maintenance))
+        .merge(public)
+        .fallback(routes::not_found)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::guest_access,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::invalidate_completions,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::maintenance,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::error_pages,
+        ))
+        .layer(middleware::from_fn(routes::request_span))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::content_security_policy,
+        ))
+        .with_state(state)
+}
+
+/// Command setting the password of a user for `[auth.local]`, read from the standard input
+const SET_PASSWORD: &str = "set-password";
+
+async fn set_password(state: &AppState, user: &str) -> anyhow::Result<()> {
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .with_context(|| "Could not read the password")?;
+
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        anyhow::bail!("The password can't be empty");
+    }
+
+    routes::set_password(state, user, password, None)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not set the password: {e:?}"))?;
+
+    tracing::info!("Password of '{user}' set");
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
-    let mut args = std::env::args();
-    args.next();
+    let mut args = std::env::args().skip(1).peekable();
 
-    let cfg: Config = if let Some(arg) = args.next() {
+    let cfg: Config = if let Some(arg) = args.next_if(|arg| arg != SET_PASSWORD) {
         toml::from_str(
             &std::fs::read_to_string(&arg)
                 .with_context(|| format!("Could not load the configuration file '{arg}'"))?,
@@ -167,28 +654,28 @@ async fn main() -> anyhow::Result<()> {
         anyhow::bail!("No configuration was supplied");
     };
 
-    cfg.metadata.check_calibre()?;
-    cfg.metadata.check_openlibrary()?;
-
-    if let Some(p) = &cfg.metadata.providers {
-        match &cfg.metadata.default_provider {
-            None => {
-                if p.len() > 1 {
-                    anyhow::bail!(
-                        "When more than one providers are enabled a default must be chosen"
-                    )
-                }
-            }
-            Some(def) => {
-                if !p.contains(def) {
-                    anyhow::bail!(
-                        "metadata.default_provider ({def:?}) must be present in metadata.providers"
-                    )
-                }
-            }
-        }
+    if cfg.auth.header.is_none() && cfg.auth.local.is_none() {
+        anyhow::bail!("Either `auth.header` or `[auth.local]` must be configured");
+    }
+
+    if cfg.auth.local.is_some() && cfg.server.secret.is_none() {
+        // A random key would log everyone out on each restart
+        anyhow::bail!("`server.secret` must be set to sign the sessions of `[auth.local]`");
+    }
+
+    cfg.html
+        .validate()
+        .with_context(|| "Invalid `[html]` configuration")?;
+
+    if let Some(csp) = &cfg.csp {
+        csp.validate()
+            .with_context(|| "Invalid `[csp]` configuration")?;
     }
 
+    let metadata = MetadataProviders::from_config(&cfg.metadata)?;
+    let covers = CoverSources::new(&cfg.metadata.cover_sources);
+    let pages = PageFetcher::new().with_context(|| "Could not build the HTTP client")?;
+
     std::fs::create_dir_all(&cfg.metadata.image_dir)
         .with_context(|| "Could not create image directory")?;
 
@@ -196,48 +683,49 @@ async fn main() -> anyhow::Result<()> {
         tracing::warn!("Running in debug mode, user is assumed to be '{user}'");
     }
 
-    let pool_config =
-        AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(&cfg.database.url);
-    let db = Pool::builder(pool_config)
-        .build()
-        .with_context(|| "Could not build database pool")?;
+    let signer = UrlSigner::new(cfg.server.secret.as_deref());
+    let throttle = Throttle::new(&cfg.throttle);
 
-    let port = cfg.server.port;
+    let db = db::pool(&cfg.database).with_context(|| "Could not build database pool")?;
 
-    let state = Arc::new(AppState { config: cfg, db });
+    let port = cfg.server.port;
+    let maintenance = AtomicBool::new(cfg.server.maintenance);
+
+    let state = Arc::new(AppState {
+        config: cfg,
+        db,
+        metadata,
+        covers,
+        pages,
+        signer,
+        throttle,
+        maintenance,
+        jobs: Default::default(),
+        completions: Default::default(),
+        lookups: Default::default(),
+    });
 
     run_migrations(&state)?;
 
-    let app = Router::new()
-        .route("/", get(routes::index))
-        .route("/add", get(routes::add_book).post(routes::do_add_book))
-        .route("/public/images/not_found", get(routes::image_not_found))
-        .route("/public/:user/images/:id", get(routes::image))
-        .route("/book/:id", get(routes::get_book))
-        .route("/unread", get(routes::unread))
-        .route(
-            "/book/:id/edit",
-            get(routes::edit_book).post(routes::do_edit_book),
-        )
-        .route("/series", get(routes::series))
-        .route("/series/:id", get(routes::get_series))
-        .route(
-            "/series/:id/edit",
-            get(routes::series_edit).post(routes::do_series_edit),
-        )
-        .route("/author/:id", get(routes::get_author))
-        .route("/ongoing", get(routes::ongoing))
-        .route("/public/:user/ongoing", get(routes::ongoing_public))
-        .route(
-            "/profile",
-            get(routes::profile).post(routes::do_edit_profile),
-        )
-        .with_state(state);
+    if args.next().as_deref() == Some(SET_PASSWORD) {
+        let user = args
+            .next()
+            .with_context(|| format!("Usage: bouquineur [config] {SET_PASSWORD} <user>"))?;
+        return set_password(&state, &user).await;
+    }
+
+    tokio::spawn(ingest::poll(state.clone()));
+
+    let app = router(state);
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}"))
         .await
         .with_context(|| "Could not create TCP Listener")?;
 
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -0,0 +1,111 @@
+//! Lookup of the number of volumes of series on MangaUpdates
+
+use serde_json::Value;
+
+use crate::volumes::VolumeCount;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct MangaUpdatesConfig {
+    /// Base URL of the API
+    #[serde(default = "default_api")]
+    pub api: String,
+}
+
+fn default_api() -> String {
+    "https://api.mangaupdates.com/v1".into()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MangaUpdatesError {
+    #[error("Error in HTTP request")]
+    Request(#[from] reqwest::Error),
+    #[error("Invalid JSON response")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Id of the first series of a search response
+fn search_result(response: &Value) -> Option<i64> {
+    response["results"][0]["record"]["series_id"].as_i64()
+}
+
+/// Parse the status of a series, of the form `12 Volumes (Ongoing)`
+fn parse_status(status: &str) -> Option<VolumeCount> {
+    let line = status.lines().next()?;
+    let (count, rest) = line.trim().split_once(' ')?;
+
+    if !rest.starts_with("Volume") {
+        return None;
+    }
+
+    Some(VolumeCount {
+        total: count.parse().ok()?,
+        ongoing: rest.contains("(Ongoing)"),
+    })
+}
+
+/// Number of volumes of the series `name`, if it is known
+pub async fn series_volumes(
+    config: &MangaUpdatesConfig,
+    name: &str,
+) -> Result<Option<VolumeCount>, MangaUpdatesError> {
+    let client = reqwest::Client::builder()
+        .user_agent("github.com/traxys/bouquineur")
+        .timeout(std::time::Duration::from_secs(10))
+        .build()?;
+
+    let search = client
+        .post(format!("{}/series/search", config.api))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "search": name, "perpage": 1 }).to_string())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let Some(id) = search_result(&serde_json::from_str(&search)?) else {
+        return Ok(None);
+    };
+
+    let series = client
+        .get(format!("{}/series/{id}", config.api))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let series: Value = serde_json::from_str(&series)?;
+
+    Ok(series["status"].as_str().and_then(parse_status))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::volumes::VolumeCount;
+
+    #[test]
+    fn parse() {
+        let search = json!({"results": [{"record": {"series_id": 55099564912i64}}]});
+        assert_eq!(super::search_result(&search), Some(55099564912));
+        assert_eq!(super::search_result(&json!({"results": []})), None);
+
+        assert_eq!(
+            super::parse_status("14 Volumes (Ongoing)\n\n2 Side Stories"),
+            Some(VolumeCount {
+                total: 14,
+                ongoing: true
+            })
+        );
+        assert_eq!(
+            super::parse_status("1 Volume (Complete)"),
+            Some(VolumeCount {
+                total: 1,
+                ongoing: false
+            })
+        );
+        assert_eq!(super::parse_status("Ongoing"), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! Cache of the provider responses, so that looking up the same ISBN again does not query the
+//! provider. Books that were not found are cached too.
+
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::{pooled_connection::deadpool::PoolError, RunQueryDsl};
+
+use crate::{schema::metadata_cache, AppState};
+
+use super::{MetadataError, NullableBookDetails};
+
+#[derive(thiserror::Error, Debug)]
+enum CacheError {
+    #[error("Could not get a connection from the pool")]
+    Pool(#[from] PoolError),
+    #[error("Database error")]
+    Db(#[from] diesel::result::Error),
+    #[error("Invalid cached details")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Response of `provider` for `isbn` if it was fetched less than `max_age` ago
+async fn cached(
+    state: &AppState,
+    provider: &str,
+    isbn: &str,
+    max_age: chrono::Duration,
+) -> Result<Option<Option<NullableBookDetails>>, CacheError> {
+    let mut conn = state.db.get().await?;
+
+    let details: Option<Option<String>> = metadata_cache::table
+        .find((provider, isbn))
+        .filter(metadata_cache::fetched.gt(chrono::Utc::now() - max_age))
+        .select(metadata_cache::details)
+        .get_result(&mut conn)
+        .await
+        .optional()?;
+
+    Ok(match details {
+        None => None,
+        Some(None) => Some(None),
+        Some(Some(details)) => Some(Some(serde_json::from_str(&details)?)),
+    })
+}
+
+async fn store(
+    state: &AppState,
+    provider: &str,
+    isbn: &str,
+    details: Option<&NullableBookDetails>,
+    max_age: chrono::Duration,
+) -> Result<(), CacheError> {
+    let details = details.map(serde_json::to_string).transpose()?;
+
+    let mut conn = state.db.get().await?;
+
+    diesel::insert_into(metadata_cache::table)
+        .values((
+            metadata_cache::provider.eq(provider),
+            metadata_cache::isbn.eq(isbn),
+            metadata_cache::details.eq(details),
+        ))
+        .on_conflict((metadata_cache::provider, metadata_cache::isbn))
+        .do_update()
+        .set((
+            metadata_cache::details.eq(excluded(metadata_cache::details)),
+            metadata_cache::fetched.eq(excluded(metadata_cache::fetched)),
+        ))
+        .execute(&mut conn)
+        .await?;
+
+    diesel::delete(metadata_cache::table)
+        .filter(metadata_cache::fetched.lt(chrono::Utc::now() - max_age))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Fetch metadata like [super::MetadataProviders::fetch_metadata], reusing the responses younger
+/// than `metadata.cache_expiry_hours`
+pub async fn fetch_metadata(
+    state: &AppState,
+    provider: Option<&str>,
+    isbn: &str,
+) -> Result<Option<NullableBookDetails>, MetadataError> {
+    let hours = state.config.metadata.cache_expiry_hours;
+    if hours == 0 {
+        return state.metadata.fetch_metadata(provider, isbn).await;
+    }
+
+    let max_age = chrono::Duration::hours(hours.into());
+    let provider = state.metadata.resolve(provider)?;
+
+    match cached(state, provider, isbn, max_age).await {
+        Ok(Some(details)) => return Ok(details),
+        Ok(None) => (),
+        Err(e) => tracing::warn!("Could not read the metadata cache: {e:?}"),
+    }
+
+    let details = state.metadata.fetch_metadata(Some(provider), isbn).await?;
+
+    if let Err(e) = store(state, provider, isbn, details.as_ref(), max_age).await {
+        tracing::warn!("Could not write the metadata cache: {e:?}");
+    }
+
+    Ok(details)
+}
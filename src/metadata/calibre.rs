@@ -1,11 +1,60 @@
-use std::io::Read;
+use std::{io::Read, time::Duration};
 
+use axum::async_trait;
 use base64::prelude::*;
 use bstr::{BString, ByteSlice};
+use tokio::sync::Semaphore;
 
-use crate::CalibreConfig;
+use super::{MetadataError, MetadataProvider, NullableBookDetails};
 
-use super::NullableBookDetails;
+#[derive(serde::Deserialize, Debug)]
+pub(super) struct CalibreConfig {
+    fetcher: String,
+    /// Seconds after which the fetcher is killed
+    #[serde(default = "CalibreConfig::default_timeout")]
+    timeout: u64,
+    /// Number of fetchers that can run at the same time
+    #[serde(default = "CalibreConfig::default_max_fetchers")]
+    max_fetchers: usize,
+}
+
+impl CalibreConfig {
+    fn default_timeout() -> u64 {
+        60
+    }
+
+    fn default_max_fetchers() -> usize {
+        2
+    }
+}
+
+struct Calibre {
+    config: CalibreConfig,
+    fetchers: Semaphore,
+}
+
+#[async_trait]
+impl MetadataProvider for Calibre {
+    fn name(&self) -> &str {
+        "Calibre"
+    }
+
+    async fn fetch_metadata(
+        &self,
+        isbn: &str,
+    ) -> Result<Option<NullableBookDetails>, MetadataError> {
+        Ok(fetch_metadata(&self.config, &self.fetchers, isbn).await?)
+    }
+}
+
+pub(super) fn provider(options: toml::Value) -> anyhow::Result<Box<dyn MetadataProvider>> {
+    let config: CalibreConfig = options.try_into()?;
+
+    Ok(Box::new(Calibre {
+        fetchers: Semaphore::new(config.max_fetchers),
+        config,
+    }))
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum CalibreMetadataError {
@@ -21,9 +70,12 @@ pub enum CalibreMetadataError {
     CoverArt(#[source] std::io::Error),
     #[error("Fetcher failed to get the metadata")]
     FetchFailure { stdout: BString, stderr: BString },
+    #[error("Fetcher did not finish in time")]
+    Timeout,
 }
 
-fn parse_opf(
+/// Details of the book described by a Calibre OPF document, with `cover_art` as its cover
+pub fn parse_opf(
     document: &str,
     cover_art: &[u8],
 ) -> Result<Option<NullableBookDetails>, CalibreMetadataError> {
@@ -59,10 +111,54 @@ fn parse_opf(
         .filter_map(|e| e.text().map(|s| s.to_owned()))
         .collect();
 
+    // Schemes that are either handled by a dedicated field or internal to calibre
+    const KNOWN_SCHEMES: &[&str] = &[
+        "calibre",
+        "uuid",
+        "isbn",
+        "google",
+        "goodreads",
+        "amazon",
+        "librarything",
+    ];
+
+    let identifiers = filter_tag("identifier")
+        .filter_map(|e| {
+            let scheme = e
+                .attribute(("http://www.idpf.org/2007/opf", "scheme"))?
+                .to_lowercase();
+            let value = e.text()?.trim();
+
+            (!KNOWN_SCHEMES.contains(&scheme.as_str()) && !value.is_empty())
+                .then(|| (scheme, value.to_owned()))
+        })
+        .collect();
+
     let tags: Vec<_> = filter_tag("subject")
         .filter_map(|e| e.text().map(|s| s.to_owned()))
         .collect();
 
+    let find_meta = |name: &'static str| {
+        filter_tag("meta")
+            .find(|e| e.attribute("name") == Some(name))
+            .and_then(|e| e.attribute("content"))
+    };
+
+    // Calibre stores the index as a float ("8.0"), and defaults it to 1 when it is missing.
+    // Fractional volumes can't be represented and are left for the user to fill.
+    let series = find_meta("calibre:series")
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .and_then(|name| {
+            let index = match find_meta("calibre:series_index") {
+                Some(index) => index.trim().parse::<f64>().ok()?,
+                None => 1.,
+            };
+
+            (index.fract() == 0. && (0. ..=i32::MAX as f64).contains(&index))
+                .then(|| (name.to_owned(), index as i32))
+        });
+
     Ok(Some(NullableBookDetails {
         title: find_str_tag("title"),
         isbn: find_str_tag_opf_attr("identifier", "scheme", "ISBN"),
@@ -77,24 +173,32 @@ fn parse_opf(
         publisher: find_str_tag("publisher"),
         language: find_str_tag("language"),
         google_id: find_str_tag_opf_attr("identifier", "scheme", "GOOGLE"),
+        goodreads_id: find_str_tag_opf_attr("identifier", "scheme", "GOODREADS"),
         amazon_id: find_str_tag_opf_attr("identifier", "scheme", "AMAZON"),
-        // TODO: Find the correct scheme for it
-        librarything_id: None,
+        librarything_id: find_str_tag_opf_attr("identifier", "scheme", "LIBRARYTHING"),
+        identifiers,
         // TODO: Find if there is a property for this
         page_count: None,
         owned: false,
         read: false,
+        reread: false,
+        priority: None,
+        location: None,
+        summary_spoilers: false,
         covert_art_b64: if cover_art.is_empty() {
             None
         } else {
             Some(BASE64_STANDARD.encode(cover_art))
         },
-        series: None,
+        cover_candidates_b64: Vec::new(),
+        series,
+        links: Vec::new(),
     }))
 }
 
-pub(super) async fn fetch_metadata(
+async fn fetch_metadata(
     config: &CalibreConfig,
+    fetchers: &Semaphore,
     isbn: &str,
 ) -> Result<Option<NullableBookDetails>, CalibreMetadataError> {
     tracing::debug!("Fetching metadata for isbn '{isbn}'");
@@ -104,14 +208,23 @@ pub(super) async fn fetch_metadata(
         .tempfile()
         .map_err(CalibreMetadataError::CoverArt)?;
 
+    let _permit = fetchers
+        .acquire()
+        .await
+        .expect("the fetcher semaphore is never closed");
+
     let output = tokio::process::Command::new(&config.fetcher)
         .arg("--isbn")
         .arg(isbn)
         .arg("--opf")
         .arg("--cover")
         .arg(tmp_file.path())
-        .output()
+        .kill_on_drop(true)
+        .output();
+
+    let output = tokio::time::timeout(Duration::from_secs(config.timeout), output)
         .await
+        .map_err(|_| CalibreMetadataError::Timeout)?
         .map_err(CalibreMetadataError::Launch)?;
 
     tracing::debug!("Stdout:\n{}", output.stdout.as_bstr());
@@ -137,7 +250,31 @@ pub(super) async fn fetch_metadata(
 
 #[cfg(test)]
 mod test {
+    use std::{io::Write, os::unix::fs::PermissionsExt};
+
     use expect_test::expect;
+    use tokio::sync::Semaphore;
+
+    use super::{CalibreConfig, CalibreMetadataError};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn timeout() {
+        let mut fetcher = tempfile::Builder::new().suffix(".sh").tempfile().unwrap();
+        writeln!(fetcher, "#!/bin/sh\nsleep 30").unwrap();
+        let fetcher = fetcher.into_temp_path();
+        std::fs::set_permissions(&fetcher, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = CalibreConfig {
+            fetcher: fetcher.to_str().unwrap().into(),
+            timeout: 1,
+            max_fetchers: 1,
+        };
+
+        let start = std::time::Instant::now();
+        let result = super::fetch_metadata(&config, &Semaphore::new(1), "9780000000000").await;
+        assert!(matches!(result, Err(CalibreMetadataError::Timeout)));
+        assert!(start.elapsed().as_secs() < 10);
+    }
 
     #[test]
     fn hp() {
@@ -178,15 +315,56 @@ mod test {
                 google_id: Some(
                     "cmNSzQEACAAJ",
                 ),
+                goodreads_id: None,
                 amazon_id: Some(
                     "1526626586",
                 ),
                 librarything_id: None,
+                identifiers: {},
                 page_count: None,
+                read: false,
+                owned: false,
+                reread: false,
+                priority: None,
+                location: None,
+                summary_spoilers: false,
                 covert_art_b64: None,
+                cover_candidates_b64: [],
+                series: None,
+                links: [],
             }
         "#]];
 
         expected.assert_debug_eq(&actual)
     }
+
+    #[test]
+    fn series() {
+        let document = include_str!("../../tests/guards.opf");
+
+        let actual = super::parse_opf(document, &[]).unwrap().unwrap();
+        assert_eq!(actual.series, Some(("Discworld".to_owned(), 8)));
+
+        let fractional = document.replace(r#"content="8.0""#, r#"content="8.5""#);
+        let actual = super::parse_opf(&fractional, &[]).unwrap().unwrap();
+        assert_eq!(actual.series, None);
+
+        let no_index = document.replace(r#"<meta name="calibre:series_index" content="8.0"/>"#, "");
+        let actual = super::parse_opf(&no_index, &[]).unwrap().unwrap();
+        assert_eq!(actual.series, Some(("Discworld".to_owned(), 1)));
+    }
+
+    #[test]
+    fn librarything() {
+        let document = include_str!("../../tests/mort.opf");
+
+        let actual = super::parse_opf(document, &[]).unwrap().unwrap();
+        assert_eq!(actual.isbn.as_deref(), Some("9780552131063"));
+        assert_eq!(actual.librarything_id.as_deref(), Some("1129"));
+        assert_eq!(
+            actual.identifiers,
+            [("oclc".to_owned(), "16801404".to_owned())].into()
+        );
+        assert_eq!(actual.series, Some(("Discworld".to_owned(), 4)));
+    }
 }
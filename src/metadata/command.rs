@@ -0,0 +1,160 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use axum::async_trait;
+use bstr::{BString, ByteSlice};
+
+use super::{MetadataError, MetadataProvider, NullableBookDetails};
+
+#[derive(serde::Deserialize, Debug)]
+struct CommandConfig {
+    /// Executable called with the ISBN as its last argument
+    command: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    /// Name displayed to the user, defaults to the identifier of the provider
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CommandMetadataError {
+    #[error("Could not launch metadata command")]
+    Launch(#[source] std::io::Error),
+    #[error("Metadata command failed")]
+    Failure { stdout: BString, stderr: BString },
+    #[error("Could not parse the command output ({0})")]
+    Json(#[from] serde_path_to_error::Error<serde_json::Error>),
+}
+
+struct Command {
+    name: String,
+    config: CommandConfig,
+}
+
+#[async_trait]
+impl MetadataProvider for Command {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch_metadata(
+        &self,
+        isbn: &str,
+    ) -> Result<Option<NullableBookDetails>, MetadataError> {
+        Ok(fetch_metadata(&self.config, isbn).await?)
+    }
+}
+
+/// Parse all the providers defined in `[metadata.command]`, keyed by their identifier
+pub(super) fn providers(
+    options: toml::Value,
+) -> anyhow::Result<BTreeMap<String, Box<dyn MetadataProvider>>> {
+    let commands: BTreeMap<String, CommandConfig> = options.try_into()?;
+
+    Ok(commands
+        .into_iter()
+        .map(|(id, config)| {
+            let provider: Box<dyn MetadataProvider> = Box::new(Command {
+                name: config.name.clone().unwrap_or_else(|| id.clone()),
+                config,
+            });
+
+            (id, provider)
+        })
+        .collect())
+}
+
+fn parse_output(output: &[u8]) -> Result<Option<NullableBookDetails>, CommandMetadataError> {
+    // An empty output is the same as `null`: the book was not found
+    if output.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let de = &mut serde_json::Deserializer::from_slice(output);
+    match serde_path_to_error::deserialize(de) {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            tracing::error!("Could not parse command output: {e:?}");
+            Err(e.into())
+        }
+    }
+}
+
+async fn fetch_metadata(
+    config: &CommandConfig,
+    isbn: &str,
+) -> Result<Option<NullableBookDetails>, CommandMetadataError> {
+    tracing::debug!(
+        "Running {} to fetch metadata for isbn '{isbn}'",
+        config.command.display()
+    );
+
+    let output = tokio::process::Command::new(&config.command)
+        .args(&config.args)
+        .arg(isbn)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(CommandMetadataError::Launch)?;
+
+    tracing::debug!("Stdout:\n{}", output.stdout.as_bstr());
+    tracing::debug!("Stderr:\n{}", output.stderr.as_bstr());
+
+    if !output.status.success() {
+        return Err(CommandMetadataError::Failure {
+            stdout: output.stdout.into(),
+            stderr: output.stderr.into(),
+        });
+    }
+
+    Ok(
+        parse_output(&output.stdout)?.map(|details| NullableBookDetails {
+            isbn: details.isbn.or_else(|| Some(isbn.to_string())),
+            ..details
+        }),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::CommandConfig;
+
+    fn shell(script: &str) -> CommandConfig {
+        CommandConfig {
+            command: "sh".into(),
+            args: vec!["-c".into(), script.into(), "sh".into()],
+            name: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn command() {
+        let config = shell(r#"echo "{\"title\": \"Book $1\", \"authors\": [\"Me\"]}""#);
+
+        let details = super::fetch_metadata(&config, "9780000000000")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(details.isbn.as_deref(), Some("9780000000000"));
+        assert_eq!(details.title.as_deref(), Some("Book 9780000000000"));
+        assert_eq!(details.authors, ["Me"]);
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let details = super::fetch_metadata(&shell("echo null"), "9780000000000").await;
+        assert_eq!(details.unwrap(), None);
+
+        let details = super::fetch_metadata(&shell("true"), "9780000000000").await;
+        assert_eq!(details.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn failure() {
+        let details = super::fetch_metadata(&shell("echo oops >&2; exit 1"), "9780000000000").await;
+        assert!(matches!(
+            details,
+            Err(super::CommandMetadataError::Failure { .. })
+        ));
+    }
+}
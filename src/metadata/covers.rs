@@ -0,0 +1,228 @@
+//! Sources of cover art, independent from the metadata providers
+
+use axum::async_trait;
+use reqwest::StatusCode;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CoverError {
+    #[error("Could not make HTTP client")]
+    MakeClient(#[source] reqwest::Error),
+    #[error("Error in HTTP request")]
+    RequestError(#[from] reqwest::Error),
+    #[error("Could not parse JSON response ({0})")]
+    Json(#[from] serde_path_to_error::Error<serde_json::Error>),
+}
+
+/// Identifiers of a book that can be used to find its cover
+pub struct CoverQuery<'a> {
+    pub isbn: &'a str,
+    pub amazon_id: Option<&'a str>,
+    pub google_id: Option<&'a str>,
+}
+
+#[async_trait]
+pub trait CoverSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn fetch_cover(
+        &self,
+        client: &reqwest::Client,
+        book: &CoverQuery<'_>,
+    ) -> Result<Option<Vec<u8>>, CoverError>;
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CoverSourceKind {
+    Amazon,
+    Google,
+}
+
+async fn fetch_image(client: &reqwest::Client, url: &str) -> Result<Option<Vec<u8>>, CoverError> {
+    let rsp = client.get(url).send().await?;
+
+    if rsp.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    Ok(Some(rsp.error_for_status()?.bytes().await?.to_vec()))
+}
+
+struct Amazon;
+
+#[async_trait]
+impl CoverSource for Amazon {
+    fn name(&self) -> &str {
+        "Amazon"
+    }
+
+    async fn fetch_cover(
+        &self,
+        client: &reqwest::Client,
+        book: &CoverQuery<'_>,
+    ) -> Result<Option<Vec<u8>>, CoverError> {
+        let Some(amazon_id) = book.amazon_id else {
+            return Ok(None);
+        };
+
+        let image = fetch_image(
+            client,
+            &format!(
+                "https://images-na.ssl-images-amazon.com/images/P/{amazon_id}.01.LZZZZZZZ.jpg"
+            ),
+        )
+        .await?;
+
+        // Amazon answers with a 1x1 GIF when it does not know the product
+        Ok(image.filter(|i| i.len() > 100))
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct GoogleImageLinks {
+    #[serde(default)]
+    thumbnail: Option<String>,
+    #[serde(default)]
+    small: Option<String>,
+    #[serde(default)]
+    medium: Option<String>,
+    #[serde(default)]
+    large: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct GoogleVolumeInfo {
+    #[serde(default, rename = "imageLinks")]
+    image_links: Option<GoogleImageLinks>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct GoogleVolume {
+    #[serde(rename = "volumeInfo")]
+    volume_info: GoogleVolumeInfo,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct GoogleVolumes {
+    #[serde(default)]
+    items: Vec<GoogleVolume>,
+}
+
+const GOOGLE_BOOKS: &str = "https://www.googleapis.com/books/v1/volumes";
+
+struct Google;
+
+impl Google {
+    async fn volume(
+        client: &reqwest::Client,
+        book: &CoverQuery<'_>,
+    ) -> Result<Option<GoogleVolume>, CoverError> {
+        let (url, by_id) = match book.google_id {
+            Some(id) => (format!("{GOOGLE_BOOKS}/{id}"), true),
+            None => (format!("{GOOGLE_BOOKS}?q=isbn:{}", book.isbn), false),
+        };
+
+        let rsp = client.get(url).send().await?;
+        if rsp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let body = rsp.error_for_status()?.text().await?;
+        let de = &mut serde_json::Deserializer::from_str(&body);
+
+        if by_id {
+            Ok(Some(serde_path_to_error::deserialize(de)?))
+        } else {
+            let volumes: GoogleVolumes = serde_path_to_error::deserialize(de)?;
+            Ok(volumes.items.into_iter().next())
+        }
+    }
+}
+
+#[async_trait]
+impl CoverSource for Google {
+    fn name(&self) -> &str {
+        "Google Books"
+    }
+
+    async fn fetch_cover(
+        &self,
+        client: &reqwest::Client,
+        book: &CoverQuery<'_>,
+    ) -> Result<Option<Vec<u8>>, CoverError> {
+        let Some(links) = Self::volume(client, book)
+            .await?
+            .and_then(|v| v.volume_info.image_links)
+        else {
+            return Ok(None);
+        };
+
+        let Some(url) = links
+            .large
+            .or(links.medium)
+            .or(links.small)
+            .or(links.thumbnail)
+        else {
+            return Ok(None);
+        };
+
+        fetch_image(client, &url.replacen("http://", "https://", 1)).await
+    }
+}
+
+/// Cover sources enabled on this instance, queried in order
+#[derive(Default)]
+pub struct CoverSources {
+    sources: Vec<Box<dyn CoverSource>>,
+}
+
+impl CoverSources {
+    pub fn new(kinds: &[CoverSourceKind]) -> Self {
+        Self {
+            sources: kinds
+                .iter()
+                .map(|kind| -> Box<dyn CoverSource> {
+                    match kind {
+                        CoverSourceKind::Amazon => Box::new(Amazon),
+                        CoverSourceKind::Google => Box::new(Google),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Return the first cover found, sources that fail are skipped
+    pub async fn fetch_cover(&self, book: &CoverQuery<'_>) -> Option<Vec<u8>> {
+        if self.sources.is_empty() {
+            return None;
+        }
+
+        let client = match reqwest::Client::builder()
+            .user_agent("github.com/traxys/bouquineur")
+            .build()
+        {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::error!("{}", CoverError::MakeClient(e));
+                return None;
+            }
+        };
+
+        for source in &self.sources {
+            match source.fetch_cover(&client, book).await {
+                Ok(Some(cover)) => return Some(cover),
+                Ok(None) => (),
+                Err(e) => tracing::warn!(
+                    "Could not fetch the cover of '{}' from {}: {e:?}",
+                    book.isbn,
+                    source.name()
+                ),
+            }
+        }
+
+        None
+    }
+}
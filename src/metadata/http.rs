@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use axum::async_trait;
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+
+use super::{MetadataError, MetadataProvider, NullableBookDetails};
+
+#[derive(serde::Deserialize, Debug)]
+struct HttpConfig {
+    /// Endpoint receiving a POST request with `{"isbn": "..."}`
+    url: String,
+    /// Name displayed to the user, defaults to the identifier of the provider
+    #[serde(default)]
+    name: Option<String>,
+    /// Seconds after which the request is abandoned
+    #[serde(default = "HttpConfig::default_timeout")]
+    timeout: u64,
+}
+
+impl HttpConfig {
+    fn default_timeout() -> u64 {
+        30
+    }
+}
+
+/// Identifier of the provider configured directly in `[metadata.http_provider]`
+const DEFAULT_ID: &str = "Http";
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum HttpProviders {
+    /// A single service, in `[metadata.http_provider]`
+    Single(HttpConfig),
+    /// Services keyed by their identifier, in `[metadata.http_provider.<id>]`
+    Multiple(BTreeMap<String, HttpConfig>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HttpMetadataError {
+    #[error("Error in HTTP request")]
+    Request(#[from] reqwest::Error),
+    #[error("Provider answered with status {0}")]
+    Status(StatusCode),
+    #[error("Could not parse the provider response ({0})")]
+    Json(#[from] serde_path_to_error::Error<serde_json::Error>),
+}
+
+struct Http {
+    name: String,
+    config: HttpConfig,
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl MetadataProvider for Http {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch_metadata(
+        &self,
+        isbn: &str,
+    ) -> Result<Option<NullableBookDetails>, MetadataError> {
+        Ok(fetch_metadata(&self.client, &self.config, isbn).await?)
+    }
+}
+
+/// Parse all the providers defined in `[metadata.http_provider]`, keyed by their identifier
+pub(super) fn providers(
+    options: toml::Value,
+) -> anyhow::Result<BTreeMap<String, Box<dyn MetadataProvider>>> {
+    let services = match options.try_into()? {
+        HttpProviders::Single(config) => BTreeMap::from([(DEFAULT_ID.to_string(), config)]),
+        HttpProviders::Multiple(services) => services,
+    };
+
+    services
+        .into_iter()
+        .map(|(id, config)| -> anyhow::Result<_> {
+            let client = reqwest::Client::builder()
+                .user_agent("github.com/traxys/bouquineur")
+                .timeout(Duration::from_secs(config.timeout))
+                .build()?;
+
+            let provider: Box<dyn MetadataProvider> = Box::new(Http {
+                name: config.name.clone().unwrap_or_else(|| id.clone()),
+                config,
+                client,
+            });
+
+            Ok((id, provider))
+        })
+        .collect()
+}
+
+#[derive(serde::Serialize)]
+struct HttpRequest<'a> {
+    isbn: &'a str,
+}
+
+async fn fetch_metadata(
+    client: &reqwest::Client,
+    config: &HttpConfig,
+    isbn: &str,
+) -> Result<Option<NullableBookDetails>, HttpMetadataError> {
+    tracing::debug!("Querying {} for isbn '{isbn}'", config.url);
+
+    let body = serde_json::to_vec(&HttpRequest { isbn }).expect("request can be serialized");
+
+    let response = client
+        .post(&config.url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await?;
+
+    match response.status() {
+        StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => return Ok(None),
+        status if !status.is_success() => return Err(HttpMetadataError::Status(status)),
+        _ => (),
+    }
+
+    let body = response.bytes().await?;
+    tracing::trace!("Response:\n{}", String::from_utf8_lossy(&body));
+
+    // An empty body is the same as `null`: the book was not found
+    if body.trim_ascii().is_empty() {
+        return Ok(None);
+    }
+
+    let de = &mut serde_json::Deserializer::from_slice(&body);
+    let details: Option<NullableBookDetails> = match serde_path_to_error::deserialize(de) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Could not parse provider response: {e:?}");
+            return Err(e.into());
+        }
+    };
+
+    Ok(details.map(|details| NullableBookDetails {
+        isbn: details.isbn.or_else(|| Some(isbn.to_string())),
+        ..details
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{http::StatusCode, routing::post, Json, Router};
+
+    use super::{HttpConfig, HttpMetadataError};
+
+    /// Serve the responses of a provider on a local port, returning its configuration
+    async fn service(router: Router) -> HttpConfig {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        HttpConfig {
+            url: format!("http://{address}/lookup"),
+            name: None,
+            timeout: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn http() {
+        let config = service(Router::new().route(
+            "/lookup",
+            post(|Json(request): Json<serde_json::Value>| async move {
+                let isbn = request["isbn"].as_str().unwrap().to_owned();
+                match isbn.as_str() {
+                    "9780000000000" => Json(serde_json::json!({
+                        "title": format!("Book {isbn}"),
+                        "authors": ["Me"],
+                    })),
+                    _ => Json(serde_json::Value::Null),
+                }
+            }),
+        ))
+        .await;
+        let client = reqwest::Client::new();
+
+        let details = super::fetch_metadata(&client, &config, "9780000000000")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(details.isbn.as_deref(), Some("9780000000000"));
+        assert_eq!(details.title.as_deref(), Some("Book 9780000000000"));
+        assert_eq!(details.authors, ["Me"]);
+
+        let details = super::fetch_metadata(&client, &config, "9781111111111").await;
+        assert_eq!(details.unwrap(), None);
+    }
+
+    #[test]
+    fn config() {
+        let single = toml::from_str(r#"url = "http://localhost:8081/lookup""#).unwrap();
+        let providers = super::providers(single).unwrap();
+        assert_eq!(providers.keys().collect::<Vec<_>>(), ["Http"]);
+        assert_eq!(providers["Http"].name(), "Http");
+
+        let multiple = toml::from_str(
+            r#"
+            [First]
+            url = "http://localhost:8081/lookup"
+
+            [Second]
+            url = "http://localhost:8082/lookup"
+            name = "Second service"
+            "#,
+        )
+        .unwrap();
+        let providers = super::providers(multiple).unwrap();
+        assert_eq!(providers.keys().collect::<Vec<_>>(), ["First", "Second"]);
+        assert_eq!(providers["Second"].name(), "Second service");
+
+        assert!(super::providers(toml::from_str("timeout = 5").unwrap()).is_err());
+    }
+
+    #[tokio::test]
+    async fn status() {
+        let config = service(
+            Router::new()
+                .route("/lookup", post(|| async { StatusCode::NOT_FOUND }))
+                .route("/failure", post(|| async { StatusCode::BAD_GATEWAY })),
+        )
+        .await;
+        let client = reqwest::Client::new();
+
+        let details = super::fetch_metadata(&client, &config, "9780000000000").await;
+        assert_eq!(details.unwrap(), None);
+
+        let config = HttpConfig {
+            url: config.url.replace("/lookup", "/failure"),
+            ..config
+        };
+        let details = super::fetch_metadata(&client, &config, "9780000000000").await;
+        assert!(matches!(
+            details,
+            Err(HttpMetadataError::Status(status)) if status == StatusCode::BAD_GATEWAY
+        ));
+    }
+}
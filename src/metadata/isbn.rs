@@ -0,0 +1,172 @@
+//! Extraction of ISBNs from free form text, such as the URL or content of a retailer page
+
+fn isbn13_valid(digits: &[u8]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| (d - b'0') as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+
+    sum.is_multiple_of(10)
+}
+
+fn isbn10_valid(digits: &[u8]) -> bool {
+    let mut sum = 0;
+
+    for (i, &d) in digits.iter().enumerate() {
+        let value = match d {
+            b'0'..=b'9' => (d - b'0') as u32,
+            b'X' | b'x' if i == 9 => 10,
+            _ => return false,
+        };
+
+        sum += value * (10 - i as u32);
+    }
+
+    sum % 11 == 0
+}
+
+/// Convert a (valid) ISBN-10 to its ISBN-13 form
+pub fn isbn10_to_13(isbn: &str) -> String {
+    let mut isbn13 = format!("978{}", &isbn[..9]);
+
+    let sum: u32 = isbn13
+        .bytes()
+        .enumerate()
+        .map(|(i, d)| (d - b'0') as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+
+    isbn13.push(char::from_digit((10 - sum % 10) % 10, 10).unwrap());
+    isbn13
+}
+
+/// Convert an ISBN-13 to its ISBN-10 form, only `978` ISBNs have one
+pub fn isbn13_to_10(isbn: &str) -> Option<String> {
+    let digits = isbn.strip_prefix("978")?;
+    if digits.len() != 10 || !digits.bytes().all(|d| d.is_ascii_digit()) {
+        return None;
+    }
+
+    let sum: u32 = digits[..9]
+        .bytes()
+        .enumerate()
+        .map(|(i, d)| (d - b'0') as u32 * (10 - i as u32))
+        .sum();
+
+    let mut isbn10 = digits[..9].to_string();
+    match (11 - sum % 11) % 11 {
+        10 => isbn10.push('X'),
+        check => isbn10.push(char::from_digit(check, 10).unwrap()),
+    }
+
+    Some(isbn10)
+}
+
+fn is_isbn_char(c: char) -> bool {
+    c.is_ascii_digit() || c == '-' || c == 'X' || c == 'x'
+}
+
+/// Find the first ISBN in `text`, returned as an ISBN-13.
+///
+/// ISBN-10 are only searched when `loose` is set, or when they directly follow the word "ISBN", to
+/// avoid matching random numbers. ISBN-13 are always preferred.
+pub fn find_isbn(text: &str, loose: bool) -> Option<String> {
+    let lowercase = text.to_ascii_lowercase();
+    let mut isbn10 = None;
+    let mut position = 0;
+
+    while let Some(start) = text[position..].find(|c: char| c.is_ascii_digit()) {
+        let start = position + start;
+        let len = text[start..]
+            .find(|c| !is_isbn_char(c))
+            .unwrap_or(text.len() - start);
+        position = start + len;
+
+        let digits: Vec<u8> = text[start..position]
+            .bytes()
+            .filter(|&c| c != b'-')
+            .collect();
+
+        match digits.len() {
+            13 if (digits.starts_with(b"978") || digits.starts_with(b"979"))
+                && digits.iter().all(u8::is_ascii_digit)
+                && isbn13_valid(&digits) =>
+            {
+                return Some(String::from_utf8(digits).unwrap());
+            }
+            10 if isbn10.is_none() && isbn10_valid(&digits) => {
+                // Skip the separators and the "-10" of "ISBN-10: "
+                let prefix = lowercase[..start]
+                    .trim_end_matches(|c: char| !c.is_ascii_alphanumeric())
+                    .trim_end_matches("10")
+                    .trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+
+                if loose || prefix.ends_with("isbn") {
+                    isbn10 = Some(isbn10_to_13(std::str::from_utf8(&digits).unwrap()));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    isbn10
+}
+
+/// Find the ISBNs of `text`, the first one of each line as with [`find_isbn`], without duplicates
+pub fn find_isbns(text: &str) -> Vec<String> {
+    let mut isbns = Vec::new();
+
+    for isbn in text.lines().filter_map(|line| find_isbn(line, false)) {
+        if !isbns.contains(&isbn) {
+            isbns.push(isbn);
+        }
+    }
+
+    isbns
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_isbn, find_isbns, isbn13_to_10};
+
+    #[test]
+    fn isbn13() {
+        assert_eq!(
+            find_isbn("ISBN-13 : 978-0552134637", false).as_deref(),
+            Some("9780552134637")
+        );
+        assert_eq!(
+            find_isbn("https://example.org/book/9780552131063.html", false).as_deref(),
+            Some("9780552131063")
+        );
+        assert_eq!(find_isbn("phone: 9780552134638", false), None);
+    }
+
+    #[test]
+    fn isbn10() {
+        assert_eq!(
+            find_isbn("https://www.amazon.fr/dp/2070584623/ref=sr_1_1", true).as_deref(),
+            Some("9782070584628")
+        );
+        assert_eq!(
+            find_isbn("<li>ISBN-10 : 2070584623</li>", false).as_deref(),
+            Some("9782070584628")
+        );
+        assert_eq!(find_isbn("order 2070584623", false), None);
+    }
+
+    #[test]
+    fn isbns() {
+        assert_eq!(
+            find_isbns("1x Mort (ISBN 0552131067)\n1x Guards! Guards! 978-0552134637\nMort 9780552131063\nOrder 2070584623"),
+            ["9780552131063", "9780552134637"]
+        );
+    }
+
+    #[test]
+    fn to_isbn10() {
+        assert_eq!(isbn13_to_10("9782070584628").as_deref(), Some("2070584623"));
+        assert_eq!(isbn13_to_10("9780552134637").as_deref(), Some("0552134635"));
+        assert_eq!(isbn13_to_10("9791032705124"), None);
+    }
+}
@@ -0,0 +1,140 @@
+use std::{borrow::Cow, collections::HashMap, path::PathBuf};
+
+use axum::async_trait;
+
+use super::{MetadataError, MetadataProvider, NullableBookDetails};
+
+#[derive(serde::Deserialize, Debug)]
+pub(super) struct MockConfig {
+    /// JSON file mapping ISBNs to book details, defaults to the bundled fixtures
+    #[serde(default)]
+    fixtures: Option<PathBuf>,
+}
+
+struct Mock {
+    config: MockConfig,
+}
+
+#[async_trait]
+impl MetadataProvider for Mock {
+    fn name(&self) -> &str {
+        "Mock"
+    }
+
+    async fn fetch_metadata(
+        &self,
+        isbn: &str,
+    ) -> Result<Option<NullableBookDetails>, MetadataError> {
+        Ok(fetch_metadata(&self.config, isbn).await?)
+    }
+
+    async fn author_works(&self, author: &str) -> Result<Option<Vec<String>>, MetadataError> {
+        Ok(author_works(&self.config, author).await?)
+    }
+}
+
+pub(super) fn provider(options: toml::Value) -> anyhow::Result<Box<dyn MetadataProvider>> {
+    Ok(Box::new(Mock {
+        config: options.try_into()?,
+    }))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MockMetadataError {
+    #[error("Could not read the fixture file")]
+    Read(#[from] std::io::Error),
+    #[error("Could not parse the fixtures ({0})")]
+    Json(#[from] serde_path_to_error::Error<serde_json::Error>),
+}
+
+const BUNDLED_FIXTURES: &str = include_str!("../../tests/mock_metadata.json");
+
+async fn load_fixtures(
+    config: &MockConfig,
+) -> Result<HashMap<String, NullableBookDetails>, MockMetadataError> {
+    let fixtures = match &config.fixtures {
+        None => Cow::Borrowed(BUNDLED_FIXTURES),
+        Some(path) => Cow::Owned(tokio::fs::read_to_string(path).await?),
+    };
+
+    let de = &mut serde_json::Deserializer::from_str(&fixtures);
+    match serde_path_to_error::deserialize(de) {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            tracing::error!("Could not parse mock fixtures: {e:?}");
+            Err(e.into())
+        }
+    }
+}
+
+async fn fetch_metadata(
+    config: &MockConfig,
+    isbn: &str,
+) -> Result<Option<NullableBookDetails>, MockMetadataError> {
+    tracing::debug!("Looking up mock metadata for isbn '{isbn}'");
+
+    let mut fixtures = load_fixtures(config).await?;
+
+    Ok(fixtures.remove(isbn).map(|details| NullableBookDetails {
+        isbn: Some(isbn.to_string()),
+        ..details
+    }))
+}
+
+/// Titles of the fixtures written by `author`
+async fn author_works(
+    config: &MockConfig,
+    author: &str,
+) -> Result<Option<Vec<String>>, MockMetadataError> {
+    let fixtures = load_fixtures(config).await?;
+
+    let mut works: Vec<String> = fixtures
+        .into_values()
+        .filter(|details| {
+            details
+                .authors
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(author))
+        })
+        .filter_map(|details| details.title)
+        .collect();
+    works.sort();
+
+    Ok((!works.is_empty()).then_some(works))
+}
+
+#[cfg(test)]
+mod test {
+    use super::MockConfig;
+
+    #[tokio::test]
+    async fn bundled() {
+        let config = MockConfig { fixtures: None };
+
+        let details = super::fetch_metadata(&config, "9780552134637")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(details.isbn.as_deref(), Some("9780552134637"));
+        assert_eq!(details.title.as_deref(), Some("Guards! Guards!"));
+        assert_eq!(details.series, Some(("Discworld".into(), 8)));
+
+        let missing = super::fetch_metadata(&config, "9780000000000")
+            .await
+            .unwrap();
+        assert_eq!(missing, None);
+    }
+
+    #[tokio::test]
+    async fn author_works() {
+        let config = MockConfig { fixtures: None };
+
+        let works = super::author_works(&config, "terry pratchett")
+            .await
+            .unwrap();
+        assert_eq!(works.unwrap(), ["Guards! Guards!", "Mort"]);
+
+        let works = super::author_works(&config, "Nobody").await.unwrap();
+        assert_eq!(works, None);
+    }
+}
@@ -1,11 +1,27 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context};
+use axum::async_trait;
 use chrono::NaiveDate;
 
-use crate::Config;
+use crate::MetadataConfig;
+
+use self::trust::ProviderTrust;
 
+pub mod cache;
 mod calibre;
+mod command;
+pub mod covers;
+mod http;
+pub mod isbn;
+mod mock;
 mod openlibrary;
+pub mod trust;
+
+pub use self::calibre::parse_opf;
 
-#[derive(Default, Debug, PartialEq, Eq)]
+#[derive(Default, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct NullableBookDetails {
     pub isbn: Option<String>,
     pub title: Option<String>,
@@ -16,13 +32,26 @@ pub struct NullableBookDetails {
     pub publisher: Option<String>,
     pub language: Option<String>,
     pub google_id: Option<String>,
+    pub goodreads_id: Option<String>,
     pub amazon_id: Option<String>,
     pub librarything_id: Option<String>,
+    /// Identifiers without a dedicated field, keyed by their lowercase scheme (`oclc`, `doi`, ...)
+    pub identifiers: BTreeMap<String, String>,
     pub page_count: Option<i32>,
     pub read: bool,
     pub owned: bool,
+    pub reread: bool,
+    pub priority: Option<i32>,
+    /// Shelf or room where the physical copy is stored
+    pub location: Option<String>,
+    /// The summary reveals parts of the plot
+    pub summary_spoilers: bool,
     pub covert_art_b64: Option<String>,
+    /// Other covers found by the provider, for the user to choose from
+    pub cover_candidates_b64: Vec<String>,
     pub series: Option<(String, i32)>,
+    /// External links, as (label, URL)
+    pub links: Vec<(String, String)>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -31,59 +60,393 @@ pub enum MetadataError {
     Calibre(#[from] calibre::CalibreMetadataError),
     #[error("Could not fetch metadata with open library")]
     OpenLibrary(#[from] openlibrary::OpenLibraryMetadataError),
+    #[error("Could not fetch metadata with a command")]
+    Command(#[from] command::CommandMetadataError),
+    #[error("Could not fetch metadata from an HTTP provider")]
+    Http(#[from] http::HttpMetadataError),
+    #[error("Could not load mock metadata")]
+    Mock(#[from] mock::MockMetadataError),
+    #[error("No metadata provider is enabled")]
+    NoProvider,
+    #[error("Unknown metadata provider {0}")]
+    UnknownProvider(String),
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq, Clone, Copy)]
-pub enum MetadataProvider {
-    Calibre,
-    OpenLibrary,
+impl MetadataError {
+    /// Why the lookup failed, in words meant for the user
+    pub fn reason(&self) -> String {
+        use self::{
+            calibre::CalibreMetadataError as Calibre, command::CommandMetadataError as Command,
+            http::HttpMetadataError as Http, openlibrary::OpenLibraryMetadataError as OpenLibrary,
+        };
+
+        match self {
+            MetadataError::Calibre(Calibre::Timeout) => "the provider timed out".into(),
+            MetadataError::OpenLibrary(OpenLibrary::RequestError(e))
+            | MetadataError::Http(Http::Request(e)) => match e.status() {
+                _ if e.is_timeout() => "the provider timed out".into(),
+                Some(status) => format!("the provider answered with the status {status}"),
+                None => "the provider could not be reached".into(),
+            },
+            MetadataError::Http(Http::Status(status)) => {
+                format!("the provider answered with the status {status}")
+            }
+            MetadataError::OpenLibrary(OpenLibrary::NotFound | OpenLibrary::MissingWork) => {
+                "the provider only has part of the book record".into()
+            }
+            MetadataError::Calibre(
+                Calibre::InvalidResponse(_)
+                | Calibre::InvalidXmlResponse(_)
+                | Calibre::InvalidDate(_),
+            )
+            | MetadataError::OpenLibrary(OpenLibrary::Json(_))
+            | MetadataError::Http(Http::Json(_))
+            | MetadataError::Command(Command::Json(_)) => {
+                "the response of the provider could not be parsed".into()
+            }
+            e => e.to_string().to_lowercase(),
+        }
+    }
 }
 
-impl MetadataProvider {
-    pub fn all() -> &'static [Self] {
-        &[Self::Calibre, Self::OpenLibrary]
+#[async_trait]
+pub trait MetadataProvider: Send + Sync {
+    /// Name of the provider as displayed to the user
+    fn name(&self) -> &str;
+
+    async fn fetch_metadata(
+        &self,
+        isbn: &str,
+    ) -> Result<Option<NullableBookDetails>, MetadataError>;
+
+    /// Titles of the works of `author`, `None` if the provider does not know the author or can't
+    /// list works
+    async fn author_works(&self, _author: &str) -> Result<Option<Vec<String>>, MetadataError> {
+        Ok(None)
     }
+}
 
-    pub fn serialized(&self) -> &'static str {
-        match self {
-            MetadataProvider::Calibre => "Calibre",
-            MetadataProvider::OpenLibrary => "OpenLibrary",
+struct Builtin {
+    id: &'static str,
+    /// Section of `[metadata]` holding the options of the provider
+    section: &'static str,
+    /// Enabled when `metadata.providers` is not set
+    implicit: bool,
+    build: fn(toml::Value) -> anyhow::Result<Box<dyn MetadataProvider>>,
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        id: "Calibre",
+        section: "calibre",
+        implicit: true,
+        build: calibre::provider,
+    },
+    Builtin {
+        id: "OpenLibrary",
+        section: "open_library",
+        implicit: true,
+        build: openlibrary::provider,
+    },
+    Builtin {
+        id: "Mock",
+        section: "mock",
+        implicit: false,
+        build: mock::provider,
+    },
+];
+
+/// Metadata providers enabled on this instance, keyed by their identifier
+#[derive(Default)]
+pub struct MetadataProviders {
+    providers: Vec<(String, Box<dyn MetadataProvider>)>,
+    default: Option<String>,
+    trust: BTreeMap<String, ProviderTrust>,
+}
+
+impl MetadataProviders {
+    pub fn from_config(config: &MetadataConfig) -> anyhow::Result<Self> {
+        let mut registry = Self::default();
+
+        let mut external = match config.options.get("command") {
+            None => BTreeMap::new(),
+            Some(options) => {
+                command::providers(options.clone()).context("Invalid `[metadata.command]`")?
+            }
+        };
+
+        if let Some(id) = external
+            .keys()
+            .find(|id| BUILTINS.iter().any(|b| b.id == id.as_str()))
+        {
+            anyhow::bail!("`[metadata.command.{id}]` has the same name as a builtin provider");
+        }
+
+        if let Some(options) = config.options.get("http_provider") {
+            let services =
+                http::providers(options.clone()).context("Invalid `[metadata.http_provider]`")?;
+
+            for (id, provider) in services {
+                if BUILTINS.iter().any(|b| b.id == id) || external.contains_key(&id) {
+                    anyhow::bail!(
+                        "`[metadata.http_provider.{id}]` has the same name as another provider"
+                    );
+                }
+
+                external.insert(id, provider);
+            }
+        }
+
+        let enabled: Vec<String> = match &config.providers {
+            None => BUILTINS
+                .iter()
+                .filter(|b| b.implicit)
+                .map(|b| b.id.to_string())
+                .chain(external.keys().cloned())
+                .collect(),
+            Some(v) => v.clone(),
+        };
+
+        for id in enabled {
+            if let Some(provider) = external.remove(&id) {
+                registry.register(id, provider);
+                continue;
+            }
+
+            let builtin = BUILTINS
+                .iter()
+                .find(|b| b.id == id)
+                .ok_or_else(|| anyhow!("Unknown metadata provider `{id}`"))?;
+
+            let options = config
+                .options
+                .get(builtin.section)
+                .cloned()
+                .ok_or_else(|| anyhow!("Missing `[metadata.{}]`", builtin.section))?;
+
+            let provider = (builtin.build)(options)
+                .with_context(|| format!("Invalid `[metadata.{}]`", builtin.section))?;
+
+            registry.register(id, provider);
+        }
+
+        match &config.default_provider {
+            None => {
+                if config.providers.is_some() && registry.len() > 1 {
+                    anyhow::bail!(
+                        "When more than one providers are enabled a default must be chosen"
+                    )
+                }
+            }
+            Some(def) => {
+                if registry.get(def).is_none() {
+                    anyhow::bail!(
+                        "metadata.default_provider ({def:?}) must be present in metadata.providers"
+                    )
+                }
+
+                registry.default = Some(def.clone());
+            }
         }
+
+        for (id, trust) in &config.trust {
+            if registry.get(id).is_none() {
+                anyhow::bail!("`[metadata.trust.{id}]` does not name an enabled provider");
+            }
+
+            if let Some(field) = trust.prefer.iter().find(|f| trust.ignore.contains(f)) {
+                anyhow::bail!("`[metadata.trust.{id}]` both ignores and prefers {field:?}");
+            }
+
+            for field in &trust.prefer {
+                if let Some(other) = registry
+                    .trust
+                    .iter()
+                    .find(|(_, t)| t.prefer.contains(field))
+                {
+                    anyhow::bail!("{field:?} is preferred from both {id} and {}", other.0);
+                }
+            }
+
+            registry.trust.insert(id.clone(), trust.clone());
+        }
+
+        Ok(registry)
     }
-}
 
-impl std::fmt::Display for MetadataProvider {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            MetadataProvider::Calibre => write!(f, "Calibre"),
-            MetadataProvider::OpenLibrary => write!(f, "Open Library"),
+    /// Make a provider available, the first registered provider is the default one unless
+    /// specified otherwise.
+    pub fn register(&mut self, id: impl Into<String>, provider: Box<dyn MetadataProvider>) {
+        let id = id.into();
+
+        if self.default.is_none() {
+            self.default = Some(id.clone());
+        }
+
+        self.providers.push((id, provider));
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn MetadataProvider> {
+        self.providers
+            .iter()
+            .find(|(i, _)| i == id)
+            .map(|(_, p)| &**p)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &dyn MetadataProvider)> {
+        self.providers.iter().map(|(i, p)| (i.as_str(), &**p))
+    }
+
+    /// Iterate over the providers, starting with the ones listed in `preferred`, then the default
+    /// provider and the others in the order they were registered
+    pub fn ordered<'a>(
+        &'a self,
+        preferred: &'a [String],
+    ) -> impl Iterator<Item = (&'a str, &'a dyn MetadataProvider)> {
+        let mut seen = Vec::new();
+
+        preferred
+            .iter()
+            .map(String::as_str)
+            .chain(self.default_provider())
+            .chain(self.iter().map(|(id, _)| id))
+            .filter_map(move |id| {
+                if seen.contains(&id) {
+                    return None;
+                }
+                seen.push(id);
+
+                self.get(id).map(|p| (id, p))
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.providers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    pub fn default_provider(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// Identifier of `provider`, or of the default provider if none is specified
+    pub fn resolve<'a>(&'a self, provider: Option<&'a str>) -> Result<&'a str, MetadataError> {
+        let id = provider
+            .or(self.default_provider())
+            .ok_or(MetadataError::NoProvider)?;
+
+        match self.get(id) {
+            Some(_) => Ok(id),
+            None => Err(MetadataError::UnknownProvider(id.to_string())),
+        }
+    }
+
+    /// Fetch metadata using `provider`, or the default provider if none is specified. The fields
+    /// preferred from other providers are taken from them.
+    pub async fn fetch_metadata(
+        &self,
+        provider: Option<&str>,
+        isbn: &str,
+    ) -> Result<Option<NullableBookDetails>, MetadataError> {
+        let id = self.resolve(provider)?;
+
+        let Some(mut details) = self.get(id).unwrap().fetch_metadata(isbn).await? else {
+            return Ok(None);
+        };
+
+        if let Some(trust) = self.trust.get(id) {
+            for field in &trust.ignore {
+                field.clear(&mut details);
+            }
+        }
+
+        for (other, trust) in &self.trust {
+            if other == id || trust.prefer.is_empty() {
+                continue;
+            }
+
+            match self.get(other).unwrap().fetch_metadata(isbn).await {
+                Ok(Some(mut preferred)) => {
+                    for field in &trust.prefer {
+                        field.transfer(&mut preferred, &mut details);
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    tracing::warn!("Could not fetch the preferred fields from {other}: {e:?}")
+                }
+            }
         }
+
+        Ok(Some(details))
     }
 }
 
-pub async fn fetch_metadata(
-    config: &Config,
-    isbn: &str,
-    provider: MetadataProvider,
-) -> Result<Option<NullableBookDetails>, MetadataError> {
-    match provider {
-        MetadataProvider::Calibre => Ok(calibre::fetch_metadata(
-            config
-                .metadata
-                .calibre
-                .as_ref()
-                .expect("missing calibre configuration"),
-            isbn,
-        )
-        .await?),
-        MetadataProvider::OpenLibrary => Ok(openlibrary::fetch_metadata(
-            config
-                .metadata
-                .open_library
-                .as_ref()
-                .expect("missing open_library configuration"),
-            isbn,
-        )
-        .await?),
+#[cfg(test)]
+mod test {
+    use axum::async_trait;
+
+    use super::{
+        calibre::CalibreMetadataError, openlibrary::OpenLibraryMetadataError, MetadataError,
+        MetadataProvider, MetadataProviders, NullableBookDetails,
+    };
+
+    struct Empty;
+
+    #[async_trait]
+    impl MetadataProvider for Empty {
+        fn name(&self) -> &str {
+            "Empty"
+        }
+
+        async fn fetch_metadata(
+            &self,
+            _isbn: &str,
+        ) -> Result<Option<NullableBookDetails>, MetadataError> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn ordered() {
+        let mut providers = MetadataProviders::default();
+        for id in ["A", "B", "C"] {
+            providers.register(id, Box::new(Empty));
+        }
+        providers.default = Some("B".into());
+
+        let order = |preferred: &[String]| {
+            providers
+                .ordered(preferred)
+                .map(|(id, _)| id.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(order(&[]), ["B", "A", "C"]);
+        assert_eq!(order(&["C".into(), "Unknown".into()]), ["C", "B", "A"]);
+        assert_eq!(
+            order(&["A".into(), "B".into(), "C".into()]),
+            ["A", "B", "C"]
+        );
+    }
+
+    #[test]
+    fn reason() {
+        let timeout = MetadataError::Calibre(CalibreMetadataError::Timeout);
+        assert_eq!(timeout.reason(), "the provider timed out");
+
+        let partial = MetadataError::OpenLibrary(OpenLibraryMetadataError::MissingWork);
+        assert_eq!(
+            partial.reason(),
+            "the provider only has part of the book record"
+        );
+
+        assert_eq!(
+            MetadataError::NoProvider.reason(),
+            "no metadata provider is enabled"
+        );
     }
 }
@@ -1,10 +1,66 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use axum::async_trait;
 use base64::prelude::*;
-use chrono::NaiveDate;
-use reqwest::StatusCode;
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::{
+    header::{
+        HeaderMap, CACHE_CONTROL, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    },
+    StatusCode,
+};
+use sha2::{Digest, Sha256};
+
+use super::{MetadataError, MetadataProvider, NullableBookDetails};
+
+/// Number of covers of an edition that are proposed to the user
+const MAX_COVERS: usize = 4;
+
+#[derive(serde::Deserialize, Debug)]
+pub(super) struct OpenLibraryConfig {
+    contact: String,
+    /// Directory keeping the last response for each URL, to honor the caching headers of Open
+    /// Library and revalidate the responses instead of downloading them again
+    #[serde(default)]
+    cache_dir: Option<PathBuf>,
+}
+
+struct OpenLibrary {
+    config: OpenLibraryConfig,
+}
+
+#[async_trait]
+impl MetadataProvider for OpenLibrary {
+    fn name(&self) -> &str {
+        "Open Library"
+    }
+
+    async fn fetch_metadata(
+        &self,
+        isbn: &str,
+    ) -> Result<Option<NullableBookDetails>, MetadataError> {
+        Ok(fetch_metadata(&self.config, isbn).await?)
+    }
+
+    async fn author_works(&self, author: &str) -> Result<Option<Vec<String>>, MetadataError> {
+        Ok(author_works(&self.config, author).await?)
+    }
+}
+
+pub(super) fn provider(options: toml::Value) -> anyhow::Result<Box<dyn MetadataProvider>> {
+    let config: OpenLibraryConfig = options.try_into()?;
 
-use crate::OpenLibraryConfig;
+    if let Some(dir) = &config.cache_dir {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create the cache directory {dir:?}"))?;
+    }
 
-use super::NullableBookDetails;
+    Ok(Box::new(OpenLibrary { config }))
+}
 
 #[derive(thiserror::Error, Debug)]
 pub enum OpenLibraryMetadataError {
@@ -79,6 +135,12 @@ struct Edition {
     covers: Vec<i64>,
     #[serde(default)]
     works: Vec<Reference>,
+    #[serde(default)]
+    identifiers: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    oclc_numbers: Vec<String>,
+    #[serde(default)]
+    lccn: Vec<String>,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -87,34 +149,244 @@ struct Author {
     name: Option<String>,
 }
 
+/// Response stored in `cache_dir`
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct CachedResponse {
+    body: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// The body can be used without asking Open Library until then
+    fresh_until: Option<DateTime<Utc>>,
+}
+
+fn cache_path(dir: &Path, url: &str) -> PathBuf {
+    let key = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(url.as_bytes()));
+    dir.join(format!("{key}.json"))
+}
+
+async fn read_cached(path: &Path) -> Option<CachedResponse> {
+    let data = match tokio::fs::read(path).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            tracing::warn!("Could not read cached response {path:?}: {e}");
+            return None;
+        }
+    };
+
+    match serde_json::from_slice(&data) {
+        Ok(cached) => Some(cached),
+        Err(e) => {
+            tracing::warn!("Invalid cached response {path:?}: {e}");
+            None
+        }
+    }
+}
+
+async fn store_cached(path: &Path, cached: &CachedResponse) {
+    let data = serde_json::to_vec(cached).expect("responses can be serialized");
+    if let Err(e) = tokio::fs::write(path, data).await {
+        tracing::warn!("Could not store response in {path:?}: {e}");
+    }
+}
+
+/// How long a response can be used without revalidation, `None` if it can't be stored at all
+fn freshness(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Option<DateTime<Utc>>> {
+    let cache_control = headers
+        .get(CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    let mut max_age = None;
+    for directive in cache_control.split(',').map(str::trim) {
+        match directive {
+            "no-store" => return None,
+            "no-cache" => return Some(None),
+            _ => {
+                if let Some(seconds) = directive.strip_prefix("max-age=") {
+                    max_age = seconds.parse::<i64>().ok();
+                }
+            }
+        }
+    }
+
+    if let Some(seconds) = max_age {
+        return Some(Some(now + chrono::Duration::seconds(seconds)));
+    }
+
+    Some(
+        headers
+            .get(EXPIRES)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+            .map(|expires| expires.with_timezone(&Utc)),
+    )
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
 async fn fetch(
+    config: &OpenLibraryConfig,
     url: &str,
     client: &reqwest::Client,
 ) -> Result<Option<String>, OpenLibraryMetadataError> {
-    let rsp = client.get(url).send().await?;
+    let path = config.cache_dir.as_deref().map(|dir| cache_path(dir, url));
+    let cached = match &path {
+        Some(path) => read_cached(path).await,
+        None => None,
+    };
+
+    let mut request = client.get(url);
+    if let Some(cached) = &cached {
+        if cached.fresh_until.is_some_and(|until| until > Utc::now()) {
+            tracing::debug!("Using the stored response for {url}");
+            return Ok(Some(cached.body.clone()));
+        }
+
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let rsp = request.send().await?;
 
     if rsp.status() == StatusCode::NOT_FOUND {
         return Ok(None);
     }
 
-    Ok(Some(rsp.error_for_status()?.text().await?))
+    if let (StatusCode::NOT_MODIFIED, Some(path), Some(mut cached)) = (rsp.status(), &path, cached)
+    {
+        tracing::debug!("Stored response for {url} is still valid");
+        if let Some(fresh_until) = freshness(rsp.headers(), Utc::now()) {
+            cached.fresh_until = fresh_until;
+            store_cached(path, &cached).await;
+        }
+        return Ok(Some(cached.body));
+    }
+
+    let rsp = rsp.error_for_status()?;
+    let headers = rsp.headers().clone();
+    let body = rsp.text().await?;
+
+    if let (Some(path), Some(fresh_until)) = (&path, freshness(&headers, Utc::now())) {
+        let cached = CachedResponse {
+            body,
+            etag: header(&headers, ETAG),
+            last_modified: header(&headers, LAST_MODIFIED),
+            fresh_until,
+        };
+        store_cached(path, &cached).await;
+        return Ok(Some(cached.body));
+    }
+
+    Ok(Some(body))
 }
 
 const OPEN_LIBRARY: &str = "https://openlibrary.org";
 
-pub(super) async fn fetch_metadata(
+fn client(config: &OpenLibraryConfig) -> Result<reqwest::Client, OpenLibraryMetadataError> {
+    let user_agent = format!("github.com/traxys/bouquineur ({})", config.contact);
+    reqwest::Client::builder()
+        .user_agent(user_agent)
+        .build()
+        .map_err(OpenLibraryMetadataError::MakeClient)
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct AuthorSearch {
+    docs: Vec<Reference>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WorkEntry {
+    #[serde(default)]
+    title: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct AuthorWorks {
+    entries: Vec<WorkEntry>,
+}
+
+/// Titles of the works of the best match for `author` in the search of Open Library
+async fn author_works(
+    config: &OpenLibraryConfig,
+    author: &str,
+) -> Result<Option<Vec<String>>, OpenLibraryMetadataError> {
+    tracing::debug!("Querying OpenLibrary for the works of '{author}'");
+
+    let client = client(config)?;
+
+    let search = client
+        .get(format!("{OPEN_LIBRARY}/search/authors.json"))
+        .query(&[("q", author)])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    tracing::trace!("Author search:\n{search}");
+    let de = &mut serde_json::Deserializer::from_str(&search);
+    let search: AuthorSearch = match serde_path_to_error::deserialize(de) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Could not parse author search: {e:?}");
+            return Err(e.into());
+        }
+    };
+
+    let Some(found) = search.docs.first() else {
+        return Ok(None);
+    };
+
+    // Search results have bare keys (`OL23919A`), unlike the references of the other resources
+    let key = found.key.trim_start_matches("/authors/");
+    let Some(works) = fetch(
+        config,
+        &format!("{OPEN_LIBRARY}/authors/{key}/works.json?limit=1000"),
+        &client,
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    tracing::trace!("Works:\n{works}");
+    let de = &mut serde_json::Deserializer::from_str(&works);
+    let works: AuthorWorks = match serde_path_to_error::deserialize(de) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Could not parse author works: {e:?}");
+            return Err(e.into());
+        }
+    };
+
+    let mut titles: Vec<String> = works.entries.into_iter().filter_map(|w| w.title).collect();
+    titles.sort();
+    titles.dedup();
+
+    Ok(Some(titles))
+}
+
+async fn fetch_metadata(
     config: &OpenLibraryConfig,
     isbn: &str,
 ) -> Result<Option<NullableBookDetails>, OpenLibraryMetadataError> {
     tracing::debug!("Querying OpenLibrary for isbn '{isbn}'");
 
-    let user_agent = format!("github.com/traxys/bouquineur ({})", config.contact);
-    let client = reqwest::Client::builder()
-        .user_agent(user_agent)
-        .build()
-        .map_err(OpenLibraryMetadataError::MakeClient)?;
+    let client = client(config)?;
 
-    let Some(edition) = fetch(&format!("{OPEN_LIBRARY}/isbn/{isbn}.json"), &client).await? else {
+    let Some(edition) = fetch(config, &format!("{OPEN_LIBRARY}/isbn/{isbn}.json"), &client).await?
+    else {
         return Ok(None);
     };
 
@@ -138,6 +410,7 @@ pub(super) async fn fetch_metadata(
     }
 
     let work = fetch(
+        config,
         &format!("{OPEN_LIBRARY}/{}.json", edition.works[0].key),
         &client,
     )
@@ -159,6 +432,7 @@ pub(super) async fn fetch_metadata(
     for author in &work.authors {
         if author.ty.key == "/type/author_role" {
             let author = fetch(
+                config,
                 &format!("{OPEN_LIBRARY}/{}.json", author.author.key),
                 &client,
             )
@@ -200,22 +474,41 @@ pub(super) async fn fetch_metadata(
         },
     };
 
-    let covert_art_b64 = match edition.covers.is_empty() {
-        true => None,
-        false => {
-            let cover = client
-                .get(&format!(
-                    "https://covers.openlibrary.org/b/id/{}-M.jpg",
-                    edition.covers[0]
-                ))
-                .send()
-                .await?
-                .bytes()
-                .await?;
-
-            Some(BASE64_STANDARD.encode(&cover))
-        }
-    };
+    // Editions can have many covers (back cover, other printings, ...), only fetch the first ones
+    let mut covers = Vec::new();
+    for id in edition.covers.iter().filter(|&&id| id > 0).take(MAX_COVERS) {
+        let cover = client
+            .get(format!("https://covers.openlibrary.org/b/id/{id}-M.jpg"))
+            .send()
+            .await?
+            .bytes()
+            .await?;
+
+        covers.push(BASE64_STANDARD.encode(&cover));
+    }
+
+    let mut covers = covers.into_iter();
+    let covert_art_b64 = covers.next();
+    let cover_candidates_b64 = covers.collect();
+
+    let mut identifiers: BTreeMap<_, _> = edition
+        .identifiers
+        .into_iter()
+        .filter_map(|(scheme, values)| Some((scheme, values.into_iter().next()?)))
+        .collect();
+
+    if let Some(oclc) = edition.oclc_numbers.into_iter().next() {
+        identifiers.insert("oclc".into(), oclc);
+    }
+
+    if let Some(lccn) = edition.lccn.into_iter().next() {
+        identifiers.insert("lccn".into(), lccn);
+    }
+
+    let amazon_id = identifiers.remove("amazon");
+    let google_id = identifiers.remove("google");
+    let goodreads_id = identifiers.remove("goodreads");
+    let librarything_id = identifiers.remove("librarything");
 
     Ok(Some(NullableBookDetails {
         isbn: Some(isbn.to_string()),
@@ -231,15 +524,57 @@ pub(super) async fn fetch_metadata(
         tags: work.subjects,
         published,
         page_count: edition.number_of_pages,
-        amazon_id: None,
-        google_id: None,
-        librarything_id: None,
+        amazon_id,
+        google_id,
+        goodreads_id,
+        librarything_id,
+        identifiers,
         owned: false,
         read: false,
+        reread: false,
+        priority: None,
+        location: None,
+        summary_spoilers: false,
         covert_art_b64,
+        cover_candidates_b64,
         series: None,
+        links: Vec::new(),
     }))
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use chrono::{TimeZone, Utc};
+    use reqwest::header::{HeaderMap, HeaderValue, CACHE_CONTROL, EXPIRES};
+
+    #[test]
+    fn freshness() {
+        let now = Utc.with_ymd_and_hms(2024, 9, 1, 12, 0, 0).unwrap();
+        let headers = |values: &[(_, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in values {
+                headers.insert(name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+
+        let max_age = headers(&[(CACHE_CONTROL, "public, max-age=3600")]);
+        assert_eq!(
+            super::freshness(&max_age, now),
+            Some(Some(Utc.with_ymd_and_hms(2024, 9, 1, 13, 0, 0).unwrap()))
+        );
+
+        let expires = headers(&[(EXPIRES, "Mon, 02 Sep 2024 12:00:00 GMT")]);
+        assert_eq!(
+            super::freshness(&expires, now),
+            Some(Some(Utc.with_ymd_and_hms(2024, 9, 2, 12, 0, 0).unwrap()))
+        );
+
+        let no_cache = headers(&[(CACHE_CONTROL, "no-cache"), (EXPIRES, "Mon, 02 Sep 2024")]);
+        assert_eq!(super::freshness(&no_cache, now), Some(None));
+        assert_eq!(super::freshness(&HeaderMap::new(), now), Some(None));
+
+        let no_store = headers(&[(CACHE_CONTROL, "no-store")]);
+        assert_eq!(super::freshness(&no_store, now), None);
+    }
+}
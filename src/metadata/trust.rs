@@ -0,0 +1,179 @@
+//! Fields each provider is trusted for. A provider can be ignored for some fields, and others can
+//! be always taken from it whichever provider the book is looked up with.
+
+use std::collections::BTreeMap;
+
+use super::NullableBookDetails;
+
+#[derive(serde::Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataField {
+    Title,
+    Authors,
+    Tags,
+    Summary,
+    Published,
+    Publisher,
+    Language,
+    PageCount,
+    Series,
+    /// The cover and the other candidates
+    Cover,
+    Links,
+    Identifiers,
+}
+
+/// Options of `[metadata.trust.<provider>]`
+#[derive(serde::Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderTrust {
+    /// Fields never taken from this provider
+    #[serde(default)]
+    pub ignore: Vec<MetadataField>,
+    /// Fields taken from this provider when it knows them, even if another one was chosen
+    #[serde(default)]
+    pub prefer: Vec<MetadataField>,
+}
+
+fn take_option<T>(from: &mut Option<T>, to: &mut Option<T>) {
+    if from.is_some() {
+        *to = from.take();
+    }
+}
+
+fn take_vec<T>(from: &mut Vec<T>, to: &mut Vec<T>) {
+    if !from.is_empty() {
+        *to = std::mem::take(from);
+    }
+}
+
+impl MetadataField {
+    pub fn clear(self, details: &mut NullableBookDetails) {
+        match self {
+            MetadataField::Title => details.title = None,
+            MetadataField::Authors => details.authors.clear(),
+            MetadataField::Tags => details.tags.clear(),
+            MetadataField::Summary => details.summary = None,
+            MetadataField::Published => details.published = None,
+            MetadataField::Publisher => details.publisher = None,
+            MetadataField::Language => details.language = None,
+            MetadataField::PageCount => details.page_count = None,
+            MetadataField::Series => details.series = None,
+            MetadataField::Cover => {
+                details.covert_art_b64 = None;
+                details.cover_candidates_b64.clear();
+            }
+            MetadataField::Links => details.links.clear(),
+            MetadataField::Identifiers => details.identifiers = BTreeMap::new(),
+        }
+    }
+
+    /// Move the field from `from` to `to`, unless `from` does not know it
+    pub fn transfer(self, from: &mut NullableBookDetails, to: &mut NullableBookDetails) {
+        match self {
+            MetadataField::Title => take_option(&mut from.title, &mut to.title),
+            MetadataField::Authors => take_vec(&mut from.authors, &mut to.authors),
+            MetadataField::Tags => take_vec(&mut from.tags, &mut to.tags),
+            MetadataField::Summary => take_option(&mut from.summary, &mut to.summary),
+            MetadataField::Published => take_option(&mut from.published, &mut to.published),
+            MetadataField::Publisher => take_option(&mut from.publisher, &mut to.publisher),
+            MetadataField::Language => take_option(&mut from.language, &mut to.language),
+            MetadataField::PageCount => take_option(&mut from.page_count, &mut to.page_count),
+            MetadataField::Series => take_option(&mut from.series, &mut to.series),
+            MetadataField::Cover => {
+                if from.covert_art_b64.is_some() {
+                    to.covert_art_b64 = from.covert_art_b64.take();
+                    to.cover_candidates_b64 = std::mem::take(&mut from.cover_candidates_b64);
+                }
+            }
+            MetadataField::Links => take_vec(&mut from.links, &mut to.links),
+            MetadataField::Identifiers => {
+                if !from.identifiers.is_empty() {
+                    to.identifiers = std::mem::take(&mut from.identifiers);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::async_trait;
+
+    use super::{MetadataField, ProviderTrust};
+    use crate::metadata::{
+        MetadataError, MetadataProvider, MetadataProviders, NullableBookDetails,
+    };
+
+    struct Fixed(fn() -> NullableBookDetails);
+
+    #[async_trait]
+    impl MetadataProvider for Fixed {
+        fn name(&self) -> &str {
+            "Fixed"
+        }
+
+        async fn fetch_metadata(
+            &self,
+            _isbn: &str,
+        ) -> Result<Option<NullableBookDetails>, MetadataError> {
+            Ok(Some((self.0)()))
+        }
+    }
+
+    #[tokio::test]
+    async fn trust() {
+        let mut providers = MetadataProviders::default();
+        providers.register(
+            "Library",
+            Box::new(Fixed(|| NullableBookDetails {
+                title: Some("Mort".into()),
+                tags: vec!["Fiction".into()],
+                page_count: Some(320),
+                ..Default::default()
+            })),
+        );
+        providers.register(
+            "Scanner",
+            Box::new(Fixed(|| NullableBookDetails {
+                title: Some("MORT".into()),
+                tags: vec!["Fantasy".into()],
+                page_count: Some(272),
+                ..Default::default()
+            })),
+        );
+
+        providers.trust.insert(
+            "Library".into(),
+            ProviderTrust {
+                ignore: vec![MetadataField::Tags],
+                prefer: vec![],
+            },
+        );
+        providers.trust.insert(
+            "Scanner".into(),
+            ProviderTrust {
+                ignore: vec![],
+                prefer: vec![MetadataField::PageCount, MetadataField::Summary],
+            },
+        );
+
+        let details = providers
+            .fetch_metadata(None, "9780552131063")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(details.title.as_deref(), Some("Mort"));
+        assert!(details.tags.is_empty());
+        assert_eq!(details.page_count, Some(272));
+        assert_eq!(details.summary, None);
+
+        let details = providers
+            .fetch_metadata(Some("Scanner"), "9780552131063")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(details.title.as_deref(), Some("MORT"));
+        assert_eq!(details.tags, ["Fantasy"]);
+    }
+}
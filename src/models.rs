@@ -1,4 +1,4 @@
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, Utc};
 use diesel::{
     backend::Backend,
     expression::AsExpression,
@@ -15,12 +15,35 @@ pub struct NewUser<'a> {
     pub name: &'a str,
 }
 
-#[derive(Queryable, Selectable)]
+#[derive(Queryable, Selectable, Clone)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct User {
     pub name: String,
     pub id: Uuid,
+    /// Identifiers of the pages that are not shown in the navigation bar
+    pub hidden_pages: Vec<String>,
+}
+
+/// Session opened by logging in with a password
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::session)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Session {
+    pub id: Uuid,
+    pub created: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::session)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewSession<'a> {
+    pub owner: Uuid,
+    pub ip: Option<String>,
+    pub user_agent: Option<&'a str>,
 }
 
 #[derive(Queryable, Selectable, Identifiable, PartialEq, Debug)]
@@ -95,6 +118,60 @@ pub struct BookTag {
     pub tag: i32,
 }
 
+#[derive(Insertable, Associations, Identifiable, Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::bookidentifier)]
+#[diesel(belongs_to(BookComplete, foreign_key = book))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(primary_key(book, scheme))]
+pub struct BookIdentifier {
+    pub book: Uuid,
+    pub scheme: String,
+    pub value: String,
+}
+
+/// Where the current value of a field of a book comes from, when it differs from the provider the
+/// book was added from
+#[derive(Insertable, Associations, Identifiable, Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::bookfieldsource)]
+#[diesel(belongs_to(BookComplete, foreign_key = book))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(primary_key(book, field))]
+pub struct BookFieldSource {
+    pub book: Uuid,
+    pub field: String,
+    /// `None` when the field was entered by hand
+    pub provider: Option<String>,
+    pub changed: DateTime<Utc>,
+}
+
+#[derive(Insertable, Associations, Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::booklink)]
+#[diesel(belongs_to(BookComplete, foreign_key = book))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct BookLink {
+    pub book: Uuid,
+    pub label: String,
+    pub url: String,
+}
+
+// Usually provided by `Identifiable`, but the serial id of the links is not needed here
+impl diesel::associations::HasTable for BookLink {
+    type Table = crate::schema::booklink::table;
+
+    fn table() -> Self::Table {
+        crate::schema::booklink::table
+    }
+}
+
+#[derive(Insertable, Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::tagimplication)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TagImplication {
+    pub owner: Uuid,
+    pub tag: i32,
+    pub implies: i32,
+}
+
 #[derive(Queryable, Selectable, Identifiable, Debug)]
 #[diesel(table_name = crate::schema::book)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -121,11 +198,20 @@ pub struct BookComplete {
     pub publisher: Option<String>,
     pub language: Option<String>,
     pub googleid: Option<String>,
+    pub goodreadsid: Option<String>,
     pub amazonid: Option<String>,
     pub librarythingid: Option<String>,
     pub pagecount: Option<i32>,
     pub owned: bool,
     pub read: bool,
+    pub reread: bool,
+    pub priority: Option<i32>,
+    pub location: Option<String>,
+    pub giveaway: bool,
+    pub summary_spoilers: bool,
+    /// Provider the details were fetched from when the book was added
+    pub metadata_source: Option<String>,
+    pub metadata_fetched: Option<DateTime<Utc>>,
 }
 
 #[derive(Insertable, Selectable, Queryable, Debug, AsChangeset)]
@@ -141,11 +227,16 @@ pub struct Book {
     pub publisher: Option<String>,
     pub language: Option<String>,
     pub googleid: Option<String>,
+    pub goodreadsid: Option<String>,
     pub amazonid: Option<String>,
     pub librarythingid: Option<String>,
     pub pagecount: Option<i32>,
     pub owned: bool,
     pub read: bool,
+    pub reread: bool,
+    pub priority: Option<i32>,
+    pub location: Option<String>,
+    pub summary_spoilers: bool,
 }
 
 #[derive(Queryable, Identifiable, Selectable, Debug)]
@@ -185,4 +276,31 @@ pub struct SeriesInfo {
     pub name: String,
     pub ongoing: bool,
     pub total_count: Option<i32>,
+    pub archived: bool,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::wish)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewWish {
+    pub owner: Uuid,
+    pub name: String,
+    pub isbn: Option<String>,
+}
+
+#[derive(Insertable, Selectable, Queryable, Debug)]
+#[diesel(table_name = crate::schema::wishauthor)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WishAuthor {
+    pub wish: Uuid,
+    pub author: i32,
+}
+
+#[derive(Insertable, Selectable, Queryable, Debug)]
+#[diesel(table_name = crate::schema::wishseries)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WishSeries {
+    pub wish: Uuid,
+    pub series: Uuid,
+    pub number: i32,
 }
@@ -0,0 +1,42 @@
+//! Hashing of the passwords of `[auth.local]` with Argon2id. The hashes are stored as PHC strings,
+//! which hold the salt and parameters along with the hash.
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+/// This is slow on purpose, it should not run on the async runtime
+pub fn hash(password: &str) -> String {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).expect("salt has a valid size");
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("default parameters are valid")
+        .to_string()
+}
+
+/// Does `password` match `hash`, this is as slow as [hash]
+pub fn verify(password: &str, hash: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn verify() {
+        let hash = super::hash("correct horse");
+        assert!(hash.starts_with("$argon2id$"));
+
+        assert!(super::verify("correct horse", &hash));
+        assert!(!super::verify("correct horse ", &hash));
+        assert!(!super::verify("correct horse", "plain"));
+        assert_ne!(super::hash("correct horse"), hash);
+    }
+}
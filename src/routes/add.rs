@@ -1,29 +1,43 @@
-use std::cmp::Ordering;
-
-use axum::extract::Query;
+use axum::{
+    extract::{Multipart, Query},
+    http::HeaderMap,
+};
+use base64::prelude::*;
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
 use maud::html;
 use uuid::Uuid;
 
 use crate::{
-    metadata::{fetch_metadata, MetadataProvider, NullableBookDetails},
+    metadata::{cache, covers::CoverQuery, parse_opf, MetadataError, NullableBookDetails},
     models::{BookAuthor, BookSeries, BookTag, Series, User},
     routes::components::book_form,
-    schema::{author, book, bookauthor, bookseries, booktag, series, tag},
+    schema::{
+        author, book, bookauthor, bookidentifier, booklink, bookseries, booktag, prefetchedwish,
+        series, tag, users,
+    },
+    AppState,
 };
 
-use super::{app_page, icons, BookInfo, Page, RouteError, State};
+use super::{
+    app_page, icons, nonce, redirect_back, referer_path, save_cover, wishlist::prefetched_wish,
+    BookInfo, Lenient, Page, RouteError, State,
+};
 
-pub(crate) async fn do_add_book(
-    state: State,
-    user: User,
-    data: BookInfo,
-) -> Result<axum::response::Redirect, RouteError> {
+/// Insert a new book owned by `user`, returning its id
+pub(super) async fn insert_book(
+    state: &AppState,
+    user: &User,
+    mut data: BookInfo,
+) -> Result<Uuid, RouteError> {
     let mut conn = state.db.get().await?;
 
     conn.transaction(|c| {
         async {
+            data.match_existing_names(c).await?;
+            data.apply_tag_implications(c).await?;
+
             diesel::insert_into(author::table)
                 .values(&data.authors)
                 .on_conflict_do_nothing()
@@ -36,12 +50,31 @@ pub(crate) async fn do_add_book(
                 .execute(c)
                 .await?;
 
+            let (source, fetched) = data.source.clone().unzip();
             let book_id: Uuid = diesel::insert_into(book::table)
-                .values(data.book)
+                .values((
+                    &data.book,
+                    book::metadata_source.eq(source),
+                    book::metadata_fetched.eq(fetched),
+                ))
                 .returning(book::id)
                 .get_result(c)
                 .await?;
 
+            diesel::insert_into(booklink::table)
+                .values(data.book_links(book_id))
+                .execute(c)
+                .await?;
+
+            diesel::insert_into(bookidentifier::table)
+                .values(data.book_identifiers(book_id))
+                .execute(c)
+                .await?;
+
+            diesel::delete(prefetchedwish::table.find((user.id, &data.book.isbn)))
+                .execute(c)
+                .await?;
+
             if let Some((name, volume)) = data.series {
                 let series = Series {
                     name: name.clone(),
@@ -117,90 +150,287 @@ pub(crate) async fn do_add_book(
             image_path.set_extension("jpg");
 
             if let Some(img) = data.image {
-                tokio::task::block_in_place(|| -> Result<_, RouteError> {
-                    img.save(image_path).map_err(RouteError::ImageSave)?;
-                    Ok(())
+                tokio::task::block_in_place(|| {
+                    save_cover(&state.config.images, &img, &image_path)
                 })?;
             }
 
-            Ok::<_, RouteError>(())
+            Ok::<_, RouteError>(book_id)
         }
         .scope_boxed()
     })
-    .await?;
+    .await
+}
 
-    Ok(axum::response::Redirect::to("/"))
+pub(crate) async fn do_add_book(
+    state: State,
+    user: User,
+    Lenient(mut data): Lenient,
+) -> Result<axum::response::Redirect, RouteError> {
+    let return_to = data.return_to.take();
+
+    insert_book(&state, &user, data).await?;
+
+    Ok(redirect_back(return_to.as_deref(), "/"))
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub(crate) struct IsbnRequest {
     isbn: Option<String>,
-    provider: Option<MetadataProvider>,
+    provider: Option<String>,
+    /// Result of a lookup made by the loading dialog
+    lookup: Option<Uuid>,
 }
 
-pub(crate) async fn add_book(
-    state: State,
-    user: User,
-    query: Query<IsbnRequest>,
-) -> Result<maud::Markup, RouteError> {
-    let has_provider = match &state.config.metadata.providers {
-        None => true,
-        Some(list) => !list.is_empty(),
-    };
+/// First provider in the order chosen by `user`, used when no provider is requested
+pub(super) async fn preferred_provider(
+    state: &AppState,
+    user: &User,
+) -> Result<Option<String>, RouteError> {
+    let mut conn = state.db.get().await?;
 
-    let providers = state
-        .config
+    let provider_order: Vec<String> = users::table
+        .find(user.id)
+        .select(users::provider_order)
+        .get_result(&mut conn)
+        .await?;
+
+    let provider = state
         .metadata
-        .providers
-        .as_deref()
-        .unwrap_or(MetadataProvider::all());
-
-    let default_provider = match providers.len().cmp(&1) {
-        Ordering::Equal => providers[0],
-        _ => state
-            .config
-            .metadata
-            .default_provider
-            .unwrap_or(MetadataProvider::Calibre),
+        .ordered(&provider_order)
+        .next()
+        .map(|(id, _)| id.to_string());
+
+    Ok(provider)
+}
+
+/// Fetch the details of a book, using the cover sources if the provider did not find a cover
+pub(super) async fn lookup_isbn(
+    state: &AppState,
+    provider: Option<&str>,
+    isbn: &str,
+) -> Result<Option<NullableBookDetails>, MetadataError> {
+    let Some(mut details) = cache::fetch_metadata(state, provider, isbn).await? else {
+        return Ok(None);
     };
 
-    enum SearchResult {
-        Found,
-        NotFound,
-        AlreadyExists,
+    if details.covert_art_b64.is_none() {
+        let query = CoverQuery {
+            isbn,
+            amazon_id: details.amazon_id.as_deref(),
+            google_id: details.google_id.as_deref(),
+        };
+
+        details.covert_art_b64 = state
+            .covers
+            .fetch_cover(&query)
+            .await
+            .map(|cover| BASE64_STANDARD.encode(cover));
     }
 
-    let (res, book_details) = match &query.isbn {
+    Ok(Some(details))
+}
+
+enum SearchResult {
+    Found,
+    NotFound,
+    AlreadyExists,
+    Failed {
+        isbn: String,
+        provider: String,
+        reason: String,
+    },
+    InvalidOpf,
+}
+
+/// Result of looking an ISBN up for the add page
+pub(crate) struct Lookup {
+    res: SearchResult,
+    details: NullableBookDetails,
+    source: Option<(String, DateTime<Utc>)>,
+    looked_up: Option<(String, String)>,
+}
+
+async fn lookup(state: &AppState, user: &User, query: &IsbnRequest) -> Result<Lookup, RouteError> {
+    let has_provider = !state.metadata.is_empty();
+    let default_provider = preferred_provider(state, user).await?;
+
+    let (res, details, source) = match &query.isbn {
         Some(isbn) if has_provider => {
             let isbn = isbn.replace('-', "");
 
             let mut conn = state.db.get().await?;
-
             let found: i64 = book::table
                 .filter(book::owner.eq(user.id).and(book::isbn.eq(&isbn)))
                 .count()
                 .get_result(&mut conn)
                 .await?;
+            drop(conn);
 
             if found == 0 {
-                fetch_metadata(
-                    &state.config,
-                    &isbn,
-                    query.provider.unwrap_or(default_provider),
-                )
-                .await?
-                .map(|v| (SearchResult::Found, v))
-                .unwrap_or_else(|| (SearchResult::NotFound, Default::default()))
+                let provider = state
+                    .metadata
+                    .resolve(query.provider.as_deref().or(default_provider.as_deref()))?;
+
+                // Wished books downloaded for offline use don't need the provider
+                let prefetched = prefetched_wish(state, user, &isbn)
+                    .await?
+                    .filter(|(_, prefetched_by, _)| prefetched_by == provider);
+
+                match prefetched {
+                    Some((details, provider, fetched)) => {
+                        (SearchResult::Found, details, Some((provider, fetched)))
+                    }
+                    None => match lookup_isbn(state, Some(provider), &isbn).await {
+                        Ok(None) => (SearchResult::NotFound, Default::default(), None),
+                        Ok(Some(details)) => (
+                            SearchResult::Found,
+                            details,
+                            Some((provider.to_string(), chrono::Utc::now())),
+                        ),
+                        Err(e) => {
+                            tracing::warn!("Provider {provider} failed for '{isbn}': {e:?}");
+                            let failed = SearchResult::Failed {
+                                reason: e.reason(),
+                                provider: provider.to_string(),
+                                isbn,
+                            };
+                            (failed, Default::default(), None)
+                        }
+                    },
+                }
             } else {
-                (SearchResult::AlreadyExists, Default::default())
+                (SearchResult::AlreadyExists, Default::default(), None)
             }
         }
-        _ => (SearchResult::Found, (NullableBookDetails::default())),
+        _ => (SearchResult::Found, NullableBookDetails::default(), None),
+    };
+
+    let looked_up = query
+        .isbn
+        .clone()
+        .zip(source.as_ref().map(|(p, _)| p.clone()));
+
+    Ok(Lookup {
+        res,
+        details,
+        source,
+        looked_up,
+    })
+}
+
+pub(crate) async fn add_book(
+    state: State,
+    user: User,
+    headers: HeaderMap,
+    query: Query<IsbnRequest>,
+) -> Result<maud::Markup, RouteError> {
+    let stashed = query.lookup.and_then(|id| state.lookups.take(user.id, id));
+    let lookup = match stashed {
+        Some(lookup) => lookup,
+        None => lookup(&state, &user, &query).await?,
+    };
+
+    add_page(
+        &state,
+        &user,
+        lookup.res,
+        lookup.details,
+        lookup.source,
+        lookup.looked_up,
+        referer_path(&headers, "/add"),
+    )
+    .await
+}
+
+/// Look an ISBN up for the loading dialog of the add page, which is then redirected to the page
+/// showing the result. Aborting the request stops the lookup.
+pub(crate) async fn do_lookup(
+    state: State,
+    user: User,
+    Query(query): Query<IsbnRequest>,
+) -> Result<[(&'static str, String); 1], RouteError> {
+    let lookup = lookup(&state, &user, &query).await?;
+    let id = state.lookups.insert(user.id, lookup);
+
+    // The ISBN is kept in the URL so that reloading the page looks it up again
+    let mut url = reqwest::Url::parse("http://localhost/add").expect("URL is valid");
+    {
+        let mut pairs = url.query_pairs_mut();
+        if let Some(isbn) = &query.isbn {
+            pairs.append_pair("isbn", isbn);
+        }
+        if let Some(provider) = &query.provider {
+            pairs.append_pair("provider", provider);
+        }
+        pairs.append_pair("lookup", &id.to_string());
+    }
+
+    Ok([(
+        "HX-Redirect",
+        format!("/add?{}", url.query().unwrap_or_default()),
+    )])
+}
+
+/// Pre-fill the add form from a Calibre `.opf` file, with the cover exported next to it
+pub(crate) async fn add_from_opf(
+    state: State,
+    user: User,
+    mut multipart: Multipart,
+) -> Result<maud::Markup, RouteError> {
+    let mut opf = None;
+    let mut cover = Vec::new();
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("opf") => opf = Some(field.bytes().await?),
+            Some("cover") => cover = field.bytes().await?.to_vec(),
+            name => tracing::warn!("Unknown field {name:?}"),
+        }
+    }
+
+    let opf = opf.ok_or(RouteError::MissingField)?;
+
+    let (res, details) = match parse_opf(&String::from_utf8_lossy(&opf), &cover) {
+        Ok(Some(details)) => (SearchResult::Found, details),
+        Ok(None) => (SearchResult::InvalidOpf, Default::default()),
+        Err(e) => {
+            tracing::warn!("Invalid OPF file: {e:?}");
+            (SearchResult::InvalidOpf, Default::default())
+        }
     };
 
+    add_page(&state, &user, res, details, None, None, None).await
+}
+
+async fn add_page(
+    state: &State,
+    user: &User,
+    res: SearchResult,
+    book_details: NullableBookDetails,
+    source: Option<(String, DateTime<Utc>)>,
+    looked_up: Option<(String, String)>,
+    return_to: Option<String>,
+) -> Result<maud::Markup, RouteError> {
+    let has_provider = !state.metadata.is_empty();
+
+    let mut conn = state.db.get().await?;
+    let provider_order: Vec<String> = users::table
+        .find(user.id)
+        .select(users::provider_order)
+        .get_result(&mut conn)
+        .await?;
+    drop(conn);
+
+    let default_provider = state
+        .metadata
+        .ordered(&provider_order)
+        .next()
+        .map(|(id, _)| id);
+
     Ok(app_page(
         Page::AddBook,
-        &user,
+        user,
         html! {
             #isbnModal .modal.fade tabindex="-1" aria-labelledby="isbnModalLabel" aria-hidden="true" {
                 .modal-dialog.modal-dialog-centered { .modal-content {
@@ -209,7 +439,8 @@ pub(crate) async fn add_book(
                         button type="button" .btn-close data-bs-dismiss="modal" aria-label="Cancel" {}
                     }
                     .modal-body {
-                        form #isbnModalForm {
+                        form #isbnModalForm hx-get="/add/lookup" hx-swap="none"
+                            hx-disabled-elt="#isbnLoad" {
                             .form-floating {
                                 input name="isbn"
                                         type="text"
@@ -219,18 +450,32 @@ pub(crate) async fn add_book(
                                 label for="isbnSearch" { "ISBN" }
                             }
                         }
+                        #isbnLoading .d-none role="status" {
+                            .d-flex.align-items-center.mt-3 {
+                                .spinner-border.spinner-border-sm."me-2" aria-hidden="true" {}
+                                span #isbnLoadingText .me-auto { "Looking the ISBN up..." }
+                                button type="button" #isbnCancel .btn.btn-sm.btn-outline-secondary {
+                                    "Cancel lookup"
+                                }
+                            }
+                        }
                     }
                     .modal-footer {
                         button type="button" .btn.btn-secondary data-bs-dismiss="modal" { "Cancel" }
-                        button type="submit" form="isbnModalForm" .btn.btn-primary { "Load" }
+                        button type="submit" form="isbnModalForm" #isbnLoad .btn.btn-primary {
+                            "Load"
+                        }
                     }
                 }  }
             }
+            script nonce=[nonce()] {
+                (maud::PreEscaped(include_str!("./isbn_lookup.js")))
+            }
 
             #scanModal .modal.fade tabindex="-1" aria-labelledby="scanModalLabel" aria-hidden="true" {
                 .modal-dialog.modal-dialog-centered { .modal-content {
                     .modal-header {
-                        h1 .modal-title."fs-5" #scanModalLabel {"Load a book from an ISBN barcode"}
+                        h1 .modal-title."fs-5" #scanModalLabel {"Load a book from an ISBN barcode, or open a labeled book"}
                         button type="button" .btn-close data-bs-dismiss="modal" aria-label="Cancel" {}
                     }
                     .modal-body {
@@ -254,31 +499,55 @@ pub(crate) async fn add_book(
                         "The requested ISBN is already in the database"
                     }
                 },
+                SearchResult::InvalidOpf => {
+                    .alert.alert-warning role="alert" {
+                        "The OPF file could not be read"
+                    }
+                },
+                SearchResult::Failed { isbn, provider, reason } => {
+                    .alert.alert-danger role="alert" {
+                        @let name = state.metadata.get(&provider).map_or("", |p| p.name());
+                        (format!("{name} could not look the ISBN up: {reason}"))
+                        form .mt-2 method="GET" action="/add" {
+                            input type="hidden" name="isbn" value=(isbn);
+                            @for (id, other) in state.metadata.ordered(&provider_order) {
+                                button type="submit" name="provider" value=(id)
+                                    .btn.btn-sm.btn-outline-danger."me-2" {
+                                    @if id == provider {
+                                        "Retry"
+                                    } @else {
+                                        "Retry with " (other.name())
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
             }
 
             .d-flex.flex-column {
                 @if has_provider {
-                    @if providers.len() > 1 {
-                        .container {
+                    @if state.metadata.len() > 1 {
+                        .container.js-only {
                             ul .list-group."mb-2" {
                                 li .list-group-item {
                                     "Metadata provider"
                                 }
-                                @for &provider in providers {
+                                @for (id, provider) in state.metadata.ordered(&provider_order) {
                                     li .list-group-item {
-                                        @let id = format!("{provider}Radio");
-                                        input .form-check-input."me-1" type="radio" #(id)
-                                              name="provider" value=(provider.serialized())
-                                              form="isbnModalForm" checked[provider == default_provider];
-                                        label .form-check-label for=(id) {
-                                            (provider.to_string())
+                                        @let radio_id = format!("{id}Radio");
+                                        input .form-check-input."me-1" type="radio" #(radio_id)
+                                              name="provider" value=(id)
+                                              form="isbnModalForm" checked[Some(id) == default_provider];
+                                        label .form-check-label for=(radio_id) {
+                                            (provider.name())
                                         }
                                     }
                                 }
                             }
                         }
                     }
-                    .d-flex.justify-content-center {
+                    .d-flex.justify-content-center.js-only {
                         button .btn.btn-primary.me-2 data-bs-toggle="modal" data-bs-target="#isbnModal" {
                             (icons::bi_123()) "Load from ISBN"
                         }
@@ -286,13 +555,437 @@ pub(crate) async fn add_book(
                             (icons::bi_upc_scan()) "Scan ISBN"
                         }
                     }
+                    form .container.no-js method="GET" action="/add" {
+                        .input-group {
+                            input .form-control name="isbn" type="text" placeholder="ISBN"
+                                aria-label="ISBN" required;
+                            @if state.metadata.len() > 1 {
+                                select .form-select name="provider" aria-label="Metadata provider" {
+                                    @for (id, provider) in state.metadata.ordered(&provider_order) {
+                                        option value=(id) selected[Some(id) == default_provider] {
+                                            (provider.name())
+                                        }
+                                    }
+                                }
+                            }
+                            input type="submit" .btn.btn-primary value="Load from ISBN";
+                        }
+                    }
+                }
+                @if let Some((isbn, looked_up)) = &looked_up {
+                    @if state.metadata.len() > 1 {
+                        .container.js-only."mb-2" {
+                            @for (id, provider) in state.metadata.ordered(&provider_order) {
+                                @if id != looked_up {
+                                    @let vals = serde_json::json!({"isbn": isbn, "provider": id});
+                                    button type="button" .btn.btn-sm.btn-outline-secondary."me-2"
+                                        hx-get="/add/fill" hx-target="#missingFields"
+                                        hx-vals=(vals) {
+                                        "Fill missing fields from " (provider.name())
+                                    }
+                                }
+                            }
+                            #missingFields .mt-2 {}
+                        }
+                        script nonce=[nonce()] {
+                            (maud::PreEscaped(include_str!("./fill_missing.js")))
+                        }
+                    }
+                }
+                details .container."mb-2" {
+                    summary { "Load from a Calibre OPF file" }
+                    form method="POST" action="/add/opf" enctype="multipart/form-data" .mt-2 {
+                        .mb-2 {
+                            label .form-label for="opfInput" { "Metadata (.opf)" }
+                            input .form-control #opfInput type="file" name="opf" accept=".opf"
+                                required;
+                        }
+                        .mb-2 {
+                            label .form-label for="opfCoverInput" { "Cover (optional)" }
+                            input .form-control #opfCoverInput type="file" name="cover"
+                                accept="image/*";
+                        }
+                        input type="submit" .btn.btn-primary value="Load from OPF";
+                    }
                 }
-                (book_form(&state, &user, book_details, "Add Book").await?)
+                (book_form(state, book_details, source, "/add", "Add Book", return_to).await?)
             }
 
-            script {
+            script nonce=[nonce()] {
                 (maud::PreEscaped(include_str!("./barcode.js")))
             }
         },
     ))
 }
+
+/// Values of the fields known by `details`, named like the inputs of the book form
+fn known_fields(details: NullableBookDetails) -> Vec<(&'static str, String)> {
+    let (series_name, series_volume) = details.series.unzip();
+
+    [
+        ("title", details.title),
+        ("summary", details.summary),
+        (
+            "published",
+            details.published.map(|d| d.format("%Y-%m-%d").to_string()),
+        ),
+        ("publisher", details.publisher),
+        ("language", details.language),
+        ("google_id", details.google_id),
+        ("goodreads_id", details.goodreads_id),
+        ("amazon_id", details.amazon_id),
+        ("librarything_id", details.librarything_id),
+        ("page_count", details.page_count.map(|p| p.to_string())),
+        ("series_name", series_name),
+        ("series_volume", series_volume.map(|v| v.to_string())),
+        ("fetched_cover", details.covert_art_b64),
+    ]
+    .into_iter()
+    .filter_map(|(field, value)| Some((field, value?)))
+    .chain(details.authors.into_iter().map(|a| ("author", a)))
+    .chain(details.tags.into_iter().map(|t| ("tag", t)))
+    .collect()
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct FillRequest {
+    isbn: String,
+    provider: String,
+}
+
+/// Fragment with the details found by a second provider, copied by the add page into the fields
+/// that are still empty
+pub(crate) async fn fill_missing(
+    state: State,
+    _user: User,
+    Query(query): Query<FillRequest>,
+) -> Result<maud::Markup, RouteError> {
+    let provider = state.metadata.resolve(Some(&query.provider))?;
+    let name = state.metadata.get(provider).map_or("", |p| p.name());
+    let isbn = query.isbn.replace('-', "");
+
+    Ok(match lookup_isbn(&state, Some(provider), &isbn).await {
+        Ok(Some(details)) => html! {
+            .text-success.small { "Filled the missing fields from " (name) }
+            @for (field, value) in known_fields(details) {
+                data data-field=(field) value=(value) {}
+            }
+        },
+        Ok(None) => html! {
+            .text-warning.small { (name) " did not find this ISBN" }
+        },
+        Err(e) => {
+            tracing::warn!("Provider {provider} failed for '{isbn}': {e:?}");
+            let reason = e.reason();
+            html! {
+                .text-danger.small { (format!("{name} could not look the ISBN up: {reason}")) }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::{author, book, bookauthor, bookseries, metadata_cache, series},
+        testing::{
+            body_text, book_form, book_id, location, test_cover, MultipartForm, TestApp, OTHER_USER,
+        },
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn add_book() {
+        let app = TestApp::new().await;
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Guards! Guards!", "9780552134637")
+                    .text("tag", "Fantasy")
+                    .text("series_name", "Discworld")
+                    .text("series_volume", "8")
+                    .text("read_box", "on"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&response), "/");
+
+        let id = book_id(&app, "9780552134637").await;
+        let mut conn = app.state.db.get().await.unwrap();
+
+        let (title, read, owned): (String, bool, bool) = book::table
+            .find(id)
+            .select((book::title, book::read, book::owned))
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(title, "Guards! Guards!");
+        assert!(read);
+        assert!(!owned);
+
+        let authors: Vec<String> = bookauthor::table
+            .filter(bookauthor::book.eq(id))
+            .inner_join(author::table)
+            .select(author::name)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(authors, ["Terry Pratchett"]);
+
+        let (series, volume): (String, i32) = bookseries::table
+            .find(id)
+            .inner_join(series::table)
+            .select((series::name, bookseries::number))
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(series, "Discworld");
+        assert_eq!(volume, 8);
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("Guards! Guards!"));
+        assert!(page.contains("Terry Pratchett"));
+        assert!(page.contains("Fantasy"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn add_book_missing_fields() {
+        let app = TestApp::new().await;
+
+        let response = app
+            .post_multipart("/add", MultipartForm::new().text("isbn", "9780552134637"))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Guards! Guards!", "9780552134637").text("series_name", "Discworld"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn oversized_cover() {
+        let app = TestApp::new().await;
+
+        // Header of a 24 bits BMP image of 20000x20000 pixels, without its pixels
+        let mut bomb = b"BM".to_vec();
+        for value in [0u32, 0, 54, 40, 20_000, 20_000] {
+            bomb.extend(value.to_le_bytes());
+        }
+        bomb.extend(1u16.to_le_bytes());
+        bomb.extend(24u16.to_le_bytes());
+        bomb.extend([0; 24]);
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Mort", "9780552131063").file("user_cover", "cover.bmp", bomb),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let books = body_text(app.get("/").await).await;
+        assert!(!books.contains("Mort"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cached_metadata() {
+        let app = TestApp::new().await;
+
+        app.get("/add?isbn=9780552134637").await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let cached: Vec<(String, String)> = metadata_cache::table
+            .select((metadata_cache::provider, metadata_cache::isbn))
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(cached, [("Mock".into(), "9780552134637".into())]);
+
+        // Cached responses are used instead of querying the provider
+        diesel::update(metadata_cache::table)
+            .set(metadata_cache::details.eq(r#"{"title": "Cached Guards"}"#))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let page = body_text(app.get("/add?isbn=9780552134637").await).await;
+        assert!(page.contains("Cached Guards"));
+
+        diesel::update(metadata_cache::table)
+            .set(metadata_cache::details.eq(None::<String>))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let page = body_text(app.get("/add?isbn=9780552134637").await).await;
+        assert!(page.contains("The requested ISBN was not found"));
+
+        // Expired responses are fetched again
+        diesel::update(metadata_cache::table)
+            .set(metadata_cache::fetched.eq(chrono::Utc::now() - chrono::Duration::days(30)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let page = body_text(app.get("/add?isbn=9780552134637").await).await;
+        assert!(page.contains("Guards! Guards!"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn add_from_metadata() {
+        let app = TestApp::new().await;
+
+        let page = body_text(app.get("/add?isbn=978-0-552-13463-7").await).await;
+        assert!(page.contains("Guards! Guards!"));
+        assert!(page.contains("Discworld"));
+        assert!(!page.contains("The requested ISBN was not found"));
+
+        let page = body_text(app.get("/add?isbn=9780000000000").await).await;
+        assert!(page.contains("The requested ISBN was not found"));
+
+        let response = app.get("/add?isbn=9780552134637&provider=Unknown").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        app.post_multipart("/add", book_form("Guards! Guards!", "9780552134637"))
+            .await;
+
+        let page = body_text(app.get("/add?isbn=9780552134637").await).await;
+        assert!(page.contains("The requested ISBN is already in the database"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fill_missing_fields() {
+        let app = TestApp::new().await;
+
+        let fragment = body_text(
+            app.get("/add/fill?isbn=978-0-552-13106-3&provider=Mock")
+                .await,
+        )
+        .await;
+        assert!(fragment.contains("Filled the missing fields from"));
+        assert!(fragment.contains(r#"<data data-field="title" value="Mort">"#));
+        assert!(fragment.contains(r#"<data data-field="series_volume" value="4">"#));
+        assert!(fragment.contains(r#"<data data-field="author" value="Terry Pratchett">"#));
+
+        let fragment = body_text(app.get("/add/fill?isbn=9780000000002&provider=Mock").await).await;
+        assert!(fragment.contains("did not find this ISBN"));
+        assert!(!fragment.contains("data-field"));
+
+        let response = app
+            .get("/add/fill?isbn=9780552131063&provider=Unknown")
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn add_from_opf() {
+        let app = TestApp::new().await;
+
+        let opf = include_bytes!("../../tests/mort.opf").to_vec();
+        let response = app
+            .post_multipart(
+                "/add/opf",
+                MultipartForm::new().file("opf", "metadata.opf", opf),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = body_text(response).await;
+        assert!(page.contains(r#"value="Mort""#));
+        assert!(page.contains(r#"value="1129""#));
+        assert!(page.contains(r#"value="Discworld""#));
+        assert!(page.contains(r#"action="/add""#));
+
+        let response = app
+            .post_multipart(
+                "/add/opf",
+                MultipartForm::new().file("opf", "metadata.opf", b"not xml".to_vec()),
+            )
+            .await;
+        assert!(body_text(response)
+            .await
+            .contains("The OPF file could not be read"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn isbn_lookup() {
+        let app = TestApp::new().await;
+
+        let response = app
+            .get("/add/lookup?isbn=978-0-552-13106-3&provider=Mock")
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let redirect = response.headers()["HX-Redirect"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(redirect.starts_with("/add?isbn=978-0-552-13106-3&provider=Mock&lookup="));
+
+        // Other users do not get the lookup, and look the ISBN up themselves
+        let page = body_text(app.get_as(OTHER_USER, &redirect).await).await;
+        assert!(page.contains("Mort"));
+        let page = body_text(app.get(&redirect).await).await;
+        assert!(page.contains("Mort"));
+        assert!(page.contains("Terry Pratchett"));
+
+        // Once taken, reloading the page looks the ISBN up again
+        let page = body_text(app.get(&redirect).await).await;
+        assert!(page.contains("Mort"));
+
+        let response = app.get("/add/lookup?isbn=9780000000002").await;
+        let page = body_text(
+            app.get(response.headers()["HX-Redirect"].to_str().unwrap())
+                .await,
+        )
+        .await;
+        assert!(page.contains("The requested ISBN was not found"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn required_fields() {
+        let app = TestApp::with_config(
+            r#"
+            [form]
+            required = ["publisher", "cover"]
+            "#,
+        )
+        .await;
+
+        let page = body_text(app.get("/add").await).await;
+        assert!(page.contains(r#"placeholder="Publisher" required"#));
+        assert!(!page.contains(r#"placeholder="Language" required"#));
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Mort", "9780552131063").file("user_cover", "cover.png", test_cover()),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Mort", "9780552131063").text("publisher", "Corgi"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Mort", "9780552131063")
+                    .text("publisher", "Corgi")
+                    .file("user_cover", "cover.png", test_cover()),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+}
@@ -0,0 +1,84 @@
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Redirect, Response},
+};
+use maud::html;
+
+use crate::{fetch, metadata::isbn::find_isbn, models::User, State};
+
+use super::{app_page, Page, RouteError};
+
+#[derive(serde::Deserialize)]
+pub(crate) struct UrlRequest {
+    url: String,
+}
+
+/// Find the ISBN of the book described at an URL (from a retailer or a publisher), and continue
+/// with the usual ISBN flow of `/add`. Only public addresses can be fetched.
+pub(crate) async fn add_from_url(
+    state: State,
+    user: User,
+    Query(query): Query<UrlRequest>,
+) -> Result<Response, RouteError> {
+    let url = reqwest::Url::parse(&query.url).map_err(|_| RouteError::InvalidForm)?;
+    if !fetch::allowed(&url) {
+        return Err(RouteError::InvalidForm);
+    }
+
+    // Retailers often use the ISBN-10 as a product identifier
+    let isbn = match find_isbn(url.path(), true) {
+        Some(isbn) => Some(isbn),
+        None => {
+            let page = state.pages.fetch(url.clone()).await?;
+            find_isbn(&page, false)
+        }
+    };
+
+    match isbn {
+        Some(isbn) => Ok(Redirect::to(&format!("/add?isbn={isbn}")).into_response()),
+        None => Ok(app_page(
+            Page::AddBook,
+            &user,
+            html! {
+                .container.text-center {
+                    .alert.alert-warning role="alert" {
+                        "No ISBN found on " a href=(url) { (url) }
+                    }
+                    a .btn.btn-primary href="/add" { "Add a book manually" }
+                }
+            },
+        )
+        .into_response()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+
+    use crate::testing::{location, TestApp};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn add_from_url() {
+        let app = TestApp::new().await;
+
+        let response = app
+            .get("/add/url?url=https%3A%2F%2Fwww.amazon.fr%2Fdp%2F2070584623%2Fref%3Dsr_1_1")
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&response), "/add?isbn=9782070584628");
+
+        let response = app.get("/add/url?url=file%3A%2F%2F%2Fetc%2Fpasswd").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // The local network can't be reached
+        let response = app
+            .get("/add/url?url=http%3A%2F%2F127.0.0.1%3A1%2Fbook")
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = app
+            .get("/add/url?url=http%3A%2F%2Flocalhost%3A1%2Fbook")
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -0,0 +1,512 @@
+//! JSON endpoints, for external tools such as browser extensions
+
+use crate::{
+    metadata::NullableBookDetails,
+    models::{BookTag, TagName, User},
+    schema::{book, booktag, tag},
+    State,
+};
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Json,
+};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use uuid::Uuid;
+
+use super::{
+    add::{insert_book, lookup_isbn, preferred_provider},
+    edit::{book_details, edit_book_details},
+    export::export_books,
+    giveaway::delete_books,
+    name_key,
+    refresh::{record_field_sources, RefreshField},
+    tag_implications::{implication_rules, imply_tags},
+    BookInfo, RouteError,
+};
+
+#[derive(serde::Deserialize)]
+pub(crate) struct MetadataRequest {
+    isbn: String,
+    provider: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ProviderRequest {
+    provider: Option<String>,
+}
+
+/// Lookup an ISBN with the configured providers, using the preferred provider of the user by
+/// default
+async fn lookup_metadata(
+    state: &State,
+    user: &User,
+    isbn: &str,
+    provider: Option<String>,
+) -> Result<Json<NullableBookDetails>, RouteError> {
+    let provider = match provider {
+        Some(p) => Some(p),
+        None => preferred_provider(state, user).await?,
+    };
+
+    let isbn = isbn.replace('-', "");
+
+    lookup_isbn(state, provider.as_deref(), &isbn)
+        .await?
+        .map(Json)
+        .ok_or(RouteError::NotFound)
+}
+
+pub(crate) async fn api_metadata(
+    state: State,
+    user: User,
+    Query(query): Query<MetadataRequest>,
+) -> Result<Json<NullableBookDetails>, RouteError> {
+    lookup_metadata(&state, &user, &query.isbn, query.provider).await
+}
+
+pub(crate) async fn api_metadata_isbn(
+    state: State,
+    user: User,
+    isbn: Path<String>,
+    Query(query): Query<ProviderRequest>,
+) -> Result<Json<NullableBookDetails>, RouteError> {
+    lookup_metadata(&state, &user, &isbn, query.provider).await
+}
+
+/// Book as exchanged by the API, the cover is only read when creating or updating a book
+#[derive(serde::Serialize)]
+pub(crate) struct ApiBook {
+    id: Uuid,
+    #[serde(flatten)]
+    details: NullableBookDetails,
+}
+
+async fn api_book_response(state: &State, user: &User, id: Uuid) -> Result<ApiBook, RouteError> {
+    Ok(ApiBook {
+        id,
+        details: book_details(state, user, id).await?,
+    })
+}
+
+pub(crate) async fn api_books(state: State, user: User) -> Result<Json<Vec<ApiBook>>, RouteError> {
+    Ok(Json(
+        export_books(&state, &user)
+            .await?
+            .into_iter()
+            .map(|(id, details)| ApiBook { id, details })
+            .collect(),
+    ))
+}
+
+pub(crate) async fn api_book(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+) -> Result<Json<ApiBook>, RouteError> {
+    Ok(Json(api_book_response(&state, &user, *id).await?))
+}
+
+pub(crate) async fn api_create_book(
+    state: State,
+    user: User,
+    Json(details): Json<NullableBookDetails>,
+) -> Result<(StatusCode, Json<ApiBook>), RouteError> {
+    let id = insert_book(&state, &user, BookInfo::from_details(&user, details)?).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(api_book_response(&state, &user, id).await?),
+    ))
+}
+
+/// Replace all the details of a book, keeping its cover unless a new one is sent
+pub(crate) async fn api_update_book(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+    Json(details): Json<NullableBookDetails>,
+) -> Result<Json<ApiBook>, RouteError> {
+    let info = BookInfo::from_details(&user, details)?;
+    edit_book_details(&state, &user, *id, info).await?;
+
+    Ok(Json(api_book_response(&state, &user, *id).await?))
+}
+
+pub(crate) async fn api_delete_book(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+) -> Result<StatusCode, RouteError> {
+    // Fails if the book does not belong to the user
+    book_details(&state, &user, *id).await?;
+
+    let mut conn = state.db.get().await?;
+    delete_books(&mut conn, &state, &user, &[*id]).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Changes applied to all the `ids` books, the flags are kept when they are not given
+#[derive(serde::Deserialize)]
+pub(crate) struct BatchUpdate {
+    ids: Vec<Uuid>,
+    read: Option<bool>,
+    owned: Option<bool>,
+    #[serde(default)]
+    add_tags: Vec<String>,
+    #[serde(default)]
+    remove_tags: Vec<String>,
+}
+
+/// Tag named `name`, or else the first one only differing by case or accents
+async fn find_tag(
+    conn: &mut diesel_async::AsyncPgConnection,
+    name: &str,
+) -> Result<Option<(i32, String)>, diesel::result::Error> {
+    let exact = tag::table
+        .filter(tag::name.eq(name))
+        .select((tag::id, tag::name))
+        .first(conn)
+        .await
+        .optional()?;
+    if exact.is_some() {
+        return Ok(exact);
+    }
+
+    tag::table
+        .filter(name_key(tag::name).eq(name_key(name)))
+        .select((tag::id, tag::name))
+        .order(tag::id)
+        .first(conn)
+        .await
+        .optional()
+}
+
+/// Ids of the tags named like `names`, each name matching a single tag
+async fn tag_ids(
+    conn: &mut diesel_async::AsyncPgConnection,
+    names: &[String],
+) -> Result<Vec<i32>, diesel::result::Error> {
+    let mut ids = Vec::new();
+
+    for name in names {
+        if let Some((id, _)) = find_tag(conn, name).await? {
+            ids.push(id);
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Update the flags and tags of several books at once. Nothing is changed if one of the books
+/// does not belong to the user.
+pub(crate) async fn api_update_books(
+    state: State,
+    user: User,
+    Json(mut update): Json<BatchUpdate>,
+) -> Result<StatusCode, RouteError> {
+    update.ids.sort();
+    update.ids.dedup();
+
+    let mut conn = state.db.get().await?;
+
+    conn.transaction(|c| {
+        async {
+            let found: i64 = book::table
+                .filter(book::owner.eq(user.id))
+                .filter(book::id.eq_any(&update.ids))
+                .count()
+                .get_result(c)
+                .await?;
+            if found as usize != update.ids.len() {
+                return Err(RouteError::NotFound);
+            }
+
+            if let Some(read) = update.read {
+                diesel::update(book::table)
+                    .filter(book::id.eq_any(&update.ids))
+                    .set(book::read.eq(read))
+                    .execute(c)
+                    .await?;
+            }
+
+            if let Some(owned) = update.owned {
+                diesel::update(book::table)
+                    .filter(book::id.eq_any(&update.ids))
+                    .set(book::owned.eq(owned))
+                    .execute(c)
+                    .await?;
+            }
+
+            let removed = tag_ids(c, &update.remove_tags).await?;
+            diesel::delete(booktag::table)
+                .filter(booktag::book.eq_any(&update.ids))
+                .filter(booktag::tag.eq_any(&removed))
+                .execute(c)
+                .await?;
+
+            // Existing tags are reused when they only differ by case or accents
+            let mut added = Vec::new();
+            for name in &update.add_tags {
+                let name = name.trim();
+                let existing = find_tag(c, name).await?.map(|(_, name)| name);
+
+                added.push(TagName {
+                    name: existing.unwrap_or_else(|| name.to_string()),
+                });
+            }
+            imply_tags(&implication_rules(c, user.id).await?, &mut added);
+
+            diesel::insert_into(tag::table)
+                .values(&added)
+                .on_conflict_do_nothing()
+                .execute(c)
+                .await?;
+
+            let names: Vec<_> = added.into_iter().map(|t| t.name).collect();
+            let mut added_ids = tag_ids(c, &names).await?;
+            added_ids.sort();
+            added_ids.dedup();
+
+            let book_tags: Vec<_> = update
+                .ids
+                .iter()
+                .flat_map(|&book| added_ids.iter().map(move |&tag| BookTag { book, tag }))
+                .collect();
+            diesel::insert_into(booktag::table)
+                .values(&book_tags)
+                .on_conflict_do_nothing()
+                .execute(c)
+                .await?;
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    if !update.add_tags.is_empty() || !update.remove_tags.is_empty() {
+        for &id in &update.ids {
+            record_field_sources(&state, id, &[RefreshField::Tags], None).await?;
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{Method, StatusCode};
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::{booktag, tag},
+        testing::{body_text, TestApp, OTHER_USER},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn api_metadata() {
+        let app = TestApp::new().await;
+
+        let response = app.get("/api/v1/metadata?isbn=978-0-552-13463-7").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let details: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(details["title"], "Guards! Guards!");
+        assert_eq!(details["series"], serde_json::json!(["Discworld", 8]));
+        assert_eq!(details["identifiers"]["oclc"], "22596384");
+
+        let response = app.get("/api/v1/metadata?isbn=9780000000002").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .get("/api/v1/metadata?isbn=9780552134637&provider=Unknown")
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .get("/api/v1/metadata/978-0-552-13106-3?provider=Mock")
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let details: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(details["title"], "Mort");
+
+        let response = app.get("/api/v1/metadata/9780000000002").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn api_books() {
+        let app = TestApp::new().await;
+
+        let book = serde_json::json!({
+            "isbn": "9780552131063",
+            "title": "Mort",
+            "authors": ["Terry Pratchett"],
+            "series": ["Discworld", 4],
+        });
+        let response = app.send_json(Method::POST, "/api/v1/books", book).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let created: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(created["title"], "Mort");
+        assert_eq!(created["read"], false);
+        let id = created["id"].as_str().unwrap().to_string();
+
+        let response = app
+            .send_json(
+                Method::POST,
+                "/api/v1/books",
+                serde_json::json!({"title": "Eric"}),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let books: serde_json::Value =
+            serde_json::from_str(&body_text(app.get("/api/v1/books").await).await).unwrap();
+        assert_eq!(books.as_array().unwrap().len(), 1);
+        assert_eq!(books[0]["id"], id.as_str());
+        assert_eq!(books[0]["series"], serde_json::json!(["Discworld", 4]));
+
+        let update = serde_json::json!({
+            "isbn": "9780552131063",
+            "title": "Mort",
+            "authors": ["Terry Pratchett"],
+            "tags": ["Fantasy"],
+            "read": true,
+        });
+        let response = app
+            .send_json(Method::PUT, &format!("/api/v1/books/{id}"), update)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let book: serde_json::Value =
+            serde_json::from_str(&body_text(app.get(&format!("/api/v1/books/{id}")).await).await)
+                .unwrap();
+        assert_eq!(book["read"], true);
+        assert_eq!(book["tags"], serde_json::json!(["Fantasy"]));
+
+        let response = app.get_as(OTHER_USER, &format!("/api/v1/books/{id}")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .send_json(
+                Method::DELETE,
+                &format!("/api/v1/books/{id}"),
+                serde_json::Value::Null,
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = app.get(&format!("/api/v1/books/{id}")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn api_batch_update() {
+        let app = TestApp::new().await;
+
+        let mut ids = Vec::new();
+        for (title, isbn) in [
+            ("Mort", "9780552131063"),
+            ("Guards! Guards!", "9780552134637"),
+        ] {
+            let book = serde_json::json!({
+                "isbn": isbn,
+                "title": title,
+                "authors": ["Terry Pratchett"],
+                "tags": ["To sort"],
+            });
+            let response = app.send_json(Method::POST, "/api/v1/books", book).await;
+            let created: serde_json::Value =
+                serde_json::from_str(&body_text(response).await).unwrap();
+            ids.push(created["id"].as_str().unwrap().to_string());
+        }
+
+        let update = serde_json::json!({
+            "ids": ids,
+            "read": true,
+            "add_tags": ["Discworld"],
+            "remove_tags": ["to sort"],
+        });
+        let response = app.send_json(Method::PATCH, "/api/v1/books", update).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        for id in &ids {
+            let book: serde_json::Value = serde_json::from_str(
+                &body_text(app.get(&format!("/api/v1/books/{id}")).await).await,
+            )
+            .unwrap();
+            assert_eq!(book["read"], true);
+            assert_eq!(book["owned"], false);
+            assert_eq!(book["tags"], serde_json::json!(["Discworld"]));
+        }
+
+        // Nothing is changed when a book is unknown
+        let update = serde_json::json!({
+            "ids": [ids[0], Uuid::new_v4()],
+            "owned": true,
+        });
+        let response = app.send_json(Method::PATCH, "/api/v1/books", update).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let book: serde_json::Value = serde_json::from_str(
+            &body_text(app.get(&format!("/api/v1/books/{}", ids[0])).await).await,
+        )
+        .unwrap();
+        assert_eq!(book["owned"], false);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn api_batch_tags_match_one_name() {
+        let app = TestApp::new().await;
+
+        let book = serde_json::json!({
+            "isbn": "9780552131063",
+            "title": "Mort",
+            "authors": ["Terry Pratchett"],
+            "tags": ["Fantasy"],
+        });
+        let response = app.send_json(Method::POST, "/api/v1/books", book).await;
+        let created: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        let id: Uuid = created["id"].as_str().unwrap().parse().unwrap();
+
+        // Tags only differing by case can predate the accent-insensitive matching
+        let mut conn = app.state.db.get().await.unwrap();
+        let fantasy: i32 = diesel::insert_into(tag::table)
+            .values(tag::name.eq("fantasy"))
+            .returning(tag::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        diesel::insert_into(booktag::table)
+            .values((booktag::book.eq(id), booktag::tag.eq(fantasy)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let update = serde_json::json!({
+            "ids": [id],
+            "remove_tags": ["fantasy"],
+        });
+        let response = app.send_json(Method::PATCH, "/api/v1/books", update).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let book: serde_json::Value =
+            serde_json::from_str(&body_text(app.get(&format!("/api/v1/books/{id}")).await).await)
+                .unwrap();
+        assert_eq!(book["tags"], serde_json::json!(["Fantasy"]));
+
+        let update = serde_json::json!({
+            "ids": [id],
+            "remove_tags": ["FANTASY"],
+        });
+        app.send_json(Method::PATCH, "/api/v1/books", update).await;
+
+        let book: serde_json::Value =
+            serde_json::from_str(&body_text(app.get(&format!("/api/v1/books/{id}")).await).await)
+                .unwrap();
+        assert_eq!(book["tags"], serde_json::json!([]));
+    }
+}
@@ -0,0 +1,276 @@
+//! Comparison of the books of an author in the library with the works known by the metadata
+//! providers
+
+use std::collections::HashMap;
+
+use axum::{extract::Path, response::Redirect, Form};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use maud::html;
+use uuid::Uuid;
+
+use crate::{
+    models::{Author, NewWish, User, WishAuthor},
+    schema::{author, book, bookauthor, users, wish, wishauthor},
+    State,
+};
+
+use super::RouteError;
+
+#[derive(serde::Deserialize)]
+pub(crate) struct WishForm {
+    title: String,
+}
+
+/// Key used to match the titles of the library with the ones of the providers
+fn title_key(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Author `id`, if `user` has books written by them
+async fn library_author(
+    conn: &mut diesel_async::AsyncPgConnection,
+    user: &User,
+    id: i32,
+) -> Result<Author, RouteError> {
+    let books: i64 = bookauthor::table
+        .inner_join(book::table)
+        .filter(bookauthor::author.eq(id))
+        .filter(book::owner.eq(user.id))
+        .count()
+        .get_result(conn)
+        .await?;
+
+    if books == 0 {
+        return Err(RouteError::NotFound);
+    }
+
+    Ok(author::table
+        .find(id)
+        .select(Author::as_select())
+        .get_result(conn)
+        .await?)
+}
+
+/// Number of known works owned and read, with the missing ones, loaded asynchronously from the
+/// author page
+pub(crate) async fn author_works(
+    state: State,
+    user: User,
+    id: Path<i32>,
+) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let author_info = library_author(&mut conn, &user, *id).await?;
+
+    let books: Vec<(String, bool, bool)> = bookauthor::table
+        .inner_join(book::table)
+        .filter(bookauthor::author.eq(*id))
+        .filter(book::owner.eq(user.id))
+        .select((book::title, book::owned, book::read))
+        .load(&mut conn)
+        .await?;
+
+    let wished: Vec<String> = wish::table
+        .inner_join(wishauthor::table)
+        .filter(wish::owner.eq(user.id))
+        .filter(wishauthor::author.eq(*id))
+        .select(wish::name)
+        .load(&mut conn)
+        .await?;
+
+    let provider_order: Vec<String> = users::table
+        .find(user.id)
+        .select(users::provider_order)
+        .get_result(&mut conn)
+        .await?;
+
+    drop(conn);
+
+    let mut works = None;
+    for (provider_id, provider) in state.metadata.ordered(&provider_order) {
+        match provider.author_works(&author_info.name).await {
+            Ok(Some(found)) => {
+                works = Some(found);
+                break;
+            }
+            Ok(None) => (),
+            Err(e) => tracing::warn!(
+                "Could not list the works of '{}' with {provider_id}: {e:?}",
+                author_info.name
+            ),
+        }
+    }
+
+    let Some(works) = works else {
+        return Ok(html! { span .text-body-secondary { "No known works" } });
+    };
+
+    let library: HashMap<_, _> = books
+        .into_iter()
+        .map(|(title, owned, read)| (title_key(&title), (owned, read)))
+        .collect();
+    let wished: Vec<_> = wished.iter().map(|name| title_key(name)).collect();
+
+    let (mut owned, mut read) = (0, 0);
+    let mut missing = Vec::new();
+    for title in &works {
+        match library.get(&title_key(title)) {
+            Some(&(is_owned, is_read)) => {
+                owned += usize::from(is_owned);
+                read += usize::from(is_read);
+            }
+            None => missing.push(title),
+        }
+    }
+
+    Ok(html! {
+        p {
+            (format!("{owned} of the {} known works owned, {read} read", works.len()))
+        }
+        @if !missing.is_empty() {
+            details .text-start."mb-2" {
+                summary { (format!("Missing works ({})", missing.len())) }
+                ul .list-unstyled.mt-2 {
+                    @for title in missing {
+                        li {
+                            (title)
+                            @if wished.contains(&title_key(title)) {
+                                span .badge.text-bg-secondary."ms-2" { "Wished" }
+                            } @else {
+                                form .d-inline method="POST"
+                                    action=(format!("/author/{}/wish", *id)) {
+                                    input type="hidden" name="title" value=(title);
+                                    button type="submit" .btn.btn-link.btn-sm {
+                                        "Add to the wishlist"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Add a work of the author to the wishlist
+pub(crate) async fn do_author_wish(
+    state: State,
+    user: User,
+    id: Path<i32>,
+    Form(form): Form<WishForm>,
+) -> Result<Redirect, RouteError> {
+    let title = form.title.trim();
+    if title.is_empty() {
+        return Err(RouteError::InvalidForm);
+    }
+
+    let mut conn = state.db.get().await?;
+
+    let author_info = library_author(&mut conn, &user, *id).await?;
+
+    conn.transaction(|c| {
+        async {
+            let wish: Uuid = diesel::insert_into(wish::table)
+                .values(NewWish {
+                    owner: user.id,
+                    name: title.to_string(),
+                    isbn: None,
+                })
+                .returning(wish::id)
+                .get_result(c)
+                .await?;
+
+            diesel::insert_into(wishauthor::table)
+                .values(WishAuthor {
+                    wish,
+                    author: author_info.id,
+                })
+                .execute(c)
+                .await?;
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(Redirect::to(&format!("/author/{}", *id)))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::{author, wish},
+        testing::{body_text, book_form, location, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn author_works() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("owned_box", "on")
+                .text("read_box", "on"),
+        )
+        .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let author_id: i32 = author::table
+            .filter(author::name.eq("Terry Pratchett"))
+            .select(author::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let page = body_text(app.get(&format!("/author/{author_id}/works")).await).await;
+        assert!(page.contains("1 of the 2 known works owned, 1 read"));
+        assert!(page.contains("Missing works (1)"));
+        assert!(page.contains("Add to the wishlist"));
+
+        let response = app
+            .post_form(
+                &format!("/author/{author_id}/wish"),
+                "title=Guards%21+Guards%21",
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&response), format!("/author/{author_id}"));
+
+        let page = body_text(app.get(&format!("/author/{author_id}/works")).await).await;
+        assert!(page.contains("Wished"));
+        assert!(!page.contains("Add to the wishlist"));
+
+        let page = body_text(app.get("/wishlist").await).await;
+        assert!(page.contains("Guards! Guards!"));
+        assert!(page.contains("Terry Pratchett"));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let wish_id: Uuid = wish::table
+            .select(wish::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let response = app
+            .post_form(&format!("/wishlist/{wish_id}/delete"), "")
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let page = body_text(app.get("/wishlist").await).await;
+        assert!(page.contains("No book is wished"));
+    }
+}
@@ -0,0 +1,160 @@
+use axum::extract::Path;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use maud::html;
+use uuid::Uuid;
+
+use crate::{
+    library::{self, LibraryConfig},
+    models::User,
+    schema::{book, wish},
+    State,
+};
+
+use super::RouteError;
+
+/// Badge telling if a book is in the catalogue of the configured library, loaded asynchronously
+/// from the book page
+pub(crate) async fn book_availability(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+) -> Result<maud::Markup, RouteError> {
+    let Some(config) = &state.config.library else {
+        return Err(RouteError::NotFound);
+    };
+
+    let mut conn = state.db.get().await?;
+
+    let isbn: String = book::table
+        .find(*id)
+        .filter(book::owner.eq(user.id))
+        .select(book::isbn)
+        .get_result(&mut conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => RouteError::NotFound,
+            _ => e.into(),
+        })?;
+
+    drop(conn);
+
+    Ok(catalogue_badge(config, &isbn).await)
+}
+
+/// Badge telling if a wished book is in the catalogue of the configured library, loaded
+/// asynchronously from the wishlist
+pub(crate) async fn wish_availability(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+) -> Result<maud::Markup, RouteError> {
+    let Some(config) = &state.config.library else {
+        return Err(RouteError::NotFound);
+    };
+
+    let mut conn = state.db.get().await?;
+
+    let isbn: Option<String> = wish::table
+        .find(*id)
+        .filter(wish::owner.eq(user.id))
+        .select(wish::isbn)
+        .first(&mut conn)
+        .await
+        .optional()?
+        .flatten();
+
+    drop(conn);
+
+    match isbn {
+        Some(isbn) => Ok(catalogue_badge(config, &isbn).await),
+        None => Err(RouteError::NotFound),
+    }
+}
+
+/// The catalogue only tells if the library has a record of the book, not if a copy can be
+/// borrowed
+async fn catalogue_badge(config: &LibraryConfig, isbn: &str) -> maud::Markup {
+    match library::records(config, isbn).await {
+        Ok(0) => html! { span .badge.text-bg-secondary { "Not in catalogue of " (config.name) } },
+        Ok(_) => html! { span .badge.text-bg-success { "In catalogue of " (config.name) } },
+        Err(e) => {
+            tracing::warn!("Could not check the catalogue for '{isbn}': {e:?}");
+            html! {
+                span .badge.text-bg-warning { "Could not check the catalogue of " (config.name) }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{extract::RawQuery, http::StatusCode};
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::wish,
+        testing::{body_text, TestApp, OTHER_USER},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn wishlist_availability() {
+        // SRU endpoint with a record for Guards! Guards! only
+        let sru = axum::Router::new().route(
+            "/sru",
+            axum::routing::get(|RawQuery(query): RawQuery| async move {
+                let count = match query.unwrap_or_default().contains("9780552134637") {
+                    true => 1,
+                    false => 0,
+                };
+                format!(
+                    r#"<srw:searchRetrieveResponse xmlns:srw="http://www.loc.gov/zing/srw/"><srw:numberOfRecords>{count}</srw:numberOfRecords></srw:searchRetrieveResponse>"#
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, sru).await.unwrap() });
+
+        let app = TestApp::with_config(&format!(
+            r#"
+            [library]
+            name = "City library"
+            sru = "http://{address}/sru"
+            "#
+        ))
+        .await;
+
+        app.post_form("/wishlist/isbn", "isbn=9780552134637").await;
+        app.post_form("/wishlist/isbn", "isbn=9780552131063").await;
+
+        let state = &app.state;
+        let wish_id = |isbn: &'static str| async move {
+            let mut conn = state.db.get().await.unwrap();
+            wish::table
+                .filter(wish::isbn.eq(isbn))
+                .select(wish::id)
+                .first::<Uuid>(&mut conn)
+                .await
+                .unwrap()
+        };
+        let guards = wish_id("9780552134637").await;
+        let mort = wish_id("9780552131063").await;
+
+        let page = body_text(app.get("/wishlist").await).await;
+        assert!(page.contains(&format!(r#"hx-get="/wishlist/{guards}/availability""#)));
+        assert!(page.contains(&format!(r#"hx-get="/wishlist/{mort}/availability""#)));
+
+        let badge = body_text(app.get(&format!("/wishlist/{guards}/availability")).await).await;
+        assert!(badge.contains("In catalogue of City library"));
+        let badge = body_text(app.get(&format!("/wishlist/{mort}/availability")).await).await;
+        assert!(badge.contains("Not in catalogue of City library"));
+
+        let response = app
+            .get_as(OTHER_USER, &format!("/wishlist/{guards}/availability"))
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -11,7 +11,7 @@ window.addEventListener('load', function () {
 		window['BarcodeDetector'] = barcodeDetectorPolyfill.BarcodeDetectorPolyfill
 	}
 
-	const barcodeDetector = new BarcodeDetector({formats: ['isbn_13']});
+	const barcodeDetector = new BarcodeDetector({formats: ['isbn_13', 'qr_code']});
 
 	let stream = null;
 	let barcodeInterval = null;
@@ -29,11 +29,31 @@ window.addEventListener('load', function () {
 		barcodeInterval = window.setInterval(async () => {
 			const barcodes = await barcodeDetector.detect(scanVideo);
 			if (barcodes.length <= 0) return;
-			
-			var searchParams = new URLSearchParams(window.location.search);
-			searchParams.set("isbn", barcodes[0].rawValue);
-			searchParams.set("provider", isbnModalForm.provider.value)
-			window.location.search = searchParams.toString();
+
+			// QR codes of the book pages open the book directly
+			if (barcodes[0].format === 'qr_code') {
+				let url;
+				try {
+					url = new URL(barcodes[0].rawValue);
+				} catch {
+					return;
+				}
+				if (url.host !== window.location.host || !url.pathname.startsWith("/book/")) return;
+
+				window.location = url.pathname;
+				bootstrap.Modal.getInstance("#scanModal").hide()
+				return;
+			}
+
+			// The ISBN is loaded from the ISBN dialog, showing the progress of the lookup
+			window.clearInterval(barcodeInterval);
+			barcodeInterval = null;
+
+			document.getElementById("isbnSearch").value = barcodes[0].rawValue;
+			scanModal.addEventListener('hidden.bs.modal', () => {
+				bootstrap.Modal.getOrCreateInstance("#isbnModal").show()
+				isbnModalForm.requestSubmit()
+			}, { once: true })
 
 			bootstrap.Modal.getInstance("#scanModal").hide()
 		}, 200);
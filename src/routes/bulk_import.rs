@@ -0,0 +1,375 @@
+//! Import of a list of ISBNs, looked up in the background with the preferred provider
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Multipart, Path},
+    response::Redirect,
+};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use maud::html;
+use uuid::Uuid;
+
+use crate::{
+    jobs::{ItemStatus, JobProgress},
+    metadata::{isbn::find_isbn, NullableBookDetails},
+    models::{NewUser, User},
+    schema::{book, users, wish},
+    AppState, State,
+};
+
+use super::{
+    add::{insert_book, lookup_isbn, preferred_provider},
+    raw_app_page,
+    wishlist::wish_isbn,
+    BookInfo, RouteError,
+};
+
+/// Split a list of ISBNs, one per line or separated by commas
+fn split_isbns(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+async fn import_isbn(
+    state: &AppState,
+    user: &User,
+    provider: Option<&str>,
+    isbn: &str,
+    owned: bool,
+) -> Result<ItemStatus, RouteError> {
+    let Some(isbn) = find_isbn(isbn, true) else {
+        return Ok(ItemStatus::Failed("invalid ISBN".into()));
+    };
+
+    let mut conn = state.db.get().await?;
+    let found: i64 = book::table
+        .filter(book::owner.eq(user.id).and(book::isbn.eq(&isbn)))
+        .count()
+        .get_result(&mut conn)
+        .await?;
+    drop(conn);
+
+    if found != 0 {
+        return Ok(ItemStatus::Skipped("already in the library".into()));
+    }
+
+    let details = match lookup_isbn(state, provider, &isbn).await {
+        Ok(Some(details)) => details,
+        Ok(None) => return Ok(ItemStatus::Failed("not found".into())),
+        Err(e) => return Ok(ItemStatus::Failed(e.reason())),
+    };
+
+    let mut info = BookInfo::from_details(
+        user,
+        NullableBookDetails {
+            isbn: Some(isbn),
+            owned,
+            ..details
+        },
+    )?;
+    info.source = state
+        .metadata
+        .resolve(provider)
+        .ok()
+        .map(|id| (id.to_string(), chrono::Utc::now()));
+
+    let title = info.book.title.clone();
+    insert_book(state, user, info).await?;
+    // The job runs after the request that started it invalidated the completions
+    state.completions.invalidate();
+
+    Ok(ItemStatus::Done(title))
+}
+
+pub(crate) async fn do_import_isbns(
+    state: State,
+    user: User,
+    mut multipart: Multipart,
+) -> Result<Redirect, RouteError> {
+    let mut isbns = Vec::new();
+    let mut owned = false;
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("isbns") => isbns.extend(split_isbns(&field.text().await?)),
+            Some("file") => {
+                isbns.extend(split_isbns(&String::from_utf8_lossy(&field.bytes().await?)))
+            }
+            Some("owned") => owned = true,
+            name => tracing::warn!("Unknown field {name:?}"),
+        }
+    }
+
+    if isbns.is_empty() {
+        return Err(RouteError::MissingField);
+    }
+
+    let provider = preferred_provider(&state, &user).await?;
+    let job = state.jobs.create(user.id, isbns);
+
+    let task_state = state.0.clone();
+    state.jobs.run(job, move |isbn| {
+        let state = task_state.clone();
+        let user = user.clone();
+        let provider = provider.clone();
+
+        async move {
+            import_isbn(&state, &user, provider.as_deref(), &isbn, owned)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Could not import '{isbn}': {e:?}");
+                    ItemStatus::Failed("internal error".into())
+                })
+        }
+    });
+
+    Ok(Redirect::to(&format!("/import/isbns/{job}")))
+}
+
+/// Book sent by email: an ISBN, or the address of a page showing it such as the Goodreads page of
+/// the book
+async fn import_sent_item(
+    state: &AppState,
+    user: &User,
+    provider: Option<&str>,
+    item: &str,
+    wish: bool,
+) -> Result<ItemStatus, RouteError> {
+    let isbn = match reqwest::Url::parse(item) {
+        Ok(url) => match state.pages.fetch(url).await {
+            Ok(page) => match find_isbn(&page, false) {
+                Some(isbn) => isbn,
+                None => return Ok(ItemStatus::Failed("no ISBN on the page".into())),
+            },
+            Err(e) => return Ok(ItemStatus::Failed(format!("could not fetch the page: {e}"))),
+        },
+        Err(_) => item.to_string(),
+    };
+
+    if !wish {
+        return import_isbn(state, user, provider, &isbn, true).await;
+    }
+
+    let Some(isbn) = find_isbn(&isbn, true) else {
+        return Ok(ItemStatus::Failed("invalid ISBN".into()));
+    };
+
+    let mut conn = state.db.get().await?;
+    let wished: i64 = wish::table
+        .filter(wish::owner.eq(user.id).and(wish::isbn.eq(&isbn)))
+        .count()
+        .get_result(&mut conn)
+        .await?;
+    drop(conn);
+
+    if wished != 0 {
+        return Ok(ItemStatus::Skipped("already wished".into()));
+    }
+
+    match wish_isbn(state, user, provider, isbn).await? {
+        Some(title) => {
+            // The wished authors are completed
+            state.completions.invalidate();
+            Ok(ItemStatus::Done(title))
+        }
+        None => Ok(ItemStatus::Failed("not found".into())),
+    }
+}
+
+/// Add the books sent by email to `[ingest.imap]` to the library of `user`, or to their wishlist
+/// for `wish`, in the background
+pub(crate) async fn import_sent(
+    state: &Arc<AppState>,
+    user: &str,
+    items: Vec<String>,
+    wish: bool,
+) -> Result<Uuid, RouteError> {
+    let mut conn = state.db.get().await?;
+    diesel::insert_into(users::table)
+        .values(&NewUser { name: user })
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await?;
+    let user: User = users::table
+        .filter(users::name.eq(user))
+        .select(User::as_select())
+        .first(&mut conn)
+        .await?;
+    drop(conn);
+
+    let provider = preferred_provider(state, &user).await?;
+    let job = state.jobs.create(user.id, items);
+
+    let task_state = state.clone();
+    state.jobs.run(job, move |item| {
+        let state = task_state.clone();
+        let user = user.clone();
+        let provider = provider.clone();
+
+        async move {
+            let status = import_sent_item(&state, &user, provider.as_deref(), &item, wish)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Could not import '{item}': {e:?}");
+                    ItemStatus::Failed("internal error".into())
+                });
+            tracing::info!(user = %user.name, "Book sent by email '{item}': {status:?}");
+
+            status
+        }
+    });
+
+    Ok(job)
+}
+
+pub(super) fn job_progress(
+    state: &AppState,
+    user: &User,
+    job: Uuid,
+) -> Result<JobProgress, RouteError> {
+    match state.jobs.progress(job) {
+        Some(progress) if progress.owner == user.id => Ok(progress),
+        _ => Err(RouteError::NotFound),
+    }
+}
+
+/// List of the ISBNs of the job, polled from `url` until it is finished. Done items are shown as
+/// `done` followed by their title.
+pub(super) fn progress_list(url: &str, done: &str, progress: &JobProgress) -> maud::Markup {
+    html! {
+        div hx-get=[(!progress.is_finished()).then_some(url)] hx-trigger="every 2s"
+            hx-swap="outerHTML" {
+            p {
+                (format!("{} of {} ISBNs processed", progress.completed(), progress.items.len()))
+            }
+            ul .list-group."mb-3" {
+                @for (isbn, status) in &progress.items {
+                    li .list-group-item {
+                        (isbn) ": "
+                        @match status {
+                            ItemStatus::Queued => {
+                                span .text-body-secondary { "queued" }
+                            },
+                            ItemStatus::Running => {
+                                span .text-body-secondary { "looking up..." }
+                            },
+                            ItemStatus::Done(title) => {
+                                span .text-success { (done) " " (title) }
+                            },
+                            ItemStatus::Skipped(reason) => { (reason) },
+                            ItemStatus::Failed(reason) => {
+                                span .text-danger { "failed (" (reason) ")" }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(crate) async fn import_isbns(
+    state: State,
+    user: User,
+    job: Path<Uuid>,
+) -> Result<maud::Markup, RouteError> {
+    let progress = job_progress(&state, &user, *job)?;
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "Import of ISBNs" }
+                (progress_list(&format!("/import/isbns/{}/progress", *job), "added", &progress))
+                @if !progress.is_finished() {
+                    a .btn.btn-secondary.no-js."me-2" href=(format!("/import/isbns/{}", *job)) {
+                        "Refresh"
+                    }
+                }
+                a .btn.btn-primary href="/" { "Back to the books" }
+            }
+        },
+    ))
+}
+
+pub(crate) async fn import_isbns_progress(
+    state: State,
+    user: User,
+    job: Path<Uuid>,
+) -> Result<maud::Markup, RouteError> {
+    let progress = job_progress(&state, &user, *job)?;
+    Ok(progress_list(
+        &format!("/import/isbns/{}/progress", *job),
+        "added",
+        &progress,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::book,
+        testing::{body_text, book_form, location, MultipartForm, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_isbns() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+
+        let response = app
+            .post_multipart(
+                "/import/isbns",
+                MultipartForm::new()
+                    .text(
+                        "isbns",
+                        "978-0552134637\n9780552131063, 9780000000002\nnot-an-isbn",
+                    )
+                    .text("owned", "on"),
+            )
+            .await;
+        let job = location(&response).to_string();
+        assert!(job.starts_with("/import/isbns/"));
+
+        let page = body_text(app.get(&job).await).await;
+        assert!(page.contains("ISBNs processed"));
+
+        let progress = loop {
+            let progress = body_text(app.get(&format!("{job}/progress")).await).await;
+            if !progress.contains("hx-get") {
+                break progress;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert!(progress.contains("4 of 4 ISBNs processed"));
+        assert!(progress.contains("added Guards! Guards!"));
+        assert!(progress.contains("already in the library"));
+        assert!(progress.contains("failed (not found)"));
+        assert!(progress.contains("failed (invalid ISBN)"));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let owned: bool = book::table
+            .filter(book::isbn.eq("9780552134637"))
+            .select(book::owned)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert!(owned);
+        drop(conn);
+
+        // Jobs are only visible to their owner
+        let response = app.get_as("someone", &job).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
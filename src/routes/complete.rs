@@ -0,0 +1,203 @@
+//! Lists completing the book forms, loaded by the page after it is rendered
+
+use axum::{
+    extract::{Path, Query, Request},
+    http::Method,
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use crate::{
+    completions::CompletionList,
+    models::User,
+    schema::{author, book, bookauthor, booktag, series, tag},
+    State,
+};
+
+use super::{
+    citext_name_key,
+    components::{author_list, location_list, series_list, tag_list},
+    name_key, RouteError,
+};
+
+/// Number of names suggested by a search
+const SEARCH_LIMIT: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub(crate) struct CompleteQuery {
+    /// Start of the names to suggest, ignoring the case and accents
+    q: Option<String>,
+}
+
+/// `LIKE` pattern matching the names starting with `prefix`
+fn like_prefix(prefix: &str) -> String {
+    let escaped = prefix
+        .trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("{escaped}%")
+}
+
+/// First names of `list` starting with `prefix`, or `None` if the list can't be searched
+async fn search(
+    state: &State,
+    user: &User,
+    list: CompletionList,
+    prefix: &str,
+) -> Result<Option<Vec<String>>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let pattern = like_prefix(prefix);
+    let user_books = book::table.filter(book::owner.eq(user.id)).select(book::id);
+
+    let names = match list {
+        CompletionList::Authors => {
+            let book_author_ids = bookauthor::table
+                .filter(bookauthor::book.eq_any(user_books))
+                .select(bookauthor::author);
+
+            author::table
+                .filter(author::id.eq_any(book_author_ids))
+                .filter(citext_name_key(author::name).like(name_key(pattern.as_str())))
+                .select(author::name)
+                .order(author::name)
+                .limit(SEARCH_LIMIT)
+                .load(&mut conn)
+                .await?
+        }
+        CompletionList::Tags => {
+            let book_tag_ids = booktag::table
+                .filter(booktag::book.eq_any(user_books))
+                .select(booktag::tag);
+
+            tag::table
+                .filter(tag::id.eq_any(book_tag_ids))
+                .filter(name_key(tag::name).like(name_key(pattern.as_str())))
+                .select(tag::name)
+                .order(tag::name)
+                .limit(SEARCH_LIMIT)
+                .load(&mut conn)
+                .await?
+        }
+        CompletionList::Series => {
+            series::table
+                .filter(series::owner.eq(user.id))
+                .filter(citext_name_key(series::name).like(name_key(pattern.as_str())))
+                .select(series::name)
+                .order(series::name)
+                .limit(SEARCH_LIMIT)
+                .load(&mut conn)
+                .await?
+        }
+        CompletionList::Locations => return Ok(None),
+    };
+
+    Ok(Some(names))
+}
+
+pub(crate) async fn complete(
+    state: State,
+    user: User,
+    list: Path<CompletionList>,
+    Query(query): Query<CompleteQuery>,
+) -> Result<Json<Vec<String>>, RouteError> {
+    if let Some(prefix) = &query.q {
+        if let Some(names) = search(&state, &user, *list, prefix).await? {
+            return Ok(Json(names));
+        }
+    }
+
+    if let Some(values) = state.completions.get(user.id, *list) {
+        return Ok(Json(values.to_vec()));
+    }
+
+    let generation = state.completions.generation();
+    let values = match *list {
+        CompletionList::Authors => author_list(&state, &user).await?,
+        CompletionList::Tags => tag_list(&state, &user).await?,
+        CompletionList::Series => series_list(&state, &user).await?,
+        CompletionList::Locations => location_list(&state, &user).await?,
+    };
+
+    state
+        .completions
+        .insert(user.id, *list, generation, values.clone());
+
+    Ok(Json(values))
+}
+
+/// Forget the completion lists once a request could have changed the library
+pub(crate) async fn invalidate_completions(state: State, request: Request, next: Next) -> Response {
+    let read_only = matches!(*request.method(), Method::GET | Method::HEAD);
+
+    let response = next.run(request).await;
+    if !read_only {
+        state.completions.invalidate();
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+
+    use crate::testing::{body_text, book_form, TestApp};
+
+    #[test]
+    fn like_prefix() {
+        assert_eq!(super::like_prefix(" Terry "), "Terry%");
+        assert_eq!(super::like_prefix("100%_\\"), "100\\%\\_\\\\%");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn completions() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("tag", "Fantasy")
+                .text("series_name", "Discworld")
+                .text("series_volume", "4"),
+        )
+        .await;
+
+        let page = body_text(app.get("/add").await).await;
+        assert!(page.contains(r#"data-search="/api/complete/authors""#));
+        assert!(page.contains(r#"data-search="/api/complete/series""#));
+        assert!(!page.contains("<option>Terry Pratchett</option>"));
+
+        let authors = body_text(app.get("/api/complete/authors").await).await;
+        assert_eq!(authors, r#"["Terry Pratchett"]"#);
+        let tags = body_text(app.get("/api/complete/tags").await).await;
+        assert_eq!(tags, r#"["Fantasy"]"#);
+
+        app.post_multipart(
+            "/add",
+            book_form("Good Omens", "9780552137034").text("author", "Neil Gaiman"),
+        )
+        .await;
+
+        let authors: Vec<String> =
+            serde_json::from_str(&body_text(app.get("/api/complete/authors").await).await).unwrap();
+        assert!(authors.contains(&"Neil Gaiman".to_string()));
+
+        let authors = body_text(app.get("/api/complete/authors?q=neil").await).await;
+        assert_eq!(authors, r#"["Neil Gaiman"]"#);
+        let authors = body_text(app.get("/api/complete/authors?q=Gaiman").await).await;
+        assert_eq!(authors, "[]");
+        let tags = body_text(app.get("/api/complete/tags?q=fan").await).await;
+        assert_eq!(tags, r#"["Fantasy"]"#);
+        let series = body_text(app.get("/api/complete/series?q=disc").await).await;
+        assert_eq!(series, r#"["Discworld"]"#);
+
+        let response = app.get("/api/complete/unknown").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
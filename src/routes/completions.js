@@ -0,0 +1,47 @@
+// The completion lists are loaded after the form, as they can be long for large libraries
+for (const datalist of document.querySelectorAll("datalist[data-completions]")) {
+    // Awesomplete removes the `list` attribute of the inputs once it is loaded
+    const inputs = [...document.querySelectorAll(`input[list="${datalist.id}"]`)]
+
+    fetch(datalist.dataset.completions)
+        .then(response => response.json())
+        .then(values => {
+            datalist.replaceChildren(...values.map(value => new Option(value)))
+
+            for (const completion of window.Awesomplete?.all ?? []) {
+                if (inputs.includes(completion.input)) {
+                    completion.list = values
+                }
+            }
+        })
+}
+
+// Pickers searching the names as they are typed, as loading them all would not scale
+for (const input of document.querySelectorAll("input[data-search]")) {
+    let pending = null
+
+    input.addEventListener("input", () => {
+        clearTimeout(pending)
+
+        const prefix = input.value.trim()
+        if (prefix == "") {
+            return
+        }
+
+        pending = setTimeout(() => {
+            fetch(`${input.dataset.search}?q=${encodeURIComponent(prefix)}`)
+                .then(response => response.json())
+                .then(values => {
+                    // A later search was started while this one was running
+                    if (input.value.trim() != prefix) {
+                        return
+                    }
+
+                    const completion = window.Awesomplete?.all.find(c => c.input == input)
+                    if (completion) {
+                        completion.list = values
+                    }
+                })
+        }, 200)
+    })
+}
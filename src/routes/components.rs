@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use maud::{html, PreEscaped};
@@ -9,12 +10,12 @@ use crate::{
     metadata::NullableBookDetails,
     models::{Author, BookAuthor, BookPreview, BookSeries, SeriesInfo, User},
     schema::{author, book, bookauthor, booktag, series, tag},
-    State,
+    RequiredField, State,
 };
 
-use super::{RouteError, SeriesAllInfo, NO_COVER};
+use super::{nonce, RouteError, SeriesAllInfo, NO_COVER};
 
-async fn author_list(state: &State, user: &User) -> Result<Vec<String>, RouteError> {
+pub(super) async fn author_list(state: &State, user: &User) -> Result<Vec<String>, RouteError> {
     let mut conn = state.db.get().await?;
 
     // List of books of an user
@@ -34,7 +35,7 @@ async fn author_list(state: &State, user: &User) -> Result<Vec<String>, RouteErr
     Ok(authors)
 }
 
-async fn tag_list(state: &State, user: &User) -> Result<Vec<String>, RouteError> {
+pub(super) async fn tag_list(state: &State, user: &User) -> Result<Vec<String>, RouteError> {
     let mut conn = state.db.get().await?;
 
     // List of books of an user
@@ -54,7 +55,21 @@ async fn tag_list(state: &State, user: &User) -> Result<Vec<String>, RouteError>
     Ok(authors)
 }
 
-async fn series_list(state: &State, user: &User) -> Result<Vec<String>, RouteError> {
+/// Locations of the books of `user`
+pub(super) async fn location_list(state: &State, user: &User) -> Result<Vec<String>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    Ok(book::table
+        .filter(book::owner.eq(user.id))
+        .filter(book::location.is_not_null())
+        .select(book::location.assume_not_null())
+        .distinct()
+        .order(book::location)
+        .load(&mut conn)
+        .await?)
+}
+
+pub(super) async fn series_list(state: &State, user: &User) -> Result<Vec<String>, RouteError> {
     let mut conn = state.db.get().await?;
 
     Ok(series::table
@@ -68,39 +83,53 @@ fn list_input(
     id: &str,
     placeholder: &str,
     defaults: &[String],
-    completions: &[String],
+    search: &str,
     remove_label: &str,
+    required: bool,
 ) -> maud::Markup {
-    let list_id = format!("{id}CompleteList");
     let values_id = format!("{id}Values");
     let input_id = format!("{id}Input");
+    let lines_id = format!("{id}Lines");
 
     html! {
-        input #(input_id) .form-control.awesomplete."mb-2" list=(list_id) data-tabSelect="true"
-            placeholder=(placeholder);
-        datalist #(list_id) {
-            @for possible in completions {
-                option { (possible) }
-            }
-        }
+        input #(input_id) .form-control.awesomplete."mb-2".js-only data-search=(search)
+            data-tabSelect="true" placeholder=(placeholder);
+        // Without scripts the values are entered one per line
+        textarea #(lines_id) .form-control."mb-2".no-js name=(id) rows="2"
+            placeholder=(format!("{placeholder} (one per line)"))
+            required[required && defaults.is_empty()] {}
         ul #(values_id) .list-group."mb-3" {
             @for item in defaults {
                 li .list-group-item.d-flex.justify-content-between.align-items-center {
-                    (item)
-                    span {
-                        button type="button" .btn-close aria-label=(remove_label) onclick=(format!("delete{id}(event)"));
+                    label .flex-grow-1 {
+                        input type="checkbox" .form-check-input."me-2".no-js name=(id) value=(item)
+                            checked;
+                        (item)
+                    }
+                    span .js-only {
+                        button type="button" .btn-close aria-label=(remove_label);
                     }
-                    input type="hidden" name=(id) value=(item);
                 }
             }
         }
-        script {
+        script nonce=[nonce()] {
             (maud::PreEscaped(format!(r#"
                 {id}Input = document.getElementById("{input_id}")
                 {id}List = document.getElementById("{values_id}")
+                document.getElementById("{lines_id}").remove()
+
+                // A required list only needs its input to be filled while it is empty
+                function {id}SetRequired() {{
+                    {id}Input.required = {required} && {id}List.children.length == 0
+                }}
 
                 function delete{id}(event) {{
                     event.srcElement.parentNode.parentNode.remove()
+                    {id}SetRequired()
+                }}
+
+                for (const button of {id}List.querySelectorAll(".btn-close")) {{
+                    button.addEventListener("click", delete{id})
                 }}
 
                 function {id}Add(value) {{
@@ -127,8 +156,11 @@ fn list_input(
                     listItem.appendChild(listInput)
 
                     {id}List.appendChild(listItem)
+                    {id}SetRequired()
                 }}
 
+                {id}SetRequired()
+
                 {id}Completing = false
 
                 {id}Input.addEventListener("awesomplete-highlight", function(event) {{
@@ -167,37 +199,74 @@ fn list_input(
     }
 }
 
+fn link_row(label: &str, url: &str) -> maud::Markup {
+    html! {
+        .row."g-2"."mb-2".align-items-center {
+            .col-4 {
+                input .form-control name="link_label" type="text" placeholder="Label" value=(label);
+            }
+            .col {
+                input .form-control name="link_url" type="url" placeholder="https://" value=(url);
+            }
+            .col-auto {
+                button type="button" .btn-close.js-only aria-label="Remove link" data-remove-row {}
+            }
+        }
+    }
+}
+
+fn identifier_row(scheme: &str, value: &str) -> maud::Markup {
+    html! {
+        .row."g-2"."mb-2".align-items-center {
+            .col-4 {
+                input .form-control name="identifier_scheme" type="text" placeholder="Scheme (oclc, doi, ...)"
+                    value=(scheme);
+            }
+            .col {
+                input .form-control name="identifier_value" type="text" placeholder="Identifier"
+                    value=(value);
+            }
+            .col-auto {
+                button type="button" .btn-close.js-only aria-label="Remove identifier"
+                    data-remove-row {}
+            }
+        }
+    }
+}
+
 pub async fn book_form(
     state: &State,
-    user: &User,
     details: NullableBookDetails,
+    source: Option<(String, DateTime<Utc>)>,
+    action: &str,
     submit: &str,
+    return_to: Option<String>,
 ) -> Result<maud::Markup, RouteError> {
     let image = details
         .covert_art_b64
         .as_ref()
         .unwrap_or_else(|| &*NO_COVER);
 
-    let authors = author_list(state, user).await?;
-    let tags = tag_list(state, user).await?;
-    let series = series_list(state, user).await?;
-
     let (series_name, series_number) = details.series.unzip();
+    let required = |field| state.config.form.requires(field);
 
     Ok(
-        html! { form .container-sm.align-items-center method="POST" enctype="multipart/form-data" .mt-2 {
+        html! { form #bookForm .container-sm.align-items-center method="POST" action=(action)
+            enctype="multipart/form-data" .mt-2 {
             .text-center.d-flex.flex-column."mb-2" {
                 label for="coverArtInput" .form-label {"Cover art"}
-                div {
+                div #coverDrop .rounded {
                     img .img-fluid."mb-2"
                         #coverArt
                         style="height:400px;"
-                        alt="Cover Art"
+                        alt="Cover of the book"
                         src=(format!("data:image/jpg;base64,{image}"));
                 }
-                input .form-control accept="image/*" type="file" name="user_cover" #coverArtInput;
-                script {
-                    (maud::PreEscaped(r#"
+                input .form-control accept="image/*" type="file" name="user_cover" #coverArtInput
+                    required[required(RequiredField::Cover) && details.covert_art_b64.is_none()];
+                .form-text.js-only { "An image can also be dropped on the cover or pasted" }
+                script nonce=[nonce()] {
+                    (maud::PreEscaped(r##"
                     coverArt = document.getElementById("coverArt")
                     coverArtInput = document.getElementById("coverArtInput")
             
@@ -205,12 +274,75 @@ pub async fn book_form(
                         const [file] = coverArtInput.files
                         if (file) {
                             coverArt.src = URL.createObjectURL(file)
+                            for (const candidate of document.querySelectorAll("#coverCandidates img")) {
+                                candidate.classList.remove("border-primary")
+                            }
                         }
                     }
-                "#))
+
+                    // Dropped and pasted images are sent as if they were picked in the input
+                    function setCover(files) {
+                        const file = [...files].find(file => file.type.startsWith("image/"))
+                        if (!file) {
+                            return false
+                        }
+
+                        const transfer = new DataTransfer()
+                        transfer.items.add(file)
+                        coverArtInput.files = transfer.files
+                        coverArtInput.dispatchEvent(new Event("change"))
+                        return true
+                    }
+
+                    const coverDrop = document.getElementById("coverDrop")
+                    coverDrop.addEventListener("dragover", event => {
+                        event.preventDefault()
+                        coverDrop.classList.add("bg-body-secondary")
+                    })
+                    coverDrop.addEventListener("dragleave", () => {
+                        coverDrop.classList.remove("bg-body-secondary")
+                    })
+                    coverDrop.addEventListener("drop", event => {
+                        event.preventDefault()
+                        coverDrop.classList.remove("bg-body-secondary")
+                        setCover(event.dataTransfer.files)
+                    })
+
+                    document.addEventListener("paste", event => {
+                        if (setCover(event.clipboardData.files)) {
+                            event.preventDefault()
+                        }
+                    })
+                "##))
+                }
+                @if !details.cover_candidates_b64.is_empty() {
+                    .d-flex.justify-content-center.flex-wrap.mt-2.js-only #coverCandidates {
+                        @let candidates = details.covert_art_b64.iter().chain(&details.cover_candidates_b64);
+                        @for (i, cover) in candidates.enumerate() {
+                            img .img-thumbnail."me-1".border-primary[i == 0] role="button"
+                                style="height: 6rem;" alt=(format!("Cover candidate {}", i + 1))
+                                src=(format!("data:image/jpg;base64,{cover}"));
+                        }
+                    }
+                    script nonce=[nonce()] {
+                        (maud::PreEscaped(r##"
+                        for (const candidate of document.querySelectorAll("#coverCandidates img")) {
+                            candidate.onclick = () => {
+                                for (const other of document.querySelectorAll("#coverCandidates img")) {
+                                    other.classList.remove("border-primary")
+                                }
+                                candidate.classList.add("border-primary")
+
+                                coverArt.src = candidate.src
+                                coverArtInput.value = ""
+                                document.getElementById("fetchedCover").value = candidate.src.split(",")[1]
+                            }
+                        }
+                    "##))
+                    }
                 }
                 @if let Some(b64) = details.covert_art_b64 {
-                    input type="hidden" value=(b64) name="fetched_cover";
+                    input type="hidden" value=(b64) name="fetched_cover" #fetchedCover;
                 }
             }
             .form-floating."mb-2" {
@@ -224,11 +356,17 @@ pub async fn book_form(
                 label for="isbn" { "ISBN" }
             }
             .form-floating."mb-2" {
-                textarea .form-control placeholder="Book summary" #summary style="height: 150px" name="summary" {
+                textarea .form-control placeholder="Book summary" #summary style="height: 150px" name="summary"
+                    required[required(RequiredField::Summary)] {
                     (details.summary.unwrap_or_default())
                 }
                 label for="summary" { "Summary" }
             }
+            .form-check {
+                input .form-check-input type="checkbox" name="spoilers_box" #spoilersBox
+                    checked[details.summary_spoilers];
+                label .form-check-label for="spoilersBox" { "The summary contains spoilers" }
+            }
             .form-check {
                 input .form-check-input type="checkbox" name="read_box" #readBox checked[details.read];
                 label .form-check-label for="readBox" { "Read" }
@@ -237,82 +375,197 @@ pub async fn book_form(
                 input .form-check-input type="checkbox" name="owned_box" #ownedBox checked[details.owned];
                 label .form-check-label for="ownedBox" { "Owned" }
             }
+            .form-check {
+                input .form-check-input type="checkbox" name="reread_box" #rereadBox checked[details.reread];
+                label .form-check-label for="rereadBox" { "Want to re-read" }
+            }
+            .form-floating."mb-2" {
+                select .form-select #priority name="priority" {
+                    option value="" selected[details.priority.is_none()] { "None" }
+                    @for p in 1..=5 {
+                        option value=(p) selected[details.priority == Some(p)] { (p) }
+                    }
+                }
+                label for="priority" { "Reading priority" }
+            }
             .row."g-2"."mb-2" {
                 .col {
-                    input #seriesInput .form-control.awesomplete."me-1" list="seriesList" name="series_name"
-                        placeholder="Series" value=[series_name];
-                    datalist #seriesList {
-                        @for series in series {
-                            option { (series) }
-                        }
-                    }
+                    input #seriesInput .form-control.awesomplete."me-1" name="series_name"
+                        data-search="/api/complete/series" placeholder="Series" value=[series_name]
+                        required[required(RequiredField::Series)];
                 }
                 .col {
                     input #seriesVolume name="series_volume" .form-control placeholder="Series volume"
-                        type="number" value=[series_number];
+                        type="number" value=[series_number]
+                        required[required(RequiredField::Series)];
                 }
-                script {
-                    (PreEscaped(r#"
-                        const seriesName = document.getElementById('seriesInput')
-                        const seriesVolume = document.getElementById('seriesVolume')
-                        const requiredOnLoad = seriesName.value != "" || seriesVolume.value != ""
-
-                        seriesName.required = requiredOnLoad
-                        seriesVolume.required = requiredOnLoad
-
-                        function setSeriesRequired() {
-                            const required = seriesName.value != "" || seriesVolume.value != ""
-                            seriesName.required = required
-                            seriesVolume.required = required
-                        }
+                @if !required(RequiredField::Series) {
+                    script nonce=[nonce()] {
+                        (PreEscaped(r#"
+                            const seriesName = document.getElementById('seriesInput')
+                            const seriesVolume = document.getElementById('seriesVolume')
+                            const requiredOnLoad = seriesName.value != "" || seriesVolume.value != ""
+
+                            seriesName.required = requiredOnLoad
+                            seriesVolume.required = requiredOnLoad
+
+                            function setSeriesRequired() {
+                                const required = seriesName.value != "" || seriesVolume.value != ""
+                                seriesName.required = required
+                                seriesVolume.required = required
+                            }
 
-                        seriesName.addEventListener('input', setSeriesRequired)
-                        seriesVolume.addEventListener('input', setSeriesRequired)
-                    "#))
+                            seriesName.addEventListener('input', setSeriesRequired)
+                            seriesVolume.addEventListener('input', setSeriesRequired)
+                        "#))
+                    }
                 }
             }
-            (list_input("author", "Author name", &details.authors, &authors, "Remove author"))
-            (list_input("tag", "Tag", &details.tags, &tags, "Remove tag"))
+            (list_input("author", "Author name", &details.authors, "/api/complete/authors",
+                "Remove author", required(RequiredField::Author)))
+            (list_input("tag", "Tag", &details.tags, "/api/complete/tags", "Remove tag",
+                required(RequiredField::Tag)))
             .form-floating."mb-2" {
                 input #published name="published" type="date" .form-control placeholder="1970-01-01"
-                      value=[details.published.map(|d| d.format("%Y-%m-%d"))];
+                      value=[details.published.map(|d| d.format("%Y-%m-%d"))]
+                      required[required(RequiredField::Published)];
                 label for="published" {"Publication Date"}
             }
             .form-floating."mb-2" {
                 input .form-control #publisher name="publisher" type="text"
-                        placeholder="Publisher" value=[details.publisher];
+                        placeholder="Publisher" value=[details.publisher]
+                        required[required(RequiredField::Publisher)];
                 label for="publisher" { "Publisher" }
             }
             .form-floating."mb-2" {
                 input .form-control #language name="language" type="text"
-                        placeholder="Language" value=[details.language];
+                        placeholder="Language" value=[details.language]
+                        required[required(RequiredField::Language)];
                 label for="language" { "Language" }
             }
             .form-floating."mb-2" {
                 input .form-control #googleID name="google_id" type="text"
-                        placeholder="Google ID" value=[details.google_id];
+                        placeholder="Google ID" value=[details.google_id]
+                        required[required(RequiredField::GoogleId)];
                 label for="googleID" { "Google ID" }
             }
+            .form-floating."mb-2" {
+                input .form-control #goodreadsID name="goodreads_id" type="text"
+                        placeholder="Goodreads ID" value=[details.goodreads_id]
+                        required[required(RequiredField::GoodreadsId)];
+                label for="goodreadsID" { "Goodreads ID" }
+            }
             .form-floating."mb-2" {
                 input .form-control #amazonID name="amazon_id" type="text"
-                        placeholder="Amazon ID" value=[details.amazon_id];
+                        placeholder="Amazon ID" value=[details.amazon_id]
+                        required[required(RequiredField::AmazonId)];
                 label for="amazonID" { "Amazon ID" }
             }
             .form-floating."mb-2" {
                 input .form-control #librarythingId name="librarything_id" type="text"
-                        placeholder="Librarything ID" value=[details.librarything_id];
+                        placeholder="Librarything ID" value=[details.librarything_id]
+                        required[required(RequiredField::LibrarythingId)];
                 label for="librarythingId" { "Librarything ID" }
             }
             .form-floating."mb-2" {
                 input .form-control #pageCount name="page_count" type="number"
-                        placeholder="Page Count" value=[details.page_count];
+                        placeholder="Page Count" value=[details.page_count]
+                        required[required(RequiredField::PageCount)];
                 label for="pageCount" { "Page Count" }
             }
+            .form-floating."mb-2" {
+                input .form-control #location name="location" type="text" list="locationList"
+                        placeholder="Location" value=[details.location];
+                label for="location" { "Location (shelf, room...)" }
+                datalist #locationList data-completions="/api/complete/locations" {}
+            }
+            h5 { "Other identifiers" }
+            #identifiers {
+                @for (scheme, value) in &details.identifiers {
+                    (identifier_row(scheme, value))
+                }
+            }
+            .no-js {
+                (identifier_row("", ""))
+            }
+            template #identifierTemplate {
+                (identifier_row("", ""))
+            }
+            button type="button" .btn.btn-outline-secondary.btn-sm."mb-3".js-only
+                data-template="identifierTemplate" data-list="identifiers" {
+                "Add identifier"
+            }
+            h5 { "Links" }
+            #links {
+                @for (label, url) in &details.links {
+                    (link_row(label, url))
+                }
+            }
+            .no-js {
+                (link_row("", ""))
+            }
+            template #linkTemplate {
+                (link_row("", ""))
+            }
+            button type="button" .btn.btn-outline-secondary.btn-sm."mb-3".js-only
+                data-template="linkTemplate" data-list="links" {
+                "Add link"
+            }
+            script nonce=[nonce()] {
+                (PreEscaped(include_str!("./completions.js")))
+            }
+            script nonce=[nonce()] {
+                (PreEscaped(r#"
+                    for (const button of document.querySelectorAll("[data-template]")) {
+                        button.addEventListener("click", () => {
+                            const template = document.getElementById(button.dataset.template)
+                            document.getElementById(button.dataset.list)
+                                .appendChild(template.content.cloneNode(true))
+                        })
+                    }
+
+                    document.addEventListener("click", event => {
+                        if (event.target.matches("[data-remove-row]"))
+                            event.target.closest(".row").remove()
+                    })
+                "#))
+            }
+            @if let Some((source, fetched)) = source {
+                input type="hidden" name="metadata_source" value=(source);
+                input type="hidden" name="metadata_fetched" value=(fetched.to_rfc3339());
+            }
+            @if let Some(return_to) = return_to {
+                input type="hidden" name="return_to" value=(return_to);
+            }
             input type="submit" .btn.btn-primary value=(submit);
         } },
     )
 }
 
+/// SVG image of a QR code encoding `data`
+pub fn qr_code(data: &str) -> Result<PreEscaped<String>, RouteError> {
+    let code = qrcode::QrCode::new(data)?;
+
+    Ok(PreEscaped(
+        code.render::<qrcode::render::svg::Color>()
+            .quiet_zone(false)
+            .min_dimensions(128, 128)
+            .build(),
+    ))
+}
+
+/// Buttons for the links configured in `[[links]]`
+pub fn external_links(state: &State, isbn: &str) -> maud::Markup {
+    html! {
+        @for link in &state.config.links {
+            a .btn.btn-outline-secondary.btn-sm."me-2"."mb-2" target="_blank" rel="noopener"
+                href=(link.url.replace("{isbn}", isbn)) {
+                (link.name)
+            }
+        }
+    }
+}
+
 pub fn make_image_url(state: &State, book: Uuid, user: &User) -> String {
     let image_path = state
         .config
@@ -327,6 +580,23 @@ pub fn make_image_url(state: &State, book: Uuid, user: &User) -> String {
     }
 }
 
+pub fn signed_image_path(user: Uuid, book: Uuid) -> String {
+    format!("/public/signed/{user}/images/{book}")
+}
+
+/// Cover URL for public pages, that does not require to be authenticated
+pub fn make_signed_image_url(state: &State, book: Uuid, user: &User) -> String {
+    state.signer.sign(&signed_image_path(user.id, book))
+}
+
+/// Cover URL usable from private pages if `private` is set, or from public pages otherwise
+pub fn make_cover_url(state: &State, book: Uuid, user: &User, private: bool) -> String {
+    match private {
+        true => make_image_url(state, book, user),
+        false => make_signed_image_url(state, book, user),
+    }
+}
+
 pub fn series_cards(
     state: &State,
     user: &User,
@@ -339,8 +609,9 @@ pub fn series_cards(
                 @for series in series {
                     .col."mb-2" {
                         .card."h-100" style="width: 9.6rem;" {
-                            img src=(make_image_url(state, series.first_volume, user)) .card-img-top
-                                alt="first volume cover" style="height: 14.4rem; width: 9.6rem;";
+                            img src=(make_cover_url(state, series.first_volume, user, private)) .card-img-top
+                                alt=(format!("Cover of the first volume of {}", series.name))
+                                style="height: 14.4rem; width: 9.6rem;";
                             .card-body {
                                 h6 .card-title {
                                     @if private {
@@ -359,17 +630,17 @@ pub fn series_cards(
                             @if series.ongoing || missing_entries {
                                 .card-footer.d-flex.justify-content-evenly {
                                     @if series.ongoing {
-                                        i .bi.bi-journal-plus
+                                        i .bi.bi-journal-plus role="img" aria-label="Ongoing"
                                             data-bs-toggle="tooltip"
                                             data-bs-title="Ongoing" {}
                                     }
                                     @if missing_entries || series.ongoing {
-                                        i .bi.bi-book-half
+                                        @let owned = format!("{}/{}", series.owned_count,
+                                                             series.total_count.unwrap());
+                                        i .bi.bi-book-half role="img"
+                                            aria-label=(format!("{owned} volumes owned"))
                                             data-bs-toggle="tooltip"
-                                            data-bs-title=(
-                                                format!("{}/{}", series.owned_count,
-                                                                 series.total_count.unwrap())
-                                            ) {}
+                                            data-bs-title=(owned) {}
                                     }
                                 }
                             }
@@ -382,15 +653,19 @@ pub fn series_cards(
 }
 
 pub const NO_SORT: Option<fn(&BookPreview, &BookPreview) -> std::cmp::Ordering> = None;
-pub async fn book_cards_for<F>(
+
+struct BookSeriesInfo {
+    name: String,
+    volume: i32,
+    series: Uuid,
+}
+
+/// Cover, authors and series of each book, in the order of `books`
+async fn book_card_data<'a>(
     state: &State,
     user: &User,
-    books: &[BookPreview],
-    sort_by: Option<F>,
-) -> Result<maud::Markup, RouteError>
-where
-    F: Fn(&BookPreview, &BookPreview) -> std::cmp::Ordering,
-{
+    books: &'a [BookPreview],
+) -> Result<Vec<(&'a BookPreview, String, Vec<Author>, Option<BookSeriesInfo>)>, RouteError> {
     let mut conn = state.db.get().await?;
 
     let authors = BookAuthor::belonging_to(books)
@@ -405,14 +680,7 @@ where
         .load::<(BookSeries, SeriesInfo)>(&mut conn)
         .await?;
 
-    #[derive(Debug)]
-    struct BookSeriesInfo {
-        name: String,
-        volume: i32,
-        series: Uuid,
-    }
-
-    let book_series = series
+    let mut book_series = series
         .into_iter()
         .map(|(bookseries, series)| {
             (
@@ -426,22 +694,34 @@ where
         })
         .collect::<HashMap<_, _>>();
 
-    let mut book_data: Vec<_> = authors
+    Ok(authors
         .grouped_by(books)
         .into_iter()
         .zip(books)
         .map(|(a, book)| {
-            Ok((
+            (
                 book,
                 make_image_url(state, book.id, user),
                 a.into_iter().map(|(_, author)| author).collect::<Vec<_>>(),
-                book_series.get(&book.id),
-            ))
+                book_series.remove(&book.id),
+            )
         })
-        .collect::<Result<_, RouteError>>()?;
+        .collect())
+}
+
+pub async fn book_cards_for<F>(
+    state: &State,
+    user: &User,
+    books: &[BookPreview],
+    sort_by: Option<F>,
+) -> Result<maud::Markup, RouteError>
+where
+    F: Fn(&BookPreview, &BookPreview) -> std::cmp::Ordering,
+{
+    let mut book_data = book_card_data(state, user, books).await?;
 
     if let Some(f) = sort_by {
-        book_data.sort_unstable_by(|(book_a, _, _, _), (book_b, _, _, _)| f(book_a, book_b));
+        book_data.sort_by(|(book_a, _, _, _), (book_b, _, _, _)| f(book_a, book_b));
     }
 
     Ok(html! {
@@ -450,7 +730,7 @@ where
                 @for (book, image, authors, series) in book_data {
                     ."col"."mb-2" {
                         .card."h-100" style="width: 9.6rem;" {
-                            img src=(image) .card-img-top alt="book cover"
+                            img src=(image) .card-img-top alt=(format!("Cover of {}", book.title))
                                 style="height: 14.4rem; width: 9.6rem;";
                             .card-body {
                                 h6 .card-title {
@@ -469,22 +749,23 @@ where
                             }
                             @if series.is_some() || book.read || book.owned {
                                 .card-footer.d-flex.justify-content-evenly {
-                                    @if let Some(series) = series {
+                                    @if let Some(series) = &series {
                                         a href=(format!("/series/{}", series.series))
                                           .link-light
                                           data-bs-toggle="tooltip"
                                           data-bs-title=(format!("{} #{}", series.name, series.volume))
+                                          aria-label=(format!("{} #{}", series.name, series.volume))
                                         {
-                                            i .bi.bi-collection {}
+                                            i .bi.bi-collection aria-hidden="true" {}
                                         }
                                     }
                                     @if book.owned {
-                                        i .bi.bi-check-circle
+                                        i .bi.bi-check-circle role="img" aria-label="Owned"
                                             data-bs-toggle="tooltip"
                                             data-bs-title="Owned" {}
                                     }
                                     @if book.read {
-                                        i .bi.bi-book-fill
+                                        i .bi.bi-book-fill role="img" aria-label="Read"
                                             data-bs-toggle="tooltip"
                                             data-bs-title="Read" {}
                                     }
@@ -497,3 +778,48 @@ where
         }
     })
 }
+
+/// Covers of the books standing on shelves, grouped by series and then by author
+pub async fn book_shelf_for(
+    state: &State,
+    user: &User,
+    books: &[BookPreview],
+) -> Result<maud::Markup, RouteError> {
+    let mut book_data = book_card_data(state, user, books).await?;
+
+    // Books of a series stand together in order, the others are grouped by their first author. The
+    // sort is stable, so the books of an author stay in the order of the query.
+    let shelf_key = |authors: &[Author], series: &Option<BookSeriesInfo>| match series {
+        Some(series) => (series.name.to_lowercase(), series.volume),
+        None => (
+            authors
+                .first()
+                .map(|a| a.name.to_lowercase())
+                .unwrap_or_default(),
+            0,
+        ),
+    };
+    book_data.sort_by(|(_, _, authors_a, series_a), (_, _, authors_b, series_b)| {
+        shelf_key(authors_a, series_a).cmp(&shelf_key(authors_b, series_b))
+    });
+
+    Ok(html! {
+        .container {
+            .d-flex.flex-wrap.align-items-end.border-bottom.border-5.border-warning-subtle."px-2"."mb-4"
+                style="row-gap: 1.5rem;" {
+                @for (book, image, authors, series) in book_data {
+                    @let title = match &series {
+                        Some(series) => format!("{} ({} #{})", book.title, series.name, series.volume),
+                        None => book.title.clone(),
+                    };
+                    @let authors = authors.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(", ");
+                    a href=(format!("/book/{}", book.id)) .d-block."me-1"
+                        data-bs-toggle="tooltip" data-bs-title=(format!("{title} by {authors}")) {
+                        img src=(image) .shadow.rounded-end alt=(title) loading="lazy"
+                            style="height: 16rem; width: 10.6rem; object-fit: cover;";
+                    }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,273 @@
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use maud::html;
+use uuid::Uuid;
+
+use crate::{metadata::covers::CoverQuery, models::User, schema::book, State};
+
+use super::{decode_cover, encode_cover, raw_app_page, save_cover, RouteError};
+
+pub(crate) async fn fetch_missing_covers(
+    state: State,
+    user: User,
+) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let books: Vec<(Uuid, String, Option<String>, Option<String>)> = book::table
+        .filter(book::owner.eq(user.id))
+        .select((book::id, book::isbn, book::amazonid, book::googleid))
+        .load(&mut conn)
+        .await?;
+
+    drop(conn);
+
+    let image_dir = state.config.metadata.image_dir.join(user.id.to_string());
+    std::fs::create_dir_all(&image_dir)
+        .map_err(|e| RouteError::ImageSave(image::ImageError::IoError(e)))?;
+
+    let mut missing = 0;
+    let mut fetched = 0;
+
+    for (id, isbn, amazon_id, google_id) in books {
+        let mut image_path = image_dir.join(id.to_string());
+        image_path.set_extension("jpg");
+
+        if image_path.exists() {
+            continue;
+        }
+
+        missing += 1;
+
+        let query = CoverQuery {
+            isbn: &isbn,
+            amazon_id: amazon_id.as_deref(),
+            google_id: google_id.as_deref(),
+        };
+
+        let Some(cover) = state.covers.fetch_cover(&query).await else {
+            continue;
+        };
+
+        let saved = tokio::task::block_in_place(|| -> Result<_, RouteError> {
+            let image = decode_cover(&cover)?;
+            save_cover(&state.config.images, &image, &image_path)
+        });
+
+        match saved {
+            Ok(()) => fetched += 1,
+            Err(e) => tracing::warn!("Invalid cover fetched for '{isbn}': {e:?}"),
+        }
+    }
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container.text-center {
+                h2 { "Missing covers" }
+                p { (format!("Found {fetched} covers for {missing} books without one")) }
+                a .btn.btn-primary href="/" { "Back to the books" }
+            }
+        },
+    ))
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.),
+    }
+}
+
+/// Quantization tables of a JPEG, which are derived from the quality it was encoded with
+fn quantization_tables(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut tables = Vec::new();
+    let Some(mut rest) = data.strip_prefix(&[0xff, 0xd8]) else {
+        return tables;
+    };
+
+    while let [0xff, marker, high, low, ..] = *rest {
+        let end = 2 + usize::from(u16::from_be_bytes([high, low]));
+        let Some(segment) = rest.get(4..end) else {
+            break;
+        };
+
+        match marker {
+            0xdb => tables.push(segment.to_vec()),
+            // The scan starts, there are no more tables
+            0xda => break,
+            _ => (),
+        }
+
+        rest = &rest[end..];
+    }
+
+    tables
+}
+
+/// Re-encode the existing covers of the user following the current `[images]` settings.
+///
+/// A cover is only replaced when it needs to be downscaled, is not a JPEG, or was encoded with
+/// another quality, so that running it again does not degrade the covers.
+pub(crate) async fn reencode_covers(state: State, user: User) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let books: Vec<Uuid> = book::table
+        .filter(book::owner.eq(user.id))
+        .select(book::id)
+        .load(&mut conn)
+        .await?;
+
+    drop(conn);
+
+    let image_dir = state.config.metadata.image_dir.join(user.id.to_string());
+
+    // Tables of the covers encoded with the current quality
+    let reference = encode_cover(&state.config.images, &image::DynamicImage::new_rgb8(8, 8))
+        .map_err(RouteError::ImageSave)?;
+    let reference = quantization_tables(&reference);
+
+    let mut total = 0;
+    let mut reencoded = 0;
+    let mut before = 0;
+    let mut after = 0;
+
+    for id in books {
+        let mut image_path = image_dir.join(id.to_string());
+        image_path.set_extension("jpg");
+
+        if !image_path.exists() {
+            continue;
+        }
+
+        total += 1;
+
+        let replaced = tokio::task::block_in_place(|| -> Result<_, RouteError> {
+            let data = std::fs::read(&image_path)?;
+            let image = decode_cover(&data)?;
+
+            let replace = !state.config.images.fits(image.width(), image.height())
+                || image::guess_format(&data)? != image::ImageFormat::Jpeg
+                || quantization_tables(&data) != reference;
+
+            let new_size = match replace {
+                true => {
+                    let encoded = encode_cover(&state.config.images, &image)
+                        .map_err(RouteError::ImageSave)?;
+
+                    // The cover is never left half written
+                    let temp_path = image_path.with_extension("jpg.tmp");
+                    std::fs::write(&temp_path, &encoded)?;
+                    std::fs::rename(&temp_path, &image_path)?;
+
+                    encoded.len()
+                }
+                false => data.len(),
+            };
+
+            Ok((replace, data.len() as u64, new_size as u64))
+        });
+
+        match replaced {
+            Ok((replaced, old_size, new_size)) => {
+                if replaced {
+                    reencoded += 1;
+                }
+                before += old_size;
+                after += new_size;
+            }
+            Err(e) => tracing::warn!("Could not re-encode the cover of '{id}': {e:?}"),
+        }
+    }
+
+    let saved = format_size(before.saturating_sub(after));
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container.text-center {
+                h2 { "Re-encoded covers" }
+                p { (format!("Re-encoded {reencoded} of {total} covers, saving {saved}")) }
+                a .btn.btn-primary href="/" { "Back to the books" }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+
+    use crate::testing::{
+        body_text, book_form, book_id, test_cover, user_id, TestApp, TEST_USER, USER_HEADER,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reencode_covers() {
+        let app = TestApp::with_config(
+            r#"
+            [images]
+            max_width = 6
+            "#,
+        )
+        .await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").file("user_cover", "cover.png", test_cover()),
+        )
+        .await;
+        app.post_multipart("/add", book_form("Eric", "9780575046368"))
+            .await;
+
+        let user = user_id(&app, TEST_USER).await;
+        let image_dir = app.state.config.metadata.image_dir.join(user.to_string());
+        let mort = image_dir.join(format!("{}.jpg", book_id(&app, "9780552131063").await));
+        let eric = image_dir.join(format!("{}.jpg", book_id(&app, "9780575046368").await));
+
+        let added = std::fs::read(&mort).unwrap();
+        assert_eq!(
+            image::guess_format(&added).unwrap(),
+            image::ImageFormat::Jpeg
+        );
+        assert_eq!(image::load_from_memory(&added).unwrap().width(), 6);
+
+        // Cover stored before the image settings were configured
+        std::fs::write(&eric, test_cover()).unwrap();
+
+        let reencode = || {
+            app.request(
+                Request::post("/covers/reencode")
+                    .header(USER_HEADER, TEST_USER)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = reencode().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_text(response)
+            .await
+            .contains("Re-encoded 1 of 2 covers"));
+
+        let reencoded = std::fs::read(&eric).unwrap();
+        assert_eq!(
+            image::guess_format(&reencoded).unwrap(),
+            image::ImageFormat::Jpeg
+        );
+        assert_eq!(image::load_from_memory(&reencoded).unwrap().width(), 6);
+        assert_eq!(std::fs::read(&mort).unwrap(), added);
+
+        // The covers already following the settings are kept as they are
+        let response = reencode().await;
+        assert!(body_text(response)
+            .await
+            .contains("Re-encoded 0 of 2 covers"));
+        assert_eq!(std::fs::read(&eric).unwrap(), reencoded);
+    }
+}
@@ -0,0 +1,100 @@
+//! `Content-Security-Policy` of the pages, allowing the inline scripts through a nonce generated
+//! for each request
+
+use axum::{
+    extract::Request,
+    http::{
+        header::{CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY},
+        HeaderValue,
+    },
+    middleware::Next,
+    response::Response,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+use crate::State;
+
+tokio::task_local! {
+    static NONCE: String;
+}
+
+/// Nonce of the request being handled, to set on the `script` elements. `None` when no policy
+/// is configured.
+pub(crate) fn nonce() -> Option<String> {
+    NONCE.try_with(String::clone).ok()
+}
+
+pub(crate) async fn content_security_policy(
+    state: State,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(config) = &state.config.csp else {
+        return next.run(request).await;
+    };
+
+    let nonce = BASE64_STANDARD.encode(rand::random::<[u8; 16]>());
+    let policy = config.policy.replace("{nonce}", &nonce);
+
+    let mut response = NONCE.scope(nonce, next.run(request)).await;
+
+    let header = match config.report_only {
+        true => CONTENT_SECURITY_POLICY_REPORT_ONLY,
+        false => CONTENT_SECURITY_POLICY,
+    };
+    let policy = HeaderValue::from_str(&policy).expect("policy was validated");
+    response.headers_mut().insert(header, policy);
+
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::header::CONTENT_SECURITY_POLICY;
+
+    use crate::testing::{body_text, TestApp};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn content_security_policy() {
+        let app = TestApp::new().await;
+
+        let response = app.get("/add").await;
+        assert!(response.headers().get(CONTENT_SECURITY_POLICY).is_none());
+        assert!(!body_text(response).await.contains("nonce="));
+
+        let app = TestApp::with_config(
+            r#"
+            [csp]
+            "#,
+        )
+        .await;
+
+        let response = app.get("/add").await;
+        let policy = response.headers()[CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let nonce = policy
+            .split("'nonce-")
+            .nth(1)
+            .and_then(|rest| rest.split('\'').next())
+            .unwrap()
+            .to_owned();
+
+        let page = body_text(response).await;
+        let scripts = page.matches("<script").count();
+        assert!(scripts > 0);
+        assert_eq!(
+            page.matches(&format!(r#"nonce="{nonce}""#)).count(),
+            scripts
+        );
+        assert!(!page.contains("onclick="));
+
+        // Each request gets its own nonce
+        let response = app.get("/add").await;
+        assert!(!response.headers()[CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .contains(&nonce));
+    }
+}
@@ -1,6 +1,4 @@
-use std::{fs::OpenOptions, io::BufWriter};
-
-use axum::{extract::Path, response::Redirect};
+use axum::{extract::Path, http::HeaderMap, response::Redirect};
 use base64::prelude::*;
 use diesel::prelude::*;
 use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
@@ -9,19 +7,28 @@ use uuid::Uuid;
 
 use crate::{
     metadata::NullableBookDetails,
-    models::{BookAuthor, BookComplete, BookId, BookSeries, BookTag, Series, User},
+    models::{
+        BookAuthor, BookComplete, BookId, BookIdentifier, BookLink, BookSeries, BookTag, Series,
+        User,
+    },
     routes::components::book_form,
-    schema::{author, book, bookauthor, bookseries, booktag, series, tag},
-    State,
+    schema::{
+        author, book, bookauthor, bookidentifier, booklink, bookseries, booktag, series, tag,
+    },
+    AppState, State,
 };
 
-use super::{app_page, BookInfo, RouteError};
+use super::{
+    app_page_with_breadcrumbs, redirect_back, referer_path,
+    refresh::{record_field_sources, RefreshField},
+    save_cover, BookInfo, Crumb, RouteError,
+};
 
 pub(crate) async fn do_edit_book(
     state: State,
     user: User,
     id: Path<Uuid>,
-    data: BookInfo,
+    mut data: BookInfo,
 ) -> Result<Redirect, RouteError> {
     let mut conn = state.db.get().await?;
 
@@ -36,15 +43,83 @@ pub(crate) async fn do_edit_book(
         return Err(RouteError::NotFound);
     }
 
+    drop(conn);
+
+    let return_to = data.return_to.take();
+    edit_book_details(&state, &user, *id, data).await?;
+
+    Ok(redirect_back(
+        return_to.as_deref(),
+        &format!("/book/{}", *id),
+    ))
+}
+
+/// Replace the details of the book `id` of `user` with `data`, recording the changed fields as
+/// entered by hand
+pub(super) async fn edit_book_details(
+    state: &AppState,
+    user: &User,
+    id: Uuid,
+    data: BookInfo,
+) -> Result<(), RouteError> {
+    let before = book_details(state, user, id).await?;
+    update_book(state, user, id, data).await?;
+    let after = book_details(state, user, id).await?;
+
+    let edited: Vec<_> = RefreshField::ALL
+        .iter()
+        .copied()
+        .filter(|field| field.value(&before) != field.value(&after))
+        .collect();
+    if !edited.is_empty() {
+        record_field_sources(state, id, &edited, None).await?;
+    }
+
+    Ok(())
+}
+
+/// Replace the details of the book `id`, which must be owned by `user`. The cover is only
+/// changed if `data` contains one.
+pub(super) async fn update_book(
+    state: &AppState,
+    user: &User,
+    id: Uuid,
+    mut data: BookInfo,
+) -> Result<(), RouteError> {
+    let mut conn = state.db.get().await?;
+
     conn.transaction(|c| {
         async {
+            data.match_existing_names(c).await?;
+            data.apply_tag_implications(c).await?;
+
             diesel::delete(bookauthor::table)
-                .filter(bookauthor::book.eq(*id))
+                .filter(bookauthor::book.eq(id))
                 .execute(c)
                 .await?;
 
             diesel::delete(booktag::table)
-                .filter(booktag::book.eq(*id))
+                .filter(booktag::book.eq(id))
+                .execute(c)
+                .await?;
+
+            diesel::delete(booklink::table)
+                .filter(booklink::book.eq(id))
+                .execute(c)
+                .await?;
+
+            diesel::insert_into(booklink::table)
+                .values(data.book_links(id))
+                .execute(c)
+                .await?;
+
+            diesel::delete(bookidentifier::table)
+                .filter(bookidentifier::book.eq(id))
+                .execute(c)
+                .await?;
+
+            diesel::insert_into(bookidentifier::table)
+                .values(data.book_identifiers(id))
                 .execute(c)
                 .await?;
 
@@ -60,7 +135,7 @@ pub(crate) async fn do_edit_book(
                 .execute(c)
                 .await?;
 
-            diesel::update(&BookId { id: *id })
+            diesel::update(&BookId { id })
                 .set(data.book)
                 .execute(c)
                 .await?;
@@ -85,7 +160,7 @@ pub(crate) async fn do_edit_book(
                     .await?;
 
                 let book_series = BookSeries {
-                    book: *id,
+                    book: id,
                     series: series_id,
                     number: volume,
                 };
@@ -109,7 +184,7 @@ pub(crate) async fn do_edit_book(
                 .values(
                     &author_ids
                         .into_iter()
-                        .map(|author| BookAuthor { book: *id, author })
+                        .map(|author| BookAuthor { book: id, author })
                         .collect::<Vec<_>>(),
                 )
                 .execute(c)
@@ -125,7 +200,7 @@ pub(crate) async fn do_edit_book(
                 .values(
                     &tag_ids
                         .into_iter()
-                        .map(|tag| BookTag { book: *id, tag })
+                        .map(|tag| BookTag { book: id, tag })
                         .collect::<Vec<_>>(),
                 )
                 .execute(c)
@@ -140,18 +215,8 @@ pub(crate) async fn do_edit_book(
             image_path.set_extension("jpg");
 
             if let Some(img) = data.image {
-                tokio::task::block_in_place(|| -> Result<_, RouteError> {
-                    let file = OpenOptions::new()
-                        .truncate(true)
-                        .write(true)
-                        .read(true)
-                        .open(&image_path)
-                        .map_err(|e| RouteError::ImageSave(image::ImageError::IoError(e)))?;
-
-                    img.write_to(&mut BufWriter::new(file), image::ImageFormat::Jpeg)
-                        .map_err(RouteError::ImageSave)?;
-
-                    Ok(())
+                tokio::task::block_in_place(|| {
+                    save_cover(&state.config.images, &img, &image_path)
                 })?;
             }
 
@@ -159,21 +224,20 @@ pub(crate) async fn do_edit_book(
         }
         .scope_boxed()
     })
-    .await?;
-
-    Ok(Redirect::to(&format!("/book/{}", *id)))
+    .await
 }
 
-pub(crate) async fn edit_book(
-    state: State,
-    user: User,
-    id: Path<Uuid>,
-) -> Result<maud::Markup, RouteError> {
+/// Current details of the book `id` of `user`, without its cover
+pub(super) async fn book_details(
+    state: &AppState,
+    user: &User,
+    id: Uuid,
+) -> Result<NullableBookDetails, RouteError> {
     let mut conn = state.db.get().await?;
 
     let book = book::table
         .filter(book::owner.eq(user.id))
-        .find(*id)
+        .find(id)
         .select(BookComplete::as_select())
         .get_result(&mut conn)
         .await
@@ -183,7 +247,7 @@ pub(crate) async fn edit_book(
         })?;
 
     let series = bookseries::table
-        .find(*id)
+        .find(id)
         .inner_join(series::table)
         .select((series::name, bookseries::number))
         .get_result(&mut conn)
@@ -202,19 +266,20 @@ pub(crate) async fn edit_book(
         .load::<String>(&mut conn)
         .await?;
 
-    let image_path = state
-        .config
-        .metadata
-        .image_dir
-        .join(user.id.to_string())
-        .join(format!("{}.jpg", *id));
+    let links = BookLink::belonging_to(&book)
+        .order(booklink::id)
+        .select((booklink::label, booklink::url))
+        .load::<(String, String)>(&mut conn)
+        .await?;
 
-    let covert_art_b64 = match image_path.exists() {
-        true => Some(BASE64_STANDARD.encode(tokio::fs::read(image_path).await?)),
-        false => None,
-    };
+    let identifiers = BookIdentifier::belonging_to(&book)
+        .select((bookidentifier::scheme, bookidentifier::value))
+        .load::<(String, String)>(&mut conn)
+        .await?
+        .into_iter()
+        .collect();
 
-    let book_details = NullableBookDetails {
+    Ok(NullableBookDetails {
         isbn: Some(book.isbn),
         title: Some(book.title),
         authors,
@@ -224,20 +289,139 @@ pub(crate) async fn edit_book(
         publisher: book.publisher,
         language: book.language,
         google_id: book.googleid,
+        goodreads_id: book.goodreadsid,
         amazon_id: book.amazonid,
         librarything_id: book.librarythingid,
+        identifiers,
         page_count: book.pagecount,
         owned: book.owned,
         read: book.read,
-        covert_art_b64,
+        reread: book.reread,
+        priority: book.priority,
+        location: book.location,
+        summary_spoilers: book.summary_spoilers,
+        covert_art_b64: None,
+        cover_candidates_b64: Vec::new(),
         series,
+        links,
+    })
+}
+
+pub(crate) async fn edit_book(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<maud::Markup, RouteError> {
+    let mut book_details = book_details(&state, &user, *id).await?;
+
+    let image_path = state
+        .config
+        .metadata
+        .image_dir
+        .join(user.id.to_string())
+        .join(format!("{}.jpg", *id));
+
+    book_details.covert_art_b64 = match image_path.exists() {
+        true => Some(BASE64_STANDARD.encode(tokio::fs::read(image_path).await?)),
+        false => None,
     };
 
-    Ok(app_page(
+    let title = book_details.title.clone().unwrap_or_default();
+    let return_to = referer_path(&headers, &format!("/book/{}/edit", *id));
+
+    Ok(app_page_with_breadcrumbs(
         super::Page::Books,
         &user,
+        &[Crumb::new(title, format!("/book/{}", *id))],
+        "Edit",
         html! {
-            (book_form(&state, &user, book_details, "Edit book").await?)
+            @let action = format!("/book/{}/edit", *id);
+            (book_form(&state, book_details, None, &action, "Edit book", return_to).await?)
         },
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::book,
+        testing::{body_text, book_form, book_id, location, MultipartForm, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn edit_book() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                MultipartForm::new()
+                    .text("title", "Mort (Discworld)")
+                    .text("isbn", "9780552131063")
+                    .text("summary", "Death takes an apprentice")
+                    .text("author", "Sir Terry Pratchett")
+                    .text("owned_box", "on"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&response), format!("/book/{id}"));
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("Mort (Discworld)"));
+        assert!(page.contains("Death takes an apprentice"));
+        assert!(page.contains("Sir Terry Pratchett"));
+        assert!(!page.contains(">Terry Pratchett<"));
+        assert!(page.contains("Owned"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lenient_add_strict_edit() {
+        let app = TestApp::new().await;
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Mort", "9780552131063")
+                    .text("page_count", "about 300")
+                    .text("provider", "Mock"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let id = book_id(&app, "9780552131063").await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let page_count: Option<i32> = book::table
+            .find(id)
+            .select(book::pagecount)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(page_count, None);
+        drop(conn);
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                book_form("Mort", "9780552131063").text("page_count", "about 300"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                book_form("Mort", "9780552131063").text("provider", "Mock"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
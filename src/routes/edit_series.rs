@@ -1,4 +1,4 @@
-use axum::{extract::Path, Form};
+use axum::{extract::Path, http::HeaderMap, Form};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use maud::html;
@@ -6,11 +6,13 @@ use uuid::Uuid;
 
 use crate::{
     models::{SeriesInfo, User},
-    schema::series,
-    State,
+    schema::{book, bookseries, series},
+    volumes, State,
 };
 
-use super::{app_page, RouteError};
+use super::{
+    app_page_with_breadcrumbs, raw_app_page, redirect_back, referer_path, Crumb, RouteError,
+};
 
 fn empty_string_as_none<'de, D>(de: D) -> Result<Option<i32>, D::Error>
 where
@@ -42,8 +44,10 @@ where
 pub(crate) struct SeriesForm {
     name: String,
     ongoing_box: Option<super::CheckboxTick>,
+    archived_box: Option<super::CheckboxTick>,
     #[serde(deserialize_with = "empty_string_as_none")]
     total_count: Option<i32>,
+    return_to: Option<String>,
 }
 
 impl SeriesForm {
@@ -52,6 +56,7 @@ impl SeriesForm {
             name: self.name,
             total_count: self.total_count,
             ongoing: self.ongoing_box.is_some(),
+            archived: self.archived_box.is_some(),
         }
     }
 }
@@ -62,6 +67,7 @@ impl SeriesForm {
 struct SeriesEdit {
     name: String,
     ongoing: bool,
+    archived: bool,
     #[diesel(treat_none_as_null = true)]
     total_count: Option<i32>,
 }
@@ -70,23 +76,88 @@ pub(crate) async fn do_series_edit(
     state: State,
     user: User,
     id: Path<Uuid>,
-    Form(form): Form<SeriesForm>,
+    Form(mut form): Form<SeriesForm>,
 ) -> Result<axum::response::Redirect, RouteError> {
     let mut conn = state.db.get().await?;
 
+    let return_to = form.return_to.take();
+
     diesel::update(series::table)
         .filter(series::id.eq(*id).and(series::owner.eq(user.id)))
         .set(form.changeset())
         .execute(&mut conn)
         .await?;
 
-    Ok(axum::response::Redirect::to(&format!("/series/{}", *id)))
+    Ok(redirect_back(
+        return_to.as_deref(),
+        &format!("/series/{}", *id),
+    ))
+}
+
+/// Fill the volume count of a series from MangaUpdates or Wikidata
+pub(crate) async fn do_series_lookup(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+) -> Result<maud::Markup, RouteError> {
+    if !volumes::can_lookup(&state.config) {
+        return Err(RouteError::NotFound);
+    }
+
+    let mut conn = state.db.get().await?;
+
+    let name: String = series::table
+        .find(*id)
+        .filter(series::owner.eq(user.id))
+        .select(series::name)
+        .get_result(&mut conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => RouteError::NotFound,
+            _ => e.into(),
+        })?;
+
+    let count = volumes::lookup(&state.config, &name).await;
+
+    if let Some(count) = count {
+        diesel::update(series::table)
+            .filter(series::id.eq(*id))
+            .set((
+                series::total_count.eq(count.total),
+                series::ongoing.eq(count.ongoing),
+            ))
+            .execute(&mut conn)
+            .await?;
+    }
+
+    Ok(raw_app_page(
+        Some(super::Page::Series),
+        &user,
+        html! {
+            .container.text-center {
+                h2 { (name) }
+                @match count {
+                    Some(count) => {
+                        p {
+                            (format!("Found {} volumes", count.total))
+                            @if count.ongoing { ", the series is ongoing" }
+                        }
+                    },
+                    None => {
+                        p { "The number of volumes of this series was not found" }
+                    },
+                }
+                a .btn.btn-primary href=(format!("/series/{}/edit", *id)) { "Back to the series" }
+            }
+        },
+    ))
 }
 
 pub(crate) async fn series_edit(
     state: State,
     user: User,
     id: Path<Uuid>,
+    headers: HeaderMap,
 ) -> Result<maud::Markup, RouteError> {
     let mut conn = state.db.get().await?;
 
@@ -101,9 +172,28 @@ pub(crate) async fn series_edit(
             _ => e.into(),
         })?;
 
-    Ok(app_page(
+    let other_series: Vec<(Uuid, String)> = series::table
+        .filter(series::owner.eq(user.id).and(series::id.ne(*id)))
+        .select((series::id, series::name))
+        .order(series::name.asc())
+        .load(&mut conn)
+        .await?;
+
+    let volumes: Vec<(Uuid, String, i32)> = bookseries::table
+        .inner_join(book::table)
+        .filter(bookseries::series.eq(*id))
+        .select((book::id, book::title, bookseries::number))
+        .order(bookseries::number.asc())
+        .load(&mut conn)
+        .await?;
+
+    let return_to = referer_path(&headers, &format!("/series/{}/edit", *id));
+
+    Ok(app_page_with_breadcrumbs(
         super::Page::Series,
         &user,
+        &[Crumb::new(s.name.clone(), format!("/series/{}", *id))],
+        "Edit",
         html! {
             form .container-sm.align-items-center method="POST" {
                 .container.text-center {
@@ -118,15 +208,143 @@ pub(crate) async fn series_edit(
                     input .form-check-input type="checkbox" name="ongoing_box" #ongoingBox checked[s.ongoing];
                     label .form-check-label for="ongoingBox" { "Ongoing" }
                 }
+                .form-check {
+                    input .form-check-input type="checkbox" name="archived_box" #archivedBox checked[s.archived];
+                    label .form-check-label for="archivedBox" { "Archived (hidden from the series and ongoing pages)" }
+                }
                 .form-floating."mb-2" {
                     input .form-control required #totalCount name="total_count" type="number"
                             placeholder="Total Count" value=[s.total_count];
                     label for="totalCount" { "Total Count" }
                 }
+                @if let Some(return_to) = return_to {
+                    input type="hidden" name="return_to" value=(return_to);
+                }
                 .container.text-center {
                     input  type="submit" .btn.btn-primary value="Edit series";
                 }
             }
+            @if s.total_count.is_none() && volumes::can_lookup(&state.config) {
+                form .container-sm.text-center."mt-2" method="POST" action=(format!("/series/{}/lookup", *id)) {
+                    input type="submit" .btn.btn-secondary value="Look up volume count";
+                }
+            }
+            @if !other_series.is_empty() {
+                form .container-sm."mt-4" method="POST" action=(format!("/series/{}/merge", *id)) {
+                    h3 { "Merge into another series" }
+                    .form-floating."mb-2" {
+                        select .form-select #mergeTarget name="target" {
+                            @for (other_id, name) in &other_series {
+                                option value=(other_id) { (name) }
+                            }
+                        }
+                        label for="mergeTarget" { "Target series" }
+                    }
+                    .form-check {
+                        input .form-check-input type="radio" name="mode" value="append" #modeAppend checked;
+                        label .form-check-label for="modeAppend" {
+                            "Number the volumes after the last one of the target"
+                        }
+                    }
+                    .form-check."mb-2" {
+                        input .form-check-input type="radio" name="mode" value="keep" #modeKeep;
+                        label .form-check-label for="modeKeep" { "Keep the volume numbers" }
+                    }
+                    .container.text-center {
+                        input type="submit" .btn.btn-danger value="Merge series";
+                    }
+                }
+            }
+            @if !volumes.is_empty() {
+                form .container-sm."mt-4" method="POST" action=(format!("/series/{}/split", *id)) {
+                    h3 { "Split into a new series" }
+                    @for (book_id, title, number) in &volumes {
+                        .form-check {
+                            input .form-check-input type="checkbox" name="book" value=(book_id)
+                                id=(format!("split-{book_id}"));
+                            label .form-check-label for=(format!("split-{book_id}")) {
+                                (format!("{number}: {title}"))
+                            }
+                        }
+                    }
+                    .form-floating."my-2" {
+                        input .form-control required #splitName name="name" type="text"
+                            placeholder="Name";
+                        label for="splitName" { "New series name" }
+                    }
+                    .container.text-center {
+                        input type="submit" .btn.btn-primary value="Split series";
+                    }
+                }
+            }
         },
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::series,
+        testing::{body_text, book_form, TestApp, OTHER_USER, USER_HEADER},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn series_volume_lookup() {
+        let app = TestApp::with_config(
+            r#"
+            [mangaupdates]
+            api = "http://127.0.0.1:1"
+            "#,
+        )
+        .await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("series_name", "Discworld")
+                .text("series_volume", "4"),
+        )
+        .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let series_id: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let page = body_text(app.get(&format!("/series/{series_id}/edit")).await).await;
+        assert!(page.contains("Look up volume count"));
+
+        let response = app
+            .post_form(&format!("/series/{series_id}/lookup"), "")
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_text(response).await.contains("was not found"));
+
+        let response = app
+            .request(
+                Request::post(format!("/series/{series_id}/lookup"))
+                    .header(USER_HEADER, OTHER_USER)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let app = TestApp::new().await;
+        let response = app
+            .post_form(&format!("/series/{series_id}/lookup"), "")
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
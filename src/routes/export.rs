@@ -0,0 +1,510 @@
+//! Exports of the books of a user, optionally bundled with their covers
+
+use std::{
+    collections::HashMap,
+    io::{Cursor, Write},
+};
+
+use axum::{
+    extract::Query,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    metadata::{isbn::isbn13_to_10, NullableBookDetails},
+    models::{BookAuthor, BookComplete, BookIdentifier, BookLink, BookTag, User},
+    schema::{author, book, booklink, bookseries, series, tag},
+    State,
+};
+
+use super::RouteError;
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ExportQuery {
+    /// Produce a zip archive containing the export and the covers
+    #[serde(default)]
+    covers: bool,
+}
+
+/// Load all the books of `user` with their authors, tags and series
+pub(super) async fn export_books(
+    state: &State,
+    user: &User,
+) -> Result<Vec<(Uuid, NullableBookDetails)>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let books: Vec<BookComplete> = book::table
+        .filter(book::owner.eq(user.id))
+        .order(book::sort_title)
+        .select(BookComplete::as_select())
+        .load(&mut conn)
+        .await?;
+
+    let authors: Vec<(BookAuthor, String)> = BookAuthor::belonging_to(&books)
+        .inner_join(author::table)
+        .select((BookAuthor::as_select(), author::name))
+        .load(&mut conn)
+        .await?;
+
+    let tags: Vec<(BookTag, String)> = BookTag::belonging_to(&books)
+        .inner_join(tag::table)
+        .select((BookTag::as_select(), tag::name))
+        .load(&mut conn)
+        .await?;
+
+    let links: Vec<BookLink> = BookLink::belonging_to(&books)
+        .order(booklink::id)
+        .select(BookLink::as_select())
+        .load(&mut conn)
+        .await?;
+
+    let identifiers: Vec<BookIdentifier> = BookIdentifier::belonging_to(&books)
+        .select(BookIdentifier::as_select())
+        .load(&mut conn)
+        .await?;
+
+    let series: HashMap<Uuid, (String, i32)> = bookseries::table
+        .inner_join(series::table)
+        .filter(series::owner.eq(user.id))
+        .select((bookseries::book, series::name, bookseries::number))
+        .load::<(Uuid, String, i32)>(&mut conn)
+        .await?
+        .into_iter()
+        .map(|(book, name, number)| (book, (name, number)))
+        .collect();
+
+    let authors = authors.grouped_by(&books);
+    let tags = tags.grouped_by(&books);
+    let links = links.grouped_by(&books);
+    let identifiers = identifiers.grouped_by(&books);
+
+    Ok(books
+        .into_iter()
+        .zip(authors)
+        .zip(tags)
+        .zip(links)
+        .zip(identifiers)
+        .map(|((((book, authors), tags), links), identifiers)| {
+            let details = NullableBookDetails {
+                isbn: Some(book.isbn),
+                title: Some(book.title),
+                authors: authors.into_iter().map(|(_, name)| name).collect(),
+                tags: tags.into_iter().map(|(_, name)| name).collect(),
+                summary: Some(book.summary),
+                published: book.published,
+                publisher: book.publisher,
+                language: book.language,
+                google_id: book.googleid,
+                goodreads_id: book.goodreadsid,
+                amazon_id: book.amazonid,
+                librarything_id: book.librarythingid,
+                identifiers: identifiers
+                    .into_iter()
+                    .map(|identifier| (identifier.scheme, identifier.value))
+                    .collect(),
+                page_count: book.pagecount,
+                owned: book.owned,
+                read: book.read,
+                reread: book.reread,
+                priority: book.priority,
+                location: book.location,
+                summary_spoilers: book.summary_spoilers,
+                covert_art_b64: None,
+                cover_candidates_b64: Vec::new(),
+                series: series.get(&book.id).cloned(),
+                links: links
+                    .into_iter()
+                    .map(|link| (link.label, link.url))
+                    .collect(),
+            };
+
+            (book.id, details)
+        })
+        .collect())
+}
+
+pub(super) fn attachment(content_type: &str, file_name: &str, data: Vec<u8>) -> Response {
+    (
+        [
+            (CONTENT_TYPE, content_type.to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+/// Zip archive containing `data` as `file_name`, and the covers of `books` in `covers/` named by
+/// their ISBN
+fn archive(
+    state: &State,
+    user: &User,
+    file_name: &str,
+    data: &[u8],
+    books: &[(Uuid, NullableBookDetails)],
+) -> Result<Vec<u8>, RouteError> {
+    let image_dir = state.config.metadata.image_dir.join(user.id.to_string());
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(file_name, SimpleFileOptions::default())?;
+    zip.write_all(data)?;
+
+    // Covers are already compressed
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    for (id, details) in books {
+        let image_path = image_dir.join(format!("{id}.jpg"));
+        if !image_path.exists() {
+            continue;
+        }
+
+        let isbn = details.isbn.as_deref().unwrap_or_default();
+        zip.start_file(format!("covers/{isbn}.jpg"), options)?;
+        zip.write_all(&std::fs::read(image_path)?)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Serve an export of the books, bundling it with the covers if requested
+pub(super) fn export_response(
+    state: &State,
+    user: &User,
+    query: &ExportQuery,
+    content_type: &str,
+    file_name: &str,
+    data: Vec<u8>,
+    books: &[(Uuid, NullableBookDetails)],
+) -> Result<Response, RouteError> {
+    if !query.covers {
+        return Ok(attachment(content_type, file_name, data));
+    }
+
+    let archive = tokio::task::block_in_place(|| archive(state, user, file_name, &data, books))?;
+
+    Ok(attachment("application/zip", "bouquineur.zip", archive))
+}
+
+pub(crate) async fn export_json(
+    state: State,
+    user: User,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, RouteError> {
+    let books = export_books(&state, &user).await?;
+
+    let data =
+        serde_json::to_vec_pretty(&books.iter().map(|(_, details)| details).collect::<Vec<_>>())
+            .expect("book details can be serialized");
+
+    export_response(
+        &state,
+        &user,
+        &query,
+        "application/json",
+        "books.json",
+        data,
+        &books,
+    )
+}
+
+/// Row of the Goodreads import format, the other columns can be left out
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct GoodreadsRow<'a> {
+    title: &'a str,
+    author: &'a str,
+    #[serde(rename = "Additional Authors")]
+    additional_authors: String,
+    #[serde(rename = "ISBN")]
+    isbn: String,
+    #[serde(rename = "ISBN13")]
+    isbn13: &'a str,
+    publisher: &'a str,
+    #[serde(rename = "Number of Pages")]
+    number_of_pages: Option<i32>,
+    #[serde(rename = "Year Published")]
+    year_published: Option<i32>,
+    #[serde(rename = "Date Added")]
+    date_added: String,
+    bookshelves: String,
+    #[serde(rename = "Exclusive Shelf")]
+    exclusive_shelf: &'static str,
+    #[serde(rename = "Read Count")]
+    read_count: u32,
+    #[serde(rename = "Owned Copies")]
+    owned_copies: u32,
+}
+
+/// Goodreads shelves are lowercase and can't contain spaces
+fn shelf_name(tag: &str) -> String {
+    tag.trim().to_lowercase().replace(char::is_whitespace, "-")
+}
+
+/// Export in the CSV format imported by Goodreads, which is also understood by StoryGraph
+pub(crate) async fn export_goodreads(
+    state: State,
+    user: User,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, RouteError> {
+    let books = export_books(&state, &user).await?;
+
+    let mut conn = state.db.get().await?;
+    let added: HashMap<Uuid, chrono::DateTime<chrono::Utc>> = book::table
+        .filter(book::owner.eq(user.id))
+        .select((book::id, book::added))
+        .load(&mut conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    for (id, details) in &books {
+        use chrono::Datelike;
+
+        let isbn = details.isbn.as_deref().unwrap_or_default();
+
+        let mut authors = details.authors.iter().map(String::as_str);
+
+        writer
+            .serialize(GoodreadsRow {
+                title: details.title.as_deref().unwrap_or_default(),
+                author: authors.next().unwrap_or_default(),
+                additional_authors: authors.collect::<Vec<_>>().join(", "),
+                isbn: isbn13_to_10(isbn).unwrap_or_default(),
+                isbn13: isbn,
+                publisher: details.publisher.as_deref().unwrap_or_default(),
+                number_of_pages: details.page_count,
+                year_published: details.published.map(|d| d.year()),
+                date_added: added
+                    .get(id)
+                    .map(|d| d.format("%Y/%m/%d").to_string())
+                    .unwrap_or_default(),
+                bookshelves: details
+                    .tags
+                    .iter()
+                    .map(|tag| shelf_name(tag))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                exclusive_shelf: match details.read {
+                    true => "read",
+                    false => "to-read",
+                },
+                read_count: details.read as u32,
+                owned_copies: details.owned as u32,
+            })
+            .expect("book details can be serialized");
+    }
+
+    let data = writer.into_inner().expect("writing to a vector can't fail");
+
+    export_response(
+        &state,
+        &user,
+        &query,
+        "text/csv",
+        "goodreads_library_export.csv",
+        data,
+        &books,
+    )
+}
+
+/// One ISBN per line for the owned books, the format of the ISBN import of Inventaire
+pub(crate) async fn export_isbns(state: State, user: User) -> Result<Response, RouteError> {
+    let books = export_books(&state, &user).await?;
+
+    let data: String = books
+        .iter()
+        .filter(|(_, details)| details.owned)
+        .filter_map(|(_, details)| details.isbn.as_deref())
+        .map(|isbn| format!("{isbn}\n"))
+        .collect();
+
+    Ok(attachment("text/plain", "isbns.txt", data.into_bytes()))
+}
+
+/// Statements about an edition, keyed by the Wikidata property they use. Only the properties
+/// taking a literal value are included, the others need to be matched to Wikidata items.
+#[derive(serde::Serialize)]
+struct WikidataEdition<'a> {
+    /// ISBN-13
+    #[serde(rename = "P212")]
+    isbn13: &'a str,
+    /// ISBN-10
+    #[serde(rename = "P957", skip_serializing_if = "Option::is_none")]
+    isbn10: Option<String>,
+    /// Title
+    #[serde(rename = "P1476")]
+    title: &'a str,
+    /// Author name strings
+    #[serde(rename = "P2093")]
+    authors: &'a [String],
+    /// Publication date
+    #[serde(rename = "P577", skip_serializing_if = "Option::is_none")]
+    published: Option<chrono::NaiveDate>,
+    /// Number of pages
+    #[serde(rename = "P1104", skip_serializing_if = "Option::is_none")]
+    pages: Option<i32>,
+    /// OCLC control number
+    #[serde(rename = "P243", skip_serializing_if = "Option::is_none")]
+    oclc: Option<&'a str>,
+    /// Google Books ID
+    #[serde(rename = "P675", skip_serializing_if = "Option::is_none")]
+    google_id: Option<&'a str>,
+    /// Goodreads version/edition ID
+    #[serde(rename = "P2969", skip_serializing_if = "Option::is_none")]
+    goodreads_id: Option<&'a str>,
+    /// Amazon Standard Identification Number
+    #[serde(rename = "P5749", skip_serializing_if = "Option::is_none")]
+    amazon_id: Option<&'a str>,
+}
+
+/// JSON dump of the owned books using Wikidata properties, to contribute them to open databases
+pub(crate) async fn export_wikidata(state: State, user: User) -> Result<Response, RouteError> {
+    let books = export_books(&state, &user).await?;
+
+    let editions: Vec<_> = books
+        .iter()
+        .filter(|(_, details)| details.owned)
+        .map(|(_, details)| {
+            let isbn = details.isbn.as_deref().unwrap_or_default();
+
+            WikidataEdition {
+                isbn13: isbn,
+                isbn10: isbn13_to_10(isbn),
+                title: details.title.as_deref().unwrap_or_default(),
+                authors: &details.authors,
+                published: details.published,
+                pages: details.page_count,
+                oclc: details.identifiers.get("oclc").map(String::as_str),
+                google_id: details.google_id.as_deref(),
+                goodreads_id: details.goodreads_id.as_deref(),
+                amazon_id: details.amazon_id.as_deref(),
+            }
+        })
+        .collect();
+
+    let data = serde_json::to_vec_pretty(&editions).expect("editions can be serialized");
+
+    Ok(attachment("application/json", "wikidata.json", data))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+
+    use crate::testing::{body_text, book_form, test_cover, TestApp};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn export() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("series_name", "Death")
+                .text("series_volume", "1")
+                .file("user_cover", "cover.png", test_cover()),
+        )
+        .await;
+        app.post_multipart("/add", book_form("Eric", "9780575046368"))
+            .await;
+
+        let response = app.get("/export/json").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/json");
+
+        let books: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(books[0]["title"], "Eric");
+        assert_eq!(books[1]["title"], "Mort");
+        assert_eq!(books[1]["authors"][0], "Terry Pratchett");
+        assert_eq!(books[1]["series"], serde_json::json!(["Death", 1]));
+
+        let response = app.get("/export/json?covers=true").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/zip");
+
+        let data = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut files: Vec<_> = archive.file_names().collect();
+        files.sort();
+        assert_eq!(files, ["books.json", "covers/9780552131063.jpg"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn export_goodreads() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("tag", "Comic Fantasy")
+                .text("published", "1987-11-12")
+                .text("read_box", "on"),
+        )
+        .await;
+
+        let response = app.get("/export/goodreads").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/csv");
+
+        let body = body_text(response).await;
+        let mut lines = body.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "Title,Author,Additional Authors,ISBN,ISBN13,Publisher,Number of Pages,Year Published,\
+             Date Added,Bookshelves,Exclusive Shelf,Read Count,Owned Copies"
+        );
+
+        let row = lines.next().unwrap();
+        assert!(row.starts_with("Mort,Terry Pratchett,,0552131067,9780552131063,,,1987,"));
+        assert!(row.ends_with(",comic-fantasy,read,1,0"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn export_open_databases() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("published", "1987-11-12")
+                .text("owned_box", "on")
+                .text("identifier_scheme", "oclc")
+                .text("identifier_value", "16714279"),
+        )
+        .await;
+        app.post_multipart("/add", book_form("Guards! Guards!", "9780552134637"))
+            .await;
+
+        // Only the owned books are exported
+        let response = app.get("/export/isbns").await;
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        assert_eq!(body_text(response).await, "9780552131063\n");
+
+        let response = app.get("/export/wikidata").await;
+        let editions: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(
+            editions,
+            serde_json::json!([{
+                "P212": "9780552131063",
+                "P957": "0552131067",
+                "P1476": "Mort",
+                "P2093": ["Terry Pratchett"],
+                "P577": "1987-11-12",
+                "P243": "16714279",
+            }])
+        );
+    }
+}
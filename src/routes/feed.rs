@@ -0,0 +1,194 @@
+//! Atom feeds of the books recently added by a user, for feed readers
+
+use axum::{
+    extract::Path,
+    http::{header::CONTENT_TYPE, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use maud::{html, PreEscaped};
+use uuid::Uuid;
+
+use crate::{
+    models::User,
+    schema::{author, book, bookauthor, users},
+    State,
+};
+
+use super::{absolute_url, components, RouteError};
+
+/// Number of books in a feed
+const FEED_LEN: i64 = 20;
+
+async fn atom_feed(
+    state: &State,
+    user: &User,
+    headers: &HeaderMap,
+    self_path: &str,
+    private: bool,
+) -> Result<Response, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let books: Vec<(Uuid, String, String, bool, DateTime<Utc>)> = book::table
+        .filter(book::owner.eq(user.id))
+        .order((book::added.desc(), book::sort_title))
+        .limit(FEED_LEN)
+        .select((
+            book::id,
+            book::title,
+            book::summary,
+            book::summary_spoilers,
+            book::added,
+        ))
+        .load(&mut conn)
+        .await?;
+
+    let ids: Vec<Uuid> = books.iter().map(|(id, ..)| *id).collect();
+    let authors: Vec<(Uuid, String)> = bookauthor::table
+        .inner_join(author::table)
+        .filter(bookauthor::book.eq_any(&ids))
+        .order(author::name)
+        .select((bookauthor::book, author::name))
+        .load(&mut conn)
+        .await?;
+
+    let updated = books
+        .iter()
+        .map(|(.., added)| *added)
+        .max()
+        .unwrap_or_else(Utc::now);
+
+    let feed = html! {
+        (PreEscaped(r#"<?xml version="1.0" encoding="utf-8"?>"#))
+        feed xmlns="http://www.w3.org/2005/Atom" {
+            title { (format!("Books added by {}", user.name)) }
+            id { (format!("urn:uuid:{}", user.id)) }
+            updated { (updated.to_rfc3339()) }
+            author { name { (user.name) } }
+            link rel="self" href=(absolute_url(headers, self_path)) {}
+            @for (id, title, summary, spoilers, added) in &books {
+                @let book_authors: Vec<_> = authors
+                    .iter()
+                    .filter(|(book, _)| book == id)
+                    .map(|(_, name)| name.as_str())
+                    .collect();
+                @let cover = absolute_url(
+                    headers,
+                    &components::make_signed_image_url(state, *id, user),
+                );
+                @let content = html! {
+                    p { img src=(cover) alt=(title); }
+                    @if !book_authors.is_empty() {
+                        p { "By " (book_authors.join(", ")) }
+                    }
+                    @if !spoilers {
+                        (PreEscaped(state.config.html.clean(summary)))
+                    }
+                };
+                entry {
+                    title { (title) }
+                    id { (format!("urn:uuid:{id}")) }
+                    updated { (added.to_rfc3339()) }
+                    published { (added.to_rfc3339()) }
+                    @if private {
+                        link rel="alternate" href=(absolute_url(headers, &format!("/book/{id}"))) {}
+                    }
+                    content type="html" { (content.into_string()) }
+                }
+            }
+        }
+    };
+
+    Ok((
+        [(CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        feed.into_string(),
+    )
+        .into_response())
+}
+
+pub(crate) async fn feed(
+    state: State,
+    user: User,
+    headers: HeaderMap,
+) -> Result<Response, RouteError> {
+    atom_feed(&state, &user, &headers, "/feed.atom", true).await
+}
+
+/// Feed of a user who made it public from their profile, without the links to the private pages
+pub(crate) async fn public_feed(
+    state: State,
+    Path(user): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let user = users::table
+        .find(user)
+        .filter(users::public_feed.eq(true))
+        .select(User::as_select())
+        .get_result(&mut conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => RouteError::NotFound,
+            _ => e.into(),
+        })?;
+    drop(conn);
+
+    let path = format!("/public/{}/feed.atom", user.id);
+    atom_feed(&state, &user, &headers, &path, false).await
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{header::CONTENT_TYPE, Request, StatusCode},
+    };
+
+    use crate::testing::{body_text, book_form, book_id, user_id, TestApp, TEST_USER};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn atom_feed() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        app.post_multipart("/add", book_form("Eric", "9780575046368"))
+            .await;
+
+        let response = app.get("/feed.atom").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            "application/atom+xml; charset=utf-8"
+        );
+        let feed = body_text(response).await;
+        assert!(feed.starts_with(r#"<?xml version="1.0" encoding="utf-8"?><feed"#));
+        assert!(feed.find("<title>Eric</title>") < feed.find("<title>Mort</title>"));
+        assert!(feed.contains("By Terry Pratchett"));
+        let mort = book_id(&app, "9780552131063").await;
+        assert!(feed.contains(&format!(r#"href="http://localhost/book/{mort}""#)));
+
+        let user = user_id(&app, TEST_USER).await;
+        let public = || {
+            app.request(
+                Request::get(format!("/public/{user}/feed.atom"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(public().await.status(), StatusCode::NOT_FOUND);
+
+        app.post_form("/profile", "feed_box=on").await;
+
+        let response = public().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let feed = body_text(response).await;
+        assert!(feed.contains("<title>Mort</title>"));
+        assert!(!feed.contains("/book/"));
+        assert!(feed.contains(&format!("/public/signed/{user}/images/")));
+    }
+}
@@ -0,0 +1,50 @@
+const missingFields = document.getElementById("missingFields")
+
+// Copy the values found by another provider into the fields that are still empty
+missingFields.addEventListener("htmx:afterSwap", () => {
+    const form = document.getElementById("bookForm")
+
+    const found = {}
+    for (const data of missingFields.querySelectorAll("data[data-field]")) {
+        (found[data.dataset.field] ??= []).push(data.value)
+    }
+
+    const inputs = [
+        "title", "summary", "published", "publisher", "language", "google_id", "goodreads_id",
+        "amazon_id", "librarything_id", "page_count",
+    ]
+    for (const name of inputs) {
+        const input = form.elements[name]
+        if (found[name] && input && input.value == "") {
+            input.value = found[name][0]
+        }
+    }
+
+    const seriesName = document.getElementById("seriesInput")
+    const seriesVolume = document.getElementById("seriesVolume")
+    if (found.series_name && seriesName.value == "" && seriesVolume.value == "") {
+        seriesName.value = found.series_name[0]
+        seriesVolume.value = found.series_volume[0]
+        seriesName.dispatchEvent(new Event("input"))
+    }
+
+    if (found.author && authorList.children.length == 0) {
+        found.author.forEach(authorAdd)
+    }
+    if (found.tag && tagList.children.length == 0) {
+        found.tag.forEach(tagAdd)
+    }
+
+    const coverInput = document.getElementById("coverArtInput")
+    if (found.fetched_cover && !document.getElementById("fetchedCover") && coverInput.files.length == 0) {
+        const fetchedCover = document.createElement("input")
+        fetchedCover.type = "hidden"
+        fetchedCover.name = "fetched_cover"
+        fetchedCover.id = "fetchedCover"
+        fetchedCover.value = found.fetched_cover[0]
+        form.appendChild(fetchedCover)
+
+        document.getElementById("coverArt").src = "data:image/jpg;base64," + found.fetched_cover[0]
+        coverInput.required = false
+    }
+})
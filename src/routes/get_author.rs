@@ -1,7 +1,7 @@
 use axum::extract::Path;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
-use maud::html;
+use maud::{html, PreEscaped};
 
 use crate::{
     models::{Author, BookAuthor, BookPreview, User},
@@ -10,7 +10,7 @@ use crate::{
     State,
 };
 
-use super::{app_page, RouteError};
+use super::{app_page_with_breadcrumbs, nonce, RouteError};
 
 pub(crate) async fn get_author(
     state: State,
@@ -32,6 +32,7 @@ pub(crate) async fn get_author(
     let author_books: Vec<BookPreview> = BookAuthor::belonging_to(&author_info)
         .inner_join(book::table)
         .filter(book::owner.eq(user.id))
+        .order(book::sort_title)
         .select(BookPreview::as_select())
         .get_results(&mut conn)
         .await?;
@@ -47,12 +48,25 @@ pub(crate) async fn get_author(
         (Some(a), Some(b)) => a.cmp(&b),
     };
 
-    Ok(app_page(
+    Ok(app_page_with_breadcrumbs(
         super::Page::Books,
         &user,
+        &[],
+        &author_info.name,
         html! {
             .text-center {
                 h2 { (author_info.name) }
+                @if !state.metadata.is_empty() {
+                    #authorWorks data-url=(format!("/author/{}/works", *id)) {}
+                    script nonce=[nonce()] {
+                        (PreEscaped(r#"
+                            const authorWorks = document.getElementById("authorWorks")
+                            fetch(authorWorks.dataset.url)
+                                .then(rsp => rsp.ok ? rsp.text() : "")
+                                .then(works => authorWorks.innerHTML = works)
+                        "#))
+                    }
+                }
                 (book_cards_for(&state, &user, &author_books, Some(date_sort)).await?)
             }
         },
@@ -1,20 +1,26 @@
-use axum::extract::Path;
+use axum::{extract::Path, http::HeaderMap};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use maud::{html, PreEscaped};
 use uuid::Uuid;
 
 use crate::{
-    models::{Author, BookAuthor, BookComplete, BookTag, User},
-    schema::{author, book, bookseries, series, tag},
+    models::{
+        Author, BookAuthor, BookComplete, BookFieldSource, BookIdentifier, BookLink, BookTag, User,
+    },
+    schema::{author, book, bookfieldsource, bookidentifier, booklink, bookseries, series, tag},
     State,
 };
 
-use super::{app_page, RouteError};
+use super::{
+    absolute_url, app_page_with_breadcrumbs, components::qr_code, nonce, refresh::RefreshField,
+    short_link::short_code, Crumb, RouteError,
+};
 
 pub(crate) async fn get_book(
     state: State,
     user: User,
+    headers: HeaderMap,
     id: Path<Uuid>,
 ) -> Result<maud::Markup, RouteError> {
     let mut conn = state.db.get().await?;
@@ -40,7 +46,7 @@ pub(crate) async fn get_book(
 
     let image_url = super::components::make_image_url(&state, *id, &user);
 
-    let summary = ammonia::clean(&book.summary);
+    let summary = state.config.html.clean(&book.summary);
 
     let authors = BookAuthor::belonging_to(&book)
         .inner_join(author::table)
@@ -54,17 +60,52 @@ pub(crate) async fn get_book(
         .load::<String>(&mut conn)
         .await?;
 
-    Ok(app_page(
+    let identifiers = BookIdentifier::belonging_to(&book)
+        .order(bookidentifier::scheme)
+        .select((bookidentifier::scheme, bookidentifier::value))
+        .load::<(String, String)>(&mut conn)
+        .await?;
+
+    let links = BookLink::belonging_to(&book)
+        .order(booklink::id)
+        .select((booklink::label, booklink::url))
+        .load::<(String, String)>(&mut conn)
+        .await?;
+
+    let field_sources = BookFieldSource::belonging_to(&book)
+        .order(bookfieldsource::changed.desc())
+        .select(BookFieldSource::as_select())
+        .load(&mut conn)
+        .await?;
+
+    let qr = qr_code(&absolute_url(&headers, &format!("/book/{}", *id)))?;
+
+    let crumbs: Vec<_> = series
+        .iter()
+        .map(|(name, _, id)| Crumb::new(name, format!("/series/{id}")))
+        .collect();
+
+    Ok(app_page_with_breadcrumbs(
         super::Page::Books,
         &user,
+        &crumbs,
+        &book.title,
         html! {
             .container.text-center {
                 h2 {
                     (book.title)
-                    a .ms-2.btn.btn-primary href=(format!("{}/edit", *id)) { i .bi.bi-pencil {} }
+                    a .ms-2.btn.btn-primary href=(format!("{}/edit", *id)) aria-label="Edit the book" {
+                        i .bi.bi-pencil aria-hidden="true" {}
+                    }
+                    @if !state.metadata.is_empty() {
+                        a .ms-2.btn.btn-secondary href=(format!("{}/refresh", *id))
+                            title="Refresh metadata" aria-label="Refresh metadata" {
+                            i .bi.bi-arrow-repeat aria-hidden="true" {}
+                        }
+                    }
                 }
                 ."mb-2" {
-                    img style="height: 24rem" src=(image_url) alt="cover art";
+                    img style="height: 24rem" src=(image_url) alt=(format!("Cover of {}", book.title));
                 }
                 .container {
                     @if let Some((name, idx, id)) = series {
@@ -89,13 +130,34 @@ pub(crate) async fn get_book(
                         }
                     }
                     br;
-                    @if book.owned || book.read {
+                    @if book.owned || book.read || book.reread || book.priority.is_some() || book.giveaway {
                         @if book.owned {
                             .span .badge.text-bg-info.me-2 { "Owned" }
                         }
                         @if book.read {
                             .span .badge.text-bg-info.me-2 { "Read" }
                         }
+                        @if book.reread {
+                            .span .badge.text-bg-info.me-2 { "Re-read" }
+                        }
+                        @if let Some(priority) = book.priority {
+                            .span .badge.text-bg-warning.me-2 { (format!("Priority {priority}")) }
+                        }
+                        @if book.giveaway {
+                            .span .badge.text-bg-secondary.me-2 { "To give away" }
+                        }
+                        br;
+                    }
+                    @if !book.owned && state.config.library.is_some() {
+                        span #availability data-url=(format!("/book/{}/availability", *id)) {}
+                        script nonce=[nonce()] {
+                            (PreEscaped(r#"
+                                const availability = document.getElementById("availability")
+                                fetch(availability.dataset.url)
+                                    .then(rsp => rsp.ok ? rsp.text() : "")
+                                    .then(badge => availability.innerHTML = badge)
+                            "#))
+                        }
                         br;
                     }
                     @for tag in tags {
@@ -103,7 +165,23 @@ pub(crate) async fn get_book(
                     }
                 }
                 .container."mb-2" {
-                    (PreEscaped(summary))
+                    @if book.summary_spoilers && !book.read {
+                        div #summarySpoiler .spoiler
+                            title="This summary contains spoilers, click to reveal it" {
+                            (PreEscaped(summary))
+                        }
+                        script nonce=[nonce()] {
+                            (PreEscaped(r#"
+                                const spoiler = document.getElementById("summarySpoiler")
+                                spoiler.addEventListener("click", () => {
+                                    spoiler.classList.remove("spoiler")
+                                    spoiler.removeAttribute("title")
+                                }, { once: true })
+                            "#))
+                        }
+                    } @else {
+                        (PreEscaped(summary))
+                    }
                     hr;
                     .text-start {
                         @if let Some(date) = book.published {
@@ -122,10 +200,346 @@ pub(crate) async fn get_book(
                             "Page count: " (page_count)
                             br;
                         }
+                        @if let Some(location) = &book.location {
+                            "Location: " (location)
+                            br;
+                        }
                         "ISBN: " (book.isbn)
+                        @for (scheme, value) in &identifiers {
+                            br;
+                            (scheme.to_uppercase()) ": " (value)
+                        }
+                        @if let Some(source) = &book.metadata_source {
+                            br;
+                            "Metadata from "
+                            (state.metadata.get(source).map(|p| p.name()).unwrap_or(source.as_str()))
+                            @if let Some(fetched) = book.metadata_fetched {
+                                (fetched.format(" on %d/%m/%Y"))
+                            }
+                        }
+                        @if !field_sources.is_empty() {
+                            details #fieldSources {
+                                summary { "Changed fields" }
+                                @for source in &field_sources {
+                                    @let field = RefreshField::from_id(&source.field)
+                                        .map_or(source.field.as_str(), |f| f.name());
+                                    (field) ": "
+                                    @match &source.provider {
+                                        Some(p) => {
+                                            @let name = state.metadata.get(p).map(|p| p.name());
+                                            "from " (name.unwrap_or(p.as_str()))
+                                        },
+                                        None => "entered by hand",
+                                    }
+                                    (source.changed.format(" on %d/%m/%Y"))
+                                    br;
+                                }
+                            }
+                        }
+                        @if let Some(goodreads_id) = &book.goodreadsid {
+                            br;
+                            "Goodreads: "
+                            a href=(format!("https://www.goodreads.com/book/show/{goodreads_id}"))
+                                target="_blank" rel="noopener" {
+                                (goodreads_id)
+                            }
+                        }
+                    }
+                }
+                @if !state.config.links.is_empty() {
+                    .container."mb-2" {
+                        (super::components::external_links(&state, &book.isbn))
+                    }
+                }
+                @if !links.is_empty() {
+                    h5 { "Links" }
+                    ul .list-unstyled {
+                        @for (label, url) in &links {
+                            li { a href=(url) target="_blank" rel="noopener" { (label) } }
+                        }
+                    }
+                }
+                .d-inline-block.bg-white."p-2"."mb-2" title="Scan to open this book" {
+                    (qr)
+                }
+                @let short_link = format!("/b/{}", short_code(*id));
+                p .small.text-body-secondary {
+                    "Short link: " a href=(short_link) { (short_link) }
+                }
+                @if book.owned || book.giveaway {
+                    form ."mb-2" method="POST" action=(format!("/book/{}/giveaway", *id)) {
+                        input type="submit" .btn.btn-outline-secondary.btn-sm
+                            value=(if book.giveaway { "Keep this book" } else { "Give this book away" });
                     }
                 }
             }
         },
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::{book, bookidentifier, booklink},
+        testing::{body_text, book_form, book_id, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn book_links() {
+        let app = TestApp::new().await;
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Mort", "9780552131063")
+                    .text("link_label", "Review")
+                    .text("link_url", "https://example.com/mort")
+                    .text("link_label", "")
+                    .text("link_url", "javascript:alert(1)"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let id = book_id(&app, "9780552131063").await;
+
+        let body = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(body.contains(r#"href="https://example.com/mort""#));
+        assert!(body.contains(">Review<"));
+        assert!(!body.contains("javascript:"));
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                book_form("Mort", "9780552131063")
+                    .text("link_label", "")
+                    .text("link_url", "javascript:alert(1)"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                book_form("Mort", "9780552131063")
+                    .text("link_label", "")
+                    .text("link_url", "https://example.org/death"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let links: Vec<(String, String)> = booklink::table
+            .filter(booklink::book.eq(id))
+            .select((booklink::label, booklink::url))
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            links,
+            [(
+                "https://example.org/death".to_string(),
+                "https://example.org/death".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn book_identifiers() {
+        let app = TestApp::new().await;
+
+        let body = body_text(app.get("/add?isbn=9780552134637").await).await;
+        assert!(body.contains(r#"value="oclc""#));
+        assert!(body.contains(r#"value="22596384""#));
+
+        let response = app
+            .post_multipart(
+                "/add",
+                book_form("Guards! Guards!", "9780552134637")
+                    .text("identifier_scheme", " OCLC ")
+                    .text("identifier_value", "22596384")
+                    .text("identifier_scheme", "doi")
+                    .text("identifier_value", ""),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let id = book_id(&app, "9780552134637").await;
+
+        let body = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(body.contains("OCLC: 22596384"));
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                book_form("Guards! Guards!", "9780552134637")
+                    .text("identifier_scheme", "")
+                    .text("identifier_value", "22596384"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                book_form("Guards! Guards!", "9780552134637")
+                    .text("identifier_scheme", "asin")
+                    .text("identifier_value", "0552134635"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let identifiers: Vec<(String, String)> = bookidentifier::table
+            .filter(bookidentifier::book.eq(id))
+            .select((bookidentifier::scheme, bookidentifier::value))
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            identifiers,
+            [("asin".to_string(), "0552134635".to_string())]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn metadata_source() {
+        let app = TestApp::new().await;
+
+        let page = body_text(app.get("/add?isbn=9780552131063").await).await;
+        assert!(page.contains(r#"name="metadata_source" value="Mock""#));
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("metadata_source", "Mock")
+                .text("metadata_fetched", "2025-01-03T10:00:00+00:00"),
+        )
+        .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("Metadata from Mock on 03/01/2025"));
+
+        app.post_multipart("/add", book_form("Eric", "9780575046368"))
+            .await;
+        let id = book_id(&app, "9780575046368").await;
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(!page.contains("Metadata from"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn goodreads_id() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").text("goodreads_id", "386372"),
+        )
+        .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let goodreads_id: Option<String> = book::table
+            .find(id)
+            .select(book::goodreadsid)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(goodreads_id.as_deref(), Some("386372"));
+        drop(conn);
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("https://www.goodreads.com/book/show/386372"));
+
+        let page = body_text(app.get(&format!("/book/{id}/edit")).await).await;
+        assert!(page.contains(r#"value="386372""#));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn external_links() {
+        let app = TestApp::with_config(
+            r#"
+            [[links]]
+            name = "Find in a library"
+            url = "https://search.worldcat.org/search?q=bn:{isbn}"
+            "#,
+        )
+        .await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("Find in a library"));
+        assert!(page.contains("https://search.worldcat.org/search?q=bn:9780552131063"));
+
+        app.post_form("/wishlist/isbn", "isbn=9780552134637").await;
+        let page = body_text(app.get("/wishlist").await).await;
+        assert!(page.contains("https://search.worldcat.org/search?q=bn:9780552134637"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn html_policy() {
+        let summary = r#"<p dir="ltr">Death <img src="https://example.com/death.jpg"><script>alert(1)</script></p>"#;
+
+        let app = TestApp::new().await;
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").text("summary", summary),
+        )
+        .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains(r#"<p>Death <img src="https://example.com/death.jpg"></p>"#));
+
+        let app = TestApp::with_config(
+            r#"
+            [html]
+            deny_tags = ["img"]
+            generic_attributes = ["dir"]
+            "#,
+        )
+        .await;
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").text("summary", summary),
+        )
+        .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains(r#"<p dir="ltr">Death </p>"#));
+        assert!(!page.contains("alert(1)"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn summary_spoilers() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").text("spoilers_box", "on"),
+        )
+        .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("This summary contains spoilers"));
+
+        app.post_multipart(
+            &format!("/book/{id}/edit"),
+            book_form("Mort", "9780552131063")
+                .text("spoilers_box", "on")
+                .text("read_box", "on"),
+        )
+        .await;
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("A summary"));
+        assert!(!page.contains("This summary contains spoilers"));
+    }
+}
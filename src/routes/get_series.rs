@@ -11,7 +11,7 @@ use crate::{
     State,
 };
 
-use super::{app_page, RouteError};
+use super::{app_page_with_breadcrumbs, RouteError};
 
 pub(crate) async fn get_series(
     state: State,
@@ -40,9 +40,11 @@ pub(crate) async fn get_series(
         .get_results(&mut conn)
         .await?;
 
-    Ok(app_page(
+    Ok(app_page_with_breadcrumbs(
         super::Page::Series,
         &user,
+        &[],
+        &series_info.name,
         html! {
             .text-center {
                 h2 {
@@ -50,9 +52,28 @@ pub(crate) async fn get_series(
                     @if series_info.ongoing {
                         " (Ongoing)"
                     }
-                    a .ms-2.btn.btn-primary href=(format!("{}/edit", *id)) { i .bi.bi-pencil {} }
+                    @if series_info.archived {
+                        " (Archived)"
+                    }
+                    a .ms-2.btn.btn-primary href=(format!("{}/edit", *id)) aria-label="Edit the series" {
+                        i .bi.bi-pencil aria-hidden="true" {}
+                    }
+                    a .ms-2.btn.btn-primary href=(format!("{}/reorder", *id))
+                        aria-label="Reorder the volumes" {
+                        i .bi.bi-sort-numeric-down aria-hidden="true" {}
+                    }
                 }
                 (book_cards_for(&state, &user, &series, NO_SORT).await?)
+                @if !state.metadata.is_empty() {
+                    form .container-sm.mt-3 method="POST" action=(format!("/series/{}/import", *id)) {
+                        .form-floating."mb-2" {
+                            textarea .form-control #isbns name="isbns" style="height: 100px"
+                                placeholder="ISBNs" required {}
+                            label for="isbns" { "ISBNs to add as the next volumes" }
+                        }
+                        input type="submit" .btn.btn-primary value="Import volumes";
+                    }
+                }
             }
         },
     ))
@@ -0,0 +1,448 @@
+//! Books that are going to leave the library, to be exported and then archived or deleted once
+//! they are given away
+
+use axum::{
+    extract::Path,
+    response::{Redirect, Response},
+    Form,
+};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use maud::{html, PreEscaped};
+use uuid::Uuid;
+
+use crate::{
+    models::{BookAuthor, BookComplete, BookPreview, User},
+    schema::{
+        author, book, bookauthor, bookfieldsource, bookidentifier, booklink, bookseries, booktag,
+        tag,
+    },
+    AppState, State,
+};
+
+use super::{
+    components::{book_cards_for, location_list, tag_list, NO_SORT},
+    export::attachment,
+    nonce, raw_app_page, CheckboxTick, RouteError,
+};
+
+#[derive(serde::Deserialize)]
+pub(crate) struct GiveawayFilter {
+    #[serde(default)]
+    location: String,
+    #[serde(default)]
+    tag: String,
+    /// Only move the books that were read
+    read: Option<CheckboxTick>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GiveawayAction {
+    /// Keep the books in the library as not owned
+    Archive,
+    /// Remove the books and their covers
+    Delete,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct GiveawayDone {
+    action: GiveawayAction,
+}
+
+async fn giveaway_books(
+    conn: &mut diesel_async::AsyncPgConnection,
+    user: &User,
+) -> Result<Vec<Uuid>, diesel::result::Error> {
+    book::table
+        .filter(book::owner.eq(user.id))
+        .filter(book::giveaway.eq(true))
+        .select(book::id)
+        .load(conn)
+        .await
+}
+
+pub(crate) async fn giveaway(state: State, user: User) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let books: Vec<BookPreview> = book::table
+        .filter(book::owner.eq(user.id))
+        .filter(book::giveaway.eq(true))
+        .order(book::sort_title)
+        .select(BookPreview::as_select())
+        .load(&mut conn)
+        .await?;
+
+    drop(conn);
+
+    let locations = location_list(&state, &user).await?;
+    let tags = tag_list(&state, &user).await?;
+    let cards = book_cards_for(&state, &user, &books, NO_SORT).await?;
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "To give away" }
+                form .row.g-2.align-items-center."mb-3" method="POST" action="/giveaway/add" {
+                    .col-auto {
+                        input .form-control name="location" type="text" list="locationList"
+                            placeholder="Location" aria-label="Location";
+                        datalist #locationList {
+                            @for location in &locations {
+                                option { (location) }
+                            }
+                        }
+                    }
+                    .col-auto {
+                        input .form-control name="tag" type="text" list="tagList"
+                            placeholder="Tag" aria-label="Tag";
+                        datalist #tagList {
+                            @for tag in &tags {
+                                option { (tag) }
+                            }
+                        }
+                    }
+                    .col-auto.form-check {
+                        input .form-check-input type="checkbox" name="read" #readFilter;
+                        label .form-check-label for="readFilter" { "Only read books" }
+                    }
+                    .col-auto {
+                        input type="submit" .btn.btn-primary value="Move the owned books matching";
+                    }
+                }
+                @if books.is_empty() {
+                    p .text-center { "No book is waiting to be given away" }
+                } @else {
+                    .text-center {
+                        (cards)
+                    }
+                    .text-center.mt-3 {
+                        a .btn.btn-secondary."me-2" href="/giveaway/export" { "Export the list" }
+                    }
+                    form .text-center.mt-3 method="POST" action="/giveaway/done" {
+                        p { "Once the books have left the house:" }
+                        button .btn.btn-warning."me-2" type="submit" name="action" value="archive" {
+                            "Mark them as not owned"
+                        }
+                        button #deleteGiveaway .btn.btn-danger type="submit" name="action"
+                            value="delete" {
+                            "Delete them"
+                        }
+                        script nonce=[nonce()] {
+                            (PreEscaped(r#"
+                                const deleteGiveaway = document.getElementById("deleteGiveaway")
+                                deleteGiveaway.addEventListener("click", event => {
+                                    if (!confirm("Delete these books from the library?"))
+                                        event.preventDefault()
+                                })
+                            "#))
+                        }
+                    }
+                }
+            }
+        },
+    ))
+}
+
+/// Move the owned books matching the filters to the giveaway list, at least one filter is required
+pub(crate) async fn do_giveaway_add(
+    state: State,
+    user: User,
+    Form(filter): Form<GiveawayFilter>,
+) -> Result<Redirect, RouteError> {
+    let location = filter.location.trim();
+    let tag = filter.tag.trim();
+
+    if location.is_empty() && tag.is_empty() && filter.read.is_none() {
+        return Err(RouteError::InvalidForm);
+    }
+
+    let mut query = book::table
+        .filter(book::owner.eq(user.id))
+        .filter(book::owned.eq(true))
+        .select(book::id)
+        .into_boxed();
+
+    if !location.is_empty() {
+        query = query.filter(book::location.eq(location.to_string()));
+    }
+
+    if !tag.is_empty() {
+        let tagged = booktag::table
+            .inner_join(tag::table)
+            .filter(tag::name.eq(tag.to_string()))
+            .select(booktag::book);
+        query = query.filter(book::id.eq_any(tagged));
+    }
+
+    if filter.read.is_some() {
+        query = query.filter(book::read.eq(true));
+    }
+
+    let mut conn = state.db.get().await?;
+    let ids: Vec<Uuid> = query.load(&mut conn).await?;
+
+    diesel::update(book::table)
+        .filter(book::id.eq_any(&ids))
+        .set(book::giveaway.eq(true))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(Redirect::to("/giveaway"))
+}
+
+/// Add or remove a single book from the giveaway list
+pub(crate) async fn do_toggle_giveaway(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let updated = diesel::update(book::table)
+        .filter(book::owner.eq(user.id))
+        .filter(book::id.eq(*id))
+        .set(book::giveaway.eq(diesel::dsl::not(book::giveaway)))
+        .execute(&mut conn)
+        .await?;
+
+    if updated == 0 {
+        return Err(RouteError::NotFound);
+    }
+
+    Ok(Redirect::to(&format!("/book/{}", *id)))
+}
+
+/// CSV list of the books to give away, with their authors and location
+pub(crate) async fn giveaway_export(state: State, user: User) -> Result<Response, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let books: Vec<BookComplete> = book::table
+        .filter(book::owner.eq(user.id))
+        .filter(book::giveaway.eq(true))
+        .order(book::sort_title)
+        .select(BookComplete::as_select())
+        .load(&mut conn)
+        .await?;
+
+    let authors: Vec<(BookAuthor, String)> = BookAuthor::belonging_to(&books)
+        .inner_join(author::table)
+        .select((BookAuthor::as_select(), author::name))
+        .load(&mut conn)
+        .await?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["Title", "Authors", "ISBN", "Location"])
+        .expect("writing to a vector can't fail");
+
+    for (book, authors) in books.iter().zip(authors.grouped_by(&books)) {
+        let authors: Vec<_> = authors.into_iter().map(|(_, name)| name).collect();
+
+        writer
+            .write_record([
+                book.title.as_str(),
+                &authors.join(", "),
+                &book.isbn,
+                book.location.as_deref().unwrap_or_default(),
+            ])
+            .expect("writing to a vector can't fail");
+    }
+
+    let data = writer.into_inner().expect("writing to a vector can't fail");
+
+    Ok(attachment("text/csv", "giveaway.csv", data))
+}
+
+/// Archive or delete all the books of the giveaway list
+pub(crate) async fn do_giveaway_done(
+    state: State,
+    user: User,
+    Form(form): Form<GiveawayDone>,
+) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+    let ids = giveaway_books(&mut conn, &user).await?;
+
+    match form.action {
+        GiveawayAction::Archive => {
+            diesel::update(book::table)
+                .filter(book::id.eq_any(&ids))
+                .set((
+                    book::owned.eq(false),
+                    book::giveaway.eq(false),
+                    book::location.eq(None::<String>),
+                ))
+                .execute(&mut conn)
+                .await?;
+        }
+        GiveawayAction::Delete => delete_books(&mut conn, &state, &user, &ids).await?,
+    }
+
+    Ok(Redirect::to("/giveaway"))
+}
+
+/// Delete the books `ids` of `user` with everything attached to them, including their covers
+pub(super) async fn delete_books(
+    conn: &mut diesel_async::AsyncPgConnection,
+    state: &AppState,
+    user: &User,
+    ids: &[Uuid],
+) -> Result<(), RouteError> {
+    conn.transaction(|c| {
+        async {
+            diesel::delete(bookauthor::table)
+                .filter(bookauthor::book.eq_any(ids))
+                .execute(c)
+                .await?;
+
+            diesel::delete(booktag::table)
+                .filter(booktag::book.eq_any(ids))
+                .execute(c)
+                .await?;
+
+            diesel::delete(booklink::table)
+                .filter(booklink::book.eq_any(ids))
+                .execute(c)
+                .await?;
+
+            diesel::delete(bookidentifier::table)
+                .filter(bookidentifier::book.eq_any(ids))
+                .execute(c)
+                .await?;
+
+            diesel::delete(bookfieldsource::table)
+                .filter(bookfieldsource::book.eq_any(ids))
+                .execute(c)
+                .await?;
+
+            diesel::delete(bookseries::table)
+                .filter(bookseries::book.eq_any(ids))
+                .execute(c)
+                .await?;
+
+            diesel::delete(book::table)
+                .filter(book::id.eq_any(ids))
+                .filter(book::owner.eq(user.id))
+                .execute(c)
+                .await?;
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    let image_dir = state.config.metadata.image_dir.join(user.id.to_string());
+    for id in ids {
+        let path = image_dir.join(format!("{id}.jpg"));
+        if path.exists() {
+            tokio::fs::remove_file(path).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::book,
+        testing::{
+            body_text, book_form, book_id, location, test_cover, user_id, TestApp, TEST_USER,
+        },
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn giveaway() {
+        let app = TestApp::new().await;
+
+        for (title, isbn, location) in [
+            ("Mort", "9780552131063", "Attic"),
+            ("Sourcery", "9780552131070", "Attic"),
+            ("Eric", "9780575046368", "Shelf A"),
+        ] {
+            app.post_multipart(
+                "/add",
+                book_form(title, isbn)
+                    .text("location", location)
+                    .text("owned_box", "on")
+                    .file("user_cover", "cover.png", test_cover()),
+            )
+            .await;
+        }
+
+        let response = app.post_form("/giveaway/add", "location=&tag=").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app.post_form("/giveaway/add", "location=Attic&tag=").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&response), "/giveaway");
+
+        let eric = book_id(&app, "9780575046368").await;
+        let response = app.post_form(&format!("/book/{eric}/giveaway"), "").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let page = body_text(app.get(&format!("/book/{eric}")).await).await;
+        assert!(page.contains("To give away"));
+        assert!(page.contains("Keep this book"));
+        app.post_form(&format!("/book/{eric}/giveaway"), "").await;
+
+        let page = body_text(app.get("/giveaway").await).await;
+        assert!(page.contains("Mort"));
+        assert!(page.contains("Sourcery"));
+        assert!(!page.contains("Eric"));
+
+        let export = body_text(app.get("/giveaway/export").await).await;
+        assert_eq!(
+            export,
+            "Title,Authors,ISBN,Location\n\
+             Mort,Terry Pratchett,9780552131063,Attic\n\
+             Sourcery,Terry Pratchett,9780552131070,Attic\n"
+        );
+
+        let mort = book_id(&app, "9780552131063").await;
+        let response = app.post_form("/giveaway/done", "action=delete").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let remaining: Vec<String> = book::table
+            .select(book::isbn)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(remaining, ["9780575046368"]);
+        drop(conn);
+
+        let response = app.get(&format!("/book/{mort}")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let owner = user_id(&app, TEST_USER).await;
+        assert!(!app
+            .state
+            .config
+            .metadata
+            .image_dir
+            .join(owner.to_string())
+            .join(format!("{mort}.jpg"))
+            .exists());
+
+        app.post_form("/giveaway/add", "read=on").await;
+        app.post_form(&format!("/book/{eric}/giveaway"), "").await;
+        app.post_form("/giveaway/done", "action=archive").await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let (owned, giveaway, shelf): (bool, bool, Option<String>) = book::table
+            .find(eric)
+            .select((book::owned, book::giveaway, book::location))
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert!(!owned);
+        assert!(!giveaway);
+        assert_eq!(shelf, None);
+    }
+}
@@ -0,0 +1,233 @@
+//! Import of the books exported by other cataloging applications
+
+use std::collections::HashSet;
+
+use axum::extract::Multipart;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use maud::html;
+
+use crate::{import::ImportFormat, models::User, schema::book, State};
+
+use super::{add::insert_book, raw_app_page, BookInfo, RouteError};
+
+pub(crate) async fn import(state: State, user: User) -> maud::Markup {
+    raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "Import books" }
+                p {
+                    "The books are created from the export of another application, the ones "
+                    "whose ISBN is already in the library are skipped. Collections and shelves "
+                    "become tags."
+                }
+                form method="POST" action="/import" enctype="multipart/form-data" {
+                    .form-floating."mb-3" {
+                        select .form-select name="format" #importFormat {
+                            @for format in ImportFormat::ALL {
+                                option value=(format.id()) { (format.name()) }
+                            }
+                        }
+                        label for="importFormat" { "Application" }
+                    }
+                    .mb-3 {
+                        label .form-label for="importFile" { "Exported file" }
+                        input .form-control type="file" name="file" #importFile
+                            accept=".csv,.tsv,.txt,.json"
+                            required;
+                    }
+                    input type="submit" .btn.btn-primary value="Import";
+                }
+                @if !state.metadata.is_empty() {
+                    h3 ."mt-4" { "From ISBNs" }
+                    p {
+                        "The ISBNs are looked up with the preferred metadata provider in the "
+                        "background, the ones already in the library are skipped."
+                    }
+                    form method="POST" action="/import/isbns" enctype="multipart/form-data" {
+                        .form-floating."mb-3" {
+                            textarea .form-control name="isbns" #importIsbns
+                                placeholder="ISBNs" style="height: 10rem" {}
+                            label for="importIsbns" { "ISBNs, one per line" }
+                        }
+                        .mb-3 {
+                            label .form-label for="importIsbnFile" { "Or a text file of ISBNs" }
+                            input .form-control type="file" name="file" #importIsbnFile
+                                accept=".txt,.csv";
+                        }
+                        .form-check."mb-3" {
+                            input .form-check-input type="checkbox" name="owned" #importOwned
+                                checked;
+                            label .form-check-label for="importOwned" { "The books are owned" }
+                        }
+                        input type="submit" .btn.btn-primary value="Import the ISBNs";
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub(crate) async fn do_import(
+    state: State,
+    user: User,
+    mut multipart: Multipart,
+) -> Result<maud::Markup, RouteError> {
+    let mut format = None;
+    let mut data = None;
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("format") => {
+                let value = field.text().await?;
+                format = Some(
+                    ImportFormat::ALL
+                        .iter()
+                        .copied()
+                        .find(|f| f.id() == value)
+                        .ok_or(RouteError::InvalidForm)?,
+                );
+            }
+            Some("file") => data = Some(field.bytes().await?),
+            name => tracing::warn!("Unknown field {name:?}"),
+        }
+    }
+
+    let (Some(format), Some(data)) = (format, data) else {
+        return Err(RouteError::MissingField);
+    };
+
+    let books = format.parse(&data)?;
+
+    let mut conn = state.db.get().await?;
+    let mut known: HashSet<String> = book::table
+        .filter(book::owner.eq(user.id))
+        .select(book::isbn)
+        .load::<String>(&mut conn)
+        .await?
+        .into_iter()
+        .collect();
+    drop(conn);
+
+    let mut imported = 0;
+    let mut skipped = Vec::new();
+
+    for details in books {
+        let title = details.title.clone().unwrap_or_default();
+
+        let reason = match &details.isbn {
+            _ if details.title.is_none() => Some("no title"),
+            None => Some("no ISBN"),
+            Some(isbn) if known.contains(isbn) => Some("already in the library"),
+            Some(_) => None,
+        };
+        if let Some(reason) = reason {
+            skipped.push((title, reason));
+            continue;
+        }
+
+        known.extend(details.isbn.clone());
+        insert_book(&state, &user, BookInfo::from_details(&user, details)?).await?;
+        imported += 1;
+    }
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { (format!("Import from {}", format.name())) }
+                .alert.alert-success role="alert" {
+                    (format!("{imported} books were imported"))
+                }
+                @if !skipped.is_empty() {
+                    h4 { "Skipped" }
+                    ul .list-group."mb-3" {
+                        @for (title, reason) in &skipped {
+                            li .list-group-item {
+                                (title) " "
+                                span .text-body-secondary { "(" (reason) ")" }
+                            }
+                        }
+                    }
+                }
+                a .btn.btn-primary href="/" { "Back to the books" }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::{book, bookseries, series},
+        testing::{body_text, book_id, MultipartForm, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_csv() {
+        let app = TestApp::new().await;
+
+        let import = |format: &str, data: &[u8]| {
+            MultipartForm::new()
+                .text("format", format)
+                .file("file", "export.csv", data.to_vec())
+        };
+
+        let response = app
+            .post_multipart(
+                "/import",
+                import("libib", include_bytes!("../../tests/libib.csv")),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_text(response).await.contains("2 books were imported"));
+
+        let id = book_id(&app, "9780552137034").await;
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("Good Omens"));
+        assert!(page.contains("Neil Gaiman"));
+
+        let response = app
+            .post_multipart(
+                "/import",
+                import("bookbuddy", include_bytes!("../../tests/bookbuddy.csv")),
+            )
+            .await;
+        assert!(body_text(response).await.contains("2 books were imported"));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let (owned, series_name): (bool, String) = book::table
+            .inner_join(bookseries::table.inner_join(series::table))
+            .filter(book::isbn.eq("9782070584628"))
+            .select((book::owned, series::name))
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert!(!owned);
+        assert_eq!(series_name, "Harry Potter");
+        drop(conn);
+
+        // The books already in the library are skipped
+        let response = app
+            .post_multipart(
+                "/import",
+                import("libib", include_bytes!("../../tests/libib.csv")),
+            )
+            .await;
+        let page = body_text(response).await;
+        assert!(page.contains("0 books were imported"));
+        assert!(page.contains("already in the library"));
+
+        let response = app
+            .post_multipart("/import", import("libib", b"title,creators\n\xff\xfe,\n"))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
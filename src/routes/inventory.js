@@ -0,0 +1,59 @@
+window.addEventListener('load', function () {
+	const scanButton = document.getElementById("inventoryScan");
+	const scanVideo = document.getElementById("inventoryVideo");
+	const isbns = document.getElementById("inventoryIsbns");
+
+	try {
+		window['BarcodeDetector'].getSupportedFormats()
+	} catch {
+		window['BarcodeDetector'] = barcodeDetectorPolyfill.BarcodeDetectorPolyfill
+	}
+
+	const barcodeDetector = new BarcodeDetector({formats: ['isbn_13']});
+
+	let stream = null;
+	let barcodeInterval = null;
+
+	const stop = () => {
+		window.clearInterval(barcodeInterval);
+		barcodeInterval = null;
+
+		stream.getTracks().forEach(function(track) {
+			track.stop();
+		});
+		stream = null
+
+		scanVideo.hidden = true;
+		scanButton.textContent = "Scan";
+	}
+
+	scanButton.addEventListener('click', async () => {
+		if (stream !== null) {
+			stop();
+			return;
+		}
+
+		stream = await navigator.mediaDevices.getUserMedia({
+			video: {
+				facingMode: { ideal: 'environment' }
+			},
+			audio: false
+		});
+		scanVideo.srcObject = stream
+		scanVideo.hidden = false;
+		await scanVideo.play()
+		scanButton.textContent = "Stop scanning";
+
+		// Books are scanned in sequence, each ISBN is only added once
+		barcodeInterval = window.setInterval(async () => {
+			const barcodes = await barcodeDetector.detect(scanVideo);
+			if (barcodes.length <= 0) return;
+
+			const isbn = barcodes[0].rawValue;
+			const scanned = isbns.value.split("\n").map(line => line.trim());
+			if (scanned.includes(isbn)) return;
+
+			isbns.value = scanned.filter(line => line !== "").concat([isbn]).join("\n") + "\n";
+		}, 200);
+	})
+})
@@ -0,0 +1,229 @@
+//! Inventory of a shelf, comparing the books scanned on it with the books assigned to it
+
+use axum::Form;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use maud::{html, Markup, PreEscaped};
+use uuid::Uuid;
+
+use crate::{models::User, schema::book, State};
+
+use super::{components::location_list, nonce, raw_app_page, RouteError};
+
+#[derive(serde::Deserialize)]
+pub(crate) struct InventoryForm {
+    location: String,
+    /// Scanned ISBNs, separated by whitespace, commas or semicolons
+    isbns: String,
+}
+
+pub(crate) async fn inventory(state: State, user: User) -> Result<maud::Markup, RouteError> {
+    let locations = location_list(&state, &user).await?;
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            form .container method="POST" {
+                h2 .text-center { "Shelf inventory" }
+                p {
+                    "Scan every book of a shelf, the inventory lists the scanned books missing from "
+                    "the library, the books of the shelf that were not scanned and the misplaced books."
+                }
+                .form-floating."mb-2" {
+                    input .form-control #location name="location" type="text" list="locationList"
+                        placeholder="Location" required;
+                    label for="location" { "Location" }
+                    datalist #locationList {
+                        @for location in &locations {
+                            option { (location) }
+                        }
+                    }
+                }
+                .form-floating."mb-2" {
+                    textarea .form-control #inventoryIsbns name="isbns" placeholder="ISBNs"
+                        style="height: 12rem" {}
+                    label for="inventoryIsbns" { "Scanned ISBNs, one per line" }
+                }
+                .text-center."mb-2" {
+                    video #inventoryVideo hidden width="300" height="200" style="border: 1px solid gray" {}
+                }
+                .text-center {
+                    button type="button" .btn.btn-secondary."me-2" #inventoryScan { "Scan" }
+                    input type="submit" .btn.btn-primary value="Check shelf";
+                }
+            }
+            script nonce=[nonce()] {
+                (PreEscaped(include_str!("./inventory.js")))
+            }
+        },
+    ))
+}
+
+fn book_list(books: &[(Uuid, String, Option<String>)], show_location: bool) -> Markup {
+    html! {
+        ul .list-group."mb-3" {
+            @for (id, title, location) in books {
+                li .list-group-item {
+                    a href=(format!("/book/{id}")) { (title) }
+                    @if show_location {
+                        span .text-body-secondary {
+                            " (" (location.as_deref().unwrap_or("no location")) ")"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(crate) async fn do_inventory(
+    state: State,
+    user: User,
+    Form(form): Form<InventoryForm>,
+) -> Result<maud::Markup, RouteError> {
+    let location = form.location.trim();
+
+    let mut scanned: Vec<String> = Vec::new();
+    for isbn in form
+        .isbns
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .filter(|s| !s.is_empty())
+    {
+        let isbn = isbn.replace('-', "");
+        if !scanned.contains(&isbn) {
+            scanned.push(isbn);
+        }
+    }
+
+    let mut conn = state.db.get().await?;
+
+    let found: Vec<(Uuid, String, String, Option<String>)> = book::table
+        .filter(book::owner.eq(user.id))
+        .filter(book::isbn.eq_any(&scanned))
+        .order(book::sort_title)
+        .select((book::id, book::isbn, book::title, book::location))
+        .load(&mut conn)
+        .await?;
+
+    let shelved: Vec<(Uuid, String, String)> = book::table
+        .filter(book::owner.eq(user.id))
+        .filter(book::location.eq(location))
+        .order(book::sort_title)
+        .select((book::id, book::isbn, book::title))
+        .load(&mut conn)
+        .await?;
+
+    let unknown: Vec<_> = scanned
+        .iter()
+        .filter(|isbn| !found.iter().any(|(_, found, _, _)| found == *isbn))
+        .collect();
+
+    let missing: Vec<_> = shelved
+        .into_iter()
+        .filter(|(_, isbn, _)| !scanned.contains(isbn))
+        .map(|(id, _, title)| (id, title, None))
+        .collect();
+
+    let misplaced: Vec<_> = found
+        .into_iter()
+        .filter(|(_, _, _, l)| l.as_deref() != Some(location))
+        .map(|(id, _, title, location)| (id, title, location))
+        .collect();
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "Inventory of " (location) }
+                p .text-center { (scanned.len()) " books scanned" }
+                h4 { "Not in the library" }
+                @if unknown.is_empty() {
+                    p { "All the scanned books are in the library" }
+                } @else {
+                    ul .list-group."mb-3" {
+                        @for isbn in &unknown {
+                            li .list-group-item {
+                                a href=(format!("/add?isbn={isbn}")) { (isbn) }
+                            }
+                        }
+                    }
+                }
+                h4 { "Not scanned" }
+                @if missing.is_empty() {
+                    p { "All the books of this location were scanned" }
+                } @else {
+                    (book_list(&missing, false))
+                }
+                h4 { "Misplaced" }
+                @if misplaced.is_empty() {
+                    p { "No book belongs to another location" }
+                } @else {
+                    (book_list(&misplaced, true))
+                }
+                .text-center {
+                    a .btn.btn-primary href="/inventory" { "New inventory" }
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+
+    use crate::testing::{body_text, book_form, book_id, TestApp};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shelf_inventory() {
+        let app = TestApp::new().await;
+
+        for (title, isbn, location) in [
+            ("Mort", "9780552131063", "Shelf A"),
+            ("Sourcery", "9780552131070", "Shelf A"),
+            ("Guards! Guards!", "9780552134637", "Shelf B"),
+        ] {
+            let response = app
+                .post_multipart("/add", book_form(title, isbn).text("location", location))
+                .await;
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        }
+
+        let mort = book_id(&app, "9780552131063").await;
+        let page = body_text(app.get(&format!("/book/{mort}")).await).await;
+        assert!(page.contains("Location: Shelf A"));
+
+        let page = body_text(app.get("/inventory").await).await;
+        assert!(page.contains("<option>Shelf B</option>"));
+
+        let page = body_text(
+            app.post_form(
+                "/inventory",
+                "location=Shelf+A&isbns=978-0-552-13106-3%0A9780552134637%0A9780000000000",
+            )
+            .await,
+        )
+        .await;
+
+        let section = |name: &str| {
+            let start = page.find(name).unwrap();
+            let end = page[start..].find("<h4>").unwrap_or(page.len() - start);
+            page[start..][..end].to_string()
+        };
+
+        let unknown = section("Not in the library");
+        assert!(unknown.contains("9780000000000"));
+        assert!(!unknown.contains("9780552131063"));
+
+        let missing = section("Not scanned");
+        assert!(missing.contains("Sourcery"));
+        assert!(!missing.contains("Mort"));
+
+        let misplaced = section("Misplaced");
+        assert!(misplaced
+            .contains(r#"Guards! Guards!</a><span class="text-body-secondary"> (Shelf B)"#));
+        assert!(!misplaced.contains("Mort"));
+    }
+}
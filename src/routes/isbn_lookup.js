@@ -0,0 +1,59 @@
+const isbnModal = document.getElementById("isbnModal")
+const isbnModalForm = document.getElementById("isbnModalForm")
+const isbnLoading = document.getElementById("isbnLoading")
+const isbnLoadingText = document.getElementById("isbnLoadingText")
+
+isbnModal.addEventListener("shown.bs.modal", () => {
+    document.getElementById("isbnSearch").focus()
+})
+
+let lookupTimer = null
+
+function lookupStopped() {
+    window.clearInterval(lookupTimer)
+    lookupTimer = null
+    isbnLoading.classList.add("d-none")
+}
+
+// The lookup can take a while with some providers, show which one is working and for how long
+isbnModalForm.addEventListener("htmx:beforeRequest", () => {
+    const provider = isbnModalForm.elements["provider"]?.value
+    const label = provider && document.querySelector(`label[for="${provider}Radio"]`)
+    const what = label ? `Looking the ISBN up with ${label.textContent.trim()}` : "Looking the ISBN up"
+
+    const started = Date.now()
+    const update = () => {
+        const seconds = Math.floor((Date.now() - started) / 1000)
+        isbnLoadingText.textContent = seconds > 0 ? `${what}... (${seconds}s)` : `${what}...`
+    }
+
+    update()
+    lookupTimer = window.setInterval(update, 1000)
+    isbnLoading.classList.remove("d-none")
+})
+
+isbnModalForm.addEventListener("htmx:afterRequest", (event) => {
+    window.clearInterval(lookupTimer)
+    lookupTimer = null
+
+    if (event.detail.successful) {
+        isbnLoadingText.textContent = "Loading the book..."
+    } else if (event.detail.xhr.status !== 0) {
+        isbnLoadingText.textContent = "The lookup failed"
+    } else {
+        isbnLoading.classList.add("d-none")
+    }
+})
+
+// Aborting the request stops the lookup on the server
+isbnModalForm.addEventListener("htmx:abort", lookupStopped)
+
+document.getElementById("isbnCancel").addEventListener("click", () => {
+    htmx.trigger(isbnModalForm, "htmx:abort")
+})
+
+isbnModal.addEventListener("hide.bs.modal", () => {
+    if (lookupTimer !== null) {
+        htmx.trigger(isbnModalForm, "htmx:abort")
+    }
+})
@@ -0,0 +1,164 @@
+//! Printable labels to tag the physical copies of the books
+
+use axum::{http::HeaderMap, Form};
+use maud::{html, PreEscaped};
+use uuid::Uuid;
+
+use crate::{models::User, State};
+
+use super::{
+    absolute_url, components, export::export_books, nonce, raw_app_page, short_link::short_code,
+    RouteError,
+};
+
+/// Selection of the books to print labels for
+pub(crate) async fn labels(state: State, user: User) -> Result<maud::Markup, RouteError> {
+    let books = export_books(&state, &user).await?;
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            form .container method="POST" action="/labels" target="_blank" {
+                h2 .text-center { "Printable labels" }
+                ul .list-group."my-2" {
+                    @for (id, book) in &books {
+                        li .list-group-item {
+                            @let input_id = format!("label-{id}");
+                            input .form-check-input."me-1" type="checkbox" #(input_id)
+                                name="book" value=(id);
+                            label .form-check-label for=(input_id) {
+                                (book.title.as_deref().unwrap_or_default())
+                                @if !book.authors.is_empty() {
+                                    span .text-body-secondary { " by " (book.authors.join(", ")) }
+                                }
+                            }
+                        }
+                    }
+                }
+                .text-center {
+                    input type="submit" .btn.btn-primary value="Print labels";
+                }
+            }
+        },
+    ))
+}
+
+/// Printable page with a label for each `book` field of the form, holding the title, authors,
+/// series and a QR code of the URL of the book
+pub(crate) async fn print_labels(
+    state: State,
+    user: User,
+    headers: HeaderMap,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Result<maud::Markup, RouteError> {
+    let selected = form
+        .into_iter()
+        .filter(|(key, _)| key == "book")
+        .map(|(_, id)| id.parse().map_err(|_| RouteError::InvalidForm))
+        .collect::<Result<Vec<Uuid>, _>>()?;
+
+    let books: Vec<_> = export_books(&state, &user)
+        .await?
+        .into_iter()
+        .filter(|(id, _)| selected.contains(id))
+        .collect();
+
+    let mut labels = Vec::with_capacity(books.len());
+    for (id, book) in books {
+        let qr = components::qr_code(&absolute_url(&headers, &format!("/book/{id}")))?;
+        labels.push((id, book, qr));
+    }
+
+    Ok(html! {
+        (maud::DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                title { "Labels" }
+                style {
+                    (PreEscaped(r#"
+                        body { margin: 0; font-family: sans-serif; font-size: 9pt; }
+                        .labels { display: flex; flex-wrap: wrap; gap: 2mm; padding: 5mm; }
+                        .label {
+                            display: flex; gap: 2mm; align-items: center; box-sizing: border-box;
+                            width: 63.5mm; height: 38.1mm; padding: 2mm; border: 1px dashed #999;
+                            overflow: hidden; break-inside: avoid;
+                        }
+                        .label svg { width: 28mm; height: 28mm; flex-shrink: 0; }
+                        .title { font-weight: bold; }
+                        @media print {
+                            .no-print { display: none; }
+                            .label { border-color: transparent; }
+                        }
+                    "#))
+                }
+            }
+            body {
+                p .no-print style="padding: 0 5mm" {
+                    button #print { "Print" }
+                    script nonce=[nonce()] {
+                        (PreEscaped(r#"
+                            document.getElementById("print").onclick = () => window.print()
+                        "#))
+                    }
+                }
+                .labels {
+                    @for (id, book, qr) in &labels {
+                        .label {
+                            (qr)
+                            div {
+                                .title { (book.title.as_deref().unwrap_or_default()) }
+                                div { (book.authors.join(", ")) }
+                                @if let Some((series, volume)) = &book.series {
+                                    div { (series) " #" (volume) }
+                                }
+                                @if let Some(isbn) = &book.isbn {
+                                    div { (isbn) }
+                                }
+                                div { "/b/" (short_code(*id)) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+
+    use crate::testing::{body_text, book_form, book_id, TestApp};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn print_labels() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        app.post_multipart("/add", book_form("Sourcery", "9780552131070"))
+            .await;
+        let mort = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get("/labels").await).await;
+        assert!(page.contains(&format!(r#"value="{mort}""#)));
+        assert!(page.contains("Sourcery"));
+
+        let response = app.post_form("/labels", &format!("book={mort}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let page = body_text(response).await;
+        assert!(page.contains("Mort"));
+        assert!(page.contains("Terry Pratchett"));
+        assert!(page.contains("<svg"));
+        assert!(!page.contains("Sourcery"));
+
+        let response = app.post_form("/labels", "book=not-a-uuid").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let page = body_text(app.get(&format!("/book/{mort}")).await).await;
+        assert!(page.contains("<svg"));
+    }
+}
@@ -0,0 +1,635 @@
+//! Login with the passwords stored in the database when `[auth.local]` is configured, the user is
+//! then kept in a signed session cookie. The sessions are stored so that they can be listed and
+//! revoked.
+
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use axum::{
+    extract::{ConnectInfo, Path},
+    http::{
+        header::{COOKIE, SET_COOKIE, USER_AGENT},
+        HeaderMap, HeaderName, StatusCode,
+    },
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use maud::{html, Markup};
+use uuid::Uuid;
+
+use crate::{
+    models::{NewSession, NewUser, Session},
+    password,
+    schema::{password as passwords, session, users},
+    AppState, State,
+};
+
+use super::{base_page, traffic::client_ip, two_factor, RouteError, User};
+
+const SESSION_COOKIE: &str = "bouquineur_session";
+/// The last use of a session is only updated when it is older than this, to not write on every
+/// request
+const LAST_USED_PRECISION: TimeDelta = TimeDelta::minutes(5);
+
+/// User and session of the session cookie of the request, if local logins are enabled. The session
+/// still needs to be checked by [`session_user`].
+pub(super) fn session_cookie(state: &AppState, headers: &HeaderMap) -> Option<(String, Uuid)> {
+    state.config.auth.local.as_ref()?;
+
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|&(name, _)| name == SESSION_COOKIE)
+        .and_then(|(_, value)| state.signer.session_user(value))
+}
+
+/// User of a session cookie, unless the session was revoked
+pub(super) async fn session_user(
+    state: &AppState,
+    (user, id): (String, Uuid),
+) -> Result<Option<String>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let last_used: Option<DateTime<Utc>> = session::table
+        .inner_join(users::table)
+        .filter(session::id.eq(id))
+        .filter(users::name.eq(&user))
+        .select(session::last_used)
+        .first(&mut conn)
+        .await
+        .optional()?;
+    let Some(last_used) = last_used else {
+        return Ok(None);
+    };
+
+    if Utc::now() - last_used > LAST_USED_PRECISION
+        && !AtomicBool::load(&state.maintenance, Ordering::Relaxed)
+    {
+        diesel::update(session::table.find(id))
+            .set(session::last_used.eq(diesel::dsl::now))
+            .execute(&mut conn)
+            .await?;
+    }
+
+    Ok(Some(user))
+}
+
+/// Open a session for `user`, returning the header setting its cookie
+pub(super) async fn open_session(
+    state: &AppState,
+    (id, name): (Uuid, &str),
+    headers: &HeaderMap,
+    peer: Option<SocketAddr>,
+) -> Result<[(HeaderName, String); 1], RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let session = diesel::insert_into(session::table)
+        .values(&NewSession {
+            owner: id,
+            ip: client_ip(state, headers, peer).map(|ip| ip.to_string()),
+            user_agent: headers.get(USER_AGENT).and_then(|h| h.to_str().ok()),
+        })
+        .returning(session::id)
+        .get_result(&mut conn)
+        .await?;
+
+    let days = state
+        .config
+        .auth
+        .local
+        .as_ref()
+        .map_or(0, |config| config.session_days);
+    let max_age = i64::from(days) * 24 * 60 * 60;
+    let session = state.signer.session(name, session, max_age);
+
+    Ok([(
+        SET_COOKIE,
+        format!(
+            "{SESSION_COOKIE}={session}; Path=/; Max-Age={max_age}; HttpOnly; Secure; SameSite=Lax"
+        ),
+    )])
+}
+
+/// Revoke the sessions of `owner`, except `keep`
+async fn revoke_sessions(
+    conn: &mut AsyncPgConnection,
+    owner: Uuid,
+    keep: Option<Uuid>,
+) -> Result<(), diesel::result::Error> {
+    diesel::delete(session::table)
+        .filter(session::owner.eq(owner))
+        .filter(session::id.ne_all(keep))
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Hash a password outside of the async runtime
+async fn hash(password: String) -> String {
+    tokio::task::spawn_blocking(move || password::hash(&password))
+        .await
+        .expect("hashing does not panic")
+}
+
+async fn verify(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || password::verify(&password, &hash))
+        .await
+        .expect("hashing does not panic")
+}
+
+async fn password_hash(state: &AppState, user: Uuid) -> Result<Option<String>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    Ok(passwords::table
+        .find(user)
+        .select(passwords::hash)
+        .first(&mut conn)
+        .await
+        .optional()?)
+}
+
+/// Set the password of `name`, creating the user if needed. Its sessions are revoked, except the
+/// `keep` one.
+pub(crate) async fn set_password(
+    state: &AppState,
+    name: &str,
+    password: &str,
+    keep: Option<Uuid>,
+) -> Result<(), RouteError> {
+    let hash = hash(password.to_string()).await;
+
+    let mut conn = state.db.get().await?;
+
+    diesel::insert_into(users::table)
+        .values(&NewUser { name })
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await?;
+
+    let user: Uuid = users::table
+        .filter(users::name.eq(name))
+        .select(users::id)
+        .first(&mut conn)
+        .await?;
+
+    diesel::insert_into(passwords::table)
+        .values((passwords::owner.eq(user), passwords::hash.eq(&hash)))
+        .on_conflict(passwords::owner)
+        .do_update()
+        .set(passwords::hash.eq(&hash))
+        .execute(&mut conn)
+        .await?;
+
+    revoke_sessions(&mut conn, user, keep).await?;
+
+    Ok(())
+}
+
+pub(super) fn login_page(error: Option<&str>) -> Markup {
+    base_page(html! {
+        .container.mt-5.row.mx-auto.justify-content-center {
+            .col-md-4 {
+            h1 .text-center."mb-4" {
+                i .bi.bi-book-half aria-hidden="true" {}
+                " Log in"
+            }
+            @if let Some(error) = error {
+                .alert.alert-danger role="alert" { (error) }
+            }
+            form method="POST" action="/login" {
+                .form-floating."mb-3" {
+                    input .form-control #username type="text" name="username" placeholder="User"
+                        autocomplete="username" required autofocus;
+                    label for="username" { "User" }
+                }
+                .form-floating."mb-3" {
+                    input .form-control #password type="password" name="password"
+                        placeholder="Password" autocomplete="current-password" required;
+                    label for="password" { "Password" }
+                }
+                button type="submit" .btn.btn-primary."w-100" { "Log in" }
+            }
+            }
+        }
+    })
+}
+
+pub(crate) async fn login(state: State) -> Result<Markup, RouteError> {
+    if state.config.auth.local.is_none() {
+        return Err(RouteError::NotFound);
+    }
+
+    Ok(login_page(None))
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct LoginForm {
+    username: String,
+    password: String,
+}
+
+pub(crate) async fn do_login(
+    state: State,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Form(form): Form<LoginForm>,
+) -> Result<Response, RouteError> {
+    if state.config.auth.local.is_none() {
+        return Err(RouteError::NotFound);
+    }
+
+    let mut conn = state.db.get().await?;
+    let stored: Option<(Uuid, String)> = passwords::table
+        .inner_join(users::table)
+        .filter(users::name.eq(&form.username))
+        .select((passwords::owner, passwords::hash))
+        .first(&mut conn)
+        .await
+        .optional()?;
+    drop(conn);
+
+    let user = match stored {
+        Some((user, stored)) => verify(form.password, stored).await.then_some(user),
+        None => {
+            // Take as long as for an existing user, to not reveal which ones exist
+            hash(form.password).await;
+            None
+        }
+    };
+
+    let Some(user) = user else {
+        tracing::info!(user = %form.username, "failed login");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            login_page(Some("Invalid user or password")),
+        )
+            .into_response());
+    };
+
+    if two_factor::enabled(&state, user).await? {
+        return Ok(two_factor::challenge(&state, &form.username).into_response());
+    }
+
+    let peer = peer.map(|ConnectInfo(addr)| addr);
+    let cookie = open_session(&state, (user, &form.username), &headers, peer).await?;
+
+    Ok((cookie, Redirect::to("/")).into_response())
+}
+
+fn clear_session() -> impl IntoResponse {
+    (
+        [(
+            SET_COOKIE,
+            format!("{SESSION_COOKIE}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax"),
+        )],
+        Redirect::to("/login"),
+    )
+}
+
+pub(crate) async fn do_logout(
+    state: State,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, RouteError> {
+    if let Some((_, id)) = session_cookie(&state, &headers) {
+        let mut conn = state.db.get().await?;
+        diesel::delete(session::table.find(id))
+            .execute(&mut conn)
+            .await?;
+    }
+
+    Ok(clear_session())
+}
+
+/// Revoke all the sessions of the user, including the current one
+pub(crate) async fn do_logout_everywhere(
+    state: State,
+    user: User,
+) -> Result<impl IntoResponse, RouteError> {
+    if state.config.auth.local.is_none() {
+        return Err(RouteError::NotFound);
+    }
+
+    let mut conn = state.db.get().await?;
+    revoke_sessions(&mut conn, user.id, None).await?;
+
+    tracing::info!(user = %user.name, "all sessions revoked");
+
+    Ok(clear_session())
+}
+
+/// Revoke one of the sessions of the user
+pub(crate) async fn do_revoke_session(
+    state: State,
+    user: User,
+    Path(id): Path<Uuid>,
+) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let deleted = diesel::delete(session::table)
+        .filter(session::id.eq(id))
+        .filter(session::owner.eq(user.id))
+        .execute(&mut conn)
+        .await?;
+    if deleted == 0 {
+        return Err(RouteError::NotFound);
+    }
+
+    Ok(Redirect::to("/profile"))
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct PasswordForm {
+    #[serde(default)]
+    current: String,
+    password: String,
+}
+
+/// The current password is needed when the user already has one. The other sessions are revoked.
+pub(crate) async fn do_change_password(
+    state: State,
+    user: User,
+    headers: HeaderMap,
+    Form(form): Form<PasswordForm>,
+) -> Result<Redirect, RouteError> {
+    if state.config.auth.local.is_none() {
+        return Err(RouteError::NotFound);
+    }
+
+    if form.password.is_empty() {
+        return Err(RouteError::MissingField);
+    }
+
+    if let Some(hash) = password_hash(&state, user.id).await? {
+        if !verify(form.current, hash).await {
+            return Err(RouteError::Forbidden);
+        }
+    }
+
+    let current = session_cookie(&state, &headers)
+        .filter(|(name, _)| *name == user.name)
+        .map(|(_, id)| id);
+    set_password(&state, &user.name, &form.password, current).await?;
+
+    Ok(Redirect::to("/profile"))
+}
+
+/// Whether the user has a password, for the profile page
+pub(super) async fn has_password(state: &AppState, user: &User) -> Result<bool, RouteError> {
+    Ok(password_hash(state, user.id).await?.is_some())
+}
+
+/// Sessions of the user, with the revocation buttons, for the profile page
+pub(super) async fn sessions(
+    state: &AppState,
+    user: &User,
+    headers: &HeaderMap,
+) -> Result<Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let sessions: Vec<Session> = session::table
+        .filter(session::owner.eq(user.id))
+        .select(Session::as_select())
+        .order(session::last_used.desc())
+        .load(&mut conn)
+        .await?;
+    let current = session_cookie(state, headers).map(|(_, id)| id);
+
+    Ok(html! {
+        @if !sessions.is_empty() {
+            table .table.table-sm.align-middle."mt-3" {
+                thead {
+                    tr {
+                        th scope="col" { "Device" }
+                        th scope="col" { "IP" }
+                        th scope="col" { "Logged in" }
+                        th scope="col" { "Last used" }
+                        th scope="col" { span .visually-hidden { "Revoke" } }
+                    }
+                }
+                tbody {
+                    @for session in &sessions {
+                        tr {
+                            td .text-break {
+                                (session.user_agent.as_deref().unwrap_or("Unknown"))
+                                @if Some(session.id) == current {
+                                    " " span .badge.text-bg-secondary { "This device" }
+                                }
+                            }
+                            td { (session.ip.as_deref().unwrap_or("unknown")) }
+                            td { (session.created.format("%Y-%m-%d %H:%M")) }
+                            td { (session.last_used.format("%Y-%m-%d %H:%M")) }
+                            td {
+                                form method="POST"
+                                    action=(format!("/profile/sessions/{}/revoke", session.id)) {
+                                    button type="submit" .btn.btn-sm.btn-outline-danger {
+                                        "Revoke"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{
+            header::{CONTENT_TYPE, COOKIE, SET_COOKIE, USER_AGENT},
+            Request, Response, StatusCode,
+        },
+    };
+
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::session,
+        testing::{body_text, location, TestApp, TEST_USER},
+    };
+
+    /// Session cookie set by `response`, as sent back by the browser
+    fn session_of(response: &Response<Body>) -> String {
+        let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+        let (session, _) = cookie.split_once(';').unwrap();
+        session.to_string()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn local_login() {
+        let app = TestApp::with_config("[auth.local]").await;
+        super::set_password(&app.state, "alice", "hunter22", None)
+            .await
+            .unwrap();
+
+        let anonymous = |request: axum::http::request::Builder| {
+            app.request(request.body(Body::empty()).unwrap())
+        };
+        let login = |form: &'static str| {
+            app.request(
+                Request::post("/login")
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(form))
+                    .unwrap(),
+            )
+        };
+
+        let response = anonymous(Request::get("/")).await;
+        assert_eq!(location(&response), "/login");
+        let response = anonymous(Request::get("/login")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = login("username=alice&password=hunter2").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(body_text(response)
+            .await
+            .contains("Invalid user or password"));
+        let response = login("username=bob&password=hunter22").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = login("username=alice&password=hunter22").await;
+        assert_eq!(location(&response), "/");
+        assert!(response.headers()[SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .contains("; Secure"));
+        let session = session_of(&response);
+
+        let response = anonymous(Request::get("/profile").header(COOKIE, &session)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_text(response).await.contains("Profile for alice"));
+
+        // The proxy header still works, and a forged session is not accepted
+        let response = app.get("/profile").await;
+        let page = body_text(response).await;
+        assert!(page.contains(&format!("Profile for {TEST_USER}")));
+        // "alice" and "bob" in base64
+        let forged = session.replace("YWxpY2U", "Ym9i");
+        let response = anonymous(Request::get("/profile").header(COOKIE, forged)).await;
+        assert_eq!(location(&response), "/login");
+
+        let response = app
+            .request(
+                Request::post("/profile/password")
+                    .header(COOKIE, &session)
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from("current=wrong&password=swordfish"))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // Changing the password revokes the other sessions
+        let other = session_of(&login("username=alice&password=hunter22").await);
+        let response = app
+            .request(
+                Request::post("/profile/password")
+                    .header(COOKIE, &session)
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from("current=hunter22&password=swordfish"))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(location(&response), "/profile");
+        let response = anonymous(Request::get("/profile").header(COOKIE, &session)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = anonymous(Request::get("/profile").header(COOKIE, &other)).await;
+        assert_eq!(location(&response), "/login");
+
+        let response = login("username=alice&password=hunter22").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = login("username=alice&password=swordfish").await;
+        assert_eq!(location(&response), "/");
+
+        let response = anonymous(Request::post("/logout").header(COOKIE, &session)).await;
+        assert_eq!(location(&response), "/login");
+        assert!(response.headers()[SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .contains("Max-Age=0"));
+        let response = anonymous(Request::get("/profile").header(COOKIE, &session)).await;
+        assert_eq!(location(&response), "/login");
+
+        // Logging out everywhere revokes all the sessions
+        let first = session_of(&login("username=alice&password=swordfish").await);
+        let second = session_of(&login("username=alice&password=swordfish").await);
+        let response = anonymous(Request::post("/logout/everywhere").header(COOKIE, &first)).await;
+        assert_eq!(location(&response), "/login");
+        for session in [&first, &second] {
+            let response = anonymous(Request::get("/profile").header(COOKIE, session)).await;
+            assert_eq!(location(&response), "/login");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sessions() {
+        let app = TestApp::with_config("[auth.local]").await;
+        super::set_password(&app.state, "alice", "hunter22", None)
+            .await
+            .unwrap();
+
+        let login = |agent: &'static str| {
+            app.request(
+                Request::post("/login")
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .header(USER_AGENT, agent)
+                    .body(Body::from("username=alice&password=hunter22"))
+                    .unwrap(),
+            )
+        };
+        let get = |uri: &str, session: &str| {
+            app.request(
+                Request::get(uri)
+                    .header(COOKIE, session)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let laptop = session_of(&login("Firefox").await);
+        let reader = session_of(&login("Kobo").await);
+
+        let page = body_text(get("/profile", &laptop).await).await;
+        assert!(page.contains("Firefox"));
+        assert!(page.contains("Kobo"));
+        assert_eq!(page.matches("This device").count(), 1);
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let id: Uuid = session::table
+            .filter(session::user_agent.eq("Kobo"))
+            .select(session::id)
+            .first(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let revoke = |id: Uuid| {
+            app.request(
+                Request::post(format!("/profile/sessions/{id}/revoke"))
+                    .header(COOKIE, &laptop)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        assert_eq!(location(&revoke(id).await), "/profile");
+        assert_eq!(location(&get("/profile", &reader).await), "/login");
+        assert_eq!(get("/profile", &laptop).await.status(), StatusCode::OK);
+
+        // The sessions of the other users can't be revoked
+        let response = revoke(id).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,104 @@
+//! Read-only mode, used during backups and migrations
+
+use std::sync::atomic::Ordering;
+
+use axum::{
+    extract::Request,
+    http::{header::RETRY_AFTER, Method},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+
+use crate::State;
+
+use super::{is_admin, RouteError, User};
+
+const TOGGLE_ROUTE: &str = "/admin/maintenance";
+/// Logging in and out does not touch the library
+const SESSION_ROUTES: &[&str] = &["/login", "/login/totp", "/logout"];
+
+/// Reject the requests that could modify the library while the maintenance mode is enabled
+pub(crate) async fn maintenance(state: State, request: Request, next: Next) -> Response {
+    let read_only = matches!(*request.method(), Method::GET | Method::HEAD);
+
+    if read_only
+        || request.uri().path() == TOGGLE_ROUTE
+        || SESSION_ROUTES.contains(&request.uri().path())
+        || !state.maintenance.load(Ordering::Relaxed)
+    {
+        return next.run(request).await;
+    }
+
+    ([(RETRY_AFTER, "300")], RouteError::Maintenance).into_response()
+}
+
+pub(crate) async fn do_toggle_maintenance(
+    state: State,
+    user: User,
+) -> Result<Redirect, RouteError> {
+    if !is_admin(&state, &user) {
+        return Err(RouteError::Forbidden);
+    }
+
+    let enabled = !state.maintenance.fetch_xor(true, Ordering::Relaxed);
+    tracing::info!(user = %user.name, enabled, "maintenance mode toggled");
+
+    Ok(Redirect::to("/profile"))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+
+    use crate::testing::{
+        body_text, book_form, location, TestApp, OTHER_USER, TEST_USER, USER_HEADER,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn maintenance_mode() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+
+        let response = app.post_form("/admin/maintenance", "").await;
+        assert_eq!(location(&response), "/profile");
+        assert!(body_text(app.get("/profile").await)
+            .await
+            .contains("Leave maintenance mode"));
+
+        let response = app
+            .post_multipart("/add", book_form("Guards! Guards!", "9780552134637"))
+            .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let page = body_text(response).await;
+        assert!(page.contains("under maintenance"));
+        assert!(page.contains(TEST_USER));
+
+        // The library can still be browsed, without remembering the queries
+        let response = app.get("/?shelf=true").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_text(response).await.contains("Mort"));
+        assert_eq!(app.get("/").await.status(), StatusCode::OK);
+
+        // Only the administrators can toggle the maintenance mode
+        let response = app
+            .request(
+                Request::post("/admin/maintenance")
+                    .header(USER_HEADER, OTHER_USER)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        app.post_form("/admin/maintenance", "").await;
+        let response = app
+            .post_multipart("/add", book_form("Guards! Guards!", "9780552134637"))
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+}
@@ -1,7 +1,11 @@
 use std::{
+    collections::BTreeMap,
     io::Cursor,
     num::ParseIntError,
-    sync::{Arc, LazyLock},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock,
+    },
 };
 
 use axum::{
@@ -9,51 +13,130 @@ use axum::{
     body::{Body, Bytes},
     extract::{
         multipart::{MultipartError, MultipartRejection},
-        FromRequest, FromRequestParts, Multipart, Path, Request,
+        FromRequest, FromRequestParts, MatchedPath, Multipart, Path, Query, RawQuery, Request,
+    },
+    http::{
+        header::{CONTENT_TYPE, HOST, REFERER},
+        HeaderMap, Method, StatusCode,
     },
-    http::{header::CONTENT_TYPE, StatusCode},
-    response::IntoResponse,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
     RequestExt,
 };
 use base64::prelude::*;
-use chrono::NaiveDate;
-use components::{book_cards_for, NO_SORT};
+use chrono::{DateTime, NaiveDate, Utc};
+use components::{book_cards_for, book_shelf_for, NO_SORT};
+use csp::nonce;
 use diesel::{prelude::*, sql_types};
 use diesel_async::pooled_connection::deadpool::PoolError;
 use diesel_async::RunQueryDsl;
 use maud::{html, Markup};
 use tokio_util::io::ReaderStream;
+use tracing::Instrument;
 use uuid::Uuid;
 
 use crate::{
-    metadata::MetadataError,
-    models::{AuthorName, Book, BookPreview, NewUser, TagName, User},
-    schema::{book, bookseries, users},
-    AppState, State,
+    import::ImportError,
+    metadata::{MetadataError, NullableBookDetails},
+    models::{AuthorName, Book, BookIdentifier, BookLink, BookPreview, NewUser, TagName, User},
+    schema::{author, book, bookseries, pagequery, series, tag, users},
+    AppState, ImageConfig, PublicImageConfig, RequiredField, State,
 };
 
 mod add;
+mod add_url;
+mod api;
+mod author_works;
+mod availability;
+mod bulk_import;
+mod complete;
+mod covers;
+mod csp;
 mod edit;
 mod edit_series;
+mod export;
+mod feed;
 mod get_author;
 mod get_book;
 mod get_series;
+mod giveaway;
 mod icons;
+mod import;
+mod inventory;
+mod labels;
+mod login;
+mod maintenance;
 mod ongoing;
 mod profile;
+mod publishers;
+mod refresh;
+mod series_import;
+mod series_merge;
+mod series_reorder;
+mod settings;
+mod short_link;
+mod tag_implications;
+mod traffic;
+mod two_factor;
 mod unread;
+mod widget;
+mod wishlist;
 
 mod components;
 
-pub(crate) use add::{add_book, do_add_book};
+pub(crate) use add::{add_book, add_from_opf, do_add_book, do_lookup, fill_missing, Lookup};
+pub(crate) use add_url::add_from_url;
+pub(crate) use api::{
+    api_book, api_books, api_create_book, api_delete_book, api_metadata, api_metadata_isbn,
+    api_update_book, api_update_books,
+};
+pub(crate) use author_works::{author_works, do_author_wish};
+pub(crate) use availability::{book_availability, wish_availability};
+pub(crate) use bulk_import::{do_import_isbns, import_isbns, import_isbns_progress, import_sent};
+pub(crate) use complete::{complete, invalidate_completions};
+pub(crate) use covers::{fetch_missing_covers, reencode_covers};
+pub(crate) use csp::content_security_policy;
 pub(crate) use edit::{do_edit_book, edit_book};
-pub(crate) use edit_series::{do_series_edit, series_edit};
+pub(crate) use edit_series::{do_series_edit, do_series_lookup, series_edit};
+pub(crate) use export::{export_goodreads, export_isbns, export_json, export_wikidata};
+pub(crate) use feed::{feed, public_feed};
 pub(crate) use get_author::get_author;
 pub(crate) use get_book::get_book;
 pub(crate) use get_series::get_series;
+pub(crate) use giveaway::{
+    do_giveaway_add, do_giveaway_done, do_toggle_giveaway, giveaway, giveaway_export,
+};
+pub(crate) use import::{do_import, import};
+pub(crate) use inventory::{do_inventory, inventory};
+pub(crate) use labels::{labels, print_labels};
+pub(crate) use login::{
+    do_change_password, do_login, do_logout, do_logout_everywhere, do_revoke_session, login,
+    set_password,
+};
+pub(crate) use maintenance::{do_toggle_maintenance, maintenance};
 pub(crate) use ongoing::{ongoing, ongoing_public};
 pub(crate) use profile::{do_edit_profile, profile};
+pub(crate) use publishers::{do_publishers_wikidata, do_set_publisher_parent, publishers};
+pub(crate) use refresh::{do_refresh_book, refresh_book};
+pub(crate) use series_import::do_series_import;
+pub(crate) use series_merge::{do_series_merge, do_series_split};
+pub(crate) use series_reorder::{do_series_reorder, series_reorder};
+pub(crate) use settings::{do_import_settings, export_settings};
+pub(crate) use short_link::short_link;
+pub(crate) use tag_implications::{
+    do_add_tag_implication, do_apply_tag_implications, do_delete_tag_implication, tag_implications,
+};
+pub(crate) use traffic::{admin_traffic, anonymous_access, guest_access};
+pub(crate) use two_factor::{
+    do_disable_totp, do_enable_totp, do_login_totp, do_new_recovery_codes, do_setup_totp,
+    setup_totp,
+};
 pub(crate) use unread::unread;
+pub(crate) use widget::widget_recent;
+pub(crate) use wishlist::{
+    do_delete_wish, do_prefetch_wishes, do_wish_isbn, do_wish_missing_volumes, prefetch_wishes,
+    prefetch_wishes_progress, wishlist,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum RouteError {
@@ -61,6 +144,8 @@ pub(crate) enum RouteError {
     Db(#[from] diesel::result::Error),
     #[error("Missing a user header")]
     NoUser,
+    #[error("Not logged in")]
+    LoginRequired,
     #[error("Could not parse user name")]
     InvalidUser(#[from] axum::http::header::ToStrError),
     #[error("Could not get a connection from the pool")]
@@ -75,6 +160,8 @@ pub(crate) enum RouteError {
     ParseInt(#[from] ParseIntError),
     #[error("Missing field in form")]
     MissingField,
+    #[error("Invalid form")]
+    InvalidForm,
     #[error("Could not parse image type")]
     ImageDetection(#[source] std::io::Error),
     #[error("Could not parse image")]
@@ -85,19 +172,45 @@ pub(crate) enum RouteError {
     B64(#[from] base64::DecodeError),
     #[error("Resource not found")]
     NotFound,
+    #[error("Access forbidden")]
+    Forbidden,
+    #[error("Too many requests")]
+    Throttled,
+    #[error("Maintenance in progress")]
+    Maintenance,
     #[error("Unexpected IO error")]
     IO(#[from] std::io::Error),
+    #[error("Could not fetch page")]
+    Fetch(#[from] reqwest::Error),
     #[error("Invalid multipart")]
     Multipart(#[from] MultipartRejection),
+    #[error("Could not create archive")]
+    Archive(#[from] zip::result::ZipError),
+    #[error("Could not create QR code")]
+    QrCode(#[from] qrcode::types::QrError),
+    #[error("Invalid export file")]
+    Import(#[from] ImportError),
 }
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
-        if !matches!(&self, Self::MultipartError(_)) {
+        if !matches!(
+            &self,
+            Self::MultipartError(_)
+                | Self::LoginRequired
+                | Self::NotFound
+                | Self::Forbidden
+                | Self::Throttled
+                | Self::Maintenance
+        ) {
             tracing::error!("route error: {self} ({self:#?})");
         }
 
         let (code, text) = match self {
+            RouteError::Metadata(MetadataError::UnknownProvider(p)) => (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown metadata provider {p}"),
+            ),
             // Don't reveal the missing authenitication header to the client, this is a
             // mis-configuration that could be exploited
             RouteError::Db(_)
@@ -106,29 +219,93 @@ impl IntoResponse for RouteError {
             | RouteError::Metadata(_)
             | RouteError::B64(_)
             | RouteError::ImageSave(_)
+            | RouteError::Archive(_)
+            | RouteError::QrCode(_)
             | RouteError::IO(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Error".into()),
             RouteError::InvalidUser(_) => (StatusCode::BAD_REQUEST, "Invalid user name".into()),
             RouteError::MultipartError(e) => (e.status(), e.body_text()),
             RouteError::DateError(e) => (StatusCode::BAD_REQUEST, e.to_string()),
             RouteError::ParseInt(e) => (StatusCode::BAD_REQUEST, e.to_string()),
             RouteError::MissingField => (StatusCode::BAD_REQUEST, "Missing field in form".into()),
+            RouteError::InvalidForm => (StatusCode::BAD_REQUEST, "Invalid form".into()),
             RouteError::ImageDetection(e) => (StatusCode::BAD_REQUEST, e.to_string()),
             RouteError::Image(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+            RouteError::Import(e) => (StatusCode::BAD_REQUEST, format!("Invalid export: {e}")),
             RouteError::NotFound => (StatusCode::NOT_FOUND, "Resource not found".into()),
+            RouteError::Forbidden => (StatusCode::FORBIDDEN, "Access forbidden".into()),
+            RouteError::Throttled => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many requests, try again later".into(),
+            ),
+            RouteError::Maintenance => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The library is under maintenance, it can't be modified for now".into(),
+            ),
+            RouteError::Fetch(_) => (StatusCode::BAD_GATEWAY, "Could not fetch the page".into()),
             RouteError::Multipart(r) => return r.into_response(),
+            RouteError::LoginRequired => return Redirect::to("/login").into_response(),
         };
 
-        (
-            code,
-            base_page(html! {
-                h1 { "Fatal Error encountered" }
-                p { (text) }
-            }),
+        let mut response = (code, base_page(error_content(&text))).into_response();
+        response.extensions_mut().insert(ErrorMessage(text));
+        response
+    }
+}
+
+/// Message of an error response, used by [error_pages] to render it in the application page
+#[derive(Clone)]
+struct ErrorMessage(String);
+
+fn error_content(text: &str) -> Markup {
+    html! {
+        .container.text-center {
+            h1 { "Error encountered" }
+            p { (text) }
+            a .btn.btn-primary href="/" { "Back to the books" }
+        }
+    }
+}
+
+/// Show errors with the application header when the user can be identified, error responses
+/// are built without access to the request so this is done in a middleware.
+pub(crate) async fn error_pages(state: State, request: Request, next: Next) -> Response {
+    let headers = request.headers().clone();
+    let response = next.run(request).await;
+
+    let Some(ErrorMessage(text)) = response.extensions().get::<ErrorMessage>().cloned() else {
+        return response;
+    };
+
+    let (mut parts, ()) = axum::http::Request::new(()).into_parts();
+    parts.headers = headers;
+
+    match User::from_request_parts(&mut parts, &state).await {
+        Ok(user) => (
+            response.status(),
+            raw_app_page(None, &user, error_content(&text)),
         )
-            .into_response()
+            .into_response(),
+        Err(_) => response,
     }
 }
 
+/// Attach the route to the logs emitted while handling a request, such as slow queries
+pub(crate) async fn request_span(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_default();
+
+    let span = tracing::info_span!("request", method = %request.method(), route);
+
+    next.run(request).instrument(span).await
+}
+
+pub(crate) async fn not_found() -> RouteError {
+    RouteError::NotFound
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 enum CheckboxTick {
     #[serde(rename = "on")]
@@ -145,14 +322,36 @@ enum Page {
 }
 
 impl Page {
-    fn variants() -> &'static [Self] {
-        &[
-            Self::Books,
-            Self::Unread,
-            Self::Series,
-            Self::Ongoing,
-            Self::AddBook,
-        ]
+    const ALL: &'static [Self] = &[
+        Self::Books,
+        Self::Unread,
+        Self::Series,
+        Self::Ongoing,
+        Self::AddBook,
+    ];
+
+    /// Pages shown in the navigation bar of `user`
+    fn variants(user: &User) -> impl Iterator<Item = Self> + '_ {
+        Self::ALL
+            .iter()
+            .copied()
+            .filter(|p| !user.hidden_pages.iter().any(|hidden| hidden == p.id()))
+    }
+
+    /// Pages that can be hidden from the navigation bar, the home page is always shown
+    fn hideable() -> impl Iterator<Item = Self> {
+        Self::ALL.iter().copied().filter(|&p| p != Page::Books)
+    }
+
+    /// Identifier of the page, as stored in the settings of the users
+    pub fn id(&self) -> &'static str {
+        match self {
+            Page::Books => "books",
+            Page::Unread => "unread",
+            Page::Series => "series",
+            Page::AddBook => "add",
+            Page::Ongoing => "ongoing",
+        }
     }
 
     pub fn name(&self) -> &'static str {
@@ -201,11 +400,23 @@ fn base_page_with_head(body: Markup, head: Option<Markup>) -> Markup {
                      href="https://cdnjs.cloudflare.com/ajax/libs/awesomplete/1.1.7/awesomplete.css"
                      integrity="sha512-GEMEzu9K8wXXaW527IHfGIOaTQ0hXxZPJXZOwGDIO+nrR9Z0ttJih1ZehiEoWY8xPtqzzD7pxAEnQInTZwn3MQ=="
                      crossorigin="anonymous";
+                // Controls that need scripts are only shown when they are enabled, and `.no-js`
+                // elements provide a server-side fallback otherwise
+                script nonce=[nonce()] {
+                    (maud::PreEscaped(r#"document.documentElement.classList.add("js")"#))
+                }
                 style type="text/css" {
                     (maud::PreEscaped(r#"
                         .awesomplete > ul {
 	                        z-index: 10;
                         }
+                        html:not(.js) .js-only, .js .no-js {
+                            display: none !important;
+                        }
+                        .js .spoiler {
+                            filter: blur(0.4rem);
+                            cursor: pointer;
+                        }
                     "#))
                 }
                 @if let Some(head) = head {
@@ -214,25 +425,34 @@ fn base_page_with_head(body: Markup, head: Option<Markup>) -> Markup {
             }
             body {
                 (body)
-                script src="https://cdn.jsdelivr.net/npm/@undecaf/zbar-wasm@0.9.15/dist/index.js"
+                script nonce=[nonce()] src="https://cdn.jsdelivr.net/npm/@undecaf/zbar-wasm@0.9.15/dist/index.js"
                        integrity="sha384-yW9Y7lGkfKYN+jnhSQpcumEsBkSCx/Ab9s2+rHyU5faxR81n4c2mhBw1K6TyFG2a"
                        crossorigin="anonymous" {}
-                script src="https://cdn.jsdelivr.net/npm/@undecaf/barcode-detector-polyfill@0.9.21/dist/index.js"
+                script nonce=[nonce()] src="https://cdn.jsdelivr.net/npm/@undecaf/barcode-detector-polyfill@0.9.21/dist/index.js"
                        integrity="sha384-MOAlrmENITvPLnTzISP6k/GAbCgTOuREHSbC1X5a3qcIHeHTNilNuzc7LfXVYKMO"
                        crossorigin="anonymous" {}
-                script src="https://cdn.jsdelivr.net/npm/bootstrap@5.3.2/dist/js/bootstrap.bundle.min.js"
+                script nonce=[nonce()] src="https://cdn.jsdelivr.net/npm/bootstrap@5.3.2/dist/js/bootstrap.bundle.min.js"
                        integrity="sha384-C6RzsynM9kWDrMNeT87bh95OGNyZPhcTNXj1NW7RuBCsyN/o0jlpcV8Qyq46cDfL"
                        crossorigin="anonymous" {}
-                script src="https://unpkg.com/htmx.org@2.0.1"
+                script nonce=[nonce()] src="https://unpkg.com/htmx.org@2.0.1"
                        integrity="sha384-QWGpdj554B4ETpJJC9z+ZHJcA/i59TyjxEPXiiUgN2WmTyV5OEZWCD6gQhgkdpB/"
                        crossorigin="anonymous" {}
-                script src="https://cdnjs.cloudflare.com/ajax/libs/awesomplete/1.1.7/awesomplete.min.js"
+                script nonce=[nonce()] src="https://cdnjs.cloudflare.com/ajax/libs/awesomplete/1.1.7/awesomplete.min.js"
                        integrity="sha512-Pc3/aEr2FIVZhHxe0RAC9SFrd+pxBJHN3pNJfJNTKc2XAFnXUjgQGIh6X935ePSXNMN6rFa3yftxSnZfJE8ZAg=="
                        crossorigin="anonymous" async {}
-                script {
+                script nonce=[nonce()] {
                     (maud::PreEscaped(r#"
                         const tooltipTriggerList = document.querySelectorAll('[data-bs-toggle="tooltip"]')
                         const tooltipList = [...tooltipTriggerList].map(tooltipTriggerEl => new bootstrap.Tooltip(tooltipTriggerEl))
+
+                        // Match completions regardless of accents, "Herve" should suggest "Hervé"
+                        const withoutAccents = text => text.normalize("NFD").replace(/\p{Diacritic}/gu, "").toLowerCase()
+                        window.addEventListener("load", () => {
+                            Awesomplete.all.forEach(completion => {
+                                completion.filter = (text, input) =>
+                                    withoutAccents(text).includes(withoutAccents(input.trim()))
+                            })
+                        })
                     "#))
                 }
             }
@@ -244,8 +464,47 @@ fn base_page(body: Markup) -> Markup {
     base_page_with_head(body, None)
 }
 
+/// Page accessible without authentication, which only sends its URL when loading resources from
+/// this server so that they can be protected against hotlinking
+fn public_page(body: Markup) -> Markup {
+    base_page_with_head(
+        body,
+        Some(html! {
+            meta name="referrer" content="same-origin";
+        }),
+    )
+}
+
+/// Intermediate link in the breadcrumbs of a page
+struct Crumb {
+    name: String,
+    href: String,
+}
+
+impl Crumb {
+    fn new(name: impl Into<String>, href: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            href: href.into(),
+        }
+    }
+}
+
 fn raw_app_page(page: Option<Page>, user: &User, body: Markup) -> Markup {
+    raw_app_page_with_breadcrumbs(page, user, None, body)
+}
+
+/// `breadcrumbs` start from `page`, followed by the crumbs and the name of the current page
+fn raw_app_page_with_breadcrumbs(
+    page: Option<Page>,
+    user: &User,
+    breadcrumbs: Option<(&[Crumb], &str)>,
+    body: Markup,
+) -> Markup {
     base_page(html! {
+        a .visually-hidden-focusable.position-absolute."m-2"."p-2".bg-body href="#content" {
+            "Skip to content"
+        }
         .container-fluid {
             header .d-flex
                    .flex-wrap
@@ -254,13 +513,15 @@ fn raw_app_page(page: Option<Page>, user: &User, body: Markup) -> Markup {
                    .justify-content-md-between
                    ."py-3"."mb-4" {
                 h2 ."col-md-3"."mb-2"."mb-md-0" {
-                    a .d-inline-flex.link-body-emphasis.text-decoration-none href="/" {
-                        i .bi.bi-book-half {}
+                    a .d-inline-flex.link-body-emphasis.text-decoration-none href="/"
+                        aria-label="Home" {
+                        i .bi.bi-book-half aria-hidden="true" {}
                     }
                 }
+                nav aria-label="Main" {
                 ul .nav.nav-pills."col-12".col-md-auto."mb-2".justify-content-center."mb-md-0" {
-                    @for p in Page::variants() {
-                        @let current = Some(*p) == page;
+                    @for p in Page::variants(user) {
+                        @let current = Some(p) == page;
                         li .nav-item {
                             a .nav-link.active[current]
                                 aria-current=[current.then(|| "page")]
@@ -270,11 +531,27 @@ fn raw_app_page(page: Option<Page>, user: &User, body: Markup) -> Markup {
                         }
                     }
                 }
+                }
                 ."col-md-3".text-end."me-2" {
                     a href="/profile" .align-middle.link-light { (user.name) }
                 }
             }
-            (body)
+            @if let Some((crumbs, current)) = breadcrumbs {
+                nav .container aria-label="breadcrumb" {
+                    ol .breadcrumb {
+                        @if let Some(page) = page {
+                            li .breadcrumb-item { a href=(page.location()) { (page.name()) } }
+                        }
+                        @for crumb in crumbs {
+                            li .breadcrumb-item { a href=(crumb.href) { (crumb.name) } }
+                        }
+                        li .breadcrumb-item.active aria-current="page" { (current) }
+                    }
+                }
+            }
+            main #content tabindex="-1" {
+                (body)
+            }
         }
     })
 }
@@ -283,6 +560,161 @@ fn app_page(page: Page, user: &User, body: Markup) -> Markup {
     raw_app_page(Some(page), user, body)
 }
 
+fn app_page_with_breadcrumbs(
+    page: Page,
+    user: &User,
+    crumbs: &[Crumb],
+    current: &str,
+    body: Markup,
+) -> Markup {
+    raw_app_page_with_breadcrumbs(Some(page), user, Some((crumbs, current)), body)
+}
+
+/// URL of the `Referer` of a request, along with its host including the port if it is not the
+/// default one
+fn referer_url(headers: &HeaderMap) -> Option<(reqwest::Url, String)> {
+    let referer = headers.get(REFERER)?.to_str().ok()?;
+    let url = reqwest::Url::parse(referer).ok()?;
+
+    let referer_host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str()?),
+        None => url.host_str()?.to_string(),
+    };
+
+    Some((url, referer_host))
+}
+
+/// Local path of the page linking to `current`, used to return there once a form is submitted
+fn referer_path(headers: &HeaderMap, current: &str) -> Option<String> {
+    let (url, referer_host) = referer_url(headers)?;
+    let host = headers.get(HOST)?.to_str().ok()?;
+
+    if referer_host != host || url.path() == current {
+        return None;
+    }
+
+    Some(match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    })
+}
+
+/// Local URL of `path` with the percent-encoded query `pairs`
+fn query_url(path: &str, pairs: &[(&str, &str)]) -> String {
+    let mut url = reqwest::Url::parse("http://localhost/").expect("URL is valid");
+    url.query_pairs_mut().extend_pairs(pairs);
+
+    format!("{path}?{}", url.query().unwrap_or_default())
+}
+
+/// Absolute URL of `path` on this server, as reached by the client
+fn absolute_url(headers: &HeaderMap, path: &str) -> String {
+    let host = headers
+        .get(HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("http");
+
+    format!("{scheme}://{host}{path}")
+}
+
+/// Redirect to `return_to` if it is a path on this server, or to `default`
+fn redirect_back(return_to: Option<&str>, default: &str) -> Redirect {
+    match return_to {
+        Some(path) if path.starts_with('/') && !path.starts_with("//") && !path.contains('\\') => {
+            Redirect::to(path)
+        }
+        _ => Redirect::to(default),
+    }
+}
+
+/// Pages that can be browsed by guests, none of them can modify the library
+const GUEST_ROUTES: &[&str] = &[
+    "/",
+    "/public/images/not_found",
+    "/public/:user/images/:id",
+    "/book/:id",
+    "/book/:id/availability",
+    "/unread",
+    "/series",
+    "/series/:id",
+    "/author/:id",
+    "/publishers",
+    "/ongoing",
+];
+
+/// Is the request allowed for a guest, if they are enabled
+fn guest_route(parts: &axum::http::request::Parts) -> bool {
+    matches!(parts.method, Method::GET | Method::HEAD)
+        && parts
+            .extensions
+            .get::<MatchedPath>()
+            .is_some_and(|path| GUEST_ROUTES.contains(&path.as_str()))
+}
+
+/// Is the request made by a guest, who browses the library of the guest user
+fn is_guest(state: &AppState, headers: &HeaderMap) -> bool {
+    state.config.guest.is_some()
+        && state.config.debug.assume_user.is_none()
+        && !state
+            .config
+            .auth
+            .header
+            .as_ref()
+            .is_some_and(|header| headers.contains_key(header))
+        && login::session_cookie(state, headers).is_none()
+}
+
+/// Pages opened with the query they were last used with
+const REMEMBERED_PAGES: &[&str] = &["/", "/unread", "/series"];
+
+/// Redirect to the query last used by `user` on `page` when it is opened without one, and remember
+/// the query otherwise. Guests share the user of the library, so their queries are not remembered,
+/// and nothing is written while the maintenance mode is enabled.
+async fn remembered_query(
+    state: &AppState,
+    user: &User,
+    headers: &HeaderMap,
+    page: &str,
+    query: Option<String>,
+) -> Result<Option<Redirect>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    match query.filter(|q| !q.is_empty()) {
+        Some(query) => {
+            if !is_guest(state, headers) && !AtomicBool::load(&state.maintenance, Ordering::Relaxed)
+            {
+                diesel::insert_into(pagequery::table)
+                    .values((
+                        pagequery::owner.eq(user.id),
+                        pagequery::page.eq(page),
+                        pagequery::query.eq(&query),
+                    ))
+                    .on_conflict((pagequery::owner, pagequery::page))
+                    .do_update()
+                    .set(pagequery::query.eq(&query))
+                    .execute(&mut conn)
+                    .await?;
+            }
+
+            Ok(None)
+        }
+        None => {
+            let saved: Option<String> = pagequery::table
+                .find((user.id, page))
+                .select(pagequery::query)
+                .first(&mut conn)
+                .await
+                .optional()?;
+
+            Ok(saved.map(|query| Redirect::to(&format!("{page}?{query}"))))
+        }
+    }
+}
+
 #[async_trait]
 impl FromRequestParts<Arc<AppState>> for User {
     type Rejection = RouteError;
@@ -291,12 +723,36 @@ impl FromRequestParts<Arc<AppState>> for User {
         parts: &mut axum::http::request::Parts,
         state: &Arc<AppState>,
     ) -> Result<Self, Self::Rejection> {
-        let user = match parts.headers.get(&state.config.auth.header) {
-            Some(user) => user.to_str()?,
-            None if state.config.debug.assume_user.is_some() => {
+        // A revoked session needs to log in again, even if the proxy header is set
+        let session = match login::session_cookie(state, &parts.headers) {
+            Some(cookie) => Some(
+                login::session_user(state, cookie)
+                    .await?
+                    .ok_or(RouteError::LoginRequired)?,
+            ),
+            None => None,
+        };
+        let header = state
+            .config
+            .auth
+            .header
+            .as_ref()
+            .and_then(|h| parts.headers.get(h));
+
+        let user = match (&session, header) {
+            (Some(user), _) => user.as_str(),
+            (None, Some(user)) => user.to_str()?,
+            (None, None) if state.config.debug.assume_user.is_some() => {
                 state.config.debug.assume_user.as_deref().unwrap()
             }
-            None => {
+            (None, None) if state.config.guest.is_some() && guest_route(parts) => {
+                &state.config.guest.as_ref().unwrap().user
+            }
+            (None, None) if state.config.auth.local.is_some() => {
+                return Err(RouteError::LoginRequired);
+            }
+            (None, None) if state.config.guest.is_some() => return Err(RouteError::Forbidden),
+            (None, None) => {
                 return Err(RouteError::NoUser);
             }
         };
@@ -317,6 +773,75 @@ impl FromRequestParts<Arc<AppState>> for User {
     }
 }
 
+/// Is `user` listed in `auth.admin`
+fn is_admin(state: &AppState, user: &User) -> bool {
+    state.config.auth.admin.contains(&user.name)
+}
+
+/// Covers larger than this, or needing more memory than `MAX_COVER_ALLOC` to be decoded, are
+/// rejected before being decoded
+const MAX_COVER_DIMENSION: u32 = 10_000;
+const MAX_COVER_ALLOC: u64 = 256 * 1024 * 1024;
+
+fn decode_cover(data: &[u8]) -> Result<image::DynamicImage, RouteError> {
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_COVER_DIMENSION);
+    limits.max_image_height = Some(MAX_COVER_DIMENSION);
+    limits.max_alloc = Some(MAX_COVER_ALLOC);
+
+    let mut reader = image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(RouteError::ImageDetection)?;
+    reader.limits(limits);
+
+    Ok(reader.decode()?)
+}
+
+/// Encode a cover following the `[images]` settings
+fn encode_cover(
+    config: &ImageConfig,
+    image: &image::DynamicImage,
+) -> Result<Vec<u8>, image::ImageError> {
+    let image = match config.fits(image.width(), image.height()) {
+        true => image.to_rgb8(),
+        false => image
+            .resize(
+                config.max_width.unwrap_or(u32::MAX),
+                config.max_height.unwrap_or(u32::MAX),
+                image::imageops::FilterType::Lanczos3,
+            )
+            .to_rgb8(),
+    };
+
+    let mut data = Vec::new();
+    image.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+        &mut data,
+        config.quality,
+    ))?;
+
+    Ok(data)
+}
+
+/// Store a cover at `path`, this is blocking
+fn save_cover(
+    config: &ImageConfig,
+    image: &image::DynamicImage,
+    path: &std::path::Path,
+) -> Result<(), RouteError> {
+    let data = encode_cover(config, image).map_err(RouteError::ImageSave)?;
+    std::fs::write(path, data).map_err(|e| RouteError::ImageSave(image::ImageError::IoError(e)))
+}
+
+diesel::define_sql_function! {
+    /// Lowercase version of a name without accents, used to find similar names
+    fn name_key(name: sql_types::Text) -> sql_types::Text;
+}
+
+diesel::define_sql_function! {
+    #[sql_name = "name_key"]
+    fn citext_name_key(name: sql_types::Citext) -> sql_types::Text;
+}
+
 #[derive(Debug)]
 pub(crate) struct BookInfo {
     book: Book,
@@ -324,16 +849,90 @@ pub(crate) struct BookInfo {
     image: Option<image::DynamicImage>,
     authors: Vec<AuthorName>,
     tags: Vec<TagName>,
+    /// External links, as (label, URL)
+    links: Vec<(String, String)>,
+    /// Identifiers without a dedicated column, keyed by scheme
+    identifiers: BTreeMap<String, String>,
+    /// Provider the details were fetched from, with the time of the lookup
+    source: Option<(String, DateTime<Utc>)>,
+    return_to: Option<String>,
+}
+
+/// Only web links are allowed, to avoid `javascript:` URLs in the book page
+fn valid_link(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Non empty lines of `text`, trimmed
+fn lines(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum ParseMode {
+    /// Reject the form on unknown fields or invalid values
+    Strict,
+    /// Ignore unknown fields and invalid optional values, logging them
+    Lenient,
+}
+
+/// [BookInfo] extractor that does not reject forms with unknown fields or invalid optional values,
+/// for forms pre-filled from metadata providers
+pub(crate) struct Lenient(pub BookInfo);
+
+fn parse_optional<T, E>(
+    mode: ParseMode,
+    field: &str,
+    text: &str,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> Result<Option<T>, RouteError>
+where
+    E: std::fmt::Debug,
+    RouteError: From<E>,
+{
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    match parse(text) {
+        Ok(v) => Ok(Some(v)),
+        Err(e) if mode == ParseMode::Lenient => {
+            tracing::warn!("Ignoring invalid value {text:?} for {field:?}: {e:?}");
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
 }
 
 #[async_trait]
 impl FromRequest<Arc<AppState>> for BookInfo {
     type Rejection = RouteError;
 
-    async fn from_request(
+    async fn from_request(req: Request, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        BookInfo::parse(req, state, ParseMode::Strict).await
+    }
+}
+
+#[async_trait]
+impl FromRequest<Arc<AppState>> for Lenient {
+    type Rejection = RouteError;
+
+    async fn from_request(req: Request, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        BookInfo::parse(req, state, ParseMode::Lenient)
+            .await
+            .map(Lenient)
+    }
+}
+
+impl BookInfo {
+    async fn parse(
         mut req: Request,
         state: &Arc<AppState>,
-    ) -> Result<Self, Self::Rejection> {
+        mode: ParseMode,
+    ) -> Result<Self, RouteError> {
         let user: User = req.extract_parts_with_state(state).await?;
         let mut multipart = Multipart::from_request(req, state).await?;
 
@@ -354,6 +953,7 @@ impl FromRequest<Arc<AppState>> for BookInfo {
             publisher: Option<String>,
             language: Option<String>,
             google_id: Option<String>,
+            goodreads_id: Option<String>,
             amazon_id: Option<String>,
             librarything_id: Option<String>,
             page_count: Option<i32>,
@@ -361,18 +961,29 @@ impl FromRequest<Arc<AppState>> for BookInfo {
             series_volume: Option<i32>,
             owned_box: bool,
             read_box: bool,
+            reread_box: bool,
+            spoilers_box: bool,
+            priority: Option<i32>,
+            location: Option<String>,
+            link_labels: Vec<String>,
+            link_urls: Vec<String>,
+            identifier_schemes: Vec<String>,
+            identifier_values: Vec<String>,
+            metadata_source: Option<String>,
+            metadata_fetched: Option<DateTime<Utc>>,
+            return_to: Option<String>,
         }
 
         let mut data = BookData::default();
         let load = |s: String| if s.is_empty() { None } else { Some(s) };
 
         while let Some(field) = multipart.next_field().await? {
-            let Some(name) = field.name() else {
+            let Some(name) = field.name().map(str::to_owned) else {
                 tracing::warn!("Unamed multipart field");
                 continue;
             };
 
-            match name {
+            match name.as_str() {
                 "user_cover" => {
                     let cover = field.bytes().await?;
                     if !cover.is_empty() {
@@ -387,40 +998,58 @@ impl FromRequest<Arc<AppState>> for BookInfo {
                 "title" => data.title = load(field.text().await?),
                 "isbn" => data.isbn = load(field.text().await?),
                 "summary" => data.summary = field.text().await?,
-                "author" => data.authors.push(AuthorName {
-                    name: field.text().await?,
-                }),
-                "tag" => data.tags.push(TagName {
-                    name: field.text().await?,
-                }),
+                // Without scripts the lists are sent as a single value with one entry per line
+                "author" => data
+                    .authors
+                    .extend(lines(&field.text().await?).map(|name| AuthorName { name })),
+                "tag" => data
+                    .tags
+                    .extend(lines(&field.text().await?).map(|name| TagName { name })),
                 "published" => {
-                    let text = field.text().await?;
-                    if !text.is_empty() {
-                        data.publication_date = Some(NaiveDate::parse_from_str(&text, "%Y-%m-%d")?)
-                    }
+                    data.publication_date =
+                        parse_optional(mode, &name, &field.text().await?, |text| {
+                            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                        })?
                 }
                 "publisher" => data.publisher = load(field.text().await?),
+                "location" => data.location = load(field.text().await?),
                 "language" => data.language = load(field.text().await?),
                 "google_id" => data.google_id = load(field.text().await?),
+                "goodreads_id" => data.goodreads_id = load(field.text().await?),
                 "amazon_id" => data.amazon_id = load(field.text().await?),
                 "librarything_id" => data.librarything_id = load(field.text().await?),
                 "page_count" => {
-                    let text = field.text().await?;
-                    if !text.is_empty() {
-                        data.page_count = Some(text.parse()?)
-                    }
+                    data.page_count = parse_optional(mode, &name, &field.text().await?, str::parse)?
                 }
                 "series_name" => data.series_name = load(field.text().await?),
                 "series_volume" => {
                     let text = field.text().await?;
-                    if !text.is_empty() {
-                        data.series_volume = Some(text.parse()?);
-                    }
+                    data.series_volume = parse_optional(mode, &name, &text, str::parse)?
                 }
                 "owned_box" => data.owned_box = true,
                 "read_box" => data.read_box = true,
+                "reread_box" => data.reread_box = true,
+                "spoilers_box" => data.spoilers_box = true,
+                "return_to" => data.return_to = load(field.text().await?),
+                "metadata_source" => data.metadata_source = load(field.text().await?),
+                "metadata_fetched" => {
+                    data.metadata_fetched = Some(
+                        DateTime::parse_from_rfc3339(&field.text().await?)?.with_timezone(&Utc),
+                    )
+                }
+                "link_label" => data.link_labels.push(field.text().await?),
+                "link_url" => data.link_urls.push(field.text().await?),
+                "identifier_scheme" => data.identifier_schemes.push(field.text().await?),
+                "identifier_value" => data.identifier_values.push(field.text().await?),
+                "priority" => {
+                    data.priority = parse_optional(mode, &name, &field.text().await?, str::parse)?
+                }
+                _ if mode == ParseMode::Strict => {
+                    tracing::warn!("Unknown field {name:?}");
+                    return Err(RouteError::InvalidForm);
+                }
                 _ => {
-                    tracing::warn!("Unknown field {:?}", field.name());
+                    tracing::warn!("Unknown field {name:?}");
                 }
             }
         }
@@ -434,30 +1063,21 @@ impl FromRequest<Arc<AppState>> for BookInfo {
             publisher: data.publisher,
             language: data.language,
             googleid: data.google_id,
+            goodreadsid: data.goodreads_id,
             amazonid: data.amazon_id,
             librarythingid: data.librarything_id,
             pagecount: data.page_count,
             owned: data.owned_box,
             read: data.read_box,
+            reread: data.reread_box,
+            priority: data.priority,
+            location: data.location,
+            summary_spoilers: data.spoilers_box,
         };
 
         let image = match data.cover_art {
-            Some(CoverArt::User(bytes)) => Some(
-                image::ImageReader::new(Cursor::new(bytes))
-                    .with_guessed_format()
-                    .map_err(RouteError::ImageDetection)?
-                    .decode()?,
-            ),
-            Some(CoverArt::Fetched(data)) => {
-                let data = BASE64_STANDARD.decode(data)?;
-
-                Some(
-                    image::ImageReader::new(Cursor::new(data))
-                        .with_guessed_format()
-                        .map_err(RouteError::ImageDetection)?
-                        .decode()?,
-                )
-            }
+            Some(CoverArt::User(bytes)) => Some(decode_cover(&bytes)?),
+            Some(CoverArt::Fetched(data)) => Some(decode_cover(&BASE64_STANDARD.decode(data)?)?),
             None => None,
         };
 
@@ -467,20 +1087,220 @@ impl FromRequest<Arc<AppState>> for BookInfo {
             _ => return Err(RouteError::MissingField),
         };
 
+        let mut links = Vec::new();
+        for (label, url) in data.link_labels.into_iter().zip(data.link_urls) {
+            let (label, url) = (label.trim(), url.trim());
+
+            if url.is_empty() {
+                continue;
+            }
+
+            if !valid_link(url) {
+                tracing::warn!("Invalid link {url:?}");
+                match mode {
+                    ParseMode::Strict => return Err(RouteError::InvalidForm),
+                    ParseMode::Lenient => continue,
+                }
+            }
+
+            let label = match label.is_empty() {
+                true => url,
+                false => label,
+            };
+            links.push((label.to_string(), url.to_string()));
+        }
+
+        let mut identifiers = BTreeMap::new();
+        for (scheme, value) in data.identifier_schemes.iter().zip(&data.identifier_values) {
+            let (scheme, value) = (scheme.trim().to_lowercase(), value.trim());
+
+            if value.is_empty() {
+                continue;
+            }
+
+            if scheme.is_empty() {
+                tracing::warn!("Missing scheme for identifier {value:?}");
+                match mode {
+                    ParseMode::Strict => return Err(RouteError::InvalidForm),
+                    ParseMode::Lenient => continue,
+                }
+            }
+
+            identifiers.insert(scheme, value.to_string());
+        }
+
+        for &field in &state.config.form.required {
+            let present = match field {
+                RequiredField::Cover => image.is_some(),
+                RequiredField::Summary => !book.summary.trim().is_empty(),
+                RequiredField::Author => !data.authors.is_empty(),
+                RequiredField::Tag => !data.tags.is_empty(),
+                RequiredField::Series => series.is_some(),
+                RequiredField::Published => book.published.is_some(),
+                RequiredField::Publisher => book.publisher.is_some(),
+                RequiredField::Language => book.language.is_some(),
+                RequiredField::PageCount => book.pagecount.is_some(),
+                RequiredField::GoogleId => book.googleid.is_some(),
+                RequiredField::GoodreadsId => book.goodreadsid.is_some(),
+                RequiredField::AmazonId => book.amazonid.is_some(),
+                RequiredField::LibrarythingId => book.librarythingid.is_some(),
+            };
+
+            if !present {
+                return Err(RouteError::MissingField);
+            }
+        }
+
         Ok(BookInfo {
             book,
             image,
             series,
             authors: data.authors,
             tags: data.tags,
+            links,
+            identifiers,
+            source: data
+                .metadata_source
+                .map(|source| (source, data.metadata_fetched.unwrap_or_else(Utc::now))),
+            return_to: data.return_to,
+        })
+    }
+
+    fn book_links(&self, book: Uuid) -> Vec<BookLink> {
+        self.links
+            .iter()
+            .map(|(label, url)| BookLink {
+                book,
+                label: label.clone(),
+                url: url.clone(),
+            })
+            .collect()
+    }
+
+    fn book_identifiers(&self, book: Uuid) -> Vec<BookIdentifier> {
+        self.identifiers
+            .iter()
+            .map(|(scheme, value)| BookIdentifier {
+                book,
+                scheme: scheme.clone(),
+                value: value.clone(),
+            })
+            .collect()
+    }
+
+    /// Add the tags implied by the tags of the book, following the rules of its owner
+    async fn apply_tag_implications(
+        &mut self,
+        conn: &mut diesel_async::AsyncPgConnection,
+    ) -> Result<(), diesel::result::Error> {
+        let rules = tag_implications::implication_rules(conn, self.book.owner).await?;
+        tag_implications::imply_tags(&rules, &mut self.tags);
+
+        Ok(())
+    }
+
+    /// Replace the names of authors, tags and series by existing ones that only differ by case or
+    /// accents, so that "Herve" does not create a duplicate of "Hervé"
+    async fn match_existing_names(
+        &mut self,
+        conn: &mut diesel_async::AsyncPgConnection,
+    ) -> Result<(), diesel::result::Error> {
+        for author in &mut self.authors {
+            let existing: Option<String> = author::table
+                .filter(citext_name_key(author::name).eq(name_key(author.name.as_str())))
+                .select(author::name)
+                .first(conn)
+                .await
+                .optional()?;
+
+            if let Some(name) = existing {
+                author.name = name;
+            }
+        }
+
+        for tag in &mut self.tags {
+            let existing: Option<String> = tag::table
+                .filter(name_key(tag::name).eq(name_key(tag.name.as_str())))
+                .select(tag::name)
+                .first(conn)
+                .await
+                .optional()?;
+
+            if let Some(name) = existing {
+                tag.name = name;
+            }
+        }
+
+        if let Some((name, _)) = &mut self.series {
+            let existing: Option<String> = series::table
+                .filter(series::owner.eq(self.book.owner))
+                .filter(citext_name_key(series::name).eq(name_key(name.as_str())))
+                .select(series::name)
+                .first(conn)
+                .await
+                .optional()?;
+
+            if let Some(existing) = existing {
+                *name = existing;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Build a book from fetched metadata, failing if the title or ISBN are missing
+    fn from_details(user: &User, details: NullableBookDetails) -> Result<Self, RouteError> {
+        let image = details
+            .covert_art_b64
+            .map(|b64| decode_cover(&BASE64_STANDARD.decode(b64)?))
+            .transpose()?;
+
+        Ok(BookInfo {
+            book: Book {
+                owner: user.id,
+                isbn: details.isbn.ok_or(RouteError::MissingField)?,
+                title: details.title.ok_or(RouteError::MissingField)?,
+                summary: details.summary.unwrap_or_default(),
+                published: details.published,
+                publisher: details.publisher,
+                language: details.language,
+                googleid: details.google_id,
+                goodreadsid: details.goodreads_id,
+                amazonid: details.amazon_id,
+                librarythingid: details.librarything_id,
+                pagecount: details.page_count,
+                owned: details.owned,
+                read: details.read,
+                reread: details.reread,
+                priority: details.priority,
+                location: details.location,
+                summary_spoilers: details.summary_spoilers,
+            },
+            series: details.series,
+            image,
+            authors: details
+                .authors
+                .into_iter()
+                .map(|name| AuthorName { name })
+                .collect(),
+            tags: details
+                .tags
+                .into_iter()
+                .map(|name| TagName { name })
+                .collect(),
+            links: details
+                .links
+                .into_iter()
+                .filter(|(_, url)| valid_link(url))
+                .collect(),
+            identifiers: details.identifiers,
+            source: None,
+            return_to: None,
         })
     }
 }
 
-pub(crate) async fn image(
-    state: State,
-    Path((user_id, book_id)): Path<(Uuid, Uuid)>,
-) -> Result<impl IntoResponse, RouteError> {
+async fn serve_image(state: &State, user_id: Uuid, book_id: Uuid) -> Result<Response, RouteError> {
     let image_path = state
         .config
         .metadata
@@ -488,6 +1308,10 @@ pub(crate) async fn image(
         .join(user_id.to_string())
         .join(format!("{}.jpg", book_id));
 
+    serve_file(&image_path).await
+}
+
+async fn serve_file(image_path: &std::path::Path) -> Result<Response, RouteError> {
     if !image_path.exists() {
         return Err(RouteError::NotFound);
     }
@@ -499,26 +1323,147 @@ pub(crate) async fn image(
     Ok(([(CONTENT_TYPE, "image/jpeg")], body).into_response())
 }
 
-pub(crate) async fn image_not_found(_user: User) -> impl IntoResponse {
+pub(crate) async fn image(
+    state: State,
+    user: User,
+    Path((user_id, book_id)): Path<(Uuid, Uuid)>,
+) -> Result<Response, RouteError> {
+    if user.id != user_id {
+        return Err(RouteError::NotFound);
+    }
+
+    serve_image(&state, user_id, book_id).await
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct SignedQuery {
+    expires: i64,
+    signature: String,
+}
+
+/// Check that the page embedding a public cover is allowed to by `[public_images]`
+fn check_hotlink(config: &PublicImageConfig, headers: &HeaderMap) -> Result<(), RouteError> {
+    if !config.hotlink_protection {
+        return Ok(());
+    }
+
+    let Some((_, referer_host)) = referer_url(headers) else {
+        return match config.allow_no_referer && !headers.contains_key(REFERER) {
+            true => Ok(()),
+            false => Err(RouteError::Forbidden),
+        };
+    };
+
+    let own_host = headers.get(HOST).and_then(|h| h.to_str().ok());
+
+    if Some(referer_host.as_str()) == own_host || config.allowed_referers.contains(&referer_host) {
+        Ok(())
+    } else {
+        tracing::debug!("Refused to serve a public cover to {referer_host}");
+        Err(RouteError::Forbidden)
+    }
+}
+
+/// Cover downscaled following `[public_images]`, cached in the `public` directory of the user
+async fn serve_public_image(
+    state: &State,
+    user_id: Uuid,
+    book_id: Uuid,
+) -> Result<Response, RouteError> {
+    let config = &state.config.public_images.encoding;
+    if config.max_width.is_none() && config.max_height.is_none() {
+        return serve_image(state, user_id, book_id).await;
+    }
+
+    let image_dir = state.config.metadata.image_dir.join(user_id.to_string());
+    let original = image_dir.join(format!("{book_id}.jpg"));
+    let public = image_dir.join("public").join(format!("{book_id}.jpg"));
+
+    if !original.exists() {
+        return Err(RouteError::NotFound);
+    }
+
+    let modified = |path: &std::path::Path| std::fs::metadata(path).and_then(|m| m.modified());
+    let stale = match (modified(&original), modified(&public)) {
+        (Ok(original), Ok(public)) => public < original,
+        _ => true,
+    };
+
+    if stale {
+        tokio::task::block_in_place(|| {
+            std::fs::create_dir_all(image_dir.join("public"))?;
+            let image = image::open(&original).map_err(RouteError::ImageSave)?;
+            save_cover(config, &image, &public)
+        })?;
+    }
+
+    serve_file(&public).await
+}
+
+/// Covers embedded in public pages, accessible without authentication through a signed URL
+pub(crate) async fn signed_image(
+    state: State,
+    Path((user_id, book_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<SignedQuery>,
+    headers: HeaderMap,
+) -> Result<Response, RouteError> {
+    let path = components::signed_image_path(user_id, book_id);
+    if !state.signer.verify(&path, query.expires, &query.signature) {
+        return Err(RouteError::NotFound);
+    }
+
+    check_hotlink(&state.config.public_images, &headers)?;
+
+    match serve_public_image(&state, user_id, book_id).await {
+        Err(RouteError::NotFound) => Ok(no_cover().into_response()),
+        r => r,
+    }
+}
+
+fn no_cover() -> impl IntoResponse {
     let image = include_bytes!("../no_cover.jpg");
 
     ([(CONTENT_TYPE, "image/jpeg")], image)
 }
 
-pub(crate) async fn index(state: State, user: User) -> Result<maud::Markup, RouteError> {
+pub(crate) async fn image_not_found(_user: User) -> impl IntoResponse {
+    no_cover()
+}
+
+#[derive(serde::Deserialize, Default)]
+pub(crate) struct IndexQuery {
+    /// Show the covers on shelves instead of the cards
+    #[serde(default)]
+    shelf: bool,
+}
+
+pub(crate) async fn index(
+    state: State,
+    user: User,
+    headers: HeaderMap,
+    RawQuery(raw_query): RawQuery,
+    Query(query): Query<IndexQuery>,
+) -> Result<Response, RouteError> {
+    if let Some(redirect) = remembered_query(&state, &user, &headers, "/", raw_query).await? {
+        return Ok(redirect.into_response());
+    }
+
     let mut conn = state.db.get().await?;
 
     let all_books: Vec<BookPreview> = book::table
         .filter(book::owner.eq(user.id))
         .left_join(bookseries::table)
-        .order((bookseries::series, bookseries::number, book::title))
+        .order((bookseries::series, bookseries::number, book::sort_title))
         .select(BookPreview::as_select())
         .load(&mut conn)
         .await?;
 
     drop(conn);
 
-    let book_data = book_cards_for(&state, &user, &all_books, NO_SORT).await?;
+    let book_data = match query.shelf {
+        true => book_shelf_for(&state, &user, &all_books).await?,
+        false => book_cards_for(&state, &user, &all_books, NO_SORT).await?,
+    };
 
     Ok(app_page(
         Page::Books,
@@ -526,10 +1471,20 @@ pub(crate) async fn index(state: State, user: User) -> Result<maud::Markup, Rout
         html! {
             .text-center {
                 h2 { "Books" }
+                @if query.shelf {
+                    a .btn.btn-outline-secondary.btn-sm."mb-2" href="/?shelf=false" {
+                        "Show the cards"
+                    }
+                } @else if !all_books.is_empty() {
+                    a .btn.btn-outline-secondary.btn-sm."mb-2" href="/?shelf=true" {
+                        "Show the shelves"
+                    }
+                }
                 (book_data)
             }
         },
-    ))
+    )
+    .into_response())
 }
 
 #[derive(QueryableByName)]
@@ -547,9 +1502,35 @@ pub struct SeriesAllInfo {
     pub first_volume: Uuid,
     #[diesel(sql_type = sql_types::Nullable<sql_types::Integer>)]
     pub total_count: Option<i32>,
+    #[diesel(sql_type = sql_types::Bool)]
+    pub archived: bool,
+}
+
+#[derive(serde::Deserialize, Default)]
+pub(crate) struct ArchivedQuery {
+    /// Also show the archived series
+    #[serde(default)]
+    archived: bool,
+}
+
+/// Link toggling the display of the archived series of a page
+fn archived_toggle(location: &str, query: &ArchivedQuery, series: &[SeriesAllInfo]) -> Markup {
+    let count = series.iter().filter(|s| s.archived).count();
+
+    html! {
+        @if query.archived {
+            a .btn.btn-outline-secondary.btn-sm."mb-2" href=(format!("{location}?archived=false")) {
+                "Hide archived series"
+            }
+        } @else if count != 0 {
+            a .btn.btn-outline-secondary.btn-sm."mb-2" href=(format!("{location}?archived=true")) {
+                (format!("Show archived series ({count})"))
+            }
+        }
+    }
 }
 
-async fn series_info(state: &State) -> Result<Vec<SeriesAllInfo>, RouteError> {
+async fn series_info(state: &State, user: &User) -> Result<Vec<SeriesAllInfo>, RouteError> {
     let mut conn = state.db.get().await?;
 
     let series = diesel::sql_query(
@@ -560,6 +1541,7 @@ async fn series_info(state: &State) -> Result<Vec<SeriesAllInfo>, RouteError> {
             series.name as name,
             ongoing,
             total_count,
+            archived,
             COALESCE(owned_count, 0) as owned_count
         FROM 
             bookseries bs 
@@ -568,7 +1550,7 @@ async fn series_info(state: &State) -> Result<Vec<SeriesAllInfo>, RouteError> {
             ON b.series = bs.series AND bs.number = b.minvolume 
         INNER JOIN 
             series 
-            ON series.id = bs.series
+            ON series.id = bs.series AND series.owner = $1
         LEFT JOIN
             (
                 SELECT series, COUNT(book) as owned_count
@@ -579,14 +1561,30 @@ async fn series_info(state: &State) -> Result<Vec<SeriesAllInfo>, RouteError> {
             ON owned_book_count.series = bs.series;
     "#,
     )
+    .bind::<sql_types::Uuid, _>(user.id)
     .get_results::<SeriesAllInfo>(&mut conn)
     .await?;
 
     Ok(series)
 }
 
-pub(crate) async fn series(state: State, user: User) -> Result<maud::Markup, RouteError> {
-    let series = series_info(&state).await?;
+pub(crate) async fn series(
+    state: State,
+    user: User,
+    headers: HeaderMap,
+    RawQuery(raw_query): RawQuery,
+    Query(query): Query<ArchivedQuery>,
+) -> Result<Response, RouteError> {
+    if let Some(redirect) = remembered_query(&state, &user, &headers, "/series", raw_query).await? {
+        return Ok(redirect.into_response());
+    }
+
+    let mut series = series_info(&state, &user).await?;
+    let toggle = archived_toggle("/series", &query, &series);
+
+    if !query.archived {
+        series.retain(|s| !s.archived);
+    }
 
     Ok(app_page(
         Page::Series,
@@ -594,8 +1592,618 @@ pub(crate) async fn series(state: State, user: User) -> Result<maud::Markup, Rou
         html! {
             .text-center {
                 h2 { "Series" }
+                (toggle)
                 (components::series_cards(&state, &user, &series, true))
             }
         },
-    ))
+    )
+    .into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{
+            header::{HOST, REFERER},
+            Request, StatusCode,
+        },
+    };
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::{author, book, bookauthor, booktag, series},
+        testing::{
+            body_text, book_form, book_id, location, test_cover, user_id, MultipartForm, TestApp,
+            OTHER_USER, TEST_USER, USER_HEADER,
+        },
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn empty_index() {
+        let app = TestApp::new().await;
+
+        let response = app.get("/").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_text(response).await.contains("Books"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn accessible_markup() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get("/").await).await;
+        assert!(page.contains(r##"href="#content""##));
+        assert!(page.contains(r#"<main id="content""#));
+        assert!(page.contains(r#"alt="Cover of Mort""#));
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains(r#"alt="Cover of Mort""#));
+        assert!(page.contains(r#"aria-label="Edit the book""#));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn without_javascript() {
+        let app = TestApp::new().await;
+
+        let page = body_text(app.get("/add").await).await;
+        assert!(page.contains(r#"id="authorLines""#));
+        assert!(page.contains(r#"action="/add""#));
+
+        // The fallback lists are sent with one value per line
+        app.post_multipart(
+            "/add",
+            MultipartForm::new()
+                .text("title", "Good Omens")
+                .text("isbn", "9780552137034")
+                .text("author", "Terry Pratchett\r\n\r\n Neil Gaiman \r\n")
+                .text("tag", "Fantasy\nHumour"),
+        )
+        .await;
+        let id = book_id(&app, "9780552137034").await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let mut authors: Vec<String> = bookauthor::table
+            .inner_join(author::table)
+            .filter(bookauthor::book.eq(id))
+            .select(author::name)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        authors.sort();
+        assert_eq!(authors, ["Neil Gaiman", "Terry Pratchett"]);
+
+        let tags: i64 = booktag::table
+            .filter(booktag::book.eq(id))
+            .count()
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(tags, 2);
+        drop(conn);
+
+        // Existing values are checked boxes that can be unticked
+        let page = body_text(app.get(&format!("/book/{id}/edit")).await).await;
+        assert!(page.contains(r#"name="author" value="Neil Gaiman" checked"#));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shelf_view() {
+        let app = TestApp::new().await;
+
+        for (title, isbn, series) in [
+            ("Eric", "9780575046368", Some("9")),
+            ("Mort", "9780552131063", Some("4")),
+            ("Good Omens", "9780552137034", None),
+        ] {
+            let mut form = book_form(title, isbn);
+            if let Some(volume) = series {
+                form = form
+                    .text("series_name", "Discworld")
+                    .text("series_volume", volume);
+            }
+            app.post_multipart("/add", form).await;
+        }
+
+        let page = body_text(app.get("/").await).await;
+        assert!(page.contains("Show the shelves"));
+
+        let page = body_text(app.get("/?shelf=true").await).await;
+        assert!(page.contains("Show the cards"));
+
+        let position = |alt: &str| page.find(&format!(r#"alt="{alt}""#)).unwrap();
+        assert!(position("Mort (Discworld #4)") < position("Eric (Discworld #9)"));
+        // Good Omens is grouped under "Terry Pratchett", after the series
+        assert!(position("Eric (Discworld #9)") < position("Good Omens"));
+
+        let response = app.get("/").await;
+        assert_eq!(location(&response), "/?shelf=true");
+
+        let page = body_text(app.get("/?shelf=false").await).await;
+        assert!(page.contains("Show the shelves"));
+        let response = app.get("/").await;
+        assert_eq!(location(&response), "/?shelf=false");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn books_are_private() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let response = app.get_as(OTHER_USER, &format!("/book/{id}")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .post_multipart_as(
+                OTHER_USER,
+                &format!("/book/{id}/edit"),
+                book_form("Stolen", "9780552131063"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn series() {
+        let app = TestApp::new().await;
+
+        for (title, isbn, volume) in [
+            ("The Colour of Magic", "9780552124751", "1"),
+            ("The Light Fantastic", "9780552128483", "2"),
+        ] {
+            app.post_multipart(
+                "/add",
+                book_form(title, isbn)
+                    .text("series_name", "Discworld")
+                    .text("series_volume", volume)
+                    .text("owned_box", "on"),
+            )
+            .await;
+        }
+
+        let page = body_text(app.get("/series").await).await;
+        assert!(page.contains("Discworld"));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let series_id: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let response = app
+            .post_form(
+                &format!("/series/{series_id}/edit"),
+                "name=Discworld&ongoing_box=on&total_count=4",
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let page = body_text(app.get(&format!("/series/{series_id}")).await).await;
+        assert!(page.contains("Discworld (Ongoing)"));
+        assert!(page.contains("The Colour of Magic"));
+        assert!(page.contains("The Light Fantastic"));
+
+        let page = body_text(app.get("/ongoing").await).await;
+        assert!(page.contains("Volume 3"));
+        assert!(page.contains("Volume 4"));
+        assert!(!page.contains("Volume 1"));
+        assert!(page.contains("Next missing: Vol. 3"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn archived_series() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("series_name", "Death")
+                .text("series_volume", "1")
+                .text("owned_box", "on"),
+        )
+        .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let series_id: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let response = app
+            .post_form(
+                &format!("/series/{series_id}/edit"),
+                "name=Death&ongoing_box=on&archived_box=on&total_count=1",
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        for page in ["/series", "/ongoing"] {
+            let body = body_text(app.get(page).await).await;
+            assert!(!body.contains(r#"alt="Cover of the first volume of Death""#));
+            assert!(body.contains("Show archived series (1)"));
+
+            let body = body_text(app.get(&format!("{page}?archived=true")).await).await;
+            assert!(body.contains(r#"alt="Cover of the first volume of Death""#));
+            assert!(body.contains("Hide archived series"));
+        }
+
+        let body = body_text(app.get(&format!("/series/{series_id}")).await).await;
+        assert!(body.contains("Death (Ongoing) (Archived)"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn series_scoped_to_owner() {
+        let app = TestApp::new().await;
+
+        app.post_multipart_as(
+            OTHER_USER,
+            "/add",
+            book_form("The Colour of Magic", "9780552124751")
+                .text("series_name", "Discworld")
+                .text("series_volume", "1"),
+        )
+        .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        diesel::update(series::table)
+            .set((series::ongoing.eq(true), series::total_count.eq(Some(3))))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let page = body_text(app.get_as(OTHER_USER, "/ongoing").await).await;
+        assert!(page.contains("Next missing: Vol. 2"));
+
+        let page = body_text(app.get("/series").await).await;
+        assert!(!page.contains("Discworld"));
+
+        let page = body_text(app.get("/ongoing").await).await;
+        assert!(!page.contains("Discworld"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn images() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").file("user_cover", "cover.png", test_cover()),
+        )
+        .await;
+        app.post_multipart("/add", book_form("Eric", "9780575046368"))
+            .await;
+
+        let user = user_id(&app, TEST_USER).await;
+        let with_cover = book_id(&app, "9780552131063").await;
+        let without_cover = book_id(&app, "9780575046368").await;
+
+        let response = app
+            .get(&format!("/public/{user}/images/{with_cover}"))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/jpeg");
+
+        let response = app
+            .get(&format!("/public/{user}/images/{without_cover}"))
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let page = body_text(app.get(&format!("/book/{without_cover}")).await).await;
+        assert!(page.contains("/public/images/not_found"));
+
+        let response = app.get("/public/images/not_found").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signed_images() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("series_name", "Death")
+                .text("series_volume", "1")
+                .text("owned_box", "on")
+                .file("user_cover", "cover.png", test_cover()),
+        )
+        .await;
+        app.post_form("/profile", "ongoing_box=on").await;
+
+        let user = user_id(&app, TEST_USER).await;
+        let book = book_id(&app, "9780552131063").await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let series_id: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        app.post_form(
+            &format!("/series/{series_id}/edit"),
+            "name=Death&ongoing_box=on&total_count=2",
+        )
+        .await;
+
+        let anonymous = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let page = body_text(
+            app.request(anonymous(&format!("/public/{user}/ongoing")))
+                .await,
+        )
+        .await;
+        assert!(!page.contains(&format!("/public/{user}/images/")));
+
+        let start = page
+            .find("/public/signed/")
+            .expect("no signed image in public page");
+        let url = page[start..][..page[start..].find('"').unwrap()].replace("&amp;", "&");
+
+        let response = app.request(anonymous(&url)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/jpeg");
+
+        let (unsigned, _) = url.split_once("&signature=").unwrap();
+        let response = app
+            .request(anonymous(&format!("{unsigned}&signature=AAAA")))
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .request(anonymous(&format!("/public/{user}/images/{book}")))
+            .await;
+        assert_ne!(response.status(), StatusCode::OK);
+
+        let response = app
+            .get_as(OTHER_USER, &format!("/public/{user}/images/{book}"))
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn public_image_protection() {
+        let app = TestApp::with_config(
+            r#"
+            [public_images]
+            hotlink_protection = true
+            allowed_referers = ["blog.example"]
+            allow_no_referer = false
+            max_width = 6
+            "#,
+        )
+        .await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").file("user_cover", "cover.png", test_cover()),
+        )
+        .await;
+        app.post_form("/profile", "widget_box=on").await;
+
+        let user = user_id(&app, TEST_USER).await;
+        let page = body_text(
+            app.request(
+                Request::get(format!("/widget/{user}/recent"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await,
+        )
+        .await;
+        assert!(page.contains(r#"<meta name="referrer" content="same-origin">"#));
+
+        let start = page
+            .find("/public/signed/")
+            .expect("no signed image in widget");
+        let url = page[start..][..page[start..].find('"').unwrap()].replace("&amp;", "&");
+
+        let with_referer = |referer: Option<&str>| {
+            let mut request = Request::get(&url).header("host", "books.example");
+            if let Some(referer) = referer {
+                request = request.header("referer", referer);
+            }
+            app.request(request.body(Body::empty()).unwrap())
+        };
+
+        let response = with_referer(None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = with_referer(Some("https://leech.example/page")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = with_referer(Some("https://books.example/public/x/ongoing")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = with_referer(Some("https://blog.example/post")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let data = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let cover = image::load_from_memory(&data).unwrap();
+        assert_eq!(cover.width(), 6);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sort_title() {
+        let app = TestApp::new().await;
+
+        for (title, isbn, language) in [
+            ("The Light Fantastic", "9780552128483", "eng"),
+            ("Mort", "9780552131063", "eng"),
+            ("Les Annales du Disque-Monde", "9782266111560", "fre"),
+            ("Eric", "9780575046368", "eng"),
+        ] {
+            app.post_multipart("/add", book_form(title, isbn).text("language", language))
+                .await;
+        }
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let titles: Vec<String> = book::table
+            .select(book::title)
+            .order(book::sort_title)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            titles,
+            [
+                "Les Annales du Disque-Monde",
+                "Eric",
+                "The Light Fantastic",
+                "Mort"
+            ]
+        );
+
+        let page = body_text(app.get("/").await).await;
+        assert!(page.find("Eric") < page.find("The Light Fantastic"));
+        assert!(page.find("The Light Fantastic") < page.find("Mort"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn accent_insensitive_names() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("L'Anomalie", "9782072895098")
+                .text("author", "Hervé Le Tellier")
+                .text("tag", "Littérature"),
+        )
+        .await;
+        app.post_multipart(
+            "/add",
+            book_form("Toutes les familles heureuses", "9782072787768")
+                .text("author", "herve le tellier")
+                .text("tag", "litterature"),
+        )
+        .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let authors: Vec<String> = author::table
+            .select(author::name)
+            .order(author::name)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(authors, ["Hervé Le Tellier", "Terry Pratchett"]);
+
+        let book_authors: i64 = bookauthor::table
+            .count()
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(book_authors, 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn breadcrumbs_and_return() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("series_name", "Discworld")
+                .text("series_volume", "4"),
+        )
+        .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let series_id: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("breadcrumb"));
+        assert!(page.contains(&format!(r#"href="/series/{series_id}""#)));
+
+        let response = app
+            .request(
+                Request::get(format!("/book/{id}/edit"))
+                    .header(USER_HEADER, TEST_USER)
+                    .header(HOST, "books.example.org")
+                    .header(
+                        REFERER,
+                        format!("https://books.example.org/series/{series_id}"),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        let page = body_text(response).await;
+        assert!(page.contains(&format!(r#"name="return_to" value="/series/{series_id}""#)));
+
+        // Pages from other sites are ignored
+        let response = app
+            .request(
+                Request::get(format!("/book/{id}/edit"))
+                    .header(USER_HEADER, TEST_USER)
+                    .header(HOST, "books.example.org")
+                    .header(REFERER, "https://www.amazon.fr/dp/2070584623")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        let page = body_text(response).await;
+        assert!(!page.contains("return_to"));
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                book_form("Mort", "9780552131063")
+                    .text("return_to", &format!("/series/{series_id}")),
+            )
+            .await;
+        assert_eq!(location(&response), format!("/series/{series_id}"));
+
+        let response = app
+            .post_multipart(
+                &format!("/book/{id}/edit"),
+                book_form("Mort", "9780552131063").text("return_to", "//evil.example.org"),
+            )
+            .await;
+        assert_eq!(location(&response), format!("/book/{id}"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn error_pages() {
+        let app = TestApp::new().await;
+
+        let response = app.get("/does/not/exist").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let page = body_text(response).await;
+        assert!(page.contains("Resource not found"));
+        assert!(page.contains(TEST_USER));
+        assert!(page.contains(r#"href="/""#));
+
+        let response = app.get(&format!("/book/{}", Uuid::nil())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(body_text(response).await.contains(TEST_USER));
+
+        let response = app
+            .request(Request::get("/").body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!body_text(response).await.contains(TEST_USER));
+    }
 }
@@ -1,41 +1,26 @@
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use maud::html;
-use std::{collections::HashMap, fmt::Write};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::{
     models::User,
-    routes::{base_page, components},
+    routes::{components, public_page},
     schema::users,
     State,
 };
 
-use super::{app_page, series_info, Page, RouteError};
-
-async fn ongoing_core(state: State, user: User, private: bool) -> Result<maud::Markup, RouteError> {
-    let series = series_info(&state).await?;
-    let mut conn = state.db.get().await?;
-
-    let (mut all_owned, mut missing): (Vec<_>, _) = series
-        .into_iter()
-        .partition(|s| s.total_count.map(|t| t as i64) == Some(s.owned_count));
-
-    all_owned.retain(|s| s.ongoing);
-    missing.retain(|s| s.total_count.is_some());
-
-    let mut missing_ids = match missing.is_empty() {
-        true => String::new(),
-        false => format!("'{}'", missing[0].id),
-    };
-
-    if missing.len() > 1 {
-        for m in &missing[1..] {
-            let _ = write!(missing_ids, ", '{}'", m.id);
-        }
-    }
+use super::{app_page, archived_toggle, series_info, ArchivedQuery, Page, RouteError};
 
+/// Volumes of the series `ids` that are not in the library, sorted, for the series with a known
+/// volume count
+pub(super) async fn missing_volumes(
+    conn: &mut diesel_async::AsyncPgConnection,
+    user: &User,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, Vec<i32>>, diesel::result::Error> {
     #[derive(QueryableByName, Debug)]
     struct MissingVolume {
         #[diesel(sql_type = diesel::sql_types::Uuid)]
@@ -44,37 +29,66 @@ async fn ongoing_core(state: State, user: User, private: bool) -> Result<maud::M
         number: i32,
     }
 
-    let mut missing_volumes_table = if missing.is_empty() {
-        Default::default()
-    } else {
-        let missing_books = diesel::sql_query(format!(
-            r#"
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let missing_books = diesel::sql_query(
+        r#"
         SELECT id as series, number 
         FROM series, generate_series(1, total_count) as number 
         WHERE total_count IS NOT NULL
-                AND id IN({missing_ids})
+                AND owner = $1
+                AND id = ANY($2)
         EXCEPT
-        SELECT series, number FROM bookseries;
-    "#
-        ))
-        .get_results::<MissingVolume>(&mut conn)
-        .await?;
-
-        let mut missing_volumes_table = HashMap::<_, Vec<_>>::new();
-        for missing in missing_books {
-            missing_volumes_table
-                .entry(missing.series)
-                .or_default()
-                .push(missing.number);
-        }
-
+        SELECT series, number FROM bookseries WHERE series = ANY($2);
+    "#,
+    )
+    .bind::<diesel::sql_types::Uuid, _>(user.id)
+    .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(ids)
+    .get_results::<MissingVolume>(conn)
+    .await?;
+
+    let mut missing_volumes_table = HashMap::<_, Vec<_>>::new();
+    for missing in missing_books {
         missing_volumes_table
-    };
+            .entry(missing.series)
+            .or_default()
+            .push(missing.number);
+    }
 
     missing_volumes_table
         .values_mut()
         .for_each(|v| v.sort_unstable());
 
+    Ok(missing_volumes_table)
+}
+
+async fn ongoing_core(
+    state: State,
+    user: User,
+    private: bool,
+    query: ArchivedQuery,
+) -> Result<maud::Markup, RouteError> {
+    let mut series = series_info(&state, &user).await?;
+    let toggle = archived_toggle("/ongoing", &query, &series);
+
+    if !query.archived {
+        series.retain(|s| !s.archived);
+    }
+
+    let mut conn = state.db.get().await?;
+
+    let (mut all_owned, mut missing): (Vec<_>, _) = series
+        .into_iter()
+        .partition(|s| s.total_count.map(|t| t as i64) == Some(s.owned_count));
+
+    all_owned.retain(|s| s.ongoing);
+    missing.retain(|s| s.total_count.is_some());
+
+    let missing_ids: Vec<Uuid> = missing.iter().map(|m| m.id).collect();
+    let missing_volumes_table = missing_volumes(&mut conn, &user, &missing_ids).await?;
+
     let body = html! {
         .container.text-center {
             h2 {
@@ -84,14 +98,26 @@ async fn ongoing_core(state: State, user: User, private: bool) -> Result<maud::M
                     (format!("Ongoing Series ({})", user.name))
                 }
             }
+            @if private {
+                (toggle)
+            }
             @if !missing.is_empty() {
                 h3 { "Missing Volumes" }
+                @if private {
+                    form method="POST" action="/ongoing/wishlist" {
+                        button type="submit" .btn.btn-sm.btn-outline-primary."mb-2" {
+                            "Add the missing volumes to the wishlist"
+                        }
+                    }
+                }
                 .ms-3 {
                     @for missing in missing {
+                        @let volumes = missing_volumes_table.get(&missing.id).map(|s| -> &[_] { s }).unwrap_or_else(|| &[]);
                         .col."mb-2" {
                             .card."h-100" style="width: 9.6rem;" {
-                                img src=(components::make_image_url(&state, missing.first_volume, &user)) .card-img-top
-                                    alt="first volume cover" style="height: 14.4rem; width: 9.6rem;";
+                                img src=(components::make_cover_url(&state, missing.first_volume, &user, private)) .card-img-top
+                                    alt=(format!("Cover of the first volume of {}", missing.name))
+                                    style="height: 14.4rem; width: 9.6rem;";
                                 .card-body {
                                     h6 .card-title {
                                         @if private {
@@ -102,9 +128,14 @@ async fn ongoing_core(state: State, user: User, private: bool) -> Result<maud::M
                                             (missing.name)
                                         }
                                     }
+                                    @if let Some(next) = volumes.first() {
+                                        p .card-text.fw-bold.text-warning {
+                                            (format!("Next missing: Vol. {next}"))
+                                        }
+                                    }
                                 }
                                 ul .list-group.d-inline-block {
-                                    @for v in missing_volumes_table.get(&missing.id).map(|s| -> &[_] { s }).unwrap_or_else(|| &[]) {
+                                    @for v in volumes {
                                         li .list-group-item { (format!("Volume {v}")) }
                                     }
                                 }
@@ -123,12 +154,16 @@ async fn ongoing_core(state: State, user: User, private: bool) -> Result<maud::M
     if private {
         Ok(app_page(Page::Ongoing, &user, body))
     } else {
-        Ok(base_page(body))
+        Ok(public_page(body))
     }
 }
 
-pub(crate) async fn ongoing(state: State, user: User) -> Result<maud::Markup, RouteError> {
-    ongoing_core(state, user, true).await
+pub(crate) async fn ongoing(
+    state: State,
+    user: User,
+    Query(query): Query<ArchivedQuery>,
+) -> Result<maud::Markup, RouteError> {
+    ongoing_core(state, user, true, query).await
 }
 
 pub(crate) async fn ongoing_public(
@@ -148,5 +183,5 @@ pub(crate) async fn ongoing_public(
             _ => e.into(),
         })?;
 
-    ongoing_core(state, user, false).await
+    ongoing_core(state, user, false, ArchivedQuery::default()).await
 }
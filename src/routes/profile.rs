@@ -1,35 +1,90 @@
-use axum::{response::Redirect, Form};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::{http::HeaderMap, response::Redirect, Form};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
-use maud::html;
+use maud::{html, PreEscaped};
 
 use crate::schema::users;
 
-use super::{raw_app_page, RouteError, State, User};
+use super::{
+    is_admin,
+    login::{has_password, sessions},
+    nonce, raw_app_page, two_factor, Page, RouteError, State, User,
+};
 
-#[derive(diesel::AsChangeset, diesel::Selectable, diesel::Queryable)]
+#[derive(
+    diesel::AsChangeset,
+    diesel::Selectable,
+    diesel::Queryable,
+    serde::Serialize,
+    serde::Deserialize,
+    Default,
+)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-struct ProfileEdit {
-    public_ongoing: bool,
-}
-
-#[derive(serde::Deserialize)]
-pub(crate) struct ProfileForm {
-    ongoing_box: Option<super::CheckboxTick>,
+#[serde(default)]
+pub(super) struct ProfileEdit {
+    pub(super) public_ongoing: bool,
+    pub(super) public_widget: bool,
+    pub(super) public_feed: bool,
+    pub(super) provider_order: Vec<String>,
+    pub(super) hidden_pages: Vec<String>,
 }
 
+/// The form contains `ongoing_box`, `widget_box`, `feed_box`, `show:<page>` for each page shown in the
+/// navigation bar, and `provider:<id>` with the position of each provider
 pub(crate) async fn do_edit_profile(
     state: State,
     user: User,
-    Form(form): Form<ProfileForm>,
+    Form(form): Form<Vec<(String, String)>>,
 ) -> Result<Redirect, RouteError> {
+    let mut public_ongoing = false;
+    let mut public_widget = false;
+    let mut public_feed = false;
+    let mut positions = Vec::new();
+    let mut shown = Vec::new();
+
+    for (key, value) in form {
+        match key.split_once(':') {
+            None if key == "ongoing_box" => public_ongoing = true,
+            None if key == "widget_box" => public_widget = true,
+            None if key == "feed_box" => public_feed = true,
+            Some(("show", page)) => shown.push(page.to_string()),
+            Some(("provider", id)) if state.metadata.get(id).is_some() => {
+                let position: i32 = value.parse().map_err(|_| RouteError::InvalidForm)?;
+                positions.push((position, id.to_string()));
+            }
+            _ => tracing::warn!("Unknown field {key:?}"),
+        }
+    }
+
+    positions.sort_by_key(|&(position, _)| position);
+
+    // Keep following the default order of the instance until the user changes it
+    let mut provider_order: Vec<String> = positions.into_iter().map(|(_, id)| id).collect();
+    if provider_order
+        .iter()
+        .map(String::as_str)
+        .eq(state.metadata.ordered(&[]).map(|(id, _)| id))
+    {
+        provider_order.clear();
+    }
+
     let mut conn = state.db.get().await?;
 
     diesel::update(users::table)
         .filter(users::id.eq(user.id))
         .set(ProfileEdit {
-            public_ongoing: form.ongoing_box.is_some(),
+            public_ongoing,
+            public_widget,
+            public_feed,
+            provider_order,
+            hidden_pages: Page::hideable()
+                .map(|p| p.id())
+                .filter(|id| !shown.iter().any(|shown| shown == id))
+                .map(str::to_string)
+                .collect(),
         })
         .execute(&mut conn)
         .await?;
@@ -37,7 +92,11 @@ pub(crate) async fn do_edit_profile(
     Ok(axum::response::Redirect::to("/profile"))
 }
 
-pub(crate) async fn profile(state: State, user: User) -> Result<maud::Markup, RouteError> {
+pub(crate) async fn profile(
+    state: State,
+    user: User,
+    headers: HeaderMap,
+) -> Result<maud::Markup, RouteError> {
     let mut conn = state.db.get().await?;
 
     let profile = users::table
@@ -47,6 +106,25 @@ pub(crate) async fn profile(state: State, user: User) -> Result<maud::Markup, Ro
         .await?;
 
     let public_url = format!("/public/{}/ongoing", user.id);
+    let widget_url = format!("/widget/{}/recent", user.id);
+    let feed_url = format!("/public/{}/feed.atom", user.id);
+    // Whether the user has a password, their sessions and their second factor, when they can log
+    // in with one
+    let password = match state.config.auth.local {
+        Some(_) => {
+            let has_password = has_password(&state, &user).await?;
+            let two_factor = match has_password {
+                true => Some(two_factor::profile_section(&state, &user).await?),
+                false => None,
+            };
+            Some((
+                has_password,
+                sessions(&state, &user, &headers).await?,
+                two_factor,
+            ))
+        }
+        None => None,
+    };
 
     Ok(raw_app_page(
         None,
@@ -63,10 +141,220 @@ pub(crate) async fn profile(state: State, user: User) -> Result<maud::Markup, Ro
                         " " a href=(public_url) {"(Public URL)"}
                     }
                 }
+                .form-check {
+                    input .form-check-input type="checkbox" name="widget_box" #widgetBox checked[profile.public_widget];
+                    label .form-check-label for="widgetBox" { "Public recent books widget" }
+                    @if profile.public_widget {
+                        " " a href=(widget_url) {"(Widget URL)"}
+                    }
+                }
+                @if profile.public_widget {
+                    .form-text {
+                        "Embed it with "
+                        code #widgetEmbed {}
+                    }
+                    script nonce=[nonce()] {
+                        (PreEscaped(format!(r#"
+                            document.getElementById("widgetEmbed").textContent =
+                                `<iframe src="${{location.origin}}{widget_url}?count=5" width="600" height="200"></iframe>`
+                        "#)))
+                    }
+                }
+                .form-check {
+                    input .form-check-input type="checkbox" name="feed_box" #feedBox checked[profile.public_feed];
+                    label .form-check-label for="feedBox" { "Public feed of the recent books" }
+                    @if profile.public_feed {
+                        " " a href=(feed_url) {"(Feed URL)"}
+                    }
+                }
+                .form-text {
+                    "The recent books can also be followed privately from "
+                    a href="/feed.atom" { "/feed.atom" }
+                }
+                ul .list-group."my-2" {
+                    li .list-group-item { "Pages shown in the navigation bar" }
+                    @for page in Page::hideable() {
+                        li .list-group-item {
+                            @let input_id = format!("{}Shown", page.id());
+                            input .form-check-input."me-1" type="checkbox" #(input_id)
+                                name=(format!("show:{}", page.id()))
+                                checked[!profile.hidden_pages.iter().any(|p| p == page.id())];
+                            label .form-check-label for=(input_id) { (page.name()) }
+                        }
+                    }
+                }
+                @if state.metadata.len() > 1 {
+                    ul .list-group."my-2" {
+                        li .list-group-item {
+                            "Metadata providers order (the first one is the default)"
+                        }
+                        @for (position, (id, provider)) in
+                            state.metadata.ordered(&profile.provider_order).enumerate()
+                        {
+                            li .list-group-item.d-flex.align-items-center {
+                                @let input_id = format!("{id}Position");
+                                label .flex-grow-1 for=(input_id) { (provider.name()) }
+                                input .form-control type="number" #(input_id) required
+                                    name=(format!("provider:{id}")) value=(position + 1)
+                                    style="width: 6rem;";
+                            }
+                        }
+                    }
+                }
                 .container.text-center {
                     input  type="submit" .btn.btn-primary value="Edit profile";
                 }
             }
+            .container-sm.text-center."mt-3" {
+                p {
+                    "Drag this bookmarklet to your bookmarks to add the book of the current page: "
+                    a #bookmarklet .btn.btn-outline-secondary { "Add to bouquineur" }
+                }
+            }
+            script nonce=[nonce()] {
+                (PreEscaped(r#"
+                    document.getElementById("bookmarklet").href =
+                        `javascript:location.href="${location.origin}/add/url?url="+encodeURIComponent(location.href)`
+                "#))
+            }
+            @if !state.covers.is_empty() {
+                form .container-sm.text-center.mt-3 method="POST" action="/covers/missing" {
+                    input type="submit" .btn.btn-secondary value="Fetch missing covers";
+                }
+            }
+            form .container-sm.text-center.mt-3 method="POST" action="/covers/reencode" {
+                input type="submit" .btn.btn-secondary value="Re-encode covers";
+            }
+            .container-sm.text-center.mt-3 {
+                a .btn.btn-secondary."me-2" href="/tags/implications" { "Tag implications" }
+                a .btn.btn-secondary."me-2" href="/publishers" { "Publishers" }
+                a .btn.btn-secondary."me-2" href="/labels" { "Printable labels" }
+                a .btn.btn-secondary."me-2" href="/inventory" { "Shelf inventory" }
+                a .btn.btn-secondary."me-2" href="/giveaway" { "To give away" }
+                a .btn.btn-secondary href="/wishlist" { "Wishlist" }
+            }
+            .container-sm.text-center.mt-3 {
+                h4 { "Export" }
+                a .btn.btn-secondary."me-2" href="/export/json" { "JSON" }
+                a .btn.btn-secondary."me-2" href="/export/json?covers=true" { "JSON with covers" }
+                a .btn.btn-secondary."me-2" href="/export/goodreads" { "Goodreads CSV" }
+                a .btn.btn-secondary."me-2" href="/export/isbns" { "ISBN list (Inventaire)" }
+                a .btn.btn-secondary."me-2" href="/export/wikidata" { "Wikidata statements" }
+                a .btn.btn-secondary href="/export/settings" { "Settings" }
+            }
+            .container-sm.text-center.mt-3 {
+                h4 { "Import" }
+                a .btn.btn-secondary href="/import" { "From Libib or BookBuddy" }
+                form .d-flex.justify-content-center.gap-2.mt-2 method="POST"
+                    action="/import/settings" enctype="multipart/form-data" {
+                    input .form-control.w-auto type="file" name="settings" accept=".json"
+                        aria-label="Settings file" required;
+                    input type="submit" .btn.btn-secondary value="Import settings";
+                }
+            }
+            @if let Some((has_password, sessions, two_factor)) = password {
+                .container-sm.text-center.mt-3 {
+                    h4 { "Password" }
+                    form .d-flex.justify-content-center.gap-2 method="POST"
+                        action="/profile/password" {
+                        @if has_password {
+                            input .form-control.w-auto type="password" name="current"
+                                placeholder="Current password" autocomplete="current-password"
+                                aria-label="Current password" required;
+                        }
+                        input .form-control.w-auto type="password" name="password"
+                            placeholder="New password" autocomplete="new-password"
+                            aria-label="New password" required;
+                        input type="submit" .btn.btn-secondary value="Change password";
+                    }
+                    .d-flex.justify-content-center."gap-2"."mt-2" {
+                        form method="POST" action="/logout" {
+                            button type="submit" .btn.btn-outline-danger { "Log out" }
+                        }
+                        form method="POST" action="/logout/everywhere" {
+                            button type="submit" .btn.btn-outline-danger { "Log out everywhere" }
+                        }
+                    }
+                    (sessions)
+                    @if let Some(two_factor) = two_factor {
+                        (two_factor)
+                    }
+                }
+            }
+            @if is_admin(&state, &user) {
+                .container-sm.text-center.mt-3 {
+                    h4 { "Administration" }
+                    a .btn.btn-secondary."me-2" href="/admin/traffic" { "Anonymous traffic" }
+                    form .d-inline method="POST" action="/admin/maintenance" {
+                        @if AtomicBool::load(&state.maintenance, Ordering::Relaxed) {
+                            button type="submit" .btn.btn-success { "Leave maintenance mode" }
+                        } @else {
+                            button type="submit" .btn.btn-warning { "Enter maintenance mode" }
+                        }
+                    }
+                }
+            }
         },
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::users,
+        testing::{body_text, user_id, TestApp, TEST_USER},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn profile_provider_order() {
+        let app = TestApp::new().await;
+
+        let response = app
+            .post_form(
+                "/profile",
+                "ongoing_box=on&provider%3AMock=1&provider%3AUnknown=2",
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let user = user_id(&app, TEST_USER).await;
+        let mut conn = app.state.db.get().await.unwrap();
+        let (public_ongoing, provider_order): (bool, Vec<String>) = users::table
+            .find(user)
+            .select((users::public_ongoing, users::provider_order))
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert!(public_ongoing);
+        // The default order is not stored, so that changes of the default provider still apply
+        assert!(provider_order.is_empty());
+
+        let response = app.post_form("/profile", "provider%3AMock=first").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hidden_pages() {
+        let app = TestApp::new().await;
+
+        let body = body_text(app.get("/").await).await;
+        assert!(body.contains(r#"href="/ongoing""#));
+
+        let response = app
+            .post_form("/profile", "show%3Aseries=on&show%3Aadd=on")
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let body = body_text(app.get("/").await).await;
+        assert!(body.contains(r#"href="/series""#));
+        assert!(!body.contains(r#"href="/ongoing""#));
+        assert!(!body.contains(r#"href="/unread""#));
+
+        let response = app.get("/ongoing").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,319 @@
+//! Publishers of the books, grouped under their parent houses
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use axum::{response::Redirect, Form};
+use diesel::{dsl::count_star, prelude::*};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use maud::html;
+use uuid::Uuid;
+
+use crate::{
+    models::User,
+    schema::{book, publisherparent},
+    wikidata, State,
+};
+
+use super::{raw_app_page, RouteError};
+
+/// Number of books of each publisher, and the parent of each publisher
+async fn publisher_data(
+    conn: &mut AsyncPgConnection,
+    owner: Uuid,
+) -> Result<(HashMap<String, i64>, HashMap<String, String>), diesel::result::Error> {
+    let counts = book::table
+        .filter(book::owner.eq(owner))
+        .filter(book::publisher.is_not_null())
+        .group_by(book::publisher)
+        .select((book::publisher, count_star()))
+        .load::<(Option<String>, i64)>(conn)
+        .await?
+        .into_iter()
+        .filter_map(|(publisher, count)| Some((publisher?, count)))
+        .collect();
+
+    let parents = publisherparent::table
+        .filter(publisherparent::owner.eq(owner))
+        .select((publisherparent::publisher, publisherparent::parent))
+        .load::<(String, String)>(conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    Ok((counts, parents))
+}
+
+/// Whether `parent` is `publisher` or one of its imprints
+pub(super) fn creates_cycle(
+    parents: &HashMap<String, String>,
+    publisher: &str,
+    parent: &str,
+) -> bool {
+    let mut current = Some(parent);
+
+    while let Some(p) = current {
+        if p == publisher {
+            return true;
+        }
+        current = parents.get(p).map(String::as_str);
+    }
+
+    false
+}
+
+struct Hierarchy<'a> {
+    counts: &'a HashMap<String, i64>,
+    parents: &'a HashMap<String, String>,
+    imprints: BTreeMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> Hierarchy<'a> {
+    fn new(counts: &'a HashMap<String, i64>, parents: &'a HashMap<String, String>) -> Self {
+        let mut imprints = BTreeMap::<_, Vec<_>>::new();
+        for (publisher, parent) in parents {
+            imprints
+                .entry(parent.as_str())
+                .or_default()
+                .push(publisher.as_str());
+        }
+        imprints.values_mut().for_each(|v| v.sort_unstable());
+
+        Self {
+            counts,
+            parents,
+            imprints,
+        }
+    }
+
+    /// Number of books of the publisher and all its imprints
+    fn total(&self, name: &str) -> i64 {
+        self.counts.get(name).copied().unwrap_or(0)
+            + self
+                .imprints
+                .get(name)
+                .into_iter()
+                .flatten()
+                .map(|imprint| self.total(imprint))
+                .sum::<i64>()
+    }
+
+    fn item(&self, name: &str) -> maud::Markup {
+        let own = self.counts.get(name).copied().unwrap_or(0);
+        let total = self.total(name);
+
+        html! {
+            li .list-group-item {
+                .d-flex.justify-content-between.align-items-center {
+                    span {
+                        (name) " " span .badge.text-bg-primary { (total) }
+                        @if total != own {
+                            small .text-body-secondary { (format!(" ({own} directly)")) }
+                        }
+                    }
+                    form .d-flex method="POST" action="/publishers/parent" {
+                        input type="hidden" name="publisher" value=(name);
+                        input .form-control.form-control-sm."me-1" list="publisherList" name="parent"
+                            placeholder="Parent house" value=[self.parents.get(name)];
+                        input type="submit" .btn.btn-sm.btn-secondary value="Set";
+                    }
+                }
+                @if let Some(imprints) = self.imprints.get(name) {
+                    ul .list-group."mt-2" {
+                        @for imprint in imprints {
+                            (self.item(imprint))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(crate) async fn publishers(state: State, user: User) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+    let (counts, parents) = publisher_data(&mut conn, user.id).await?;
+
+    let names: BTreeSet<&str> = counts
+        .keys()
+        .chain(parents.values())
+        .map(String::as_str)
+        .collect();
+
+    let hierarchy = Hierarchy::new(&counts, &parents);
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "Publishers" }
+                ul .list-group."mb-3" {
+                    @for name in names.iter().filter(|n| !parents.contains_key(**n)) {
+                        (hierarchy.item(name))
+                    }
+                }
+                datalist #publisherList {
+                    @for name in &names {
+                        option { (name) }
+                    }
+                }
+                @if state.config.wikidata.is_some() {
+                    form .text-center method="POST" action="/publishers/wikidata" {
+                        input type="submit" .btn.btn-secondary
+                            value="Find the missing parent houses on Wikidata";
+                    }
+                }
+            }
+        },
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ParentForm {
+    publisher: String,
+    parent: String,
+}
+
+/// Set the parent house of a publisher, an empty parent removes it
+pub(crate) async fn do_set_publisher_parent(
+    state: State,
+    user: User,
+    Form(form): Form<ParentForm>,
+) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+    let parent = form.parent.trim();
+
+    if parent.is_empty() {
+        diesel::delete(publisherparent::table)
+            .filter(publisherparent::owner.eq(user.id))
+            .filter(publisherparent::publisher.eq(&form.publisher))
+            .execute(&mut conn)
+            .await?;
+
+        return Ok(Redirect::to("/publishers"));
+    }
+
+    let (_, parents) = publisher_data(&mut conn, user.id).await?;
+    if creates_cycle(&parents, &form.publisher, parent) {
+        return Err(RouteError::InvalidForm);
+    }
+
+    diesel::insert_into(publisherparent::table)
+        .values((
+            publisherparent::owner.eq(user.id),
+            publisherparent::publisher.eq(&form.publisher),
+            publisherparent::parent.eq(parent),
+        ))
+        .on_conflict((publisherparent::owner, publisherparent::publisher))
+        .do_update()
+        .set(publisherparent::parent.eq(parent))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(Redirect::to("/publishers"))
+}
+
+/// Look up the parent house of the publishers that don't have one on Wikidata
+pub(crate) async fn do_publishers_wikidata(
+    state: State,
+    user: User,
+) -> Result<maud::Markup, RouteError> {
+    let Some(config) = &state.config.wikidata else {
+        return Err(RouteError::NotFound);
+    };
+
+    let mut conn = state.db.get().await?;
+    let (counts, mut parents) = publisher_data(&mut conn, user.id).await?;
+
+    let mut missing: Vec<_> = counts
+        .into_keys()
+        .filter(|p| !parents.contains_key(p))
+        .collect();
+    missing.sort_unstable();
+
+    let mut found = 0;
+
+    for publisher in &missing {
+        let parent = match wikidata::parent_organization(config, publisher).await {
+            Ok(Some(parent)) => parent,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Could not find the parent of '{publisher}' on Wikidata: {e:?}");
+                continue;
+            }
+        };
+
+        if creates_cycle(&parents, publisher, &parent) {
+            continue;
+        }
+
+        diesel::insert_into(publisherparent::table)
+            .values((
+                publisherparent::owner.eq(user.id),
+                publisherparent::publisher.eq(publisher),
+                publisherparent::parent.eq(&parent),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await?;
+
+        parents.insert(publisher.clone(), parent);
+        found += 1;
+    }
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container.text-center {
+                h2 { "Publishers" }
+                p { (format!("Found the parent house of {found} of {} publishers", missing.len())) }
+                a .btn.btn-primary href="/publishers" { "Back to the publishers" }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+
+    use crate::testing::{body_text, book_form, TestApp};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn publisher_hierarchy() {
+        let app = TestApp::new().await;
+
+        for (title, isbn, publisher) in [
+            ("Mistborn", "9780765311788", "Tor"),
+            ("Elantris", "9780765311771", "Tor"),
+            ("Wolf Hall", "9780312429980", "Picador"),
+        ] {
+            app.post_multipart("/add", book_form(title, isbn).text("publisher", publisher))
+                .await;
+        }
+
+        for form in [
+            "publisher=Tor&parent=Macmillan",
+            "publisher=Picador&parent=Macmillan",
+        ] {
+            let response = app.post_form("/publishers/parent", form).await;
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        }
+
+        let page = body_text(app.get("/publishers").await).await;
+        assert!(page.contains(r#"Macmillan <span class="badge text-bg-primary">3</span>"#));
+        assert!(page.contains(r#"Tor <span class="badge text-bg-primary">2</span>"#));
+
+        let response = app
+            .post_form("/publishers/parent", "publisher=Macmillan&parent=Tor")
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        app.post_form("/publishers/parent", "publisher=Picador&parent=")
+            .await;
+        let page = body_text(app.get("/publishers").await).await;
+        assert!(page.contains(r#"Macmillan <span class="badge text-bg-primary">2</span>"#));
+        assert!(page.contains(r#"Picador <span class="badge text-bg-primary">1</span>"#));
+    }
+}
@@ -0,0 +1,480 @@
+//! Comparison of the details of a book with the ones currently returned by a metadata provider,
+//! so that the changes can be accepted field by field
+
+use axum::{
+    extract::{Path, Query},
+    response::Redirect,
+    Form,
+};
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+use maud::{html, Markup, PreEscaped};
+use uuid::Uuid;
+
+use crate::{
+    metadata::{cache, NullableBookDetails},
+    models::{BookFieldSource, User},
+    schema::{book, bookfieldsource},
+    AppState, HtmlConfig, State,
+};
+
+use super::{
+    add::preferred_provider,
+    app_page_with_breadcrumbs,
+    edit::{book_details, update_book},
+    BookInfo, Crumb, RouteError,
+};
+
+/// Fields of a book that can be taken from a metadata provider
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub(super) enum RefreshField {
+    Title,
+    Summary,
+    Authors,
+    Tags,
+    Published,
+    Publisher,
+    Language,
+    PageCount,
+    GoogleId,
+    GoodreadsId,
+    AmazonId,
+    LibrarythingId,
+}
+
+impl RefreshField {
+    pub(super) const ALL: &'static [Self] = &[
+        Self::Title,
+        Self::Summary,
+        Self::Authors,
+        Self::Tags,
+        Self::Published,
+        Self::Publisher,
+        Self::Language,
+        Self::PageCount,
+        Self::GoogleId,
+        Self::GoodreadsId,
+        Self::AmazonId,
+        Self::LibrarythingId,
+    ];
+
+    fn id(&self) -> &'static str {
+        match self {
+            RefreshField::Title => "title",
+            RefreshField::Summary => "summary",
+            RefreshField::Authors => "authors",
+            RefreshField::Tags => "tags",
+            RefreshField::Published => "published",
+            RefreshField::Publisher => "publisher",
+            RefreshField::Language => "language",
+            RefreshField::PageCount => "page_count",
+            RefreshField::GoogleId => "google_id",
+            RefreshField::GoodreadsId => "goodreads_id",
+            RefreshField::AmazonId => "amazon_id",
+            RefreshField::LibrarythingId => "librarything_id",
+        }
+    }
+
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            RefreshField::Title => "Title",
+            RefreshField::Summary => "Summary",
+            RefreshField::Authors => "Authors",
+            RefreshField::Tags => "Tags",
+            RefreshField::Published => "Publication date",
+            RefreshField::Publisher => "Publisher",
+            RefreshField::Language => "Language",
+            RefreshField::PageCount => "Page count",
+            RefreshField::GoogleId => "Google ID",
+            RefreshField::GoodreadsId => "Goodreads ID",
+            RefreshField::AmazonId => "Amazon ID",
+            RefreshField::LibrarythingId => "Librarything ID",
+        }
+    }
+
+    pub(super) fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.id() == id)
+    }
+
+    /// Value of the field in `details`, `None` if it is not set
+    pub(super) fn value(&self, details: &NullableBookDetails) -> Option<String> {
+        let list = |values: &[String]| (!values.is_empty()).then(|| values.join(", "));
+
+        match self {
+            RefreshField::Title => details.title.clone(),
+            RefreshField::Summary => details.summary.clone().filter(|s| !s.trim().is_empty()),
+            RefreshField::Authors => list(&details.authors),
+            RefreshField::Tags => list(&details.tags),
+            RefreshField::Published => details.published.map(|d| d.format("%d/%m/%Y").to_string()),
+            RefreshField::Publisher => details.publisher.clone(),
+            RefreshField::Language => details.language.clone(),
+            RefreshField::PageCount => details.page_count.map(|c| c.to_string()),
+            RefreshField::GoogleId => details.google_id.clone(),
+            RefreshField::GoodreadsId => details.goodreads_id.clone(),
+            RefreshField::AmazonId => details.amazon_id.clone(),
+            RefreshField::LibrarythingId => details.librarything_id.clone(),
+        }
+    }
+
+    /// Copy the field from `fetched` to `current`
+    fn apply(&self, fetched: &NullableBookDetails, current: &mut NullableBookDetails) {
+        match self {
+            RefreshField::Title => current.title.clone_from(&fetched.title),
+            RefreshField::Summary => current.summary.clone_from(&fetched.summary),
+            RefreshField::Authors => current.authors.clone_from(&fetched.authors),
+            RefreshField::Tags => current.tags.clone_from(&fetched.tags),
+            RefreshField::Published => current.published = fetched.published,
+            RefreshField::Publisher => current.publisher.clone_from(&fetched.publisher),
+            RefreshField::Language => current.language.clone_from(&fetched.language),
+            RefreshField::PageCount => current.page_count = fetched.page_count,
+            RefreshField::GoogleId => current.google_id.clone_from(&fetched.google_id),
+            RefreshField::GoodreadsId => current.goodreads_id.clone_from(&fetched.goodreads_id),
+            RefreshField::AmazonId => current.amazon_id.clone_from(&fetched.amazon_id),
+            RefreshField::LibrarythingId => {
+                current.librarything_id.clone_from(&fetched.librarything_id)
+            }
+        }
+    }
+
+    fn render(&self, policy: &HtmlConfig, value: Option<&str>) -> Markup {
+        match (self, value) {
+            (_, None) => html! { span .text-body-secondary { "Not set" } },
+            (RefreshField::Summary, Some(summary)) => PreEscaped(policy.clean(summary)),
+            (_, Some(value)) => html! { (value) },
+        }
+    }
+}
+
+/// Record that `fields` of the book `id` were taken from `provider`, or entered by hand
+pub(super) async fn record_field_sources(
+    state: &AppState,
+    id: Uuid,
+    fields: &[RefreshField],
+    provider: Option<&str>,
+) -> Result<(), RouteError> {
+    let now = chrono::Utc::now();
+    let sources: Vec<_> = fields
+        .iter()
+        .map(|field| BookFieldSource {
+            book: id,
+            field: field.id().to_string(),
+            provider: provider.map(str::to_string),
+            changed: now,
+        })
+        .collect();
+
+    let mut conn = state.db.get().await?;
+    diesel::insert_into(bookfieldsource::table)
+        .values(&sources)
+        .on_conflict((bookfieldsource::book, bookfieldsource::field))
+        .do_update()
+        .set((
+            bookfieldsource::provider.eq(excluded(bookfieldsource::provider)),
+            bookfieldsource::changed.eq(excluded(bookfieldsource::changed)),
+        ))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct RefreshQuery {
+    provider: Option<String>,
+}
+
+/// Provider the book `id` was added from, if it is still enabled
+async fn metadata_source(
+    state: &State,
+    user: &User,
+    id: Uuid,
+) -> Result<Option<String>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let source: Option<String> = book::table
+        .find(id)
+        .filter(book::owner.eq(user.id))
+        .select(book::metadata_source)
+        .get_result(&mut conn)
+        .await
+        .optional()?
+        .flatten();
+
+    Ok(source.filter(|source| state.metadata.get(source).is_some()))
+}
+
+/// Details of the book `id` and the ones fetched from `provider`
+async fn compare(
+    state: &State,
+    user: &User,
+    id: Uuid,
+    provider: Option<&str>,
+) -> Result<(NullableBookDetails, Option<NullableBookDetails>), RouteError> {
+    let current = book_details(state, user, id).await?;
+    let isbn = current.isbn.as_deref().unwrap_or_default();
+
+    let fetched = cache::fetch_metadata(state, provider, isbn).await?;
+
+    Ok((current, fetched))
+}
+
+pub(crate) async fn refresh_book(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+    Query(query): Query<RefreshQuery>,
+) -> Result<maud::Markup, RouteError> {
+    let provider = match query.provider {
+        Some(p) => Some(p),
+        None => match metadata_source(&state, &user, *id).await? {
+            Some(source) => Some(source),
+            None => preferred_provider(&state, &user).await?,
+        },
+    };
+    let (current, fetched) = compare(&state, &user, *id, provider.as_deref()).await?;
+
+    let title = current.title.clone().unwrap_or_default();
+    let action = format!("/book/{}/refresh", *id);
+
+    let rows: Vec<_> = match &fetched {
+        None => Vec::new(),
+        Some(fetched) => RefreshField::ALL
+            .iter()
+            .map(|field| (*field, field.value(&current), field.value(fetched)))
+            .collect(),
+    };
+    let changed: Vec<_> = rows
+        .iter()
+        .filter(|(_, current, fetched)| fetched.is_some() && current != fetched)
+        .map(|(field, _, _)| *field)
+        .collect();
+
+    Ok(app_page_with_breadcrumbs(
+        super::Page::Books,
+        &user,
+        &[Crumb::new(title, format!("/book/{}", *id))],
+        "Refresh metadata",
+        html! {
+            .container {
+                @if state.metadata.len() > 1 {
+                    .text-center."mb-3" {
+                        @for (provider_id, p) in state.metadata.iter() {
+                            @let current = provider.as_deref() == Some(provider_id);
+                            a .btn.btn-sm."me-2".btn-primary[current].btn-outline-primary[!current]
+                                href=(format!("{action}?provider={provider_id}")) {
+                                (p.name())
+                            }
+                        }
+                    }
+                }
+                @if fetched.is_none() {
+                    .alert.alert-warning role="alert" {
+                        "The ISBN of this book was not found"
+                    }
+                } @else if changed.is_empty() {
+                    .alert.alert-info role="alert" {
+                        "The book is identical to the fetched metadata"
+                    }
+                } @else {
+                    table .table.align-middle {
+                        thead {
+                            tr {
+                                th scope="col" { "Field" }
+                                th scope="col" { "Current" }
+                                th scope="col" { "Fetched" }
+                                th scope="col" {}
+                            }
+                        }
+                        tbody {
+                            @for (field, current, fetched) in &rows {
+                                @let differs = changed.contains(field);
+                                tr .table-active[differs] {
+                                    th scope="row" { (field.name()) }
+                                    td style="width: 40%" {
+                                        (field.render(&state.config.html, current.as_deref()))
+                                    }
+                                    td style="width: 40%" {
+                                        (field.render(&state.config.html, fetched.as_deref()))
+                                    }
+                                    td {
+                                        @if differs {
+                                            form method="POST" action=(action) {
+                                                @if let Some(provider) = &provider {
+                                                    input type="hidden" name="provider" value=(provider);
+                                                }
+                                                input type="hidden" name="field" value=(field.id());
+                                                input type="submit" .btn.btn-sm.btn-primary value="Accept";
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    form .text-center method="POST" action=(action) {
+                        @if let Some(provider) = &provider {
+                            input type="hidden" name="provider" value=(provider);
+                        }
+                        @for field in &changed {
+                            input type="hidden" name="field" value=(field.id());
+                        }
+                        input type="submit" .btn.btn-primary value="Accept all the changes";
+                    }
+                }
+            }
+        },
+    ))
+}
+
+/// The form contains the `provider` and a `field` for each field to take from it
+pub(crate) async fn do_refresh_book(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Result<Redirect, RouteError> {
+    let mut provider = None;
+    let mut fields = Vec::new();
+
+    for (key, value) in form {
+        match key.as_str() {
+            "provider" => provider = Some(value),
+            "field" => fields.push(RefreshField::from_id(&value).ok_or(RouteError::InvalidForm)?),
+            _ => tracing::warn!("Unknown field {key:?}"),
+        }
+    }
+
+    let (mut current, fetched) = compare(&state, &user, *id, provider.as_deref()).await?;
+    let fetched = fetched.ok_or(RouteError::NotFound)?;
+
+    for field in &fields {
+        field.apply(&fetched, &mut current);
+    }
+
+    let data = BookInfo::from_details(&user, current)?;
+    update_book(&state, &user, *id, data).await?;
+
+    let source = state.metadata.resolve(provider.as_deref())?;
+    record_field_sources(&state, *id, &fields, Some(source)).await?;
+
+    let location = match provider {
+        Some(provider) => format!("/book/{}/refresh?provider={provider}", *id),
+        None => format!("/book/{}/refresh", *id),
+    };
+
+    Ok(Redirect::to(&location))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::book,
+        testing::{body_text, book_form, book_id, location, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refresh_metadata() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").text("location", "Attic"),
+        )
+        .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get(&format!("/book/{id}/refresh")).await).await;
+        assert!(page.contains("A summary"));
+        assert!(page.contains("Death comes to us all"));
+        assert!(page.contains(r#"<input type="hidden" name="field" value="summary">"#));
+        assert!(!page.contains(r#"<input type="hidden" name="field" value="authors">"#));
+
+        let response = app
+            .post_form(
+                &format!("/book/{id}/refresh"),
+                "provider=Mock&field=summary",
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            location(&response),
+            format!("/book/{id}/refresh?provider=Mock")
+        );
+
+        app.post_form(
+            &format!("/book/{id}/refresh"),
+            "field=publisher&field=page_count",
+        )
+        .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let (summary, publisher, page_count, published, shelf): (
+            String,
+            Option<String>,
+            Option<i32>,
+            Option<chrono::NaiveDate>,
+            Option<String>,
+        ) = book::table
+            .find(id)
+            .select((
+                book::summary,
+                book::publisher,
+                book::pagecount,
+                book::published,
+                book::location,
+            ))
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert!(summary.contains("Death comes to us all"));
+        assert_eq!(publisher.as_deref(), Some("Corgi"));
+        assert_eq!(page_count, Some(320));
+        assert_eq!(published, None);
+        assert_eq!(shelf.as_deref(), Some("Attic"));
+        drop(conn);
+
+        let response = app
+            .post_form(&format!("/book/{id}/refresh"), "field=unknown")
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        app.post_multipart("/add", book_form("Unknown", "9780000000000"))
+            .await;
+        let unknown = book_id(&app, "9780000000000").await;
+        let page = body_text(app.get(&format!("/book/{unknown}/refresh")).await).await;
+        assert!(page.contains("The ISBN of this book was not found"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn field_sources() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        let id = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(!page.contains("Changed fields"));
+
+        app.post_multipart(
+            &format!("/book/{id}/edit"),
+            book_form("Mort", "9780552131063").text("page_count", "300"),
+        )
+        .await;
+        app.post_form(
+            &format!("/book/{id}/refresh"),
+            "provider=Mock&field=summary",
+        )
+        .await;
+
+        let page = body_text(app.get(&format!("/book/{id}")).await).await;
+        assert!(page.contains("Changed fields"));
+        assert!(page.contains("Page count: entered by hand"));
+        assert!(page.contains("Summary: from Mock"));
+        assert!(!page.contains("Title: entered by hand"));
+    }
+}
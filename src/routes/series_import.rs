@@ -0,0 +1,209 @@
+use axum::{extract::Path, Form};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use maud::html;
+use uuid::Uuid;
+
+use crate::{
+    metadata::cache,
+    models::{SeriesInfo, User},
+    schema::{book, bookseries, series},
+    State,
+};
+
+use super::{
+    add::{insert_book, preferred_provider},
+    app_page, BookInfo, Page, RouteError,
+};
+
+#[derive(serde::Deserialize)]
+pub(crate) struct SeriesImportForm {
+    isbns: String,
+}
+
+enum ImportResult {
+    Added { title: String, volume: i32 },
+    AlreadyExists,
+    NotFound,
+    Failed(RouteError),
+}
+
+pub(crate) async fn do_series_import(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+    Form(form): Form<SeriesImportForm>,
+) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let series_info = series::table
+        .find(*id)
+        .filter(series::owner.eq(user.id))
+        .select(SeriesInfo::as_select())
+        .get_result(&mut conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => RouteError::NotFound,
+            _ => e.into(),
+        })?;
+
+    let last_volume: Option<i32> = bookseries::table
+        .filter(bookseries::series.eq(*id))
+        .select(diesel::dsl::max(bookseries::number))
+        .get_result(&mut conn)
+        .await?;
+
+    let provider = preferred_provider(&state, &user).await?;
+
+    let mut next_volume = last_volume.unwrap_or(0) + 1;
+    let mut results = Vec::new();
+
+    for isbn in form
+        .isbns
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .filter(|s| !s.is_empty())
+    {
+        let isbn = isbn.replace('-', "");
+
+        let found: i64 = book::table
+            .filter(book::owner.eq(user.id).and(book::isbn.eq(&isbn)))
+            .count()
+            .get_result(&mut conn)
+            .await?;
+
+        if found != 0 {
+            results.push((isbn, ImportResult::AlreadyExists));
+            continue;
+        }
+
+        let details = match cache::fetch_metadata(&state, provider.as_deref(), &isbn).await {
+            Ok(Some(details)) => details,
+            Ok(None) => {
+                results.push((isbn, ImportResult::NotFound));
+                continue;
+            }
+            Err(e) => {
+                results.push((isbn, ImportResult::Failed(e.into())));
+                continue;
+            }
+        };
+
+        let result = match BookInfo::from_details(&user, details) {
+            Err(e) => ImportResult::Failed(e),
+            Ok(mut info) => {
+                let volume = next_volume;
+                let title = info.book.title.clone();
+                info.series = Some((series_info.name.clone(), volume));
+                info.source = state
+                    .metadata
+                    .resolve(provider.as_deref())
+                    .ok()
+                    .map(|id| (id.to_string(), chrono::Utc::now()));
+
+                match insert_book(&state, &user, info).await {
+                    Ok(_) => {
+                        next_volume += 1;
+                        ImportResult::Added { title, volume }
+                    }
+                    Err(e) => ImportResult::Failed(e),
+                }
+            }
+        };
+
+        results.push((isbn, result));
+    }
+
+    Ok(app_page(
+        Page::Series,
+        &user,
+        html! {
+            .container.text-center {
+                h2 { (format!("Import into {}", series_info.name)) }
+                ul .list-group."mb-3".text-start {
+                    @for (isbn, result) in results {
+                        li .list-group-item {
+                            (isbn) ": "
+                            @match result {
+                                ImportResult::Added { title, volume } => {
+                                    (format!("added {title} as volume {volume}"))
+                                },
+                                ImportResult::AlreadyExists => { "already in the library" },
+                                ImportResult::NotFound => { "not found" },
+                                ImportResult::Failed(e) => {
+                                    span .text-danger { (format!("failed ({e})")) }
+                                },
+                            }
+                        }
+                    }
+                }
+                a .btn.btn-primary href=(format!("/series/{}", *id)) { "Back to the series" }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::{book, bookseries, series},
+        testing::{body_text, book_form, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn series_import() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("The Colour of Magic", "9780552124751")
+                .text("series_name", "Discworld")
+                .text("series_volume", "1"),
+        )
+        .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let series_id: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let response = app
+            .post_form(
+                &format!("/series/{series_id}/import"),
+                "isbns=978-0-552-13106-3%0A9780000000000,+9780552124751+9780552134637",
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let page = body_text(response).await;
+        assert!(page.contains("added Mort as volume 2"));
+        assert!(page.contains("9780000000000: not found"));
+        assert!(page.contains("9780552124751: already in the library"));
+        assert!(page.contains("added Guards! Guards! as volume 3"));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let volumes: Vec<(String, i32)> = bookseries::table
+            .inner_join(book::table)
+            .filter(bookseries::series.eq(series_id))
+            .select((book::title, bookseries::number))
+            .order(bookseries::number)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            volumes,
+            [
+                ("The Colour of Magic".into(), 1),
+                ("Mort".into(), 2),
+                ("Guards! Guards!".into(), 3)
+            ]
+        );
+    }
+}
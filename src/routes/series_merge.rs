@@ -0,0 +1,310 @@
+use std::collections::HashSet;
+
+use axum::{extract::Path, response::Redirect, Form};
+use diesel::{dsl::sql, prelude::*, sql_types};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use uuid::Uuid;
+
+use crate::{
+    models::{Series, User},
+    schema::{bookseries, series, wishseries},
+    State,
+};
+
+use super::RouteError;
+
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MergeMode {
+    /// Keep the volume numbers, failing if some of them are already used
+    Keep,
+    /// Number the volumes after the last volume of the target series
+    Append,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct MergeForm {
+    target: Uuid,
+    mode: MergeMode,
+}
+
+async fn check_owner(
+    conn: &mut diesel_async::AsyncPgConnection,
+    user: &User,
+    ids: &[Uuid],
+) -> Result<(), RouteError> {
+    let count: i64 = series::table
+        .filter(series::id.eq_any(ids).and(series::owner.eq(user.id)))
+        .count()
+        .get_result(conn)
+        .await?;
+
+    match count as usize == ids.len() {
+        true => Ok(()),
+        false => Err(RouteError::NotFound),
+    }
+}
+
+/// Move all the volumes (and wishes) of a series into another one, then delete it
+pub(crate) async fn do_series_merge(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+    Form(form): Form<MergeForm>,
+) -> Result<Redirect, RouteError> {
+    if form.target == *id {
+        return Err(RouteError::InvalidForm);
+    }
+
+    let mut conn = state.db.get().await?;
+    check_owner(&mut conn, &user, &[*id, form.target]).await?;
+
+    let target_numbers: HashSet<i32> = bookseries::table
+        .filter(bookseries::series.eq(form.target))
+        .select(bookseries::number)
+        .union(
+            wishseries::table
+                .filter(wishseries::series.eq(form.target))
+                .select(wishseries::number),
+        )
+        .load::<i32>(&mut conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    let offset = match form.mode {
+        MergeMode::Keep => 0,
+        MergeMode::Append => target_numbers.iter().copied().max().unwrap_or(0),
+    };
+
+    if form.mode == MergeMode::Keep {
+        let source_numbers: Vec<i32> = bookseries::table
+            .filter(bookseries::series.eq(*id))
+            .select(bookseries::number)
+            .union(
+                wishseries::table
+                    .filter(wishseries::series.eq(*id))
+                    .select(wishseries::number),
+            )
+            .load(&mut conn)
+            .await?;
+
+        if source_numbers.iter().any(|n| target_numbers.contains(n)) {
+            return Err(RouteError::InvalidForm);
+        }
+    }
+
+    conn.transaction(|c| {
+        async {
+            diesel::update(bookseries::table)
+                .filter(bookseries::series.eq(*id))
+                .set((
+                    bookseries::series.eq(form.target),
+                    bookseries::number.eq(bookseries::number + offset),
+                ))
+                .execute(c)
+                .await?;
+
+            diesel::update(wishseries::table)
+                .filter(wishseries::series.eq(*id))
+                .set((
+                    wishseries::series.eq(form.target),
+                    wishseries::number.eq(wishseries::number + offset),
+                ))
+                .execute(c)
+                .await?;
+
+            diesel::delete(series::table)
+                .filter(series::id.eq(*id))
+                .execute(c)
+                .await?;
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(Redirect::to(&format!("/series/{}", form.target)))
+}
+
+/// Create a new series from some of the volumes of an existing one, the volumes are numbered from
+/// one in their previous order.
+pub(crate) async fn do_series_split(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Result<Redirect, RouteError> {
+    let mut name = None;
+    let mut books = Vec::new();
+
+    for (key, value) in form {
+        match key.as_str() {
+            "name" => name = Some(value),
+            "book" => books.push(Uuid::parse_str(&value).map_err(|_| RouteError::InvalidForm)?),
+            _ => tracing::warn!("Unknown field {key:?}"),
+        }
+    }
+
+    let name = name
+        .filter(|n| !n.is_empty())
+        .ok_or(RouteError::MissingField)?;
+
+    if books.is_empty() {
+        return Err(RouteError::MissingField);
+    }
+
+    let mut conn = state.db.get().await?;
+    check_owner(&mut conn, &user, &[*id]).await?;
+
+    let new_id = conn
+        .transaction(|c| {
+            async {
+                let volumes: Vec<Uuid> = bookseries::table
+                    .filter(bookseries::series.eq(*id))
+                    .filter(bookseries::book.eq_any(&books))
+                    .select(bookseries::book)
+                    .order(bookseries::number.asc())
+                    .load(c)
+                    .await?;
+
+                if volumes.len() != books.len() {
+                    return Err(RouteError::InvalidForm);
+                }
+
+                let new_id: Uuid = diesel::insert_into(series::table)
+                    .values(&Series {
+                        owner: user.id,
+                        name,
+                        ongoing: Some(false),
+                    })
+                    .returning(series::id)
+                    .get_result(c)
+                    .await?;
+
+                // Temporary numbers to avoid conflicts with the remaining volumes
+                diesel::update(bookseries::table)
+                    .filter(bookseries::book.eq_any(&volumes))
+                    .set((
+                        bookseries::series.eq(new_id),
+                        bookseries::number.eq(sql::<sql_types::Integer>("-number - 1")),
+                    ))
+                    .execute(c)
+                    .await?;
+
+                for (number, book) in volumes.iter().enumerate() {
+                    diesel::update(bookseries::table)
+                        .filter(bookseries::book.eq(book))
+                        .set(bookseries::number.eq(number as i32 + 1))
+                        .execute(c)
+                        .await?;
+                }
+
+                Ok::<_, RouteError>(new_id)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    Ok(Redirect::to(&format!("/series/{new_id}")))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::{bookseries, series},
+        testing::{book_form, book_id, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn series_split_merge() {
+        let app = TestApp::new().await;
+
+        for (title, isbn, volume) in [
+            ("The Colour of Magic", "9780552124751", "1"),
+            ("Mort", "9780552131063", "2"),
+            ("Guards! Guards!", "9780552134637", "3"),
+        ] {
+            app.post_multipart(
+                "/add",
+                book_form(title, isbn)
+                    .text("series_name", "Discworld")
+                    .text("series_volume", volume),
+            )
+            .await;
+        }
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let discworld: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let colour = book_id(&app, "9780552124751").await;
+        let mort = book_id(&app, "9780552131063").await;
+        let guards = book_id(&app, "9780552134637").await;
+
+        let response = app
+            .post_form(
+                &format!("/series/{discworld}/split"),
+                &format!("name=Death&book={mort}"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let death: Uuid = series::table
+            .filter(series::name.eq("Death"))
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        let volumes: Vec<(Uuid, Uuid, i32)> = bookseries::table
+            .select((bookseries::book, bookseries::series, bookseries::number))
+            .order((bookseries::series, bookseries::number))
+            .load(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+        assert!(volumes.contains(&(mort, death, 1)));
+        assert!(volumes.contains(&(colour, discworld, 1)));
+        assert!(volumes.contains(&(guards, discworld, 3)));
+
+        let response = app
+            .post_form(
+                &format!("/series/{death}/merge"),
+                &format!("target={discworld}&mode=keep"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .post_form(
+                &format!("/series/{death}/merge"),
+                &format!("target={discworld}&mode=append"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let volumes: Vec<(Uuid, i32)> = bookseries::table
+            .filter(bookseries::series.eq(discworld))
+            .select((bookseries::book, bookseries::number))
+            .order(bookseries::number)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(volumes, [(colour, 1), (guards, 3), (mort, 4)]);
+
+        let remaining: i64 = series::table.count().get_result(&mut conn).await.unwrap();
+        assert_eq!(remaining, 1);
+    }
+}
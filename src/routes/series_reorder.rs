@@ -0,0 +1,244 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{extract::Path, response::Redirect, Form};
+use diesel::{dsl::sql, prelude::*, sql_types};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use maud::{html, PreEscaped};
+use uuid::Uuid;
+
+use crate::{
+    models::{SeriesInfo, User},
+    schema::{book, bookseries, series},
+    State,
+};
+
+use super::{
+    app_page_with_breadcrumbs, components::make_image_url, nonce, Crumb, Page, RouteError,
+};
+
+async fn owned_series(
+    conn: &mut diesel_async::AsyncPgConnection,
+    user: &User,
+    id: Uuid,
+) -> Result<SeriesInfo, RouteError> {
+    series::table
+        .find(id)
+        .filter(series::owner.eq(user.id))
+        .select(SeriesInfo::as_select())
+        .get_result(conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => RouteError::NotFound,
+            _ => e.into(),
+        })
+}
+
+/// Form mapping each book of the series to its new volume number
+pub(crate) async fn do_series_reorder(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+    Form(volumes): Form<HashMap<Uuid, i32>>,
+) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    owned_series(&mut conn, &user, *id).await?;
+
+    let books: HashSet<Uuid> = bookseries::table
+        .filter(bookseries::series.eq(*id))
+        .select(bookseries::book)
+        .load::<Uuid>(&mut conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    let numbers: HashSet<i32> = volumes.values().copied().collect();
+
+    if volumes.keys().any(|b| !books.contains(b))
+        || volumes.len() != books.len()
+        || numbers.len() != volumes.len()
+        || numbers.iter().any(|&n| n < 0)
+    {
+        return Err(RouteError::InvalidForm);
+    }
+
+    conn.transaction(|c| {
+        async {
+            // Move all volumes out of the way first, as numbers are unique in a series
+            diesel::update(bookseries::table)
+                .filter(bookseries::series.eq(*id))
+                .set(bookseries::number.eq(sql::<sql_types::Integer>("-number - 1")))
+                .execute(c)
+                .await?;
+
+            for (book, number) in volumes {
+                diesel::update(bookseries::table)
+                    .filter(bookseries::book.eq(book))
+                    .set(bookseries::number.eq(number))
+                    .execute(c)
+                    .await?;
+            }
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(Redirect::to(&format!("/series/{}", *id)))
+}
+
+pub(crate) async fn series_reorder(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let series_info = owned_series(&mut conn, &user, *id).await?;
+
+    let volumes: Vec<(Uuid, String, i32)> = bookseries::table
+        .inner_join(book::table)
+        .filter(bookseries::series.eq(*id))
+        .select((book::id, book::title, bookseries::number))
+        .order(bookseries::number.asc())
+        .load(&mut conn)
+        .await?;
+
+    Ok(app_page_with_breadcrumbs(
+        Page::Series,
+        &user,
+        &[Crumb::new(&series_info.name, format!("/series/{}", *id))],
+        "Reorder",
+        html! {
+            form .container-sm method="POST" {
+                .container.text-center {
+                    h2 { (format!("Reorder {}", series_info.name)) }
+                    p .text-body-secondary {
+                        "Drag the volumes to reorder them, or edit the numbers directly"
+                    }
+                }
+                ul #volumes .list-group."mb-3" {
+                    @for (book, title, number) in volumes {
+                        li .list-group-item.d-flex.align-items-center draggable="true" {
+                            i .bi.bi-grip-vertical."me-2" aria-hidden="true" {}
+                            img ."me-2" src=(make_image_url(&state, book, &user))
+                                alt=(format!("Cover of {title}")) style="height: 3rem;";
+                            span .flex-grow-1 { (title) }
+                            input .form-control.volume-number type="number" min="0" required
+                                name=(book) value=(number) style="width: 6rem;";
+                        }
+                    }
+                }
+                .container.text-center {
+                    input type="submit" .btn.btn-primary value="Save order";
+                }
+            }
+            script nonce=[nonce()] {
+                (PreEscaped(r#"
+                    const volumes = document.getElementById("volumes")
+                    let dragged = null
+
+                    function renumber() {
+                        const inputs = [...volumes.querySelectorAll(".volume-number")]
+                        const numbers = inputs.map(i => parseInt(i.value)).sort((a, b) => a - b)
+                        inputs.forEach((input, i) => input.value = numbers[i])
+                    }
+
+                    volumes.addEventListener("dragstart", event => {
+                        dragged = event.target.closest("li")
+                    })
+
+                    volumes.addEventListener("dragover", event => {
+                        event.preventDefault()
+                        const target = event.target.closest("li")
+                        if (target === null || target === dragged)
+                            return
+
+                        const rect = target.getBoundingClientRect()
+                        const after = event.clientY > rect.top + rect.height / 2
+                        volumes.insertBefore(dragged, after ? target.nextSibling : target)
+                    })
+
+                    volumes.addEventListener("drop", event => {
+                        event.preventDefault()
+                        dragged = null
+                        renumber()
+                    })
+                "#))
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::{bookseries, series},
+        testing::{body_text, book_form, book_id, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn series_reorder() {
+        let app = TestApp::new().await;
+
+        for (title, isbn, volume) in [
+            ("The Colour of Magic", "9780552124751", "1"),
+            ("Mort", "9780552131063", "2"),
+            ("Guards! Guards!", "9780552134637", "3"),
+        ] {
+            app.post_multipart(
+                "/add",
+                book_form(title, isbn)
+                    .text("series_name", "Discworld")
+                    .text("series_volume", volume),
+            )
+            .await;
+        }
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let series_id: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let colour = book_id(&app, "9780552124751").await;
+        let mort = book_id(&app, "9780552131063").await;
+        let guards = book_id(&app, "9780552134637").await;
+
+        let page = body_text(app.get(&format!("/series/{series_id}/reorder")).await).await;
+        assert!(page.contains("Mort"));
+
+        let response = app
+            .post_form(
+                &format!("/series/{series_id}/reorder"),
+                &format!("{colour}=1&{mort}=4&{guards}=8"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let response = app
+            .post_form(
+                &format!("/series/{series_id}/reorder"),
+                &format!("{colour}=1&{mort}=1&{guards}=8"),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let volumes: Vec<(Uuid, i32)> = bookseries::table
+            .select((bookseries::book, bookseries::number))
+            .order(bookseries::number)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(volumes, [(colour, 1), (mort, 4), (guards, 8)]);
+    }
+}
@@ -0,0 +1,247 @@
+//! Export of the settings of a user as JSON, to import them on another instance. The books are
+//! exported separately.
+
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::Multipart,
+    response::{Redirect, Response},
+};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+
+use crate::{
+    import::ImportError,
+    models::{TagImplication, User},
+    schema::{pagequery, publisherparent, tagimplication, users},
+    State,
+};
+
+use super::{
+    export::attachment,
+    profile::ProfileEdit,
+    publishers::creates_cycle,
+    tag_implications::{implication_rules, tag_id},
+    Page, RouteError, REMEMBERED_PAGES,
+};
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+#[serde(default)]
+struct UserSettings {
+    #[serde(flatten)]
+    profile: ProfileEdit,
+    /// Rules as (tag, implied tag)
+    tag_implications: Vec<(String, String)>,
+    /// Parent house of the publishers
+    publisher_parents: BTreeMap<String, String>,
+    /// Last query of the list pages, keyed by path
+    page_queries: BTreeMap<String, String>,
+}
+
+pub(crate) async fn export_settings(state: State, user: User) -> Result<Response, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let profile = users::table
+        .find(user.id)
+        .select(ProfileEdit::as_select())
+        .get_result(&mut conn)
+        .await?;
+
+    let settings = UserSettings {
+        profile,
+        tag_implications: implication_rules(&mut conn, user.id).await?,
+        publisher_parents: publisherparent::table
+            .filter(publisherparent::owner.eq(user.id))
+            .select((publisherparent::publisher, publisherparent::parent))
+            .load::<(String, String)>(&mut conn)
+            .await?
+            .into_iter()
+            .collect(),
+        page_queries: pagequery::table
+            .filter(pagequery::owner.eq(user.id))
+            .select((pagequery::page, pagequery::query))
+            .load::<(String, String)>(&mut conn)
+            .await?
+            .into_iter()
+            .collect(),
+    };
+
+    let data = serde_json::to_vec_pretty(&settings).expect("settings can be serialized");
+    Ok(attachment("application/json", "settings.json", data))
+}
+
+/// Replace the profile settings of the user by the ones of the `settings` file, and add its tag
+/// implications, publisher parents and page queries to the existing ones
+pub(crate) async fn do_import_settings(
+    state: State,
+    user: User,
+    mut multipart: Multipart,
+) -> Result<Redirect, RouteError> {
+    let mut data = None;
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("settings") => data = Some(field.bytes().await?),
+            name => tracing::warn!("Unknown field {name:?}"),
+        }
+    }
+
+    let data = data.ok_or(RouteError::MissingField)?;
+    let mut settings: UserSettings = serde_json::from_slice(&data).map_err(ImportError::from)?;
+
+    // Providers and pages can differ between instances
+    settings
+        .profile
+        .provider_order
+        .retain(|id| state.metadata.get(id).is_some());
+    settings
+        .profile
+        .hidden_pages
+        .retain(|id| Page::hideable().any(|p| p.id() == id));
+    settings
+        .page_queries
+        .retain(|page, _| REMEMBERED_PAGES.contains(&page.as_str()));
+
+    let mut conn = state.db.get().await?;
+
+    conn.transaction(|c| {
+        async {
+            diesel::update(users::table)
+                .filter(users::id.eq(user.id))
+                .set(&settings.profile)
+                .execute(c)
+                .await?;
+
+            for (tag, implies) in &settings.tag_implications {
+                if tag.trim().is_empty() || implies.trim().is_empty() {
+                    continue;
+                }
+
+                let rule = TagImplication {
+                    owner: user.id,
+                    tag: tag_id(c, tag.trim()).await?,
+                    implies: tag_id(c, implies.trim()).await?,
+                };
+                if rule.tag == rule.implies {
+                    continue;
+                }
+
+                diesel::insert_into(tagimplication::table)
+                    .values(&rule)
+                    .on_conflict_do_nothing()
+                    .execute(c)
+                    .await?;
+            }
+
+            let mut parents: HashMap<String, String> = publisherparent::table
+                .filter(publisherparent::owner.eq(user.id))
+                .select((publisherparent::publisher, publisherparent::parent))
+                .load::<(String, String)>(c)
+                .await?
+                .into_iter()
+                .collect();
+
+            for (publisher, parent) in &settings.publisher_parents {
+                if creates_cycle(&parents, publisher, parent) {
+                    tracing::warn!(
+                        "Skipping the parent {parent:?} of {publisher:?}, it is a cycle"
+                    );
+                    continue;
+                }
+                parents.insert(publisher.clone(), parent.clone());
+
+                diesel::insert_into(publisherparent::table)
+                    .values((
+                        publisherparent::owner.eq(user.id),
+                        publisherparent::publisher.eq(publisher),
+                        publisherparent::parent.eq(parent),
+                    ))
+                    .on_conflict((publisherparent::owner, publisherparent::publisher))
+                    .do_update()
+                    .set(publisherparent::parent.eq(parent))
+                    .execute(c)
+                    .await?;
+            }
+
+            for (page, query) in &settings.page_queries {
+                diesel::insert_into(pagequery::table)
+                    .values((
+                        pagequery::owner.eq(user.id),
+                        pagequery::page.eq(page),
+                        pagequery::query.eq(query),
+                    ))
+                    .on_conflict((pagequery::owner, pagequery::page))
+                    .do_update()
+                    .set(pagequery::query.eq(query))
+                    .execute(c)
+                    .await?;
+            }
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(Redirect::to("/profile"))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+
+    use crate::testing::{body_text, location, user_id, MultipartForm, TestApp, OTHER_USER};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn settings_export() {
+        let app = TestApp::new().await;
+
+        app.post_form("/profile", "feed_box=on").await;
+        app.post_form("/tags/implications", "tag=Manga&implies=Comics")
+            .await;
+        app.get("/unread?min_priority=3").await;
+
+        let response = app.get("/export/settings").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = body_text(response).await;
+        let settings: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(settings["public_feed"], true);
+        assert_eq!(
+            settings["tag_implications"],
+            serde_json::json!([["Manga", "Comics"]])
+        );
+        assert_eq!(settings["page_queries"]["/unread"], "min_priority=3");
+
+        let response = app
+            .post_multipart_as(
+                OTHER_USER,
+                "/import/settings",
+                MultipartForm::new().file("settings", "settings.json", data.into_bytes()),
+            )
+            .await;
+        assert_eq!(location(&response), "/profile");
+
+        let user = user_id(&app, OTHER_USER).await;
+        let response = app
+            .request(
+                Request::get(format!("/public/{user}/feed.atom"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.get_as(OTHER_USER, "/unread").await;
+        assert_eq!(location(&response), "/unread?min_priority=3");
+
+        let response = app
+            .post_multipart(
+                "/import/settings",
+                MultipartForm::new().file("settings", "settings.json", b"[1, 2]".to_vec()),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
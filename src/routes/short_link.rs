@@ -0,0 +1,127 @@
+//! Short links to the books, such as `/b/8FkQ2xz`, meant to be written on bookmarks or labels. The
+//! code is the start of the id of the book, so they don't need to be stored.
+
+use axum::{extract::Path, response::Redirect};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+use crate::{models::User, schema::book, State};
+
+use super::RouteError;
+
+/// Base 58, without the characters that are easily mixed up when handwritten
+const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// Number of bytes of the id in the code
+const PREFIX_LEN: usize = 5;
+/// Length of the code, enough to hold all the prefixes
+const CODE_LEN: usize = 7;
+
+pub(super) fn short_code(id: Uuid) -> String {
+    let mut value = id.as_bytes()[..PREFIX_LEN]
+        .iter()
+        .fold(0u64, |acc, &b| (acc << 8) | b as u64);
+
+    let mut code = [ALPHABET[0]; CODE_LEN];
+    for c in code.iter_mut().rev() {
+        *c = ALPHABET[(value % ALPHABET.len() as u64) as usize];
+        value /= ALPHABET.len() as u64;
+    }
+
+    String::from_utf8(code.to_vec()).expect("the alphabet is ASCII")
+}
+
+/// Range of the ids starting with the prefix encoded in `code`
+fn id_range(code: &str) -> Option<(Uuid, Uuid)> {
+    if code.len() != CODE_LEN {
+        return None;
+    }
+
+    let value = code.bytes().try_fold(0u64, |acc, c| {
+        let digit = ALPHABET.iter().position(|&a| a == c)?;
+        Some(acc * ALPHABET.len() as u64 + digit as u64)
+    })?;
+    if value >= 1 << (8 * PREFIX_LEN) {
+        return None;
+    }
+
+    let prefix = &value.to_be_bytes()[8 - PREFIX_LEN..];
+    let mut start = [0; 16];
+    let mut end = [0xff; 16];
+    start[..PREFIX_LEN].copy_from_slice(prefix);
+    end[..PREFIX_LEN].copy_from_slice(prefix);
+
+    Some((Uuid::from_bytes(start), Uuid::from_bytes(end)))
+}
+
+pub(crate) async fn short_link(
+    state: State,
+    user: User,
+    code: Path<String>,
+) -> Result<Redirect, RouteError> {
+    let (start, end) = id_range(&code).ok_or(RouteError::NotFound)?;
+
+    let mut conn = state.db.get().await?;
+    let id: Uuid = book::table
+        .filter(book::owner.eq(user.id))
+        .filter(book::id.between(start, end))
+        .select(book::id)
+        .order(book::added)
+        .first(&mut conn)
+        .await
+        .optional()?
+        .ok_or(RouteError::NotFound)?;
+
+    Ok(Redirect::to(&format!("/book/{id}")))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use uuid::Uuid;
+
+    use crate::testing::{body_text, book_form, book_id, location, TestApp, OTHER_USER};
+
+    #[test]
+    fn short_code() {
+        let id: Uuid = "0191f6a2-8b3c-7d4e-9f00-112233445566".parse().unwrap();
+
+        let code = super::short_code(id);
+        assert_eq!(code.len(), super::CODE_LEN);
+
+        let (start, end) = super::id_range(&code).unwrap();
+        assert!(start <= id && id <= end);
+        assert_eq!(start.as_bytes()[..5], id.as_bytes()[..5]);
+
+        assert_eq!(super::short_code(Uuid::nil()), "1111111");
+
+        assert_eq!(super::id_range("zzzzzzz"), None);
+        assert_eq!(super::id_range("0OIl000"), None);
+        assert_eq!(super::id_range("8FkQ2"), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn short_links() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        let mort = book_id(&app, "9780552131063").await;
+
+        let page = body_text(app.get(&format!("/book/{mort}")).await).await;
+        let start = page.find("/b/").expect("no short link on the book page");
+        let link = &page[start..start + "/b/".len() + 7];
+
+        let response = app.get(link).await;
+        assert_eq!(location(&response), format!("/book/{mort}"));
+
+        let page = body_text(app.post_form("/labels", &format!("book={mort}")).await).await;
+        assert!(page.contains(link));
+
+        let response = app.get_as(OTHER_USER, link).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app.get("/b/0OIl000").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,308 @@
+//! Rules adding tags implied by other tags, like "manga" implying "comics"
+
+use axum::{response::Redirect, Form};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use maud::html;
+use uuid::Uuid;
+
+use crate::{
+    models::{TagImplication, TagName, User},
+    schema::{tag, tagimplication},
+    State,
+};
+
+use super::{components::tag_list, name_key, raw_app_page, RouteError};
+
+/// Rules of `owner`, as (tag, implied tag) names
+pub(super) async fn implication_rules(
+    conn: &mut AsyncPgConnection,
+    owner: Uuid,
+) -> Result<Vec<(String, String)>, diesel::result::Error> {
+    let (implying, implied) = diesel::alias!(tag as implying, tag as implied);
+
+    tagimplication::table
+        .filter(tagimplication::owner.eq(owner))
+        .inner_join(implying.on(implying.field(tag::id).eq(tagimplication::tag)))
+        .inner_join(implied.on(implied.field(tag::id).eq(tagimplication::implies)))
+        .select((implying.field(tag::name), implied.field(tag::name)))
+        .order((implying.field(tag::name), implied.field(tag::name)))
+        .load(conn)
+        .await
+}
+
+/// Add the tags implied by `tags`, including the ones implied by other implied tags
+pub(super) fn imply_tags(rules: &[(String, String)], tags: &mut Vec<TagName>) {
+    let mut added = true;
+
+    while added {
+        added = false;
+
+        for (tag, implies) in rules {
+            if tags.iter().any(|t| &t.name == tag) && !tags.iter().any(|t| &t.name == implies) {
+                tags.push(TagName {
+                    name: implies.clone(),
+                });
+                added = true;
+            }
+        }
+    }
+}
+
+/// Add the implied tags to all the books of `owner`, returning the number of added tags
+async fn apply_rules(conn: &mut AsyncPgConnection, owner: Uuid) -> Result<usize, RouteError> {
+    let mut total = 0;
+
+    // Each pass adds one level of implications, stop when nothing changes
+    loop {
+        let added = diesel::sql_query(
+            r#"
+        INSERT INTO booktag (book, tag)
+        SELECT booktag.book, tagimplication.implies
+        FROM booktag
+            JOIN book ON book.id = booktag.book
+            JOIN tagimplication ON tagimplication.tag = booktag.tag
+                AND tagimplication.owner = book.owner
+        WHERE book.owner = $1
+        ON CONFLICT DO NOTHING
+    "#,
+        )
+        .bind::<diesel::sql_types::Uuid, _>(owner)
+        .execute(conn)
+        .await?;
+
+        if added == 0 {
+            return Ok(total);
+        }
+
+        total += added;
+    }
+}
+
+pub(crate) async fn tag_implications(state: State, user: User) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+    let rules = implication_rules(&mut conn, user.id).await?;
+    drop(conn);
+
+    let tags = tag_list(&state, &user).await?;
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "Tag implications" }
+                p {
+                    "Adding a tag to a book also adds the tags it implies."
+                }
+                @if !rules.is_empty() {
+                    ul .list-group."mb-3" {
+                        @for (tag, implies) in &rules {
+                            li .list-group-item.d-flex.justify-content-between.align-items-center {
+                                (format!("{tag} → {implies}"))
+                                form method="POST" action="/tags/implications/delete" {
+                                    input type="hidden" name="tag" value=(tag);
+                                    input type="hidden" name="implies" value=(implies);
+                                    button type="submit" .btn-close aria-label="Remove rule" {}
+                                }
+                            }
+                        }
+                    }
+                }
+                form .row."g-2"."mb-3" method="POST" action="/tags/implications" {
+                    .col {
+                        input .form-control list="tagList" name="tag" placeholder="Tag" required;
+                    }
+                    .col {
+                        input .form-control list="tagList" name="implies" placeholder="Implied tag"
+                            required;
+                    }
+                    .col-auto {
+                        input type="submit" .btn.btn-primary value="Add rule";
+                    }
+                    datalist #tagList {
+                        @for tag in tags {
+                            option { (tag) }
+                        }
+                    }
+                }
+                form .text-center method="POST" action="/tags/implications/apply" {
+                    input type="submit" .btn.btn-secondary value="Apply to existing books";
+                }
+            }
+        },
+    ))
+}
+
+/// Id of the tag named `name`, ignoring case and accents, creating it if needed
+pub(super) async fn tag_id(
+    conn: &mut AsyncPgConnection,
+    name: &str,
+) -> Result<i32, diesel::result::Error> {
+    let existing = tag::table
+        .filter(name_key(tag::name).eq(name_key(name)))
+        .select(tag::id)
+        .first(conn)
+        .await
+        .optional()?;
+
+    match existing {
+        Some(id) => Ok(id),
+        None => {
+            diesel::insert_into(tag::table)
+                .values(TagName { name: name.into() })
+                .returning(tag::id)
+                .get_result(conn)
+                .await
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct RuleForm {
+    tag: String,
+    implies: String,
+}
+
+pub(crate) async fn do_add_tag_implication(
+    state: State,
+    user: User,
+    Form(form): Form<RuleForm>,
+) -> Result<Redirect, RouteError> {
+    let (tag, implies) = (form.tag.trim(), form.implies.trim());
+    if tag.is_empty() || implies.is_empty() || tag == implies {
+        return Err(RouteError::InvalidForm);
+    }
+
+    let mut conn = state.db.get().await?;
+
+    let rule = TagImplication {
+        owner: user.id,
+        tag: tag_id(&mut conn, tag).await?,
+        implies: tag_id(&mut conn, implies).await?,
+    };
+
+    if rule.tag == rule.implies {
+        return Err(RouteError::InvalidForm);
+    }
+
+    diesel::insert_into(tagimplication::table)
+        .values(&rule)
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await?;
+
+    Ok(Redirect::to("/tags/implications"))
+}
+
+pub(crate) async fn do_delete_tag_implication(
+    state: State,
+    user: User,
+    Form(form): Form<RuleForm>,
+) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let tag_id = |name: String| tag::table.filter(tag::name.eq(name)).select(tag::id);
+
+    diesel::delete(tagimplication::table)
+        .filter(tagimplication::owner.eq(user.id))
+        .filter(tagimplication::tag.eq_any(tag_id(form.tag)))
+        .filter(tagimplication::implies.eq_any(tag_id(form.implies)))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(Redirect::to("/tags/implications"))
+}
+
+pub(crate) async fn do_apply_tag_implications(
+    state: State,
+    user: User,
+) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+    let added = apply_rules(&mut conn, user.id).await?;
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container.text-center {
+                h2 { "Tag implications" }
+                p { (format!("Added {added} implied tags")) }
+                a .btn.btn-primary href="/tags/implications" { "Back to the rules" }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::{book, booktag, tag},
+        testing::{body_text, book_form, TestApp},
+    };
+
+    async fn book_tags(app: &TestApp, isbn: &str) -> Vec<String> {
+        let mut conn = app.state.db.get().await.unwrap();
+
+        let mut tags: Vec<String> = booktag::table
+            .inner_join(book::table)
+            .inner_join(tag::table)
+            .filter(book::isbn.eq(isbn))
+            .select(tag::name)
+            .load(&mut conn)
+            .await
+            .unwrap();
+
+        tags.sort();
+        tags
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tag_implications() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Akira", "9781935429005").text("tag", "Manga"),
+        )
+        .await;
+
+        for form in ["tag=manga&implies=comics", "tag=shonen&implies=manga"] {
+            let response = app.post_form("/tags/implications", form).await;
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        }
+
+        let response = app
+            .post_form("/tags/implications", "tag=comics&implies=Comics")
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let page = body_text(app.get("/tags/implications").await).await;
+        assert!(page.contains("Manga → comics"));
+        assert!(page.contains("shonen → Manga"));
+
+        app.post_multipart(
+            "/add",
+            book_form("Dragon Ball", "9781569319208").text("tag", "Shonen"),
+        )
+        .await;
+        assert_eq!(
+            book_tags(&app, "9781569319208").await,
+            ["Manga", "comics", "shonen"]
+        );
+
+        assert_eq!(book_tags(&app, "9781935429005").await, ["Manga"]);
+        let response = app.post_form("/tags/implications/apply", "").await;
+        assert!(body_text(response).await.contains("Added 1 implied tags"));
+        assert_eq!(book_tags(&app, "9781935429005").await, ["Manga", "comics"]);
+
+        app.post_form("/tags/implications/delete", "tag=Manga&implies=comics")
+            .await;
+        let page = body_text(app.get("/tags/implications").await).await;
+        assert!(!page.contains("Manga → comics"));
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header::RETRY_AFTER, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use maud::html;
+
+use crate::{throttle::AnonymousAccess, AppState, State};
+
+use super::{is_admin, is_guest, raw_app_page, RouteError, User};
+
+/// Address added to `X-Forwarded-For` by the first of the `proxies` trusted proxies, the ones
+/// before it can be forged by the client
+fn forwarded_ip(headers: &HeaderMap, proxies: usize) -> Option<IpAddr> {
+    let entries: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .collect();
+
+    entries
+        .len()
+        .checked_sub(proxies.max(1))
+        .and_then(|i| entries[i].trim().parse().ok())
+}
+
+/// Address of the client, connected from `peer`
+pub(super) fn client_ip(
+    state: &AppState,
+    headers: &HeaderMap,
+    peer: Option<SocketAddr>,
+) -> Option<IpAddr> {
+    let config = &state.config.throttle;
+
+    if config.trust_forwarded_for {
+        if let Some(ip) = forwarded_ip(headers, config.trusted_proxies) {
+            return Some(ip);
+        }
+    }
+
+    peer.map(|addr| addr.ip())
+}
+
+/// Marks the requests that were already throttled, as the public pages are also browsed by guests
+#[derive(Clone)]
+struct Recorded;
+
+/// Throttle the anonymous requests by IP address, and record them
+pub(crate) async fn anonymous_access(state: State, mut request: Request, next: Next) -> Response {
+    if request.extensions().get::<Recorded>().is_some() {
+        return next.run(request).await;
+    }
+    request.extensions_mut().insert(Recorded);
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|&ConnectInfo(addr)| addr);
+    let ip = client_ip(&state, request.headers(), peer);
+    let path = request.uri().path().to_string();
+
+    let response = match state.throttle.allow(ip) {
+        true => next.run(request).await,
+        false => ([(RETRY_AFTER, "60")], RouteError::Throttled).into_response(),
+    };
+
+    let status = response.status().as_u16();
+    tracing::info!(ip = ?ip, path, status, "anonymous access");
+
+    state.throttle.record(AnonymousAccess {
+        time: chrono::Utc::now(),
+        ip,
+        path,
+        status,
+    });
+
+    response
+}
+
+/// Throttle and record the requests of the guests like the other anonymous requests
+pub(crate) async fn guest_access(state: State, request: Request, next: Next) -> Response {
+    match is_guest(&state, request.headers()) {
+        true => anonymous_access(state, request, next).await,
+        false => next.run(request).await,
+    }
+}
+
+pub(crate) async fn admin_traffic(state: State, user: User) -> Result<maud::Markup, RouteError> {
+    if !is_admin(&state, &user) {
+        return Err(RouteError::Forbidden);
+    }
+
+    let accesses = state.throttle.recent();
+
+    let mut by_ip = HashMap::<_, (usize, usize)>::new();
+    for access in &accesses {
+        let (total, throttled) = by_ip.entry(access.ip).or_default();
+        *total += 1;
+        if access.status == 429 {
+            *throttled += 1;
+        }
+    }
+
+    let mut by_ip: Vec<_> = by_ip.into_iter().collect();
+    by_ip.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+
+    let show_ip = |ip: Option<IpAddr>| ip.map(|ip| ip.to_string()).unwrap_or("unknown".into());
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "Anonymous traffic" }
+                @if accesses.is_empty() {
+                    p .text-center { "No anonymous access was recorded since the server started" }
+                } @else {
+                    h4 { "By address" }
+                    table .table {
+                        thead { tr { th { "Address" } th { "Requests" } th { "Throttled" } } }
+                        tbody {
+                            @for (ip, (total, throttled)) in &by_ip {
+                                tr { td { (show_ip(*ip)) } td { (total) } td { (throttled) } }
+                            }
+                        }
+                    }
+                    h4 { "Recent requests" }
+                    table .table.table-sm {
+                        thead { tr { th { "Time" } th { "Address" } th { "Path" } th { "Status" } } }
+                        tbody {
+                            @for access in &accesses {
+                                tr {
+                                    td { (access.time.format("%Y-%m-%d %H:%M:%S")) }
+                                    td { (show_ip(access.ip)) }
+                                    td { code { (access.path) } }
+                                    td { (access.status) }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{HeaderMap, Request, StatusCode},
+    };
+
+    use super::forwarded_ip;
+    use crate::testing::{body_text, book_form, book_id, user_id, TestApp, OTHER_USER, TEST_USER};
+
+    #[test]
+    fn forwarded() {
+        let mut headers = HeaderMap::new();
+        headers.append("x-forwarded-for", "203.0.113.7, 192.0.2.1".parse().unwrap());
+        headers.append("x-forwarded-for", "198.51.100.2".parse().unwrap());
+
+        assert_eq!(forwarded_ip(&headers, 1), "198.51.100.2".parse().ok());
+        assert_eq!(forwarded_ip(&headers, 2), "192.0.2.1".parse().ok());
+        assert_eq!(forwarded_ip(&headers, 4), None);
+        assert_eq!(forwarded_ip(&HeaderMap::new(), 1), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn guest_access() {
+        let app = TestApp::with_config(&format!(
+            r#"
+            [guest]
+            user = "{TEST_USER}"
+            "#
+        ))
+        .await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        let book = book_id(&app, "9780552131063").await;
+
+        let anonymous = |method: &str, uri: &str| {
+            app.request(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = anonymous("GET", "/").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_text(response).await.contains("Mort"));
+
+        let response = anonymous("GET", &format!("/book/{book}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for uri in [
+            "/add",
+            "/profile",
+            "/export/json",
+            format!("/book/{book}/edit").as_str(),
+        ] {
+            let response = anonymous("GET", uri).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+        }
+
+        let response = anonymous("POST", &format!("/book/{book}/edit")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // Authenticated users keep their own library
+        let response = app.get_as(OTHER_USER, "/").await;
+        assert!(!body_text(response).await.contains("Mort"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn guest_throttling() {
+        let app = TestApp::with_config(&format!(
+            r#"
+            [guest]
+            user = "{TEST_USER}"
+
+            [throttle]
+            requests_per_minute = 2
+            "#
+        ))
+        .await;
+
+        let anonymous = |uri: &str| app.request(Request::get(uri).body(Body::empty()).unwrap());
+
+        assert_eq!(anonymous("/").await.status(), StatusCode::OK);
+        assert_eq!(anonymous("/series").await.status(), StatusCode::OK);
+        assert_eq!(anonymous("/").await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // The users are not throttled
+        assert_eq!(app.get("/").await.status(), StatusCode::OK);
+        assert_eq!(app.get("/").await.status(), StatusCode::OK);
+        assert_eq!(app.get("/").await.status(), StatusCode::OK);
+
+        let traffic = body_text(app.get("/admin/traffic").await).await;
+        assert!(traffic.contains("/series"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn anonymous_throttling() {
+        let app = TestApp::with_config(
+            r#"
+            [throttle]
+            requests_per_minute = 2
+            trust_forwarded_for = true
+            "#,
+        )
+        .await;
+
+        app.post_form("/profile", "widget_box=on").await;
+        let user = user_id(&app, TEST_USER).await;
+
+        let widget = |ip: &str| {
+            app.request(
+                Request::get(format!("/widget/{user}/recent"))
+                    .header("x-forwarded-for", ip)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(widget("192.0.2.1").await.status(), StatusCode::OK);
+        assert_eq!(widget("192.0.2.1").await.status(), StatusCode::OK);
+        assert_eq!(
+            widget("192.0.2.1").await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(widget("192.0.2.2").await.status(), StatusCode::OK);
+        // The entries added by the client before the one of the proxy are ignored
+        assert_eq!(
+            widget("198.51.100.9, 192.0.2.1").await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        let traffic = body_text(app.get("/admin/traffic").await).await;
+        assert!(traffic.contains("192.0.2.1"));
+        assert!(traffic.contains(&format!("/widget/{user}/recent")));
+
+        let response = app.get_as(OTHER_USER, "/admin/traffic").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,558 @@
+//! Two-factor authentication of the local logins: once set up from the profile, a code of an
+//! authenticator app or a recovery code is asked after the password.
+
+use std::net::SocketAddr;
+
+use axum::{
+    extract::ConnectInfo,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use diesel::prelude::*;
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use maud::{html, Markup};
+use uuid::Uuid;
+
+use crate::{
+    schema::{recoverycode, totp as totps, users},
+    totp, AppState, State,
+};
+
+use super::{
+    base_page,
+    components::qr_code,
+    login::{login_page, open_session},
+    raw_app_page, RouteError, User,
+};
+
+/// Time to enter the code after the password, in seconds
+const CHALLENGE_DURATION: i64 = 5 * 60;
+/// Wrong codes in a row after which only the recovery codes are accepted, so that the codes of
+/// the authenticator can't be guessed
+const MAX_FAILURES: i32 = 10;
+
+/// Whether `owner` must enter a code after their password
+pub(super) async fn enabled(state: &AppState, owner: Uuid) -> Result<bool, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let enabled = totps::table
+        .find(owner)
+        .select(totps::enabled)
+        .first(&mut conn)
+        .await
+        .optional()?;
+
+    Ok(enabled.unwrap_or(false))
+}
+
+/// Check a code of the authenticator or a recovery code of `owner`. A code of the authenticator is
+/// only accepted once, and a recovery code is removed once used.
+async fn check_code(
+    conn: &mut AsyncPgConnection,
+    owner: Uuid,
+    code: &str,
+) -> Result<bool, diesel::result::Error> {
+    let stored: Option<(Vec<u8>, i32)> = totps::table
+        .find(owner)
+        .filter(totps::enabled)
+        .select((totps::secret, totps::failures))
+        .first(conn)
+        .await
+        .optional()?;
+    let Some((secret, failures)) = stored else {
+        return Ok(false);
+    };
+
+    let step = match failures < MAX_FAILURES {
+        true => totp::verify(&secret, code, chrono::Utc::now().timestamp()),
+        false => None,
+    };
+    if let Some(step) = step {
+        // Only accepted if no code of this step or a later one was used before
+        let accepted = diesel::update(totps::table.find(owner))
+            .filter(totps::last_step.lt(step))
+            .set((totps::last_step.eq(step), totps::failures.eq(0)))
+            .execute(conn)
+            .await?;
+        if accepted == 1 {
+            return Ok(true);
+        }
+    }
+
+    let recovered = diesel::delete(recoverycode::table.find((owner, totp::recovery_hash(code))))
+        .execute(conn)
+        .await?;
+    let update = diesel::update(totps::table.find(owner));
+    match recovered {
+        0 => {
+            update
+                .set(totps::failures.eq(totps::failures + 1))
+                .execute(conn)
+                .await?
+        }
+        _ => update.set(totps::failures.eq(0)).execute(conn).await?,
+    };
+
+    Ok(recovered == 1)
+}
+
+/// Replace the recovery codes of `owner`, returning the new ones
+async fn new_recovery_codes(
+    conn: &mut AsyncPgConnection,
+    owner: Uuid,
+) -> Result<Vec<String>, diesel::result::Error> {
+    let codes = totp::recovery_codes();
+
+    conn.transaction(|c| {
+        async {
+            diesel::delete(recoverycode::table)
+                .filter(recoverycode::owner.eq(owner))
+                .execute(c)
+                .await?;
+
+            diesel::insert_into(recoverycode::table)
+                .values(
+                    codes
+                        .iter()
+                        .map(|code| {
+                            (
+                                recoverycode::owner.eq(owner),
+                                recoverycode::hash.eq(totp::recovery_hash(code)),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(c)
+                .await?;
+
+            Ok::<_, diesel::result::Error>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(codes)
+}
+
+fn code_input() -> Markup {
+    html! {
+        input .form-control.w-auto type="text" name="code" placeholder="Code"
+            autocomplete="one-time-code" aria-label="Code" required;
+    }
+}
+
+fn challenge_page(token: &str, error: Option<&str>) -> Markup {
+    base_page(html! {
+        .container.mt-5.row.mx-auto.justify-content-center {
+            .col-md-4 {
+            h1 .text-center."mb-4" {
+                i .bi.bi-shield-lock aria-hidden="true" {}
+                " Two-factor authentication"
+            }
+            @if let Some(error) = error {
+                .alert.alert-danger role="alert" { (error) }
+            }
+            form method="POST" action="/login/totp" {
+                input type="hidden" name="token" value=(token);
+                .form-floating."mb-3" {
+                    input .form-control #code type="text" name="code" placeholder="Code"
+                        autocomplete="one-time-code" required autofocus;
+                    label for="code" { "Code of the authenticator app or recovery code" }
+                }
+                button type="submit" .btn.btn-primary."w-100" { "Log in" }
+            }
+            }
+        }
+    })
+}
+
+/// Page asking for the code, once the password of `user` was checked
+pub(super) fn challenge(state: &AppState, user: &str) -> Markup {
+    challenge_page(&state.signer.second_factor(user, CHALLENGE_DURATION), None)
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ChallengeForm {
+    token: String,
+    code: String,
+}
+
+pub(crate) async fn do_login_totp(
+    state: State,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Form(form): Form<ChallengeForm>,
+) -> Result<Response, RouteError> {
+    if state.config.auth.local.is_none() {
+        return Err(RouteError::NotFound);
+    }
+
+    let Some(name) = state.signer.second_factor_user(&form.token) else {
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            login_page(Some("The login expired, please try again")),
+        )
+            .into_response());
+    };
+
+    let mut conn = state.db.get().await?;
+    let user: Uuid = users::table
+        .filter(users::name.eq(&name))
+        .select(users::id)
+        .first(&mut conn)
+        .await?;
+
+    if !check_code(&mut conn, user, &form.code).await? {
+        tracing::info!(user = %name, "failed second factor");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            challenge_page(&form.token, Some("Invalid code")),
+        )
+            .into_response());
+    }
+    drop(conn);
+
+    let peer = peer.map(|ConnectInfo(addr)| addr);
+    let cookie = open_session(&state, (user, &name), &headers, peer).await?;
+
+    Ok((cookie, Redirect::to("/")).into_response())
+}
+
+/// Two-factor authentication part of the profile page
+pub(super) async fn profile_section(state: &AppState, user: &User) -> Result<Markup, RouteError> {
+    let enabled = enabled(state, user.id).await?;
+
+    let mut conn = state.db.get().await?;
+    let recovery: i64 = recoverycode::table
+        .filter(recoverycode::owner.eq(user.id))
+        .count()
+        .get_result(&mut conn)
+        .await?;
+
+    Ok(html! {
+        h4 ."mt-3" { "Two-factor authentication" }
+        @if enabled {
+            p { (format!("Enabled, {recovery} recovery codes left")) }
+            form .d-flex.justify-content-center."gap-2" method="POST"
+                action="/profile/totp/recovery" {
+                (code_input())
+                input type="submit" .btn.btn-secondary value="New recovery codes";
+                input type="submit" .btn.btn-outline-danger formaction="/profile/totp/disable"
+                    value="Disable";
+            }
+        } @else {
+            form method="POST" action="/profile/totp" {
+                input type="submit" .btn.btn-secondary value="Set up";
+            }
+        }
+    })
+}
+
+/// Secret of the user that still needs to be confirmed with a first code
+async fn pending_secret(state: &AppState, user: &User) -> Result<Option<Vec<u8>>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    Ok(totps::table
+        .find(user.id)
+        .filter(totps::enabled.eq(false))
+        .select(totps::secret)
+        .first(&mut conn)
+        .await
+        .optional()?)
+}
+
+/// Generate a new secret, it is only used once confirmed on the setup page
+pub(crate) async fn do_setup_totp(state: State, user: User) -> Result<Redirect, RouteError> {
+    if state.config.auth.local.is_none() {
+        return Err(RouteError::NotFound);
+    }
+
+    // The authenticator can only be replaced once disabled, which needs a code
+    if enabled(&state, user.id).await? {
+        return Err(RouteError::Forbidden);
+    }
+
+    let secret = totp::new_secret();
+    let mut conn = state.db.get().await?;
+    diesel::insert_into(totps::table)
+        .values((totps::owner.eq(user.id), totps::secret.eq(&secret)))
+        .on_conflict(totps::owner)
+        .do_update()
+        .set(totps::secret.eq(&secret))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(Redirect::to("/profile/totp"))
+}
+
+fn setup_page(user: &User, secret: &[u8], error: Option<&str>) -> Result<Markup, RouteError> {
+    let qr = qr_code(&totp::uri(&user.name, secret))?;
+
+    Ok(raw_app_page(
+        None,
+        user,
+        html! {
+            .container-sm.text-center {
+                h1 { "Two-factor authentication" }
+                p { "Scan this QR code with an authenticator app, or enter the key by hand." }
+                .d-inline-block.bg-white."p-2"."mb-2" { (qr) }
+                p { code { (totp::encode(secret)) } }
+                @if let Some(error) = error {
+                    .alert.alert-danger role="alert" { (error) }
+                }
+                form .d-flex.justify-content-center."gap-2" method="POST"
+                    action="/profile/totp/enable" {
+                    (code_input())
+                    input type="submit" .btn.btn-primary value="Enable";
+                }
+            }
+        },
+    ))
+}
+
+pub(crate) async fn setup_totp(state: State, user: User) -> Result<Response, RouteError> {
+    match pending_secret(&state, &user).await? {
+        Some(secret) => Ok(setup_page(&user, &secret, None)?.into_response()),
+        None => Ok(Redirect::to("/profile").into_response()),
+    }
+}
+
+fn recovery_page(user: &User, codes: &[String]) -> Markup {
+    raw_app_page(
+        None,
+        user,
+        html! {
+            .container-sm.text-center {
+                h1 { "Recovery codes" }
+                p {
+                    "Each of these codes can be used once instead of the authenticator app. "
+                    "Keep them somewhere safe, they won't be shown again."
+                }
+                ul .list-unstyled.font-monospace.fs-5 {
+                    @for code in codes {
+                        li { code { (code) } }
+                    }
+                }
+                a .btn.btn-primary href="/profile" { "Back to the profile" }
+            }
+        },
+    )
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct CodeForm {
+    code: String,
+}
+
+/// Enable the pending secret once a code shows that the authenticator has it
+pub(crate) async fn do_enable_totp(
+    state: State,
+    user: User,
+    Form(form): Form<CodeForm>,
+) -> Result<Response, RouteError> {
+    let Some(secret) = pending_secret(&state, &user).await? else {
+        return Ok(Redirect::to("/profile").into_response());
+    };
+
+    let Some(step) = totp::verify(&secret, &form.code, chrono::Utc::now().timestamp()) else {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            setup_page(
+                &user,
+                &secret,
+                Some("Invalid code, check the time of the device"),
+            )?,
+        )
+            .into_response());
+    };
+
+    let mut conn = state.db.get().await?;
+    diesel::update(totps::table.find(user.id))
+        .set((
+            totps::enabled.eq(true),
+            totps::last_step.eq(step),
+            totps::failures.eq(0),
+        ))
+        .execute(&mut conn)
+        .await?;
+    let codes = new_recovery_codes(&mut conn, user.id).await?;
+
+    tracing::info!(user = %user.name, "two-factor authentication enabled");
+
+    Ok(recovery_page(&user, &codes).into_response())
+}
+
+pub(crate) async fn do_new_recovery_codes(
+    state: State,
+    user: User,
+    Form(form): Form<CodeForm>,
+) -> Result<Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+    if !check_code(&mut conn, user.id, &form.code).await? {
+        return Err(RouteError::Forbidden);
+    }
+
+    let codes = new_recovery_codes(&mut conn, user.id).await?;
+
+    Ok(recovery_page(&user, &codes))
+}
+
+pub(crate) async fn do_disable_totp(
+    state: State,
+    user: User,
+    Form(form): Form<CodeForm>,
+) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+    if !check_code(&mut conn, user.id, &form.code).await? {
+        return Err(RouteError::Forbidden);
+    }
+
+    diesel::delete(recoverycode::table)
+        .filter(recoverycode::owner.eq(user.id))
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(totps::table.find(user.id))
+        .execute(&mut conn)
+        .await?;
+
+    tracing::info!(user = %user.name, "two-factor authentication disabled");
+
+    Ok(Redirect::to("/profile"))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{
+            header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
+            Request, StatusCode,
+        },
+    };
+
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+
+    use crate::{
+        schema::totp as totps,
+        testing::{body_text, location, user_id, TestApp},
+        totp,
+    };
+
+    /// Value of the `name` input of `page`
+    fn input<'a>(page: &'a str, name: &str) -> &'a str {
+        let (_, rest) = page
+            .split_once(&format!(r#"name="{name}" value=""#))
+            .unwrap();
+        rest.split('"').next().unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn totp_login() {
+        let app = TestApp::with_config("[auth.local]").await;
+        crate::routes::set_password(&app.state, "alice", "hunter22", None)
+            .await
+            .unwrap();
+
+        let post = |uri: &'static str, session: Option<&str>, form: String| {
+            let mut request =
+                Request::post(uri).header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+            if let Some(session) = session {
+                request = request.header(COOKIE, session);
+            }
+            app.request(request.body(Body::from(form)).unwrap())
+        };
+        let login = || post("/login", None, "username=alice&password=hunter22".into());
+
+        let response = login().await;
+        let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+        let session = cookie.split_once(';').unwrap().0.to_string();
+        let session = Some(session.as_str());
+
+        let response = post("/profile/totp", session, String::new()).await;
+        assert_eq!(location(&response), "/profile/totp");
+        let user = user_id(&app, "alice").await;
+        let mut conn = app.state.db.get().await.unwrap();
+        let secret: Vec<u8> = totps::table
+            .find(user)
+            .select(totps::secret)
+            .first(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+        let step = totp::step(chrono::Utc::now().timestamp());
+
+        let response = post("/profile/totp/enable", session, "code=000000".into()).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        // Still not asked before it is enabled
+        assert_eq!(location(&login().await), "/");
+
+        let code = totp::code(&secret, step);
+        let response = post("/profile/totp/enable", session, format!("code={code}")).await;
+        let page = body_text(response).await;
+        let recovery: Vec<&str> = page
+            .split("<li><code>")
+            .skip(1)
+            .map(|code| code.split('<').next().unwrap())
+            .collect();
+        assert_eq!(recovery.len(), 10);
+
+        // The password is not enough anymore
+        let response = login().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(SET_COOKIE).is_none());
+        let page = body_text(response).await;
+        let token = input(&page, "token");
+
+        let second = |code: &str| post("/login/totp", None, format!("token={token}&code={code}"));
+        let response = second("000000").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // The code used to enable it can't be used again
+        let response = second(&code).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let next = totp::code(&secret, step + 1);
+        let response = second(&next).await;
+        assert_eq!(location(&response), "/");
+        assert!(response.headers().contains_key(SET_COOKIE));
+        let response = second(&next).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Recovery codes are only accepted once
+        let response = second(recovery[0]).await;
+        assert_eq!(location(&response), "/");
+        let response = second(recovery[0]).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = post(
+            "/login/totp",
+            None,
+            format!("token=forged&code={}", recovery[1]),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(body_text(response).await.contains("The login expired"));
+
+        // Too many wrong codes only leave the recovery codes
+        for _ in 0..super::MAX_FAILURES {
+            second("000000").await;
+        }
+        let response = second(&totp::code(&secret, step + 2)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = second(recovery[1]).await;
+        assert_eq!(location(&response), "/");
+
+        let response = post("/profile/totp/disable", session, "code=000000".into()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = post(
+            "/profile/totp/disable",
+            session,
+            format!("code={}", recovery[2]),
+        )
+        .await;
+        assert_eq!(location(&response), "/profile");
+        assert_eq!(location(&login().await), "/");
+    }
+}
@@ -1,5 +1,10 @@
 use std::collections::HashMap;
 
+use axum::{
+    extract::{Query, RawQuery},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use maud::html;
@@ -11,17 +16,56 @@ use crate::{
     State,
 };
 
-use super::{app_page, RouteError};
+use super::{app_page, remembered_query, CheckboxTick, RouteError};
+
+fn empty_string_as_none<'de, D>(de: D) -> Result<Option<i32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: Option<String> = serde::Deserialize::deserialize(de)?;
+    match s.as_deref() {
+        None | Some("") => Ok(None),
+        Some(v) => v.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct UnreadFilter {
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    min_priority: Option<i32>,
+    reread: Option<CheckboxTick>,
+}
+
+pub(crate) async fn unread(
+    state: State,
+    user: User,
+    headers: HeaderMap,
+    RawQuery(raw_query): RawQuery,
+    Query(filter): Query<UnreadFilter>,
+) -> Result<Response, RouteError> {
+    if let Some(redirect) = remembered_query(&state, &user, &headers, "/unread", raw_query).await? {
+        return Ok(redirect.into_response());
+    }
 
-pub(crate) async fn unread(state: State, user: User) -> Result<maud::Markup, RouteError> {
     let mut conn = state.db.get().await?;
 
-    let unread: Vec<(BookPreview, Option<SeriesInfo>)> = book::table
-        .filter(book::read.eq(false).and(book::owner.eq(user.id)))
+    let mut query = book::table
+        .filter(book::owner.eq(user.id))
         .left_join(bookseries::table.inner_join(series::table))
         .select((BookPreview::as_select(), Option::<SeriesInfo>::as_select()))
-        .load(&mut conn)
-        .await?;
+        .order((book::priority.desc().nulls_last(), book::sort_title))
+        .into_boxed();
+
+    query = match filter.reread {
+        Some(_) => query.filter(book::read.eq(false).or(book::reread.eq(true))),
+        None => query.filter(book::read.eq(false)),
+    };
+
+    if let Some(min_priority) = filter.min_priority {
+        query = query.filter(book::priority.ge(min_priority));
+    }
+
+    let unread: Vec<(BookPreview, Option<SeriesInfo>)> = query.load(&mut conn).await?;
 
     let mut by_series = HashMap::new();
 
@@ -35,11 +79,82 @@ pub(crate) async fn unread(state: State, user: User) -> Result<maud::Markup, Rou
         super::Page::Unread,
         &user,
         html! { .container {
+            form .row.g-2.align-items-center."mb-3" {
+                .col-auto {
+                    select .form-select name="min_priority" aria-label="Minimum priority" {
+                        option value="" selected[filter.min_priority.is_none()] { "Any priority" }
+                        @for p in 1..=5 {
+                            option value=(p) selected[filter.min_priority == Some(p)] {
+                                (format!("Priority {p} or more"))
+                            }
+                        }
+                    }
+                }
+                .col-auto.form-check {
+                    input .form-check-input type="checkbox" name="reread" #rereadFilter
+                        checked[filter.reread.is_some()];
+                    label .form-check-label for="rereadFilter" { "Include books to re-read" }
+                }
+                .col-auto {
+                    input type="submit" .btn.btn-primary value="Filter";
+                }
+            }
             (book_cards_for(&state, &user, &no_series, NO_SORT).await?)
             @for (s, books) in by_series {
                 h2 { (s.unwrap().name) }
                 (book_cards_for(&state, &user, &books, NO_SORT).await?)
             }
         }},
-    ))
+    )
+    .into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+
+    use crate::testing::{body_text, book_form, location, TestApp, OTHER_USER};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unread_filters() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063").text("priority", "4"),
+        )
+        .await;
+        app.post_multipart(
+            "/add",
+            book_form("Sourcery", "9780552131070").text("priority", "1"),
+        )
+        .await;
+        app.post_multipart(
+            "/add",
+            book_form("Guards! Guards!", "9780552134637")
+                .text("read_box", "on")
+                .text("reread_box", "on"),
+        )
+        .await;
+
+        let page = body_text(app.get("/unread").await).await;
+        assert!(page.contains("Mort"));
+        assert!(page.contains("Sourcery"));
+        assert!(!page.contains("Guards! Guards!"));
+        assert!(page.find("Mort") < page.find("Sourcery"));
+
+        let page = body_text(app.get("/unread?min_priority=3").await).await;
+        assert!(page.contains("Mort"));
+        assert!(!page.contains("Sourcery"));
+
+        let page = body_text(app.get("/unread?min_priority=&reread=on").await).await;
+        assert!(page.contains("Guards! Guards!"));
+
+        // The last filters are restored when coming back to the page
+        let response = app.get("/unread").await;
+        assert_eq!(location(&response), "/unread?min_priority=&reread=on");
+
+        let response = app.get_as(OTHER_USER, "/unread").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
@@ -0,0 +1,117 @@
+use axum::extract::{Path, Query};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use maud::{html, PreEscaped};
+use uuid::Uuid;
+
+use crate::{
+    models::User,
+    schema::{book, users},
+    State,
+};
+
+use super::{components, RouteError};
+
+const DEFAULT_COUNT: i64 = 5;
+const MAX_COUNT: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub(crate) struct WidgetQuery {
+    /// Number of books to show, capped to [MAX_COUNT]
+    count: Option<i64>,
+}
+
+/// Self-contained page meant to be embedded in an iframe, listing the last books added by a user
+pub(crate) async fn widget_recent(
+    state: State,
+    Path(user): Path<Uuid>,
+    Query(query): Query<WidgetQuery>,
+) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let user = users::table
+        .find(user)
+        .filter(users::public_widget.eq(true))
+        .select(User::as_select())
+        .get_result(&mut conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => RouteError::NotFound,
+            _ => e.into(),
+        })?;
+
+    let count = query.count.unwrap_or(DEFAULT_COUNT).clamp(1, MAX_COUNT);
+
+    let books: Vec<(Uuid, String)> = book::table
+        .filter(book::owner.eq(user.id))
+        .order((book::added.desc(), book::sort_title))
+        .limit(count)
+        .select((book::id, book::title))
+        .load(&mut conn)
+        .await?;
+
+    Ok(html! {
+        (maud::DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                meta name="referrer" content="same-origin";
+                title { (format!("Recent books of {}", user.name)) }
+                style {
+                    (PreEscaped(r#"
+                        body { margin: 0; font-family: sans-serif; font-size: 0.8rem; }
+                        ul { display: flex; flex-wrap: wrap; gap: 0.5rem; list-style: none; margin: 0; padding: 0.5rem; }
+                        li { width: 6rem; text-align: center; }
+                        img { width: 6rem; height: 9rem; object-fit: cover; display: block; }
+                    "#))
+                }
+            }
+            body {
+                ul {
+                    @for (id, title) in &books {
+                        li {
+                            img src=(components::make_signed_image_url(&state, *id, &user)) alt=(title);
+                            (title)
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+
+    use crate::testing::{body_text, book_form, user_id, TestApp, TEST_USER};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn widget_recent() {
+        let app = TestApp::new().await;
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+        app.post_multipart("/add", book_form("Eric", "9780575046368"))
+            .await;
+
+        let user = user_id(&app, TEST_USER).await;
+        let widget = |uri: String| app.request(Request::get(uri).body(Body::empty()).unwrap());
+
+        let response = widget(format!("/widget/{user}/recent")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        app.post_form("/profile", "widget_box=on").await;
+
+        let response = widget(format!("/widget/{user}/recent?count=1")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = body_text(response).await;
+        assert!(page.contains("Eric"));
+        assert!(!page.contains("Mort"));
+        assert!(page.contains(&format!("/public/signed/{user}/images/")));
+    }
+}
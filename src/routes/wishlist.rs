@@ -0,0 +1,729 @@
+//! Books that are wanted but not in the library yet
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Path, Query},
+    response::Redirect,
+    Form,
+};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use maud::html;
+use uuid::Uuid;
+
+use crate::{
+    jobs::ItemStatus,
+    metadata::{cache, isbn::find_isbn, NullableBookDetails},
+    models::{Author, NewWish, Series, User, WishAuthor, WishSeries},
+    schema::{author, prefetchedwish, series, wish, wishauthor, wishseries},
+    AppState, State,
+};
+
+use super::{
+    add::{lookup_isbn, preferred_provider},
+    bulk_import::{job_progress, progress_list},
+    components::external_links,
+    ongoing::missing_volumes,
+    query_url, raw_app_page, series_info, BookInfo, RouteError,
+};
+
+#[derive(serde::Deserialize)]
+pub(crate) struct WishlistQuery {
+    /// ISBN that was not found by the metadata provider
+    not_found: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct WishIsbn {
+    isbn: String,
+}
+
+pub(crate) async fn wishlist(
+    state: State,
+    user: User,
+    Query(query): Query<WishlistQuery>,
+) -> Result<maud::Markup, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let wishes: Vec<(Uuid, String, Option<String>)> = wish::table
+        .filter(wish::owner.eq(user.id))
+        .order(wish::name)
+        .select((wish::id, wish::name, wish::isbn))
+        .load(&mut conn)
+        .await?;
+
+    let ids: Vec<Uuid> = wishes.iter().map(|(id, _, _)| *id).collect();
+
+    let mut authors: HashMap<Uuid, Vec<Author>> = HashMap::new();
+    for (wish, author) in wishauthor::table
+        .inner_join(author::table)
+        .filter(wishauthor::wish.eq_any(&ids))
+        .order(author::name)
+        .select((wishauthor::wish, Author::as_select()))
+        .load::<(Uuid, Author)>(&mut conn)
+        .await?
+    {
+        authors.entry(wish).or_default().push(author);
+    }
+
+    let series: HashMap<Uuid, (Uuid, String, i32)> = wishseries::table
+        .inner_join(series::table)
+        .filter(wishseries::wish.eq_any(&ids))
+        .select((
+            wishseries::wish,
+            series::id,
+            series::name,
+            wishseries::number,
+        ))
+        .load::<(Uuid, Uuid, String, i32)>(&mut conn)
+        .await?
+        .into_iter()
+        .map(|(wish, id, name, number)| (wish, (id, name, number)))
+        .collect();
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "Wishlist" }
+                @if let Some(isbn) = &query.not_found {
+                    .alert.alert-warning role="alert" {
+                        (format!("The ISBN {isbn} was not found"))
+                    }
+                }
+                @if !state.metadata.is_empty() {
+                    form .row.g-2.justify-content-center."mb-3" method="POST"
+                        action="/wishlist/isbn" {
+                        .col-auto {
+                            input .form-control name="isbn" type="text" placeholder="ISBN"
+                                aria-label="ISBN" required;
+                        }
+                        .col-auto {
+                            input type="submit" .btn.btn-primary value="Wish from an ISBN";
+                        }
+                    }
+                    @if wishes.iter().any(|(_, _, isbn)| isbn.is_some()) {
+                        form .text-center."mb-3" method="POST" action="/wishlist/prefetch" {
+                            button type="submit" .btn.btn-outline-secondary {
+                                "Download the wished books for offline use"
+                            }
+                        }
+                    }
+                }
+                @if wishes.is_empty() {
+                    p .text-center { "No book is wished" }
+                } @else {
+                    ul .list-group {
+                        @for (id, name, isbn) in &wishes {
+                            li .list-group-item.d-flex.justify-content-between.align-items-center {
+                                div {
+                                    @match series.get(id) {
+                                        // Missing volumes are named after their series
+                                        Some((series_id, series_name, number))
+                                            if *name == format!("{series_name} #{number}") => {
+                                            a href=(format!("/series/{series_id}")) { (name) }
+                                        }
+                                        Some((series_id, series_name, number)) => {
+                                            (name) " ("
+                                            a href=(format!("/series/{series_id}")) {
+                                                (format!("{series_name} #{number}"))
+                                            }
+                                            ")"
+                                        }
+                                        None => (name),
+                                    }
+                                    @if let Some(authors) = authors.get(id) {
+                                        br;
+                                        @for (i, author) in authors.iter().enumerate() {
+                                            @if i != 0 {
+                                                ", "
+                                            }
+                                            a .link-secondary href=(format!("/author/{}", author.id)) {
+                                                (author.name)
+                                            }
+                                        }
+                                    }
+                                    @if isbn.is_some() && state.config.library.is_some() {
+                                        br;
+                                        span hx-get=(format!("/wishlist/{id}/availability"))
+                                            hx-trigger="load" {}
+                                    }
+                                    @if let Some(isbn) = isbn {
+                                        .mt-2 { (external_links(&state, isbn)) }
+                                    }
+                                }
+                                .d-flex {
+                                    @if let Some(isbn) = isbn {
+                                        a .btn.btn-sm.btn-outline-primary."me-2"
+                                            href=(query_url("/add", &[("isbn", isbn)]))
+                                            aria-label="Add to the library" {
+                                            i .bi.bi-plus-lg aria-hidden="true" {}
+                                        }
+                                    }
+                                    form method="POST" action=(format!("/wishlist/{id}/delete")) {
+                                        button type="submit" .btn.btn-sm.btn-outline-danger
+                                            aria-label="Remove from the wishlist" {
+                                            i .bi.bi-trash aria-hidden="true" {}
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    ))
+}
+
+pub(crate) async fn do_delete_wish(
+    state: State,
+    user: User,
+    id: Path<Uuid>,
+) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    conn.transaction(|c| {
+        async {
+            let owned: i64 = wish::table
+                .filter(wish::id.eq(*id))
+                .filter(wish::owner.eq(user.id))
+                .count()
+                .get_result(c)
+                .await?;
+
+            if owned == 0 {
+                return Err(RouteError::NotFound);
+            }
+
+            diesel::delete(wishauthor::table)
+                .filter(wishauthor::wish.eq(*id))
+                .execute(c)
+                .await?;
+
+            diesel::delete(wishseries::table)
+                .filter(wishseries::wish.eq(*id))
+                .execute(c)
+                .await?;
+
+            diesel::delete(wish::table)
+                .filter(wish::id.eq(*id))
+                .execute(c)
+                .await?;
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(Redirect::to("/wishlist"))
+}
+
+/// Wish the volumes missing from the series shown on the ongoing page, skipping the ones that are
+/// already wished
+pub(crate) async fn do_wish_missing_volumes(
+    state: State,
+    user: User,
+) -> Result<Redirect, RouteError> {
+    let series: Vec<_> = series_info(&state, &user)
+        .await?
+        .into_iter()
+        .filter(|s| !s.archived && s.total_count.is_some())
+        .collect();
+    let ids: Vec<Uuid> = series.iter().map(|s| s.id).collect();
+
+    let mut conn = state.db.get().await?;
+    let missing = missing_volumes(&mut conn, &user, &ids).await?;
+
+    let wished: HashSet<(Uuid, i32)> = wishseries::table
+        .inner_join(wish::table)
+        .filter(wish::owner.eq(user.id))
+        .select((wishseries::series, wishseries::number))
+        .load::<(Uuid, i32)>(&mut conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    let volumes: Vec<_> = series
+        .iter()
+        .flat_map(|s| {
+            missing
+                .get(&s.id)
+                .into_iter()
+                .flatten()
+                .map(move |&number| (s, number))
+        })
+        .filter(|(s, number)| !wished.contains(&(s.id, *number)))
+        .collect();
+
+    conn.transaction(|c| {
+        async {
+            for (series, number) in volumes {
+                let wish: Uuid = diesel::insert_into(wish::table)
+                    .values(NewWish {
+                        owner: user.id,
+                        name: format!("{} #{number}", series.name),
+                        isbn: None,
+                    })
+                    .returning(wish::id)
+                    .get_result(c)
+                    .await?;
+
+                diesel::insert_into(wishseries::table)
+                    .values(WishSeries {
+                        wish,
+                        series: series.id,
+                        number,
+                    })
+                    .execute(c)
+                    .await?;
+            }
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(Redirect::to("/wishlist"))
+}
+
+/// Wish a book from its ISBN, with the title, authors and series found by `provider`. The title is
+/// returned, unless the book was not found.
+pub(super) async fn wish_isbn(
+    state: &AppState,
+    user: &User,
+    provider: Option<&str>,
+    isbn: String,
+) -> Result<Option<String>, RouteError> {
+    let Some(details) = cache::fetch_metadata(state, provider, &isbn).await? else {
+        return Ok(None);
+    };
+
+    let mut data = BookInfo::from_details(
+        user,
+        NullableBookDetails {
+            isbn: Some(isbn),
+            covert_art_b64: None,
+            ..details
+        },
+    )?;
+
+    let mut conn = state.db.get().await?;
+
+    conn.transaction(|c| {
+        async {
+            data.match_existing_names(c).await?;
+
+            diesel::insert_into(author::table)
+                .values(&data.authors)
+                .on_conflict_do_nothing()
+                .execute(c)
+                .await?;
+
+            let wish: Uuid = diesel::insert_into(wish::table)
+                .values(NewWish {
+                    owner: user.id,
+                    name: data.book.title.clone(),
+                    isbn: Some(data.book.isbn.clone()),
+                })
+                .returning(wish::id)
+                .get_result(c)
+                .await?;
+
+            let author_ids: Vec<i32> = author::table
+                .filter(author::name.eq_any(&data.authors))
+                .select(author::id)
+                .load(c)
+                .await?;
+
+            diesel::insert_into(wishauthor::table)
+                .values(
+                    author_ids
+                        .into_iter()
+                        .map(|author| WishAuthor { wish, author })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(c)
+                .await?;
+
+            if let Some((name, number)) = &data.series {
+                diesel::insert_into(series::table)
+                    .values(Series {
+                        name: name.clone(),
+                        owner: user.id,
+                        ongoing: Some(false),
+                    })
+                    .on_conflict_do_nothing()
+                    .execute(c)
+                    .await?;
+
+                let series_id: Uuid = series::table
+                    .filter(series::owner.eq(user.id).and(series::name.eq(name)))
+                    .select(series::id)
+                    .first(c)
+                    .await?;
+
+                // A volume can only be wished once
+                let wished: i64 = wishseries::table
+                    .filter(wishseries::series.eq(series_id))
+                    .filter(wishseries::number.eq(*number))
+                    .count()
+                    .get_result(c)
+                    .await?;
+
+                if wished == 0 {
+                    diesel::insert_into(wishseries::table)
+                        .values(WishSeries {
+                            wish,
+                            series: series_id,
+                            number: *number,
+                        })
+                        .execute(c)
+                        .await?;
+                }
+            }
+
+            Ok::<_, RouteError>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(Some(data.book.title))
+}
+
+/// Wish a book from its ISBN, with the metadata provider preferred by the user
+pub(crate) async fn do_wish_isbn(
+    state: State,
+    user: User,
+    Form(form): Form<WishIsbn>,
+) -> Result<Redirect, RouteError> {
+    let isbn = find_isbn(&form.isbn, true).ok_or(RouteError::InvalidForm)?;
+    let provider = preferred_provider(&state, &user).await?;
+
+    match wish_isbn(&state, &user, provider.as_deref(), isbn.clone()).await? {
+        Some(_) => Ok(Redirect::to("/wishlist")),
+        None => Ok(Redirect::to(&query_url(
+            "/wishlist",
+            &[("not_found", &isbn)],
+        ))),
+    }
+}
+
+/// Details of a wished book kept by [do_prefetch_wishes], with the provider that found them and
+/// when. They do not expire, as they are meant for when the providers can't be reached.
+pub(super) async fn prefetched_wish(
+    state: &AppState,
+    user: &User,
+    isbn: &str,
+) -> Result<Option<(NullableBookDetails, String, DateTime<Utc>)>, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let prefetched: Option<(String, String, DateTime<Utc>)> = prefetchedwish::table
+        .find((user.id, isbn))
+        .select((
+            prefetchedwish::details,
+            prefetchedwish::provider,
+            prefetchedwish::fetched,
+        ))
+        .get_result(&mut conn)
+        .await
+        .optional()?;
+
+    Ok(prefetched.and_then(
+        |(details, provider, fetched)| match serde_json::from_str(&details) {
+            Ok(details) => Some((details, provider, fetched)),
+            Err(e) => {
+                tracing::warn!("Invalid prefetched details for '{isbn}': {e:?}");
+                None
+            }
+        },
+    ))
+}
+
+async fn prefetch_wish(
+    state: &AppState,
+    user: &User,
+    provider: Option<&str>,
+    isbn: &str,
+) -> Result<ItemStatus, RouteError> {
+    let details = match lookup_isbn(state, provider, isbn).await {
+        Ok(Some(details)) => details,
+        Ok(None) => return Ok(ItemStatus::Failed("not found".into())),
+        Err(e) => return Ok(ItemStatus::Failed(e.reason())),
+    };
+
+    let provider = state.metadata.resolve(provider)?;
+    let title = details.title.clone().unwrap_or_else(|| isbn.to_string());
+    let details = serde_json::to_string(&details).expect("details can be serialized");
+
+    let mut conn = state.db.get().await?;
+    diesel::insert_into(prefetchedwish::table)
+        .values((
+            prefetchedwish::owner.eq(user.id),
+            prefetchedwish::isbn.eq(isbn),
+            prefetchedwish::provider.eq(provider),
+            prefetchedwish::details.eq(&details),
+        ))
+        .on_conflict((prefetchedwish::owner, prefetchedwish::isbn))
+        .do_update()
+        .set((
+            prefetchedwish::provider.eq(provider),
+            prefetchedwish::details.eq(&details),
+            prefetchedwish::fetched.eq(Utc::now()),
+        ))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(ItemStatus::Done(title))
+}
+
+/// Look the wished ISBNs up in the background and keep the details with their cover, so that
+/// adding the books once bought does not need the providers
+pub(crate) async fn do_prefetch_wishes(state: State, user: User) -> Result<Redirect, RouteError> {
+    let mut conn = state.db.get().await?;
+
+    let isbns: Vec<String> = wish::table
+        .filter(wish::owner.eq(user.id))
+        .filter(wish::isbn.is_not_null())
+        .select(wish::isbn.assume_not_null())
+        .distinct()
+        .load(&mut conn)
+        .await?;
+
+    // Forget the books that are no longer wished
+    diesel::delete(prefetchedwish::table)
+        .filter(prefetchedwish::owner.eq(user.id))
+        .filter(prefetchedwish::isbn.ne_all(&isbns))
+        .execute(&mut conn)
+        .await?;
+
+    drop(conn);
+
+    if isbns.is_empty() {
+        return Ok(Redirect::to("/wishlist"));
+    }
+
+    let provider = preferred_provider(&state, &user).await?;
+    let job = state.jobs.create(user.id, isbns);
+
+    let task_state = state.0.clone();
+    state.jobs.run(job, move |isbn| {
+        let state = task_state.clone();
+        let user = user.clone();
+        let provider = provider.clone();
+
+        async move {
+            prefetch_wish(&state, &user, provider.as_deref(), &isbn)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Could not prefetch '{isbn}': {e:?}");
+                    ItemStatus::Failed("internal error".into())
+                })
+        }
+    });
+
+    Ok(Redirect::to(&format!("/wishlist/prefetch/{job}")))
+}
+
+pub(crate) async fn prefetch_wishes(
+    state: State,
+    user: User,
+    job: Path<Uuid>,
+) -> Result<maud::Markup, RouteError> {
+    let progress = job_progress(&state, &user, *job)?;
+    let url = format!("/wishlist/prefetch/{}/progress", *job);
+
+    Ok(raw_app_page(
+        None,
+        &user,
+        html! {
+            .container {
+                h2 .text-center { "Offline metadata" }
+                (progress_list(&url, "downloaded", &progress))
+                @if !progress.is_finished() {
+                    a .btn.btn-secondary.no-js."me-2" href=(format!("/wishlist/prefetch/{}", *job)) {
+                        "Refresh"
+                    }
+                }
+                a .btn.btn-primary href="/wishlist" { "Back to the wishlist" }
+            }
+        },
+    ))
+}
+
+pub(crate) async fn prefetch_wishes_progress(
+    state: State,
+    user: User,
+    job: Path<Uuid>,
+) -> Result<maud::Markup, RouteError> {
+    let progress = job_progress(&state, &user, *job)?;
+    Ok(progress_list(
+        &format!("/wishlist/prefetch/{}/progress", *job),
+        "downloaded",
+        &progress,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use uuid::Uuid;
+
+    use crate::{
+        schema::{metadata_cache, prefetchedwish, series, wish, wishseries},
+        testing::{body_text, book_form, location, TestApp},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn wish_missing_volumes() {
+        let app = TestApp::new().await;
+
+        app.post_multipart(
+            "/add",
+            book_form("Mort", "9780552131063")
+                .text("series_name", "Discworld")
+                .text("series_volume", "2"),
+        )
+        .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let series_id: Uuid = series::table
+            .select(series::id)
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        app.post_form(
+            &format!("/series/{series_id}/edit"),
+            "name=Discworld&ongoing_box=on&total_count=3",
+        )
+        .await;
+
+        let page = body_text(app.get("/ongoing").await).await;
+        assert!(page.contains("Add the missing volumes to the wishlist"));
+
+        let response = app.post_form("/ongoing/wishlist", "").await;
+        assert_eq!(location(&response), "/wishlist");
+
+        let page = body_text(app.get("/wishlist").await).await;
+        assert!(page.contains("Discworld #1"));
+        assert!(!page.contains("Discworld #2"));
+        assert!(page.contains("Discworld #3"));
+        assert!(page.contains(&format!("/series/{series_id}")));
+
+        // The volumes already wished are not added again
+        app.post_form("/ongoing/wishlist", "").await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let wishes: i64 = wish::table.count().get_result(&mut conn).await.unwrap();
+        assert_eq!(wishes, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn wish_isbn() {
+        let app = TestApp::new().await;
+
+        let response = app.post_form("/wishlist/isbn", "isbn=978-0552131063").await;
+        assert_eq!(location(&response), "/wishlist");
+
+        let page = body_text(app.get("/wishlist").await).await;
+        assert!(page.contains("Mort"));
+        assert!(page.contains("Terry Pratchett"));
+        assert!(page.contains("Discworld #4"));
+        assert!(page.contains(r#"href="/add?isbn=9780552131063""#));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let wished: i64 = wishseries::table
+            .inner_join(series::table)
+            .filter(series::name.eq("Discworld"))
+            .filter(wishseries::number.eq(4))
+            .count()
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(wished, 1);
+        drop(conn);
+
+        let response = app.post_form("/wishlist/isbn", "isbn=9780000000002").await;
+        assert_eq!(location(&response), "/wishlist?not_found=9780000000002");
+        let page = body_text(app.get(location(&response)).await).await;
+        assert!(page.contains("The ISBN 9780000000002 was not found"));
+
+        for invalid in ["Mort", "9780552131064", "%C3%A9%0A"] {
+            let response = app
+                .post_form("/wishlist/isbn", &format!("isbn={invalid}"))
+                .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn prefetch_wishes() {
+        let app = TestApp::new().await;
+
+        app.post_form("/wishlist/isbn", "isbn=9780552131063").await;
+        let page = body_text(app.get("/wishlist").await).await;
+        assert!(page.contains(r#"action="/wishlist/prefetch""#));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        diesel::delete(metadata_cache::table)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let response = app.post_form("/wishlist/prefetch", "").await;
+        let job = location(&response).to_string();
+        assert!(job.starts_with("/wishlist/prefetch/"));
+
+        let progress = loop {
+            let progress = body_text(app.get(&format!("{job}/progress")).await).await;
+            if !progress.contains("hx-get") {
+                break progress;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert!(progress.contains("1 of 1 ISBNs processed"));
+        assert!(progress.contains("downloaded Mort"));
+
+        // The add page uses the prefetched details instead of the provider
+        let mut conn = app.state.db.get().await.unwrap();
+        diesel::delete(metadata_cache::table)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let page = body_text(app.get("/add?isbn=9780552131063").await).await;
+        assert!(page.contains("Mort"));
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let cached: i64 = metadata_cache::table
+            .count()
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(cached, 0);
+        drop(conn);
+
+        app.post_multipart("/add", book_form("Mort", "9780552131063"))
+            .await;
+
+        let mut conn = app.state.db.get().await.unwrap();
+        let prefetched: i64 = prefetchedwish::table
+            .count()
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(prefetched, 0);
+    }
+}
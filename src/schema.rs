@@ -25,6 +25,15 @@ diesel::table! {
         pagecount -> Nullable<Int4>,
         owned -> Bool,
         read -> Bool,
+        reread -> Bool,
+        priority -> Nullable<Int4>,
+        sort_title -> Text,
+        added -> Timestamptz,
+        location -> Nullable<Text>,
+        giveaway -> Bool,
+        summary_spoilers -> Bool,
+        metadata_source -> Nullable<Text>,
+        metadata_fetched -> Nullable<Timestamptz>,
     }
 }
 
@@ -35,6 +44,32 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    bookfieldsource (book, field) {
+        book -> Uuid,
+        field -> Text,
+        provider -> Nullable<Text>,
+        changed -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    bookidentifier (book, scheme) {
+        book -> Uuid,
+        scheme -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    booklink (id) {
+        id -> Int4,
+        book -> Uuid,
+        label -> Text,
+        url -> Text,
+    }
+}
+
 diesel::table! {
     bookseries (book) {
         book -> Uuid,
@@ -50,6 +85,55 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    metadata_cache (provider, isbn) {
+        provider -> Text,
+        isbn -> Text,
+        details -> Nullable<Text>,
+        fetched -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    pagequery (owner, page) {
+        owner -> Uuid,
+        page -> Text,
+        query -> Text,
+    }
+}
+
+diesel::table! {
+    password (owner) {
+        owner -> Uuid,
+        hash -> Text,
+    }
+}
+
+diesel::table! {
+    prefetchedwish (owner, isbn) {
+        owner -> Uuid,
+        isbn -> Text,
+        provider -> Text,
+        details -> Text,
+        fetched -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    publisherparent (owner, publisher) {
+        owner -> Uuid,
+        publisher -> Text,
+        parent -> Text,
+    }
+}
+
+diesel::table! {
+    recoverycode (owner, hash) {
+        owner -> Uuid,
+        hash -> Text,
+    }
+}
+
 diesel::table! {
     series (id) {
         id -> Uuid,
@@ -57,6 +141,18 @@ diesel::table! {
         name -> Citext,
         ongoing -> Bool,
         total_count -> Nullable<Int4>,
+        archived -> Bool,
+    }
+}
+
+diesel::table! {
+    session (id) {
+        id -> Uuid,
+        owner -> Uuid,
+        created -> Timestamptz,
+        last_used -> Timestamptz,
+        ip -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
     }
 }
 
@@ -67,11 +163,33 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    tagimplication (owner, tag, implies) {
+        owner -> Uuid,
+        tag -> Int4,
+        implies -> Int4,
+    }
+}
+
+diesel::table! {
+    totp (owner) {
+        owner -> Uuid,
+        secret -> Bytea,
+        enabled -> Bool,
+        last_step -> Int8,
+        failures -> Int4,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Uuid,
         name -> Text,
         public_ongoing -> Bool,
+        provider_order -> Array<Text>,
+        public_widget -> Bool,
+        hidden_pages -> Array<Text>,
+        public_feed -> Bool,
     }
 }
 
@@ -80,6 +198,7 @@ diesel::table! {
         id -> Uuid,
         owner -> Uuid,
         name -> Text,
+        isbn -> Nullable<Text>,
     }
 }
 
@@ -101,11 +220,22 @@ diesel::table! {
 diesel::joinable!(book -> users (owner));
 diesel::joinable!(bookauthor -> author (author));
 diesel::joinable!(bookauthor -> book (book));
+diesel::joinable!(bookfieldsource -> book (book));
+diesel::joinable!(bookidentifier -> book (book));
+diesel::joinable!(booklink -> book (book));
 diesel::joinable!(bookseries -> book (book));
 diesel::joinable!(bookseries -> series (series));
 diesel::joinable!(booktag -> book (book));
 diesel::joinable!(booktag -> tag (tag));
+diesel::joinable!(pagequery -> users (owner));
+diesel::joinable!(password -> users (owner));
+diesel::joinable!(prefetchedwish -> users (owner));
+diesel::joinable!(publisherparent -> users (owner));
+diesel::joinable!(recoverycode -> users (owner));
 diesel::joinable!(series -> users (owner));
+diesel::joinable!(session -> users (owner));
+diesel::joinable!(tagimplication -> users (owner));
+diesel::joinable!(totp -> users (owner));
 diesel::joinable!(wish -> users (owner));
 diesel::joinable!(wishauthor -> author (author));
 diesel::joinable!(wishauthor -> wish (wish));
@@ -116,10 +246,22 @@ diesel::allow_tables_to_appear_in_same_query!(
     author,
     book,
     bookauthor,
+    bookfieldsource,
+    bookidentifier,
+    booklink,
     bookseries,
     booktag,
+    metadata_cache,
+    pagequery,
+    password,
+    prefetchedwish,
+    publisherparent,
+    recoverycode,
     series,
+    session,
     tag,
+    tagimplication,
+    totp,
     users,
     wish,
     wishauthor,
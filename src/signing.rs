@@ -0,0 +1,207 @@
+//! Signed URLs, giving time-limited access to resources without authentication, and signed
+//! session cookies
+
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const DAY: i64 = 24 * 60 * 60;
+
+pub struct UrlSigner {
+    key: Vec<u8>,
+}
+
+impl UrlSigner {
+    /// Without a secret a random key is used, invalidating the URLs on restart
+    pub fn new(secret: Option<&str>) -> Self {
+        let key = match secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => rand::random::<[u8; 32]>().to_vec(),
+        };
+
+        Self { key }
+    }
+
+    fn mac(&self, path: &str, expires: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key size");
+        mac.update(path.as_bytes());
+        mac.update(&expires.to_be_bytes());
+        mac
+    }
+
+    /// Sign `path`, the signature is valid for one to two days. URLs are stable during a day so
+    /// that they can be cached.
+    pub fn sign(&self, path: &str) -> String {
+        let expires = (chrono::Utc::now().timestamp() / DAY + 2) * DAY;
+        let signature = self.mac(path, expires).finalize().into_bytes();
+        let signature = BASE64_URL_SAFE_NO_PAD.encode(signature);
+
+        format!("{path}?expires={expires}&signature={signature}")
+    }
+
+    pub fn verify(&self, path: &str, expires: i64, signature: &str) -> bool {
+        if expires < chrono::Utc::now().timestamp() {
+            return false;
+        }
+
+        let Ok(signature) = BASE64_URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+
+        self.mac(path, expires).verify_slice(&signature).is_ok()
+    }
+
+    /// Value of the cookie of the session `id` of `user`, valid for `duration` seconds unless the
+    /// session is revoked before
+    pub fn session(&self, user: &str, id: Uuid, duration: i64) -> String {
+        let expires = chrono::Utc::now().timestamp() + duration;
+        let signature = self
+            .mac(&session_path(user, id), expires)
+            .finalize()
+            .into_bytes();
+
+        format!(
+            "{}.{id}.{expires}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(user),
+            BASE64_URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// User and session of a session cookie, if it is valid
+    pub fn session_user(&self, cookie: &str) -> Option<(String, Uuid)> {
+        let mut parts = cookie.split('.');
+        let (Some(user), Some(id), Some(expires), Some(signature), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return None;
+        };
+
+        let user = String::from_utf8(BASE64_URL_SAFE_NO_PAD.decode(user).ok()?).ok()?;
+        let id = id.parse().ok()?;
+        let expires = expires.parse().ok()?;
+
+        self.verify(&session_path(&user, id), expires, signature)
+            .then_some((user, id))
+    }
+
+    /// Token of `user` between the password and the code of the authenticator, valid for
+    /// `duration` seconds
+    pub fn second_factor(&self, user: &str, duration: i64) -> String {
+        let expires = chrono::Utc::now().timestamp() + duration;
+        let signature = self
+            .mac(&second_factor_path(user), expires)
+            .finalize()
+            .into_bytes();
+
+        format!(
+            "{}.{expires}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(user),
+            BASE64_URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// User of a second factor token, if it is valid
+    pub fn second_factor_user(&self, token: &str) -> Option<String> {
+        let mut parts = token.split('.');
+        let (Some(user), Some(expires), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+
+        let user = String::from_utf8(BASE64_URL_SAFE_NO_PAD.decode(user).ok()?).ok()?;
+        let expires = expires.parse().ok()?;
+
+        self.verify(&second_factor_path(&user), expires, signature)
+            .then_some(user)
+    }
+}
+
+/// Sessions are signed like a path that can't be the one of a signed URL
+fn session_path(user: &str, id: Uuid) -> String {
+    format!("session:{id}:{user}")
+}
+
+fn second_factor_path(user: &str) -> String {
+    format!("totp:{user}")
+}
+
+#[cfg(test)]
+mod test {
+    use base64::prelude::*;
+    use hmac::Mac;
+    use uuid::Uuid;
+
+    use super::UrlSigner;
+
+    fn query(url: &str) -> (i64, String) {
+        let (_, query) = url.split_once('?').unwrap();
+        let (expires, signature) = query.split_once('&').unwrap();
+
+        (
+            expires.strip_prefix("expires=").unwrap().parse().unwrap(),
+            signature.strip_prefix("signature=").unwrap().to_string(),
+        )
+    }
+
+    #[test]
+    fn sign() {
+        let signer = UrlSigner::new(Some("secret"));
+        let url = signer.sign("/image");
+        let (expires, signature) = query(&url);
+
+        assert!(signer.verify("/image", expires, &signature));
+        assert!(!signer.verify("/other", expires, &signature));
+        assert!(!signer.verify("/image", expires + 1, &signature));
+        assert!(!UrlSigner::new(Some("other")).verify("/image", expires, &signature));
+    }
+
+    #[test]
+    fn expired() {
+        let signer = UrlSigner::new(None);
+        let signature = signer.mac("/image", 0).finalize().into_bytes();
+        let signature = BASE64_URL_SAFE_NO_PAD.encode(signature);
+
+        assert!(!signer.verify("/image", 0, &signature));
+    }
+
+    #[test]
+    fn session() {
+        let signer = UrlSigner::new(Some("secret"));
+        let id = Uuid::new_v4();
+        let cookie = signer.session("reader", id, 60);
+
+        assert_eq!(signer.session_user(&cookie), Some(("reader".into(), id)));
+        assert_eq!(UrlSigner::new(Some("other")).session_user(&cookie), None);
+        assert_eq!(signer.session_user(&signer.session("reader", id, -1)), None);
+
+        let (_, rest) = cookie.split_once('.').unwrap();
+        let forged = format!("{}.{rest}", BASE64_URL_SAFE_NO_PAD.encode("admin"));
+        assert_eq!(signer.session_user(&forged), None);
+        let forged = cookie.replacen(&id.to_string(), &Uuid::new_v4().to_string(), 1);
+        assert_eq!(signer.session_user(&forged), None);
+    }
+
+    #[test]
+    fn second_factor() {
+        let signer = UrlSigner::new(Some("secret"));
+        let token = signer.second_factor("reader", 60);
+
+        assert_eq!(signer.second_factor_user(&token), Some("reader".into()));
+        assert_eq!(
+            signer.second_factor_user(&signer.second_factor("reader", -1)),
+            None
+        );
+        // A token is not a session, nor the reverse
+        assert_eq!(signer.session_user(&token), None);
+        let session = signer.session("reader", Uuid::new_v4(), 60);
+        assert_eq!(signer.second_factor_user(&session), None);
+    }
+}
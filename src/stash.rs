@@ -0,0 +1,81 @@
+//! Values computed by a request and handed to a later request of the same user, such as the result
+//! of a lookup that the add page loads once it is done
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+/// Values that were not taken are forgotten after this long
+const RETENTION: Duration = Duration::from_secs(10 * 60);
+
+struct Stashed<T> {
+    owner: Uuid,
+    stored: Instant,
+    value: T,
+}
+
+pub struct Stash<T> {
+    values: Mutex<HashMap<Uuid, Stashed<T>>>,
+}
+
+impl<T> Default for Stash<T> {
+    fn default() -> Self {
+        Self {
+            values: Default::default(),
+        }
+    }
+}
+
+impl<T> Stash<T> {
+    /// Keep `value` for `owner`, returning the id to take it back
+    pub fn insert(&self, owner: Uuid, value: T) -> Uuid {
+        let mut values = self.values.lock().unwrap();
+        values.retain(|_, stashed| stashed.stored.elapsed() < RETENTION);
+
+        let id = Uuid::new_v4();
+        values.insert(
+            id,
+            Stashed {
+                owner,
+                stored: Instant::now(),
+                value,
+            },
+        );
+
+        id
+    }
+
+    /// Remove the value `id` if it belongs to `owner`
+    pub fn take(&self, owner: Uuid, id: Uuid) -> Option<T> {
+        let mut values = self.values.lock().unwrap();
+
+        match values.get(&id) {
+            Some(stashed) if stashed.owner == owner && stashed.stored.elapsed() < RETENTION => {
+                values.remove(&id).map(|stashed| stashed.value)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::Stash;
+
+    #[test]
+    fn take() {
+        let stash = Stash::default();
+        let owner = Uuid::new_v4();
+
+        let id = stash.insert(owner, "Mort");
+        assert_eq!(stash.take(Uuid::new_v4(), id), None);
+        assert_eq!(stash.take(owner, id), Some("Mort"));
+        assert_eq!(stash.take(owner, id), None);
+    }
+}
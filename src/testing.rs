@@ -0,0 +1,326 @@
+//! Helpers to run the application against a throw-away Postgres database
+//!
+//! All the tests of the binary share a single Postgres cluster, each of them getting its own
+//! database.
+
+use std::sync::{atomic::AtomicBool, Arc, Mutex};
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Method, Request, Response},
+    Router,
+};
+use diesel::prelude::*;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use postgresql_embedded::{PostgreSQL, Settings, BOOTSTRAP_DATABASE};
+use tokio::sync::OnceCell;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::{
+    fetch::PageFetcher,
+    metadata::{covers::CoverSources, MetadataProviders},
+    run_migrations,
+    schema::{book, users},
+    signing::UrlSigner,
+    throttle::Throttle,
+    AppState, Config,
+};
+
+pub(crate) const USER_HEADER: &str = "x-bouquineur-user";
+pub(crate) const TEST_USER: &str = "reader";
+pub(crate) const OTHER_USER: &str = "other-reader";
+
+const BOUNDARY: &str = "bouquineur-test-boundary";
+
+pub(crate) struct TestApp {
+    pub state: Arc<AppState>,
+    router: Router,
+    // Only kept alive for the duration of the test
+    _image_dir: tempfile::TempDir,
+}
+
+/// Cluster shared by all the tests, it is only stopped when the test binary exits
+static CLUSTER: OnceCell<Settings> = OnceCell::const_new();
+static SERVER: Mutex<Option<PostgreSQL>> = Mutex::new(None);
+
+extern "C" fn stop_cluster() {
+    // Dropping the server stops it and removes its data directory
+    if let Ok(mut server) = SERVER.lock() {
+        server.take();
+    }
+}
+
+async fn cluster() -> &'static Settings {
+    CLUSTER
+        .get_or_init(|| async {
+            let mut postgres = PostgreSQL::default();
+            postgres.setup().await.expect("could not setup postgres");
+            postgres.start().await.expect("could not start postgres");
+
+            let settings = postgres.settings().clone();
+            *SERVER.lock().unwrap() = Some(postgres);
+            // SAFETY: `stop_cluster` does not unwind
+            unsafe { libc::atexit(stop_cluster) };
+
+            settings
+        })
+        .await
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with_config("").await
+    }
+
+    /// Start a new application, `extra` is appended to the generated configuration file
+    pub async fn with_config(extra: &str) -> Self {
+        let cluster = cluster().await;
+        let database = format!("bouquineur_{}", Uuid::new_v4().simple());
+        let mut conn = AsyncPgConnection::establish(&cluster.url(BOOTSTRAP_DATABASE))
+            .await
+            .expect("could not connect to postgres");
+        diesel::sql_query(format!("CREATE DATABASE {database}"))
+            .execute(&mut conn)
+            .await
+            .expect("could not create database");
+
+        let url = cluster.url(&database);
+        let image_dir = tempfile::tempdir().expect("could not create image directory");
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [metadata]
+            providers = ["Mock"]
+            image_dir = "{}"
+
+            [metadata.mock]
+
+            [auth]
+            header = "{USER_HEADER}"
+            admin = ["{TEST_USER}"]
+
+            [database]
+            url = "{url}"
+
+            [server]
+            port = 0
+
+            {extra}
+            "#,
+            image_dir.path().display()
+        ))
+        .expect("invalid test configuration");
+
+        let db = crate::db::pool(&config.database).expect("could not build database pool");
+
+        let metadata =
+            MetadataProviders::from_config(&config.metadata).expect("invalid metadata providers");
+
+        let covers = CoverSources::new(&config.metadata.cover_sources);
+        let throttle = Throttle::new(&config.throttle);
+
+        let state = Arc::new(AppState {
+            config,
+            db,
+            metadata,
+            covers,
+            pages: PageFetcher::new().expect("could not build the HTTP client"),
+            signer: UrlSigner::new(None),
+            throttle,
+            maintenance: AtomicBool::new(false),
+            jobs: Default::default(),
+            completions: Default::default(),
+            lookups: Default::default(),
+        });
+        run_migrations(&state).expect("could not run migrations");
+
+        Self {
+            router: crate::router(state.clone()),
+            state,
+            _image_dir: image_dir,
+        }
+    }
+
+    pub async fn request(&self, request: Request<Body>) -> Response<Body> {
+        self.router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("router can't fail")
+    }
+
+    pub async fn get(&self, uri: &str) -> Response<Body> {
+        self.get_as(TEST_USER, uri).await
+    }
+
+    pub async fn get_as(&self, user: &str, uri: &str) -> Response<Body> {
+        self.request(
+            Request::get(uri)
+                .header(USER_HEADER, user)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+    }
+
+    pub async fn post_form(&self, uri: &str, form: &str) -> Response<Body> {
+        self.request(
+            Request::post(uri)
+                .header(USER_HEADER, TEST_USER)
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(form.to_owned()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    pub async fn send_json(
+        &self,
+        method: Method,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> Response<Body> {
+        self.request(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(USER_HEADER, TEST_USER)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    pub async fn post_multipart(&self, uri: &str, form: MultipartForm) -> Response<Body> {
+        self.post_multipart_as(TEST_USER, uri, form).await
+    }
+
+    pub async fn post_multipart_as(
+        &self,
+        user: &str,
+        uri: &str,
+        form: MultipartForm,
+    ) -> Response<Body> {
+        self.request(
+            Request::post(uri)
+                .header(USER_HEADER, user)
+                .header(
+                    CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={BOUNDARY}"),
+                )
+                .body(Body::from(form.into_body()))
+                .unwrap(),
+        )
+        .await
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct MultipartForm {
+    fields: Vec<(String, Option<String>, Vec<u8>)>,
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.fields
+            .push((name.into(), None, value.as_bytes().to_vec()));
+        self
+    }
+
+    pub fn file(mut self, name: &str, file_name: &str, data: Vec<u8>) -> Self {
+        self.fields
+            .push((name.into(), Some(file_name.into()), data));
+        self
+    }
+
+    fn into_body(self) -> Vec<u8> {
+        let mut body = Vec::new();
+
+        for (name, file_name, data) in self.fields {
+            body.extend_from_slice(format!("--{BOUNDARY}\r\n").as_bytes());
+            match file_name {
+                None => body.extend_from_slice(
+                    format!("Content-Disposition: form-data; name=\"{name}\"\r\n\r\n").as_bytes(),
+                ),
+                Some(file_name) => body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{name}\"; filename=\"{file_name}\"\r\n\
+                         Content-Type: application/octet-stream\r\n\r\n"
+                    )
+                    .as_bytes(),
+                ),
+            }
+            body.extend_from_slice(&data);
+            body.extend_from_slice(b"\r\n");
+        }
+
+        body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+        body
+    }
+}
+
+pub(crate) async fn body_text(response: Response<Body>) -> String {
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("could not read body");
+
+    String::from_utf8(bytes.to_vec()).expect("body is not utf-8")
+}
+
+pub(crate) fn location(response: &Response<Body>) -> &str {
+    response
+        .headers()
+        .get(axum::http::header::LOCATION)
+        .expect("response is not a redirect")
+        .to_str()
+        .unwrap()
+}
+
+/// Form adding a book with the fields required by default
+pub(crate) fn book_form(title: &str, isbn: &str) -> MultipartForm {
+    MultipartForm::new()
+        .text("title", title)
+        .text("isbn", isbn)
+        .text("summary", "A summary")
+        .text("author", "Terry Pratchett")
+}
+
+pub(crate) async fn user_id(app: &TestApp, name: &str) -> Uuid {
+    let mut conn = app.state.db.get().await.unwrap();
+
+    users::table
+        .filter(users::name.eq(name))
+        .select(users::id)
+        .get_result(&mut conn)
+        .await
+        .unwrap()
+}
+
+pub(crate) async fn book_id(app: &TestApp, isbn: &str) -> Uuid {
+    let mut conn = app.state.db.get().await.unwrap();
+
+    book::table
+        .filter(book::isbn.eq(isbn))
+        .select(book::id)
+        .get_result(&mut conn)
+        .await
+        .unwrap()
+}
+
+/// Small RGB image suitable as a cover
+pub(crate) fn test_cover() -> Vec<u8> {
+    let mut data = Vec::new();
+    image::RgbImage::from_pixel(12, 18, image::Rgb([200, 30, 30]))
+        .write_to(
+            &mut std::io::Cursor::new(&mut data),
+            image::ImageFormat::Png,
+        )
+        .expect("could not encode test cover");
+    data
+}
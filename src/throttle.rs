@@ -0,0 +1,168 @@
+//! Per-IP throttling and journal of the anonymous accesses to the public pages
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ThrottleConfig {
+    /// Anonymous requests allowed per minute for each IP address
+    #[serde(default = "ThrottleConfig::default_requests_per_minute")]
+    pub requests_per_minute: u32,
+    /// Number of anonymous accesses kept for the administrators
+    #[serde(default = "ThrottleConfig::default_journal_size")]
+    pub journal_size: usize,
+    /// Use the `X-Forwarded-For` header set by a reverse proxy to find the client address
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+    /// Number of reverse proxies appending to `X-Forwarded-For`, the entries before the ones they
+    /// added are set by the client
+    #[serde(default = "ThrottleConfig::default_trusted_proxies")]
+    pub trusted_proxies: usize,
+}
+
+impl ThrottleConfig {
+    fn default_requests_per_minute() -> u32 {
+        60
+    }
+
+    fn default_trusted_proxies() -> usize {
+        1
+    }
+
+    fn default_journal_size() -> usize {
+        200
+    }
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: Self::default_requests_per_minute(),
+            journal_size: Self::default_journal_size(),
+            trust_forwarded_for: false,
+            trusted_proxies: Self::default_trusted_proxies(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnonymousAccess {
+    pub time: DateTime<Utc>,
+    pub ip: Option<IpAddr>,
+    pub path: String,
+    pub status: u16,
+}
+
+pub struct Throttle {
+    requests_per_minute: u32,
+    journal_size: usize,
+    /// Start of the current window of each address, and the number of requests in it
+    windows: Mutex<HashMap<Option<IpAddr>, (Instant, u32)>>,
+    journal: Mutex<VecDeque<AnonymousAccess>>,
+}
+
+impl Throttle {
+    pub fn new(config: &ThrottleConfig) -> Self {
+        Self {
+            requests_per_minute: config.requests_per_minute,
+            journal_size: config.journal_size,
+            windows: Default::default(),
+            journal: Default::default(),
+        }
+    }
+
+    /// Count a request from `ip`, returning if it is allowed
+    pub fn allow(&self, ip: Option<IpAddr>) -> bool {
+        self.allow_at(ip, Instant::now())
+    }
+
+    fn allow_at(&self, ip: Option<IpAddr>, now: Instant) -> bool {
+        let mut windows = self.windows.lock().unwrap();
+
+        // Forget the finished windows from time to time, to avoid growing without bounds
+        if windows.len() > 1024 {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < WINDOW);
+        }
+
+        let (start, count) = windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(*start) >= WINDOW {
+            *start = now;
+            *count = 0;
+        }
+
+        *count += 1;
+        *count <= self.requests_per_minute
+    }
+
+    pub fn record(&self, access: AnonymousAccess) {
+        let mut journal = self.journal.lock().unwrap();
+
+        if journal.len() >= self.journal_size {
+            journal.pop_front();
+        }
+        journal.push_back(access);
+    }
+
+    /// Recorded accesses, the most recent first
+    pub fn recent(&self) -> Vec<AnonymousAccess> {
+        self.journal.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use super::{AnonymousAccess, Throttle, ThrottleConfig};
+
+    fn throttle(requests_per_minute: u32, journal_size: usize) -> Throttle {
+        Throttle::new(&ThrottleConfig {
+            requests_per_minute,
+            journal_size,
+            trust_forwarded_for: false,
+            trusted_proxies: 1,
+        })
+    }
+
+    #[test]
+    fn window() {
+        let throttle = throttle(2, 10);
+        let ip = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+        let other = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)));
+        let now = Instant::now();
+
+        assert!(throttle.allow_at(ip, now));
+        assert!(throttle.allow_at(ip, now + Duration::from_secs(1)));
+        assert!(!throttle.allow_at(ip, now + Duration::from_secs(2)));
+        assert!(throttle.allow_at(other, now + Duration::from_secs(2)));
+        assert!(throttle.allow_at(ip, now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn journal() {
+        let throttle = throttle(2, 2);
+
+        for path in ["/a", "/b", "/c"] {
+            throttle.record(AnonymousAccess {
+                time: chrono::Utc::now(),
+                ip: None,
+                path: path.into(),
+                status: 200,
+            });
+        }
+
+        let paths: Vec<_> = throttle.recent().into_iter().map(|a| a.path).collect();
+        assert_eq!(paths, ["/c", "/b"]);
+    }
+}
@@ -0,0 +1,150 @@
+//! Time-based one-time passwords (RFC 6238), the second factor of `[auth.local]`. The codes are
+//! the ones of the usual authenticator apps: 6 digits from HMAC-SHA1, changing every 30 seconds.
+
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+const STEP: i64 = 30;
+const DIGITS: u32 = 6;
+/// Codes of the steps around the current one are accepted, as clocks drift
+const DRIFT: i64 = 1;
+const ISSUER: &str = "Bouquineur";
+const RECOVERY_CODES: usize = 10;
+
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn new_secret() -> Vec<u8> {
+    rand::random::<[u8; 20]>().to_vec()
+}
+
+/// Unpadded base32, as typed in the authenticator apps
+pub fn encode(data: &[u8]) -> String {
+    let mut encoded = String::new();
+
+    for chunk in data.chunks(5) {
+        let mut block = [0; 8];
+        block[3..3 + chunk.len()].copy_from_slice(chunk);
+        let bits = u64::from_be_bytes(block);
+
+        for i in 0..(chunk.len() * 8).div_ceil(5) {
+            encoded.push(BASE32[(bits >> (35 - i * 5)) as usize & 31] as char);
+        }
+    }
+
+    encoded
+}
+
+/// URI of the secret of `user`, scanned as a QR code by the authenticator apps
+pub fn uri(user: &str, secret: &[u8]) -> String {
+    let mut url = reqwest::Url::parse("otpauth://totp").expect("URL is valid");
+    url.set_path(&format!("{ISSUER}:{user}"));
+    url.query_pairs_mut()
+        .append_pair("secret", &encode(secret))
+        .append_pair("issuer", ISSUER);
+
+    url.to_string()
+}
+
+pub fn step(time: i64) -> i64 {
+    time / STEP
+}
+
+/// Code of the time `step`
+pub fn code(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = usize::from(hash[hash.len() - 1] & 0xf);
+    let value = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Step of `code` if it is valid at `time`, so that it is only accepted once
+pub fn verify(secret: &[u8], code: &str, time: i64) -> Option<i64> {
+    let code = code.trim();
+    let current = step(time);
+
+    (current - DRIFT..=current + DRIFT).find(|&step| self::code(secret, step) == code)
+}
+
+/// Codes usable once instead of the authenticator, in case it is lost
+pub fn recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = encode(&rand::random::<[u8; 5]>()).to_ascii_lowercase();
+            format!("{}-{}", &code[..4], &code[4..])
+        })
+        .collect()
+}
+
+/// Recovery codes are random enough to not need a slow hash
+pub fn recovery_hash(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(code))
+}
+
+#[cfg(test)]
+mod test {
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn encode() {
+        assert_eq!(super::encode(SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(super::encode(b"f"), "MY");
+        assert_eq!(super::encode(b"fooba"), "MZXW6YTB");
+    }
+
+    #[test]
+    fn code() {
+        // Test vectors of RFC 6238, truncated to 6 digits
+        assert_eq!(super::code(SECRET, super::step(59)), "287082");
+        assert_eq!(super::code(SECRET, super::step(1111111109)), "081804");
+        assert_eq!(super::code(SECRET, super::step(2000000000)), "279037");
+    }
+
+    #[test]
+    fn verify() {
+        let step = super::step(1111111109);
+
+        assert_eq!(super::verify(SECRET, " 081804", 1111111109), Some(step));
+        assert_eq!(super::verify(SECRET, "081804", 1111111109 + 30), Some(step));
+        assert_eq!(super::verify(SECRET, "081804", 1111111109 + 90), None);
+        assert_eq!(super::verify(SECRET, "081805", 1111111109), None);
+    }
+
+    #[test]
+    fn recovery() {
+        let codes = super::recovery_codes();
+        assert_eq!(codes.len(), 10);
+        assert_eq!(codes[0].len(), 9);
+
+        let hash = super::recovery_hash(&codes[0]);
+        assert_eq!(
+            super::recovery_hash(&codes[0].to_uppercase().replace('-', " ")),
+            hash
+        );
+        assert_ne!(super::recovery_hash(&codes[1]), hash);
+    }
+
+    #[test]
+    fn uri() {
+        assert_eq!(
+            super::uri("alice smith", SECRET),
+            "otpauth://totp/Bouquineur:alice%20smith\
+             ?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Bouquineur"
+        );
+    }
+}
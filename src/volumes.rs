@@ -0,0 +1,35 @@
+//! Lookup of the number of volumes of a series on the configured databases
+
+use crate::{mangaupdates, wikidata, Config};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct VolumeCount {
+    pub total: i32,
+    pub ongoing: bool,
+}
+
+/// Is there any database configured to look volume counts up
+pub fn can_lookup(config: &Config) -> bool {
+    config.mangaupdates.is_some() || config.wikidata.is_some()
+}
+
+/// Query MangaUpdates then Wikidata for the number of volumes of the series `name`
+pub async fn lookup(config: &Config, name: &str) -> Option<VolumeCount> {
+    if let Some(mangaupdates) = &config.mangaupdates {
+        match mangaupdates::series_volumes(mangaupdates, name).await {
+            Ok(Some(count)) => return Some(count),
+            Ok(None) => (),
+            Err(e) => tracing::warn!("Could not lookup '{name}' on MangaUpdates: {e:?}"),
+        }
+    }
+
+    if let Some(wikidata) = &config.wikidata {
+        match wikidata::series_volumes(wikidata, name).await {
+            Ok(Some(count)) => return Some(count),
+            Ok(None) => (),
+            Err(e) => tracing::warn!("Could not lookup '{name}' on Wikidata: {e:?}"),
+        }
+    }
+
+    None
+}
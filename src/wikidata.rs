@@ -0,0 +1,214 @@
+//! Lookups on Wikidata: parent organization of publishers and number of volumes of series
+
+use serde_json::Value;
+
+use crate::volumes::VolumeCount;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct WikidataConfig {
+    /// URL of the MediaWiki API
+    #[serde(default = "default_api")]
+    pub api: String,
+    /// Language used to search and name the organizations
+    #[serde(default = "default_language")]
+    pub language: String,
+}
+
+fn default_api() -> String {
+    "https://www.wikidata.org/w/api.php".into()
+}
+
+fn default_language() -> String {
+    "en".into()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WikidataError {
+    #[error("Error in HTTP request")]
+    Request(#[from] reqwest::Error),
+    #[error("Invalid JSON response")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Id of the first item of a `wbsearchentities` response
+fn search_result(response: &Value) -> Option<&str> {
+    response["search"][0]["id"].as_str()
+}
+
+/// Id of the parent organization (P749) of `id` in a `wbgetentities` response
+fn parent_claim<'a>(response: &'a Value, id: &str) -> Option<&'a str> {
+    response["entities"][id]["claims"]["P749"][0]["mainsnak"]["datavalue"]["value"]["id"].as_str()
+}
+
+/// Number of parts of the work (P2635) `id` in a `wbgetentities` response
+fn parts_claim(response: &Value, id: &str) -> Option<i32> {
+    response["entities"][id]["claims"]["P2635"][0]["mainsnak"]["datavalue"]["value"]["amount"]
+        .as_str()?
+        .trim_start_matches('+')
+        .parse()
+        .ok()
+}
+
+/// A work with an end time (P582) is finished
+fn has_ended(response: &Value, id: &str) -> bool {
+    response["entities"][id]["claims"]["P582"][0].is_object()
+}
+
+fn label<'a>(response: &'a Value, id: &str, language: &str) -> Option<&'a str> {
+    response["entities"][id]["labels"][language]["value"].as_str()
+}
+
+fn client() -> Result<reqwest::Client, WikidataError> {
+    Ok(reqwest::Client::builder()
+        .user_agent("github.com/traxys/bouquineur")
+        .timeout(std::time::Duration::from_secs(10))
+        .build()?)
+}
+
+/// Id of the first item matching `name`
+async fn search(
+    client: &reqwest::Client,
+    config: &WikidataConfig,
+    name: &str,
+) -> Result<Option<String>, WikidataError> {
+    let search = get(
+        client,
+        config,
+        &[
+            ("action", "wbsearchentities"),
+            ("type", "item"),
+            ("limit", "1"),
+            ("language", &config.language),
+            ("search", name),
+        ],
+    )
+    .await?;
+
+    Ok(search_result(&search).map(str::to_string))
+}
+
+async fn entity_claims(
+    client: &reqwest::Client,
+    config: &WikidataConfig,
+    id: &str,
+) -> Result<Value, WikidataError> {
+    get(
+        client,
+        config,
+        &[
+            ("action", "wbgetentities"),
+            ("props", "claims"),
+            ("ids", id),
+        ],
+    )
+    .await
+}
+
+async fn get(
+    client: &reqwest::Client,
+    config: &WikidataConfig,
+    params: &[(&str, &str)],
+) -> Result<Value, WikidataError> {
+    let response = client
+        .get(&config.api)
+        .query(&[("format", "json")])
+        .query(params)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(serde_json::from_str(&response)?)
+}
+
+/// Name of the parent organization of the publisher `name`, if it is known
+pub async fn parent_organization(
+    config: &WikidataConfig,
+    name: &str,
+) -> Result<Option<String>, WikidataError> {
+    let client = client()?;
+
+    let Some(id) = search(&client, config, name).await? else {
+        return Ok(None);
+    };
+
+    let claims = entity_claims(&client, config, &id).await?;
+
+    let Some(parent) = parent_claim(&claims, &id) else {
+        return Ok(None);
+    };
+
+    let labels = get(
+        &client,
+        config,
+        &[
+            ("action", "wbgetentities"),
+            ("props", "labels"),
+            ("languages", &config.language),
+            ("ids", parent),
+        ],
+    )
+    .await?;
+
+    Ok(label(&labels, parent, &config.language).map(str::to_string))
+}
+
+/// Number of volumes of the series `name`, if it is known
+pub async fn series_volumes(
+    config: &WikidataConfig,
+    name: &str,
+) -> Result<Option<VolumeCount>, WikidataError> {
+    let client = client()?;
+
+    let Some(id) = search(&client, config, name).await? else {
+        return Ok(None);
+    };
+
+    let claims = entity_claims(&client, config, &id).await?;
+
+    Ok(parts_claim(&claims, &id).map(|total| VolumeCount {
+        total,
+        ongoing: !has_ended(&claims, &id),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    #[test]
+    fn parse() {
+        let search = json!({"search": [{"id": "Q2384279", "label": "Tor Books"}]});
+        assert_eq!(super::search_result(&search), Some("Q2384279"));
+        assert_eq!(super::search_result(&json!({"search": []})), None);
+
+        let claims = json!({"entities": {"Q2384279": {"claims": {"P749": [
+            {"mainsnak": {"datavalue": {"value": {"entity-type": "item", "id": "Q1140419"}}}}
+        ]}}}});
+        assert_eq!(super::parent_claim(&claims, "Q2384279"), Some("Q1140419"));
+        assert_eq!(super::parent_claim(&claims, "Q1"), None);
+
+        let labels = json!({"entities": {"Q1140419": {"labels": {
+            "en": {"language": "en", "value": "Macmillan Publishers"}
+        }}}});
+        assert_eq!(
+            super::label(&labels, "Q1140419", "en"),
+            Some("Macmillan Publishers")
+        );
+    }
+
+    #[test]
+    fn parts() {
+        let claims = json!({"entities": {"Q46751": {"claims": {
+            "P2635": [{"mainsnak": {"datavalue": {"value": {"amount": "+41", "unit": "1"}}}}],
+            "P582": [{"mainsnak": {"datavalue": {"value": {"time": "+2015-03-12T00:00:00Z"}}}}]
+        }}}});
+        assert_eq!(super::parts_claim(&claims, "Q46751"), Some(41));
+        assert!(super::has_ended(&claims, "Q46751"));
+
+        let claims = json!({"entities": {"Q1": {"claims": {}}}});
+        assert_eq!(super::parts_claim(&claims, "Q1"), None);
+        assert!(!super::has_ended(&claims, "Q1"));
+    }
+}